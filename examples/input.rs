@@ -14,7 +14,7 @@ fn main() {
     match Input::new("data/simulation.toml").and_then(|input| input.read()) {
         Err(error) => println!("Error in input: {}", error),
         Ok(mut config) => {
-            config.simulation.run(&mut config.system, config.nsteps);
+            config.run();
         }
     }
 }
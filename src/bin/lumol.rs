@@ -7,27 +7,159 @@ extern crate lumol_input;
 #[macro_use]
 extern crate log;
 extern crate clap;
+extern crate ctrlc;
 
-use lumol::Logger;
-use lumol_input::Input;
-use clap::{App, ArgMatches};
+use lumol::{Logger, LogLevel};
+use lumol::sys::{System, Particle, CellShape};
+use lumol::sim::stop;
+use lumol::units;
+use lumol_input::{Input, Config};
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
 
+use std::collections::BTreeMap;
+use std::f64::consts::PI;
+use std::mem;
+use std::path::{Path, PathBuf};
 use std::process::exit;
 
+/// Exit code used when the simulation is interrupted by a signal
+const INTERRUPTED_EXIT_CODE: i32 = 130;
+
+/// Arguments for running a simulation, used by the `run` subcommand and when
+/// no subcommand is given.
+fn run_args<'a, 'b>() -> Vec<Arg<'a, 'b>> {
+    vec![
+        Arg::from_usage("--seed [seed] 'Seed for all the random number generators, overriding the input file'"),
+        Arg::from_usage("--restart [checkpoint] 'Restart the simulation from a checkpoint file'"),
+        Arg::from_usage("--nsteps [nsteps] 'Number of steps for all the simulations, overriding the input file'"),
+        Arg::from_usage("--log-level [level] 'Logging level: error, warn, info, debug or trace, overriding the input file'"),
+        Arg::from_usage("-v --verbose... 'Increase the logging level, can be used multiple times'"),
+        Arg::from_usage("-q --quiet 'Only log errors'").conflicts_with("verbose"),
+        Arg::from_usage("--output-dir [directory] 'Directory for the output files with relative paths'"),
+    ]
+}
+
 fn parse_args<'a>() -> ArgMatches<'a> {
     App::new("lumol")
         .version(env!("CARGO_PKG_VERSION"))
         .about("An extensible molecular simulation engine")
-        .args_from_usage("<input.toml>      'Simulation input file'")
+        .setting(AppSettings::SubcommandsNegateReqs)
+        .setting(AppSettings::VersionlessSubcommands)
+        .arg(Arg::from_usage("<input.toml> 'Simulation input file, running it is the same as using the run subcommand'"))
+        .args(&run_args())
+        .subcommand(SubCommand::with_name("run")
+            .about("Run the simulations in an input file")
+            .arg(Arg::from_usage("<input.toml> 'Simulation input file'"))
+            .args(&run_args()))
+        .subcommand(SubCommand::with_name("validate")
+            .about("Check an input file, reporting all the errors, without running the simulations")
+            .arg(Arg::from_usage("<input.toml> 'Simulation input file'")))
+        .subcommand(SubCommand::with_name("info")
+            .about("Print information about the system in an input file")
+            .arg(Arg::from_usage("<input.toml> 'Simulation input file'")))
         .get_matches()
 }
 
 fn main() {
     let args = parse_args();
-    // TODO: add logger specification to the input file
-    Logger::stdout();
-    let input = args.value_of("input.toml").unwrap();
-    let mut config = match Input::new(input).and_then(|input| input.read()) {
+    match args.subcommand() {
+        ("run", Some(args)) => run(args),
+        ("validate", Some(args)) => validate(args),
+        ("info", Some(args)) => info(args),
+        _ => run(&args),
+    }
+}
+
+/// Parse the value of the argument `name` in `args`, exiting on errors
+fn parse_value<T>(args: &ArgMatches, name: &str) -> Option<T>
+    where T: std::str::FromStr, T::Err: std::fmt::Display
+{
+    args.value_of(name).map(|value| {
+        match value.parse::<T>() {
+            Ok(value) => value,
+            Err(err) => {
+                error!("invalid value '{}' for --{}: {}", value, name, err);
+                exit(2);
+            }
+        }
+    })
+}
+
+/// Read the input file given in the command line arguments, exiting on errors.
+/// The logger is not initialized by this function, except to report errors.
+fn read_input(args: &ArgMatches) -> Input {
+    let path = args.value_of("input.toml").unwrap();
+    match Input::new(path) {
+        Ok(input) => input,
+        Err(err) => {
+            Logger::stdout();
+            error!("bad input file: {}", err);
+            exit(2);
+        }
+    }
+}
+
+/// Initialize the logger from the `[log]` section of the `input` and the
+/// verbosity flags in `args`, exiting on errors
+fn init_logger(input: &Input, args: &ArgMatches) {
+    let mut log = match input.read_log() {
+        Ok(log) => log,
+        Err(err) => {
+            Logger::stdout();
+            error!("bad input file: {}", err);
+            exit(2);
+        }
+    };
+
+    let mut level = log.level.unwrap_or(Logger::default_level());
+    if args.is_present("quiet") {
+        level = LogLevel::Error;
+    }
+    for _ in 0..args.occurrences_of("verbose") {
+        level = match level {
+            LogLevel::Error => LogLevel::Warn,
+            LogLevel::Warn => LogLevel::Info,
+            LogLevel::Info => LogLevel::Debug,
+            LogLevel::Debug | LogLevel::Trace => LogLevel::Trace,
+        };
+    }
+    if let Some(value) = args.value_of("log-level") {
+        level = match value.parse() {
+            Ok(level) => level,
+            Err(_) => {
+                Logger::stdout();
+                error!("invalid value '{}' for --log-level", value);
+                exit(2);
+            }
+        };
+    }
+    log.level = Some(level);
+
+    if let Err(err) = log.init() {
+        Logger::stdout();
+        error!("could not initialize logging: {}", err);
+        exit(2);
+    }
+}
+
+fn run(args: &ArgMatches) {
+    let mut input = read_input(args);
+    if let Some(directory) = args.value_of("output-dir") {
+        input.set_output_dir(directory);
+    }
+    init_logger(&input, args);
+
+    if let Some(seed) = parse_value::<u64>(args, "seed") {
+        input.set_seed(seed);
+    }
+    if let Some(nsteps) = parse_value::<usize>(args, "nsteps") {
+        input.set_nsteps(nsteps);
+    }
+    if let Some(checkpoint) = args.value_of("restart") {
+        input.set_restart(checkpoint);
+    }
+
+    let mut config = match input.read() {
         Ok(config) => config,
         Err(err) => {
             error!("bad input file: {}", err);
@@ -35,5 +167,219 @@ fn main() {
         }
     };
 
-    config.simulation.run(&mut config.system, config.nsteps);
+    let handler = ctrlc::set_handler(|| {
+        if stop::interrupt_requested() {
+            error!("received a second termination signal, exiting now");
+            exit(INTERRUPTED_EXIT_CODE);
+        }
+        warn!("received a termination signal, stopping the simulation after the current step");
+        stop::request_interrupt();
+    });
+    if let Err(err) = handler {
+        warn!("could not set the termination signal handler: {}", err);
+    }
+
+    config.run();
+    if stop::interrupt_requested() {
+        save_interrupted(&config, &default_checkpoint(args));
+    }
+    // Make sure all the files are closed before exiting
+    drop(config);
+    if stop::interrupt_requested() {
+        exit(INTERRUPTED_EXIT_CODE);
+    }
+}
+
+/// Get the path of the checkpoint written when an interrupted simulation does
+/// not write checkpoints: the input file name with a `.chk` extension, in the
+/// output directory if there is one, or next to the input file.
+fn default_checkpoint(args: &ArgMatches) -> PathBuf {
+    let input = Path::new(args.value_of("input.toml").unwrap()).with_extension("chk");
+    match args.value_of("output-dir") {
+        Some(directory) => Path::new(directory).join(input.file_name().unwrap_or("lumol.chk".as_ref())),
+        None => input,
+    }
+}
+
+/// Write a checkpoint of the interrupted simulation in `config` to `path`, so
+/// that it can be restarted later. Nothing is done if the simulation already
+/// wrote its own checkpoint.
+fn save_interrupted(config: &Config, path: &Path) {
+    let mut simulations = Some(&config.simulation).into_iter()
+                                                  .chain(config.stages.iter().map(|stage| &stage.simulation));
+    let interrupted = simulations.find(|simulation| simulation.interrupted());
+    if let Some(simulation) = interrupted {
+        if simulation.checkpoint().is_none() {
+            match simulation.save_checkpoint(&config.system, path) {
+                Ok(()) => warn!(
+                    "wrote a checkpoint of the interrupted simulation to '{}', use `--restart {}` to continue it",
+                    path.display(), path.display()
+                ),
+                Err(err) => error!("could not write checkpoint to '{}': {}", path.display(), err),
+            }
+        }
+    }
+}
+
+fn validate(args: &ArgMatches) {
+    let input = read_input(args);
+    Logger::stdout();
+    let path = args.value_of("input.toml").unwrap();
+    let errors = input.check();
+    if errors.is_empty() {
+        info!("'{}' is a valid input file", path);
+    } else {
+        for err in &errors {
+            error!("{}", err);
+        }
+        error!("found {} error(s) in '{}'", errors.len(), path);
+        exit(2);
+    }
+}
+
+fn info(args: &ArgMatches) {
+    let input = read_input(args);
+    Logger::stdout();
+    let system = match input.read_system() {
+        Ok(system) => system,
+        Err(err) => {
+            error!("bad input file: {}", err);
+            exit(2);
+        }
+    };
+
+    print_system(&system);
+    print_molecules(&system);
+    print_interactions(&system);
+    print_estimates(&system);
+}
+
+fn print_system(system: &System) {
+    let mut composition = BTreeMap::new();
+    for particle in system {
+        *composition.entry(particle.name()).or_insert(0) += 1;
+    }
+    let composition = composition.iter()
+                                 .map(|(name, count)| format!("{} {}", count, name))
+                                 .collect::<Vec<_>>();
+
+    println!("System");
+    println!("    {} particles in {} molecules", system.size(), system.molecules().len());
+    println!("    composition: {}", composition.join(", "));
+
+    let cell = system.cell();
+    let shape = match cell.shape() {
+        CellShape::Infinite => "infinite",
+        CellShape::Orthorombic => "orthorhombic",
+        CellShape::Triclinic => "triclinic",
+    };
+    if cell.is_infinite() {
+        println!("    cell: {}", shape);
+    } else {
+        println!(
+            "    cell: {}, a = {} A, b = {} A, c = {} A, alpha = {}°, beta = {}°, gamma = {}°",
+            shape, unit_to(cell.a(), "A"), unit_to(cell.b(), "A"), unit_to(cell.c(), "A"),
+            cell.alpha(), cell.beta(), cell.gamma()
+        );
+        println!("    volume: {} A^3", unit_to(cell.volume(), "A^3"));
+    }
+}
+
+fn print_molecules(system: &System) {
+    // Molecule type => (first molecule with this type, number of molecules)
+    let mut types = BTreeMap::new();
+    for molid in 0..system.molecules().len() {
+        let entry = types.entry(system.molecule_type(molid)).or_insert((molid, 0));
+        entry.1 += 1;
+    }
+    let mut types = types.values().cloned().collect::<Vec<_>>();
+    types.sort();
+
+    println!("Molecule types");
+    for (molid, count) in types {
+        let molecule = system.molecule(molid);
+        let names = (molecule.start()..molecule.end()).map(|i| system[i].name())
+                                                       .collect::<Vec<_>>();
+        println!(
+            "    {} molecule(s) of {} atom(s) ({}), with {} bond(s), {} angle(s) and {} dihedral(s)",
+            count, molecule.size(), names.join(" "), molecule.bonds().len(),
+            molecule.angles().len(), molecule.dihedrals().len()
+        );
+    }
+}
+
+fn print_interactions(system: &System) {
+    // One particle of each kind, sorted by name
+    let mut species = BTreeMap::new();
+    for particle in system {
+        let _ = species.entry(particle.name()).or_insert(particle.kind);
+    }
+    let species = species.into_iter().collect::<Vec<_>>();
+
+    println!("Pair interactions");
+    for (i, &(name_i, kind_i)) in species.iter().enumerate() {
+        for &(name_j, kind_j) in &species[i..] {
+            let pairs = system.interactions().pairs(kind_i, kind_j);
+            if pairs.is_empty() {
+                println!("    {}-{}: no potential", name_i, name_j);
+            } else {
+                let cutoffs = pairs.iter()
+                                   .map(|pair| format!("{} A", unit_to(pair.get_cutoff(), "A")))
+                                   .collect::<Vec<_>>();
+                println!(
+                    "    {}-{}: {} potential(s), cutoff: {}",
+                    name_i, name_j, pairs.len(), cutoffs.join(", ")
+                );
+            }
+        }
+    }
+
+    let coulomb = if system.interactions().coulomb().is_some() {"yes"} else {"no"};
+    println!("Other interactions");
+    println!("    electrostatic interactions: {}", coulomb);
+    println!("    global potentials: {}", system.interactions().globals().len());
+}
+
+fn print_estimates(system: &System) {
+    let natoms = system.size() as f64;
+    let particles = natoms * mem::size_of::<Particle>() as f64;
+    // Monte-Carlo simulations store the energy of all the pairs of particles
+    let cache = natoms * natoms * mem::size_of::<f64>() as f64;
+
+    let npairs = natoms * (natoms - 1.0) / 2.0;
+    let cutoff = system.interactions().all_pairs().iter()
+                       .map(|pair| pair.get_cutoff())
+                       .fold(0.0, f64::max);
+    let fraction = if system.cell().is_infinite() {
+        1.0
+    } else {
+        f64::min(1.0, 4.0 / 3.0 * PI * cutoff * cutoff * cutoff / system.cell().volume())
+    };
+
+    println!("Estimates");
+    println!("    memory: {} for the particles, {} for the Monte-Carlo energy cache", bytes(particles), bytes(cache));
+    println!(
+        "    cost per step: {} pairs of particles, about {} of them within the cutoff",
+        npairs, (fraction * npairs).round()
+    );
+    if system.interactions().coulomb().is_some() || !system.interactions().globals().is_empty() {
+        println!("    the cost of the electrostatic and global interactions is not included");
+    }
+}
+
+/// Convert `value` from internal units to `unit`
+fn unit_to(value: f64, unit: &str) -> f64 {
+    units::to(value, unit).expect("bad unit")
+}
+
+/// Format a number of bytes in a human readable way
+fn bytes(value: f64) -> String {
+    let mut value = value;
+    for unit in &["B", "kB", "MB", "GB"] {
+        if value < 1024.0 {
+            return format!("{:.1} {}", value, unit);
+        }
+        value /= 1024.0;
+    }
+    return format!("{:.1} TB", value);
 }
@@ -0,0 +1,525 @@
+// Lumol, an extensible molecular simulation engine
+// Copyright (C) 2015-2016 Lumol's contributors — BSD license
+
+//! Binary checkpoints of the simulation state, used to restart simulations.
+//!
+//! A checkpoint file starts with the `LUMOLCHK` magic bytes and the format
+//! version, followed by the checkpoint data. All the values are stored in
+//! little-endian order, and floating point values are stored with their
+//! exact bit pattern so that a restarted simulation continues exactly as the
+//! original one would have.
+//!
+//! The data is organized in length-prefixed sections, so that each component
+//! of the simulation (system, propagator, outputs) can only read its own
+//! data. Components implement the `Checkpoint` trait to save and restore
+//! their state.
+use std::error;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::prelude::*;
+use std::io;
+use std::path::Path;
+
+use types::{Vector3D, Matrix3, Zero};
+use rng::{Random, RngState};
+
+/// Magic bytes at the beginning of all checkpoint files
+const MAGIC: &'static [u8; 8] = b"LUMOLCHK";
+
+/// Current version of the checkpoint format. This must be incremented every
+/// time the format changes.
+pub const CHECKPOINT_VERSION: u64 = 1;
+
+/// Possible causes of error when reading or writing checkpoints
+#[derive(Debug)]
+pub enum CheckpointError {
+    /// Error while reading or writing the checkpoint file
+    Io(io::Error),
+    /// The checkpoint data is invalid, or does not correspond to the current
+    /// simulation
+    Format(String),
+}
+
+impl From<io::Error> for CheckpointError {
+    fn from(err: io::Error) -> CheckpointError {
+        CheckpointError::Io(err)
+    }
+}
+
+impl<'a> From<&'a str> for CheckpointError {
+    fn from(err: &'a str) -> CheckpointError {
+        CheckpointError::Format(String::from(err))
+    }
+}
+
+impl From<String> for CheckpointError {
+    fn from(err: String) -> CheckpointError {
+        CheckpointError::Format(err)
+    }
+}
+
+impl fmt::Display for CheckpointError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        match *self {
+            CheckpointError::Io(ref err) => write!(fmt, "{}", err),
+            CheckpointError::Format(ref err) => write!(fmt, "{}", err),
+        }
+    }
+}
+
+impl error::Error for CheckpointError {
+    fn description(&self) -> &str {
+        match *self {
+            CheckpointError::Io(ref err) => err.description(),
+            CheckpointError::Format(ref err) => err,
+        }
+    }
+
+    fn cause(&self) -> Option<&error::Error> {
+        match *self {
+            CheckpointError::Io(ref err) => Some(err),
+            CheckpointError::Format(..) => None,
+        }
+    }
+}
+
+/// The `Checkpoint` trait allow to save the state of an object in a
+/// checkpoint, and to restore it later.
+pub trait Checkpoint {
+    /// Save the state of this object with the `writer`
+    fn save(&self, writer: &mut CheckpointWriter);
+
+    /// Restore the state of this object from the data in `reader`, which was
+    /// written by `Checkpoint::save`.
+    fn restore(&mut self, reader: &mut CheckpointReader) -> Result<(), CheckpointError>;
+}
+
+/// Binary writer for checkpoint data
+#[derive(Clone, Debug, Default)]
+pub struct CheckpointWriter {
+    data: Vec<u8>,
+}
+
+impl CheckpointWriter {
+    /// Create a new empty `CheckpointWriter`
+    pub fn new() -> CheckpointWriter {
+        CheckpointWriter {
+            data: Vec::new(),
+        }
+    }
+
+    /// Get the data written so far
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Write an unsigned integer
+    pub fn write_u64(&mut self, value: u64) {
+        for i in 0..8 {
+            self.data.push((value >> (8 * i)) as u8);
+        }
+    }
+
+    /// Write a signed integer
+    pub fn write_i64(&mut self, value: i64) {
+        self.write_u64(value as u64);
+    }
+
+    /// Write a size or an index
+    pub fn write_usize(&mut self, value: usize) {
+        self.write_u64(value as u64);
+    }
+
+    /// Write a floating point value, keeping all its bits
+    pub fn write_f64(&mut self, value: f64) {
+        self.write_u64(value.to_bits());
+    }
+
+    /// Write a boolean value
+    pub fn write_bool(&mut self, value: bool) {
+        self.data.push(value as u8);
+    }
+
+    /// Write a string
+    pub fn write_str(&mut self, value: &str) {
+        self.write_bytes(value.as_bytes());
+    }
+
+    /// Write a length-prefixed array of bytes
+    pub fn write_bytes(&mut self, value: &[u8]) {
+        self.write_usize(value.len());
+        self.data.extend_from_slice(value);
+    }
+
+    /// Write a vector
+    pub fn write_vector(&mut self, value: &Vector3D) {
+        for i in 0..3 {
+            self.write_f64(value[i]);
+        }
+    }
+
+    /// Write a matrix
+    pub fn write_matrix(&mut self, value: &Matrix3) {
+        for i in 0..3 {
+            for j in 0..3 {
+                self.write_f64(value[i][j]);
+            }
+        }
+    }
+
+    /// Write a list of vectors
+    pub fn write_vectors(&mut self, values: &[Vector3D]) {
+        self.write_usize(values.len());
+        for value in values {
+            self.write_vector(value);
+        }
+    }
+
+    /// Write a list of floating point values
+    pub fn write_f64s(&mut self, values: &[f64]) {
+        self.write_usize(values.len());
+        for &value in values {
+            self.write_f64(value);
+        }
+    }
+
+    /// Write the state of `value` in a separated section. The corresponding
+    /// `CheckpointReader::read_section` will only be able to read the data
+    /// written by `value`.
+    pub fn write_section<T: Checkpoint + ?Sized>(&mut self, value: &T) {
+        let mut writer = CheckpointWriter::new();
+        value.save(&mut writer);
+        self.write_bytes(writer.as_bytes());
+    }
+}
+
+/// Binary reader for checkpoint data written by a `CheckpointWriter`
+#[derive(Clone, Debug)]
+pub struct CheckpointReader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> CheckpointReader<'a> {
+    /// Create a new `CheckpointReader` reading from `data`
+    pub fn new(data: &'a [u8]) -> CheckpointReader<'a> {
+        CheckpointReader {
+            data: data,
+        }
+    }
+
+    /// Check if all the data has been read
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    fn take(&mut self, size: usize) -> Result<&'a [u8], CheckpointError> {
+        if self.data.len() < size {
+            return Err(CheckpointError::from("unexpected end of checkpoint data"));
+        }
+        let (value, rest) = self.data.split_at(size);
+        self.data = rest;
+        Ok(value)
+    }
+
+    /// Read an unsigned integer
+    pub fn read_u64(&mut self) -> Result<u64, CheckpointError> {
+        let bytes = try!(self.take(8));
+        let mut value = 0;
+        for (i, &byte) in bytes.iter().enumerate() {
+            value |= u64::from(byte) << (8 * i);
+        }
+        Ok(value)
+    }
+
+    /// Read a signed integer
+    pub fn read_i64(&mut self) -> Result<i64, CheckpointError> {
+        self.read_u64().map(|value| value as i64)
+    }
+
+    /// Read a size or an index
+    pub fn read_usize(&mut self) -> Result<usize, CheckpointError> {
+        self.read_u64().map(|value| value as usize)
+    }
+
+    /// Read a floating point value
+    pub fn read_f64(&mut self) -> Result<f64, CheckpointError> {
+        self.read_u64().map(f64::from_bits)
+    }
+
+    /// Read a boolean value
+    pub fn read_bool(&mut self) -> Result<bool, CheckpointError> {
+        match try!(self.take(1))[0] {
+            0 => Ok(false),
+            1 => Ok(true),
+            other => Err(CheckpointError::from(format!("invalid boolean value {} in checkpoint", other))),
+        }
+    }
+
+    /// Read a string
+    pub fn read_string(&mut self) -> Result<String, CheckpointError> {
+        let bytes = try!(self.read_bytes());
+        String::from_utf8(bytes.to_vec()).map_err(|_| {
+            CheckpointError::from("invalid UTF-8 string in checkpoint")
+        })
+    }
+
+    /// Read a length-prefixed array of bytes
+    pub fn read_bytes(&mut self) -> Result<&'a [u8], CheckpointError> {
+        let size = try!(self.read_usize());
+        self.take(size)
+    }
+
+    /// Read a vector
+    pub fn read_vector(&mut self) -> Result<Vector3D, CheckpointError> {
+        let x = try!(self.read_f64());
+        let y = try!(self.read_f64());
+        let z = try!(self.read_f64());
+        Ok(Vector3D::new(x, y, z))
+    }
+
+    /// Read a matrix
+    pub fn read_matrix(&mut self) -> Result<Matrix3, CheckpointError> {
+        let mut matrix = Matrix3::zero();
+        for i in 0..3 {
+            for j in 0..3 {
+                matrix[i][j] = try!(self.read_f64());
+            }
+        }
+        Ok(matrix)
+    }
+
+    /// Read the number of items in a list, where each item takes at least
+    /// `size` bytes. This checks that there is enough data left for all the
+    /// items, so that the count can be used to allocate memory.
+    pub fn read_count(&mut self, size: usize) -> Result<usize, CheckpointError> {
+        let count = try!(self.read_usize());
+        match count.checked_mul(size) {
+            Some(total) if total <= self.data.len() => Ok(count),
+            _ => Err(CheckpointError::from("invalid number of items in checkpoint")),
+        }
+    }
+
+    /// Read a list of vectors
+    pub fn read_vectors(&mut self) -> Result<Vec<Vector3D>, CheckpointError> {
+        let size = try!(self.read_count(24));
+        let mut values = Vec::with_capacity(size);
+        for _ in 0..size {
+            values.push(try!(self.read_vector()));
+        }
+        Ok(values)
+    }
+
+    /// Read a list of floating point values
+    pub fn read_f64s(&mut self) -> Result<Vec<f64>, CheckpointError> {
+        let size = try!(self.read_count(8));
+        let mut values = Vec::with_capacity(size);
+        for _ in 0..size {
+            values.push(try!(self.read_f64()));
+        }
+        Ok(values)
+    }
+
+    /// Restore the state of `value` from a section written by
+    /// `CheckpointWriter::write_section`. This function checks that `value`
+    /// read all the data in the section.
+    pub fn read_section<T: Checkpoint + ?Sized>(&mut self, value: &mut T) -> Result<(), CheckpointError> {
+        let mut reader = CheckpointReader::new(try!(self.read_bytes()));
+        try!(value.restore(&mut reader));
+        if reader.is_empty() {
+            Ok(())
+        } else {
+            Err(CheckpointError::from("unexpected data at the end of a checkpoint section"))
+        }
+    }
+}
+
+/// Write the checkpoint `data` to the file at `path`. The data is first
+/// written to a temporary file which is then renamed, so that an existing
+/// checkpoint is never left half written.
+pub fn write_file<P: AsRef<Path>>(path: P, data: &[u8]) -> Result<(), CheckpointError> {
+    let path = path.as_ref();
+    let mut temporary = path.as_os_str().to_owned();
+    temporary.push(".tmp");
+    {
+        let mut file = try!(File::create(&temporary));
+        let mut header = CheckpointWriter::new();
+        header.write_u64(CHECKPOINT_VERSION);
+        try!(file.write_all(MAGIC));
+        try!(file.write_all(header.as_bytes()));
+        try!(file.write_all(data));
+        try!(file.sync_all());
+    }
+    try!(fs::rename(&temporary, path));
+    Ok(())
+}
+
+/// Read the checkpoint data from the file at `path`, checking the file
+/// header and the format version.
+pub fn read_file<P: AsRef<Path>>(path: P) -> Result<Vec<u8>, CheckpointError> {
+    let mut file = try!(File::open(path));
+    let mut buffer = Vec::new();
+    let _ = try!(file.read_to_end(&mut buffer));
+
+    if buffer.len() < MAGIC.len() || &buffer[..MAGIC.len()] != MAGIC {
+        return Err(CheckpointError::from("this file is not a Lumol checkpoint"));
+    }
+
+    let data = {
+        let mut reader = CheckpointReader::new(&buffer[MAGIC.len()..]);
+        let version = try!(reader.read_u64());
+        if version != CHECKPOINT_VERSION {
+            return Err(CheckpointError::from(format!(
+                "unsupported checkpoint version {}, expected version {}", version, CHECKPOINT_VERSION
+            )));
+        }
+        reader.data.to_vec()
+    };
+    Ok(data)
+}
+
+impl Checkpoint for Random {
+    fn save(&self, writer: &mut CheckpointWriter) {
+        match *self.state() {
+            RngState::XorShift(ref words) => {
+                writer.write_u64(0);
+                for &word in words {
+                    writer.write_u64(u64::from(word));
+                }
+            }
+            RngState::Pcg32{state, increment} => {
+                writer.write_u64(1);
+                writer.write_u64(state);
+                writer.write_u64(increment);
+            }
+        }
+    }
+
+    fn restore(&mut self, reader: &mut CheckpointReader) -> Result<(), CheckpointError> {
+        let state = match try!(reader.read_u64()) {
+            0 => {
+                let mut words = [0; 4];
+                for word in &mut words {
+                    *word = try!(reader.read_u64()) as u32;
+                }
+                if words.iter().all(|&word| word == 0) {
+                    return Err(CheckpointError::from("invalid xorshift state in checkpoint"));
+                }
+                RngState::XorShift(words)
+            }
+            1 => {
+                let state = try!(reader.read_u64());
+                let increment = try!(reader.read_u64());
+                if increment % 2 == 0 {
+                    return Err(CheckpointError::from("invalid PCG32 state in checkpoint"));
+                }
+                RngState::Pcg32{state: state, increment: increment}
+            }
+            other => {
+                return Err(CheckpointError::from(format!(
+                    "unknown random number generator {} in checkpoint", other
+                )));
+            }
+        };
+        *self = Random::from_state(state);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate tempfile;
+    use self::tempfile::NamedTempFile;
+
+    use super::*;
+    use rand::Rng;
+    use rng::{Random, RngAlgorithm};
+    use types::{Vector3D, Matrix3};
+
+    #[test]
+    fn values() {
+        let mut writer = CheckpointWriter::new();
+        writer.write_u64(42);
+        writer.write_i64(-3);
+        writer.write_f64(0.1);
+        writer.write_bool(true);
+        writer.write_str("Hello");
+        writer.write_vector(&Vector3D::new(1.0, -2.0, 3.5));
+        writer.write_matrix(&Matrix3::new(1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 8.0, 9.0));
+
+        let mut reader = CheckpointReader::new(writer.as_bytes());
+        assert_eq!(reader.read_u64().unwrap(), 42);
+        assert_eq!(reader.read_i64().unwrap(), -3);
+        assert_eq!(reader.read_f64().unwrap(), 0.1);
+        assert_eq!(reader.read_bool().unwrap(), true);
+        assert_eq!(reader.read_string().unwrap(), "Hello");
+        assert_eq!(reader.read_vector().unwrap(), Vector3D::new(1.0, -2.0, 3.5));
+        assert_eq!(reader.read_matrix().unwrap(), Matrix3::new(1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 8.0, 9.0));
+        assert!(reader.is_empty());
+        assert!(reader.read_u64().is_err());
+    }
+
+    #[test]
+    fn count() {
+        let mut writer = CheckpointWriter::new();
+        writer.write_vectors(&[Vector3D::zero(), Vector3D::new(1.0, 2.0, 3.0)]);
+        let mut reader = CheckpointReader::new(writer.as_bytes());
+        assert_eq!(reader.read_vectors().unwrap().len(), 2);
+
+        let mut writer = CheckpointWriter::new();
+        writer.write_f64s(&[1.0, -0.5, 2.5]);
+        let mut reader = CheckpointReader::new(writer.as_bytes());
+        assert_eq!(reader.read_f64s().unwrap(), vec![1.0, -0.5, 2.5]);
+
+        let mut writer = CheckpointWriter::new();
+        writer.write_usize(usize::max_value());
+        writer.write_vector(&Vector3D::zero());
+        let mut reader = CheckpointReader::new(writer.as_bytes());
+        assert!(reader.read_vectors().is_err());
+
+        let mut reader = CheckpointReader::new(writer.as_bytes());
+        assert!(reader.read_count(4).is_err());
+        let mut reader = CheckpointReader::new(writer.as_bytes());
+        assert_eq!(reader.read_count(0).unwrap(), usize::max_value());
+    }
+
+    #[test]
+    fn random() {
+        for &algorithm in &[RngAlgorithm::XorShift, RngAlgorithm::Pcg32] {
+            let mut rng = Random::new(algorithm, 1234);
+            let _ = rng.next_f64();
+            let mut writer = CheckpointWriter::new();
+            writer.write_section(&rng);
+
+            let mut restored = Random::new(RngAlgorithm::XorShift, 0);
+            let mut reader = CheckpointReader::new(writer.as_bytes());
+            reader.read_section(&mut restored).unwrap();
+            assert_eq!(restored.next_f64(), rng.next_f64());
+        }
+    }
+
+    #[test]
+    fn section_size() {
+        let mut writer = CheckpointWriter::new();
+        writer.write_section(&Random::new(RngAlgorithm::Pcg32, 1234));
+        let mut rng = Random::new(RngAlgorithm::XorShift, 0);
+        let mut reader = CheckpointReader::new(writer.as_bytes());
+        assert!(reader.read_section(&mut rng).is_ok());
+
+        // Additional data at the end of the section
+        let mut data = writer.as_bytes().to_vec();
+        data[0] += 1;
+        data.push(0);
+        let mut reader = CheckpointReader::new(&data);
+        assert!(reader.read_section(&mut rng).is_err());
+    }
+
+    #[test]
+    fn files() {
+        let tempfile = NamedTempFile::new().unwrap();
+        write_file(tempfile.path(), &[1, 2, 3]).unwrap();
+        assert_eq!(read_file(tempfile.path()).unwrap(), vec![1, 2, 3]);
+
+        let tempfile = NamedTempFile::new().unwrap();
+        assert!(read_file(tempfile.path()).is_err());
+    }
+}
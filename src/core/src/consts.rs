@@ -11,3 +11,5 @@ pub const BOHR_RADIUS: f64 = 0.52917720859;
 pub const NA: f64 = 6.02214179e23;
 /// 4 * pi * epsilon_0
 pub const ELCC: f64 = 7.197589831304046;
+/// Speed of light in vacuum
+pub const SPEED_OF_LIGHT: f64 = 2997.92458;
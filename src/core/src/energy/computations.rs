@@ -113,6 +113,22 @@ impl TableComputation {
             potential: potential,
         }
     }
+
+    /// Get the potential tabulated by this computation
+    pub fn potential(&self) -> &PairPotential {
+        &*self.potential
+    }
+
+    /// Get the number of tabulated points
+    pub fn size(&self) -> usize {
+        self.energy_table.len()
+    }
+
+    /// Get the maximal tabulated value, after which the energy and the force
+    /// are zero.
+    pub fn max(&self) -> f64 {
+        self.cutoff
+    }
 }
 
 impl Computation for TableComputation {
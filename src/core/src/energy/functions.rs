@@ -169,6 +169,8 @@ impl DihedralPotential for Harmonic {}
 pub struct CosineHarmonic {
     /// Spring constant
     k: f64,
+    /// Equilibrium value
+    x0: f64,
     /// Cosine of the equilibrium value
     cos_x0: f64,
 }
@@ -177,7 +179,17 @@ impl CosineHarmonic {
     /// Create a new `CosineHarmonic` potentials, with elastic constant of `k`
     /// and equilibrium value of `x0`
     pub fn new(k: f64, x0:f64) -> CosineHarmonic {
-        CosineHarmonic{k: k, cos_x0: f64::cos(x0)}
+        CosineHarmonic{k: k, x0: x0, cos_x0: f64::cos(x0)}
+    }
+
+    /// Get the elastic constant of this potential
+    pub fn k(&self) -> f64 {
+        self.k
+    }
+
+    /// Get the equilibrium value of this potential
+    pub fn x0(&self) -> f64 {
+        self.x0
     }
 }
 
@@ -441,6 +453,8 @@ mod tests {
     #[test]
     fn cosine_harmonic() {
         let harmonic = CosineHarmonic::new(50.0, 2.0);
+        assert_eq!(harmonic.k(), 50.0);
+        assert_eq!(harmonic.x0(), 2.0);
         assert_eq!(harmonic.energy(2.0), 0.0);
         let dcos = f64::cos(2.5) - f64::cos(2.0);
         assert_eq!(harmonic.energy(2.5), 0.5 * 50.0 * dcos * dcos);
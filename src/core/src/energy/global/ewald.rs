@@ -8,6 +8,7 @@ use std::f64;
 use sys::{System, UnitCell, CellShape};
 use types::{Matrix3, Vector3D, Array3, Complex, Zero};
 use consts::ELCC;
+use utils;
 use energy::{PairRestriction, RestrictionInfo};
 
 use super::{GlobalPotential, CoulombicPotential, GlobalCache};
@@ -109,6 +110,26 @@ impl Ewald {
         self.alpha = alpha;
     }
 
+    /// Get the value of the alpha parameter for ewald computation
+    pub fn alpha(&self) -> f64 {
+        self.alpha
+    }
+
+    /// Get the real-space cutoff of this Ewald summation
+    pub fn cutoff(&self) -> f64 {
+        self.rc
+    }
+
+    /// Get the number of points in k-space of this Ewald summation
+    pub fn kmax(&self) -> usize {
+        self.kmax
+    }
+
+    /// Get the pair restriction used by this Ewald summation
+    pub fn restriction(&self) -> PairRestriction {
+        self.restriction
+    }
+
     fn precompute(&mut self, cell: &UnitCell) {
         if let Some(ref prev_cell) = self.previous_cell {
             if cell == prev_cell {
@@ -324,26 +345,7 @@ impl Ewald {
     /// Compute the Fourier transform of the electrostatic density
     fn density_fft(&mut self, system: &System) {
         let natoms = system.size();
-        self.fourier_phases.resize_if_different((self.kmax, natoms, 3));
-
-        // Do the k=0, 1 cases first
-        for i in 0..natoms {
-            let ri = system.cell().fractional(&system[i].position);
-            for j in 0..3 {
-                self.fourier_phases[(0, i, j)] = Complex::polar(1.0, 0.0);
-                self.fourier_phases[(1, i, j)] = Complex::polar(1.0, -2.0 * PI * ri[j]);
-            }
-        }
-
-        // Use recursive definition for computing the factor for all the other values of k.
-        for k in 2..self.kmax {
-            for i in 0..natoms {
-                for j in 0..3 {
-                    self.fourier_phases[(k, i, j)] = self.fourier_phases[(k - 1, i, j)]
-                                                   * self.fourier_phases[(1, i, j)];
-                }
-            }
-        }
+        utils::fourier_phases(system, self.kmax, &mut self.fourier_phases);
 
         for ikx in 0..self.kmax {
             for iky in 0..self.kmax {
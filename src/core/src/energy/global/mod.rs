@@ -8,6 +8,7 @@
 use sys::System;
 use types::{Matrix3, Vector3D};
 use energy::PairRestriction;
+use utils::AsAny;
 
 /// A potential acting on the whole [System][System] at once.
 ///
@@ -173,7 +174,7 @@ pub trait GlobalCache {
 /// coulombic potential solvers.
 ///
 /// [GlobalPotential]: trait.GlobalPotential.html
-pub trait CoulombicPotential : GlobalPotential + BoxCloneCoulombic {
+pub trait CoulombicPotential : GlobalPotential + BoxCloneCoulombic + AsAny {
     /// Set the pair restriction scheme to use to the given `restriction`. All
     /// future call to `GlobalPotential::energy`, `GlobalPotential::force` or
     /// `GlobalPotential::virial` should use this restriction.
@@ -181,6 +182,7 @@ pub trait CoulombicPotential : GlobalPotential + BoxCloneCoulombic {
 }
 
 impl_box_clone!(CoulombicPotential, BoxCloneCoulombic, box_clone_coulombic);
+impl_downcast!(CoulombicPotential);
 
 mod wolf;
 pub use self::wolf::Wolf;
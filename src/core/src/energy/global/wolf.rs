@@ -82,6 +82,16 @@ impl Wolf {
         }
     }
 
+    /// Get the real-space cutoff of this Wolf summation
+    pub fn cutoff(&self) -> f64 {
+        self.cutoff
+    }
+
+    /// Get the pair restriction used by this Wolf summation
+    pub fn restriction(&self) -> PairRestriction {
+        self.restriction
+    }
+
     /// Compute the energy for the pair of particles with charge `qi` and `qj`,
     /// at the distance of `rij`. The `scaling` parameter comes from the
     /// restriction associated with this potential.
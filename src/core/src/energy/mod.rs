@@ -60,6 +60,7 @@
 //! [GlobalPotential]: trait.GlobalPotential.html
 //! [CoulombicPotential]: trait.CoulombicPotential.html
 use types::{Matrix3, Vector3D};
+use utils::AsAny;
 
 /// A potential for force and energy computations.
 ///
@@ -132,7 +133,7 @@ pub trait Potential : Sync + Send {
 ///     }
 /// }
 /// ```
-pub trait PairPotential : Potential + BoxClonePair {
+pub trait PairPotential : Potential + BoxClonePair + AsAny {
     /// Compute the virial contribution corresponding to the distance `r`
     /// between the particles.
     fn virial(&self, r: &Vector3D) -> Matrix3 {
@@ -163,6 +164,7 @@ pub trait PairPotential : Potential + BoxClonePair {
     fn tail_virial(&self, cutoff: f64) -> f64;
 }
 impl_box_clone!(PairPotential, BoxClonePair, box_clone_pair);
+impl_downcast!(PairPotential);
 
 /// Marker trait for potentials that can be used for molecular bonds.
 ///
@@ -183,7 +185,7 @@ impl_box_clone!(PairPotential, BoxClonePair, box_clone_pair);
 /// // Now we can use the Null potential for bonds
 /// impl BondPotential for Null {}
 /// ```
-pub trait BondPotential : Potential + BoxCloneBond {
+pub trait BondPotential : Potential + BoxCloneBond + AsAny {
     /// Compute the virial contribution corresponding to the distance `r`
     /// between the particles.
     fn virial(&self, r: &Vector3D) -> Matrix3 {
@@ -194,6 +196,7 @@ pub trait BondPotential : Potential + BoxCloneBond {
     }
 }
 impl_box_clone!(BondPotential, BoxCloneBond, box_clone_bond);
+impl_downcast!(BondPotential);
 
 /// Marker trait for potentials that can be used for molecular angles.
 ///
@@ -214,8 +217,9 @@ impl_box_clone!(BondPotential, BoxCloneBond, box_clone_bond);
 /// // Now we can use the Null potential for angles
 /// impl AnglePotential for Null {}
 /// ```
-pub trait AnglePotential : Potential + BoxCloneAngle {}
+pub trait AnglePotential : Potential + BoxCloneAngle + AsAny {}
 impl_box_clone!(AnglePotential, BoxCloneAngle, box_clone_angle);
+impl_downcast!(AnglePotential);
 
 /// Marker trait for potentials that can be used for molecular dihedral angles.
 ///
@@ -236,8 +240,9 @@ impl_box_clone!(AnglePotential, BoxCloneAngle, box_clone_angle);
 /// // Now we can use the Null potential for dihedral angles
 /// impl DihedralPotential for Null {}
 /// ```
-pub trait DihedralPotential : Potential + BoxCloneDihedral {}
+pub trait DihedralPotential : Potential + BoxCloneDihedral + AsAny {}
 impl_box_clone!(DihedralPotential, BoxCloneDihedral, box_clone_dihedral);
+impl_downcast!(DihedralPotential);
 
 mod functions;
 pub use self::functions::{NullPotential, LennardJones, Harmonic, CosineHarmonic};
@@ -164,6 +164,62 @@ impl PairInteraction {
     pub fn get_cutoff(&self) -> f64 {
         self.cutoff
     }
+
+    /// Get the potential used by this interaction
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use lumol::energy::PairInteraction;
+    /// use lumol::energy::Harmonic;
+    ///
+    /// let potential = Box::new(Harmonic{x0: 0.5, k: 4.2});
+    /// let interaction = PairInteraction::new(potential, 2.0);
+    ///
+    /// let harmonic = interaction.potential().downcast_ref::<Harmonic>().unwrap();
+    /// assert_eq!(harmonic.k, 4.2);
+    /// ```
+    pub fn potential(&self) -> &PairPotential {
+        &*self.potential
+    }
+
+    /// Check if this interaction uses shifted computation of the energy
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use lumol::energy::PairInteraction;
+    /// use lumol::energy::Harmonic;
+    ///
+    /// let potential = Box::new(Harmonic{x0: 0.5, k: 4.2});
+    /// let interaction = PairInteraction::shifted(potential, 2.0);
+    /// assert!(interaction.is_shifted());
+    /// ```
+    pub fn is_shifted(&self) -> bool {
+        match self.computation {
+            PairComputation::Cutoff => false,
+            PairComputation::Shifted(_) => true,
+        }
+    }
+
+    /// Check if tail corrections are enabled for this interaction
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use lumol::energy::PairInteraction;
+    /// use lumol::energy::LennardJones;
+    ///
+    /// let potential = Box::new(LennardJones{sigma: 0.5, epsilon: 4.2});
+    /// let mut interaction = PairInteraction::new(potential, 2.0);
+    /// assert!(!interaction.has_tail_corrections());
+    ///
+    /// interaction.enable_tail_corrections();
+    /// assert!(interaction.has_tail_corrections());
+    /// ```
+    pub fn has_tail_corrections(&self) -> bool {
+        self.tail
+    }
 }
 
 impl PairInteraction {
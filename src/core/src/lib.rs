@@ -60,8 +60,10 @@ mod logging;
 
 pub mod units;
 pub mod consts;
+pub mod rng;
+pub mod checkpoint;
 
-pub use logging::{Logger, LogLevel};
+pub use logging::{Logger, LogLevel, LogLevelFilter, Target};
 
 // Main modules
 pub mod types;
@@ -18,7 +18,7 @@ use std::path::Path;
 use std::sync::{Arc, Mutex, RwLock};
 
 use log::{Log, LogRecord, LogMetadata, set_logger};
-pub use log::LogLevel;
+pub use log::{LogLevel, LogLevelFilter};
 
 /// Log an error, and then panic with the same message
 macro_rules! fatal_error {
@@ -53,6 +53,8 @@ pub enum Target {
 /// error stream or a file.
 pub struct Logger {
     level: LogLevel,
+    /// Maximal log level for specific modules, overriding `level`
+    modules: Vec<(String, LogLevelFilter)>,
     target: Target,
     writer: Arc<Mutex<Box<Write + Send + Sync>>>,
 }
@@ -68,6 +70,7 @@ impl Logger{
         }).expect("Could not set the chemfiles warning callback");
         Logger {
             level: level,
+            modules: Vec::new(),
             target: target,
             writer: Arc::new(Mutex::new(Box::new(handle))),
         }
@@ -75,7 +78,11 @@ impl Logger{
 
     fn init(logger: Logger) {
         let res = set_logger(|max_log_level| {
-            max_log_level.set(logger.level.to_log_level_filter());
+            let max_level = logger.modules.iter().fold(
+                logger.level.to_log_level_filter(),
+                |max_level, &(_, level)| ::std::cmp::max(max_level, level)
+            );
+            max_log_level.set(max_level);
             let mut target = TARGET.write().expect("Logging TARGET lock is poisonned");
             *target = logger.target.clone();
             Box::new(logger)
@@ -127,6 +134,47 @@ impl Logger{
         Ok(())
     }
 
+    /// Initialize the global logger to write to `target`, with the maximum
+    /// log level of `level`. The `modules` list contains maximum log levels
+    /// for specific modules (for example `lumol::sim::mc`) and their
+    /// sub-modules, overriding `level`. If multiple modules match, the most
+    /// specific one is used. Nothing is initialized if `target` is
+    /// `Target::None`.
+    pub fn init_with(target: Target, level: LogLevel, modules: Vec<(String, LogLevelFilter)>) -> Result<(), io::Error> {
+        let mut logger = match target {
+            Target::StdOut => Logger::new(level, target, io::stdout()),
+            Target::StdErr => Logger::new(level, target, io::stderr()),
+            Target::File(path) => {
+                let file = try!(OpenOptions::new().write(true).create(true).append(true).open(&path));
+                Logger::new(level, Target::File(path), file)
+            }
+            Target::None => return Ok(()),
+        };
+        logger.modules = modules;
+        Logger::init(logger);
+        Ok(())
+    }
+
+    /// Get the default maximum log level, used by the initialization
+    /// functions when no level is given.
+    pub fn default_level() -> LogLevel {
+        levels::DEFAULT_LEVEL
+    }
+
+    /// Get the maximum log level for events coming from `target`, which is
+    /// usually a module path.
+    fn level_for(&self, target: &str) -> LogLevelFilter {
+        let mut best: Option<&(String, LogLevelFilter)> = None;
+        for filter in &self.modules {
+            let module = &filter.0;
+            let matches = target == module || target.starts_with(&format!("{}::", module));
+            if matches && best.map_or(true, |best| module.len() > best.0.len()) {
+                best = Some(filter);
+            }
+        }
+        best.map_or(self.level.to_log_level_filter(), |best| best.1)
+    }
+
     /// Get the target for log events
     pub fn target() -> Target {
         TARGET.read().expect("Logging TARGET lock is poisonned").clone()
@@ -167,10 +215,14 @@ impl Logger{
 /// Implements Log trait for Logger
 impl Log for Logger {
     fn enabled(&self, metadata: &LogMetadata) -> bool {
-        metadata.level() <= self.level
+        metadata.level() <= self.level_for(metadata.target())
     }
 
     fn log(&self, record: &LogRecord) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
         let mut out = self.writer.lock().expect("Could not lock the logger.");
         let write_res = match record.level() {
             LogLevel::Info => write!(&mut out, "{}\n", record.args()),
@@ -204,3 +256,26 @@ impl Log for Logger {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn modules_levels() {
+        let mut logger = Logger::new(LogLevel::Warn, Target::StdErr, io::sink());
+        logger.modules = vec![
+            (String::from("lumol::sim"), LogLevelFilter::Info),
+            (String::from("lumol::sim::mc"), LogLevelFilter::Trace),
+            (String::from("lumol::sys"), LogLevelFilter::Off),
+        ];
+
+        assert_eq!(logger.level_for("lumol::energy"), LogLevelFilter::Warn);
+        assert_eq!(logger.level_for("lumol::sim"), LogLevelFilter::Info);
+        assert_eq!(logger.level_for("lumol::sim::md::integrators"), LogLevelFilter::Info);
+        assert_eq!(logger.level_for("lumol::sim::mc::moves"), LogLevelFilter::Trace);
+        assert_eq!(logger.level_for("lumol::sim::mcfoo"), LogLevelFilter::Info);
+        assert_eq!(logger.level_for("lumol::sys::systems"), LogLevelFilter::Off);
+        assert_eq!(logger.level_for("lumol_input"), LogLevelFilter::Warn);
+    }
+}
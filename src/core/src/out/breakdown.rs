@@ -0,0 +1,187 @@
+// Lumol, an extensible molecular simulation engine
+// Copyright (C) 2015-2016 Lumol's contributors — BSD license
+
+//! Energy decomposition output
+use std::io::prelude::*;
+use std::io;
+use std::path::Path;
+
+use utils;
+use sys::System;
+use checkpoint::{Checkpoint, CheckpointWriter, CheckpointReader, CheckpointError};
+use super::{Output, OutputFile};
+
+/// The `EnergyBreakdownOutput` writes the different terms of the energy of
+/// the system to a file, in kJ/mol. The columns are the step, the energy of
+/// the non-bonded pairs, split between intramolecular and intermolecular
+/// pairs, the tail corrections, the bonds, angles, dihedral angles,
+/// electrostatic and global energies, the potential, kinetic and total
+/// energies, and finally the energy of the non-bonded pairs for all the pairs
+/// of species in the system.
+pub struct EnergyBreakdownOutput {
+    file: OutputFile,
+    /// Pairs of species in the system, in lexicographic order
+    species: Vec<(String, String)>,
+}
+
+impl EnergyBreakdownOutput {
+    /// Create a new `EnergyBreakdownOutput` writing to `filename`. The file is
+    /// replaced if it already exists, unless the simulation is restarted.
+    pub fn new<P: AsRef<Path>>(filename: P) -> Result<EnergyBreakdownOutput, io::Error> {
+        Ok(EnergyBreakdownOutput {
+            file: try!(OutputFile::create(filename.as_ref())),
+            species: Vec::new(),
+        })
+    }
+
+    /// Get the path of the file written by this output
+    pub fn path(&self) -> &Path {
+        self.file.path()
+    }
+
+    fn write_header(&mut self) -> Result<(), io::Error> {
+        try!(writeln!(&mut self.file, "# Energy decomposition of the simulation (kJ/mol)"));
+        try!(write!(
+            &mut self.file,
+            "# Step Pairs Intramolecular Intermolecular Tail Bonds Angles Dihedrals Coulomb Global Potential Kinetic Total"
+        ));
+        for &(ref a, ref b) in &self.species {
+            try!(write!(&mut self.file, " {}-{}", a, b));
+        }
+        try!(writeln!(&mut self.file, ""));
+        Ok(())
+    }
+
+    fn write_energies(&mut self, system: &System) -> Result<(), io::Error> {
+        let breakdown = system.energy_breakdown();
+        let energies = [
+            breakdown.pairs, breakdown.intramolecular, breakdown.intermolecular,
+            breakdown.tail, breakdown.bonds, breakdown.angles, breakdown.dihedrals,
+            breakdown.coulomb, breakdown.global, breakdown.potential(),
+            breakdown.kinetic, breakdown.total()
+        ];
+
+        try!(write!(&mut self.file, "{}", system.step()));
+        for &energy in &energies {
+            try!(write!(&mut self.file, " {}", utils::unit_to(energy, "kJ/mol")));
+        }
+        for pair in &self.species {
+            let energy = breakdown.species.get(pair).cloned().unwrap_or(0.0);
+            try!(write!(&mut self.file, " {}", utils::unit_to(energy, "kJ/mol")));
+        }
+        try!(writeln!(&mut self.file, ""));
+        Ok(())
+    }
+}
+
+impl Output for EnergyBreakdownOutput {
+    fn set_restart(&mut self) {
+        self.file.set_restart();
+    }
+
+    fn setup(&mut self, system: &System) {
+        let mut names = system.iter().map(|particle| String::from(particle.name())).collect::<Vec<_>>();
+        names.sort();
+        names.dedup();
+
+        self.species.clear();
+        for (i, a) in names.iter().enumerate() {
+            for b in &names[i..] {
+                self.species.push((a.clone(), b.clone()));
+            }
+        }
+
+        match self.file.start() {
+            Ok(true) => {}
+            // The header was already written before the checkpoint
+            Ok(false) => return,
+            Err(err) => fatal_error!("Could not write to file '{}': {}", self.file.path().display(), err),
+        }
+        if let Err(err) = self.write_header() {
+            fatal_error!("Could not write to file '{}': {}", self.file.path().display(), err);
+        }
+    }
+
+    fn write(&mut self, system: &System) {
+        if let Err(err) = self.write_energies(system) {
+            error!("Could not write to file '{}': {}", self.file.path().display(), err);
+        }
+    }
+
+    fn save_state(&self, writer: &mut CheckpointWriter) {
+        self.file.save(writer);
+    }
+
+    fn restore_state(&mut self, reader: &mut CheckpointReader) -> Result<(), CheckpointError> {
+        self.file.restore(reader)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate tempfile;
+    use self::tempfile::NamedTempFile;
+
+    use std::io::prelude::*;
+
+    use super::*;
+    use sys::*;
+    use energy::{Harmonic, PairInteraction};
+    use utils::unit_from;
+
+    fn read_file(file: &NamedTempFile) -> Vec<String> {
+        let mut buffer = String::new();
+        let _ = file.reopen().unwrap().read_to_string(&mut buffer).unwrap();
+        buffer.lines().map(String::from).collect()
+    }
+
+    fn parse(line: &str) -> Vec<f64> {
+        line.split_whitespace().map(|value| value.parse::<f64>().unwrap()).collect()
+    }
+
+    fn testing_system() -> System {
+        let mut system = System::from_cell(UnitCell::cubic(10.0));
+        system.add_particle(Particle::new("Ar"));
+        system.add_particle(Particle::new("Ar"));
+        system.add_particle(Particle::new("Kr"));
+        system[1].position[0] = 2.0;
+        system[2].position[1] = 2.0;
+        assert!(system.add_bond(0, 1).is_empty());
+
+        let harmonic = Harmonic{k: unit_from(100.0, "kJ/mol/A^2"), x0: 1.0};
+        system.interactions_mut().add_pair("Ar", "Ar", PairInteraction::new(Box::new(harmonic), 8.0));
+        system.interactions_mut().add_pair("Ar", "Kr", PairInteraction::new(Box::new(harmonic), 8.0));
+        system.interactions_mut().add_bond("Ar", "Ar", Box::new(harmonic));
+        return system;
+    }
+
+    #[test]
+    fn breakdown() {
+        let tempfile = NamedTempFile::new().unwrap();
+        let system = testing_system();
+        {
+            let mut out = EnergyBreakdownOutput::new(tempfile.path()).unwrap();
+            out.setup(&system);
+            out.write(&system);
+        }
+
+        let lines = read_file(&tempfile);
+        assert_eq!(lines[0], "# Energy decomposition of the simulation (kJ/mol)");
+        assert_eq!(lines[1], "# Step Pairs Intramolecular Intermolecular Tail Bonds Angles Dihedrals Coulomb Global Potential Kinetic Total Ar-Ar Ar-Kr Kr-Kr");
+
+        let values = parse(&lines[2]);
+        assert_eq!(values.len(), 16);
+        assert_eq!(values[0], 0.0);
+        // Ar-Ar at 2 A, and two Ar-Kr pairs at 2 A and sqrt(8) A
+        let ar_ar = 50.0;
+        let ar_kr = 50.0 + 50.0 * (f64::sqrt(8.0) - 1.0).powi(2);
+        assert_relative_eq!(values[1], ar_ar + ar_kr, max_relative=1e-9);
+        assert_relative_eq!(values[2], ar_ar, max_relative=1e-9);
+        assert_relative_eq!(values[3], ar_kr, max_relative=1e-9);
+        assert_relative_eq!(values[5], 50.0, max_relative=1e-9);
+        assert_relative_eq!(values[10], values[1] + values[4] + values[5], max_relative=1e-9);
+        assert_relative_eq!(values[13], ar_ar, max_relative=1e-9);
+        assert_relative_eq!(values[14], ar_kr, max_relative=1e-9);
+        assert_eq!(values[15], 0.0);
+    }
+}
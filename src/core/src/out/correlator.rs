@@ -0,0 +1,340 @@
+// Lumol, an extensible molecular simulation engine
+// Copyright (C) 2015-2016 Lumol's contributors — BSD license
+
+//! Multiple-tau correlator for time correlation functions
+use std::collections::VecDeque;
+
+use checkpoint::{Checkpoint, CheckpointWriter, CheckpointReader, CheckpointError};
+
+/// A single level in the multiple-tau correlator
+#[derive(Clone)]
+struct Level {
+    /// Last values at this level, the most recent being at the front
+    values: VecDeque<Vec<f64>>,
+    /// Accumulated correlation for each lag in this level
+    correlation: Vec<f64>,
+    /// Number of accumulated samples for each lag in this level
+    counts: Vec<usize>,
+    /// Sum of the values not yet passed to the next level
+    accumulator: Vec<f64>,
+    /// Number of values in `accumulator`
+    accumulated: usize,
+}
+
+impl Level {
+    fn new(dimension: usize, points: usize) -> Level {
+        Level {
+            values: VecDeque::with_capacity(points),
+            correlation: vec![0.0; points],
+            counts: vec![0; points],
+            accumulator: vec![0.0; dimension],
+            accumulated: 0,
+        }
+    }
+}
+
+/// Multiple-tau correlator, computing time correlation functions on the fly
+/// with a fixed memory cost and a logarithmic spacing of the time lags.
+///
+/// Observables are vectors of a fixed `dimension`, and the correlation is
+/// the scalar product `<A(t0) · A(t0 + t)>` averaged over all the time
+/// origins `t0`. Scalar observables are vectors of dimension 1.
+///
+/// The first level of the correlator stores the last `points` values, giving
+/// the correlation for lags from 0 to `points - 1` samples. Every `averaging`
+/// values, the average of these values is passed to the next level, which
+/// gives the correlation for lags `averaging` times longer, and so on for all
+/// the levels. See Ramírez et al., J. Chem. Phys. 133, 154103 (2010).
+#[derive(Clone)]
+pub struct Correlator {
+    /// Size of the observables
+    dimension: usize,
+    /// Number of points in each level
+    points: usize,
+    /// Number of values averaged when going from a level to the next one
+    averaging: usize,
+    /// All the levels
+    levels: Vec<Level>,
+}
+
+impl Correlator {
+    /// Create a new `Correlator` for observables of the given `dimension`,
+    /// using `levels` levels with `points` points each, and averaging
+    /// `averaging` values between levels. The longest time lag is then
+    /// `points * averaging^(levels - 1)` samples.
+    ///
+    /// # Panics
+    ///
+    /// If `dimension` or `levels` is zero, if `averaging` is smaller than 2,
+    /// or if `points` is not a multiple of `averaging`.
+    pub fn new(dimension: usize, points: usize, averaging: usize, levels: usize) -> Correlator {
+        assert!(dimension > 0, "observables dimension must be positive in correlator");
+        assert!(levels > 0, "number of levels must be positive in correlator");
+        assert!(averaging >= 2, "averaging must be at least 2 in correlator");
+        assert!(points >= averaging && points % averaging == 0, "number of points must be a multiple of averaging in correlator");
+        Correlator {
+            dimension: dimension,
+            points: points,
+            averaging: averaging,
+            levels: vec![Level::new(dimension, points); levels],
+        }
+    }
+
+    /// Add a new `value` of the observable to this correlator.
+    ///
+    /// # Panics
+    ///
+    /// If `value` does not have the correlator dimension.
+    pub fn add(&mut self, value: &[f64]) {
+        assert_eq!(value.len(), self.dimension, "wrong observable dimension in correlator");
+        self.add_to_level(0, value.to_vec());
+    }
+
+    fn add_to_level(&mut self, index: usize, value: Vec<f64>) {
+        let points = self.points;
+        let averaging = self.averaging;
+        let next = {
+            let level = &mut self.levels[index];
+            for (sum, &v) in level.accumulator.iter_mut().zip(&value) {
+                *sum += v;
+            }
+            level.accumulated += 1;
+
+            if level.values.len() == points {
+                let _ = level.values.pop_back();
+            }
+            level.values.push_front(value);
+
+            let current = &level.values[0];
+            for (lag, previous) in level.values.iter().enumerate() {
+                level.correlation[lag] += dot(current, previous);
+                level.counts[lag] += 1;
+            }
+
+            if level.accumulated == averaging {
+                let average = level.accumulator.iter().map(|sum| sum / averaging as f64).collect();
+                for sum in &mut level.accumulator {
+                    *sum = 0.0;
+                }
+                level.accumulated = 0;
+                Some(average)
+            } else {
+                None
+            }
+        };
+
+        if let Some(average) = next {
+            if index + 1 < self.levels.len() {
+                self.add_to_level(index + 1, average);
+            }
+        }
+    }
+
+    /// Get the current correlation function, as a list of time lags (in
+    /// number of samples) and the corresponding correlation. Lags without
+    /// any data yet are not included.
+    pub fn correlation(&self) -> Vec<(usize, f64)> {
+        let mut result = Vec::new();
+        let mut scale = 1;
+        for (index, level) in self.levels.iter().enumerate() {
+            // Shorter lags are already given by the previous level
+            let start = if index == 0 {0} else {self.points / self.averaging};
+            for lag in start..self.points {
+                if level.counts[lag] != 0 {
+                    result.push((lag * scale, level.correlation[lag] / level.counts[lag] as f64));
+                }
+            }
+            scale *= self.averaging;
+        }
+        return result;
+    }
+
+    /// Reset this correlator, removing all the data
+    pub fn clear(&mut self) {
+        let levels = self.levels.len();
+        self.levels = vec![Level::new(self.dimension, self.points); levels];
+    }
+}
+
+/// The checkpoint of a correlator contains all the levels, and restoring it
+/// fails if the correlator was created with different parameters.
+impl Checkpoint for Correlator {
+    fn save(&self, writer: &mut CheckpointWriter) {
+        writer.write_usize(self.dimension);
+        writer.write_usize(self.points);
+        writer.write_usize(self.averaging);
+        writer.write_usize(self.levels.len());
+        for level in &self.levels {
+            writer.write_usize(level.values.len());
+            for value in &level.values {
+                writer.write_f64s(value);
+            }
+            writer.write_f64s(&level.correlation);
+            writer.write_usize(level.counts.len());
+            for &count in &level.counts {
+                writer.write_usize(count);
+            }
+            writer.write_f64s(&level.accumulator);
+            writer.write_usize(level.accumulated);
+        }
+    }
+
+    fn restore(&mut self, reader: &mut CheckpointReader) -> Result<(), CheckpointError> {
+        let dimension = try!(reader.read_usize());
+        let points = try!(reader.read_usize());
+        let averaging = try!(reader.read_usize());
+        let nlevels = try!(reader.read_usize());
+        if dimension != self.dimension || points != self.points ||
+           averaging != self.averaging || nlevels != self.levels.len() {
+            return Err(CheckpointError::from("the correlator in the checkpoint does not match the output"));
+        }
+
+        let mut levels = Vec::with_capacity(nlevels);
+        for _ in 0..nlevels {
+            let mut level = Level::new(dimension, points);
+            // Each value takes at least 8 bytes in the checkpoint
+            let nvalues = try!(reader.read_count(8));
+            if nvalues > points {
+                return Err(CheckpointError::from("too many values in correlator checkpoint"));
+            }
+            for _ in 0..nvalues {
+                let value = try!(reader.read_f64s());
+                if value.len() != dimension {
+                    return Err(CheckpointError::from("wrong observable dimension in correlator checkpoint"));
+                }
+                level.values.push_back(value);
+            }
+
+            level.correlation = try!(reader.read_f64s());
+            if try!(reader.read_count(8)) != points || level.correlation.len() != points {
+                return Err(CheckpointError::from("wrong number of points in correlator checkpoint"));
+            }
+            for count in &mut level.counts {
+                *count = try!(reader.read_usize());
+            }
+            level.accumulator = try!(reader.read_f64s());
+            if level.accumulator.len() != dimension {
+                return Err(CheckpointError::from("wrong observable dimension in correlator checkpoint"));
+            }
+            level.accumulated = try!(reader.read_usize());
+            levels.push(level);
+        }
+        self.levels = levels;
+        Ok(())
+    }
+}
+
+/// Integrate the correlation function `correlation`, given as pairs of time
+/// and value, using the trapezoidal rule.
+pub fn integrate(correlation: &[(f64, f64)]) -> f64 {
+    correlation.windows(2).fold(0.0, |integral, window| {
+        let (t0, c0) = window[0];
+        let (t1, c1) = window[1];
+        integral + 0.5 * (c0 + c1) * (t1 - t0)
+    })
+}
+
+fn dot(a: &[f64], b: &[f64]) -> f64 {
+    a.iter().zip(b).fold(0.0, |sum, (x, y)| sum + x * y)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{Rng, SeedableRng, XorShiftRng};
+
+    #[test]
+    fn constant() {
+        let mut correlator = Correlator::new(2, 4, 2, 3);
+        for _ in 0..100 {
+            correlator.add(&[1.0, 2.0]);
+        }
+
+        let correlation = correlator.correlation();
+        let lags = correlation.iter().map(|c| c.0).collect::<Vec<_>>();
+        assert_eq!(lags, vec![0, 1, 2, 3, 4, 6, 8, 12]);
+        for &(_, value) in &correlation {
+            assert_relative_eq!(value, 5.0);
+        }
+    }
+
+    #[test]
+    fn alternating() {
+        let mut correlator = Correlator::new(1, 4, 2, 1);
+        for i in 0..10 {
+            correlator.add(&[if i % 2 == 0 {1.0} else {-1.0}]);
+        }
+
+        let correlation = correlator.correlation();
+        assert_eq!(correlation, vec![(0, 1.0), (1, -1.0), (2, 1.0), (3, -1.0)]);
+    }
+
+    #[test]
+    fn exponential() {
+        // Discrete AR(1) process, with correlation exp(-lag / tau)
+        let tau: f64 = 8.0;
+        let decay = f64::exp(-1.0 / tau);
+        let mut rng = XorShiftRng::from_seed([2015, 42, 3, 12]);
+        let mut correlator = Correlator::new(1, 16, 2, 6);
+        let mut value = 0.0;
+        for _ in 0..200000 {
+            let noise = f64::sqrt(12.0) * (rng.next_f64() - 0.5);
+            value = decay * value + f64::sqrt(1.0 - decay * decay) * noise;
+            correlator.add(&[value]);
+        }
+
+        let correlation = correlator.correlation();
+        assert_relative_eq!(correlation[0].1, 1.0, epsilon=0.05);
+        for &(lag, value) in &correlation {
+            if lag <= 32 {
+                assert_relative_eq!(value, f64::exp(-(lag as f64) / tau), epsilon=0.05);
+            }
+        }
+    }
+
+    #[test]
+    fn clear() {
+        let mut correlator = Correlator::new(1, 4, 2, 2);
+        correlator.add(&[1.0]);
+        correlator.clear();
+        assert!(correlator.correlation().is_empty());
+    }
+
+    #[test]
+    fn checkpoint() {
+        use checkpoint::{CheckpointWriter, CheckpointReader};
+
+        let mut correlator = Correlator::new(2, 4, 2, 3);
+        for i in 0..13 {
+            correlator.add(&[i as f64, 1.0 - i as f64]);
+        }
+        let mut writer = CheckpointWriter::new();
+        writer.write_section(&correlator);
+
+        let mut restored = Correlator::new(2, 4, 2, 3);
+        let mut reader = CheckpointReader::new(writer.as_bytes());
+        reader.read_section(&mut restored).unwrap();
+        for i in 13..50 {
+            correlator.add(&[i as f64, 1.0 - i as f64]);
+            restored.add(&[i as f64, 1.0 - i as f64]);
+        }
+        assert_eq!(restored.correlation(), correlator.correlation());
+
+        let mut other = Correlator::new(2, 4, 2, 2);
+        let mut reader = CheckpointReader::new(writer.as_bytes());
+        assert!(reader.read_section(&mut other).is_err());
+    }
+
+    #[test]
+    fn trapezoidal() {
+        let data = [(0.0, 1.0), (1.0, 3.0), (3.0, 3.0)];
+        assert_eq!(integrate(&data), 8.0);
+    }
+
+    #[test]
+    #[should_panic]
+    fn wrong_dimension() {
+        let mut correlator = Correlator::new(3, 4, 2, 2);
+        correlator.add(&[1.0]);
+    }
+}
@@ -0,0 +1,625 @@
+// Lumol, an extensible molecular simulation engine
+// Copyright (C) 2015-2016 Lumol's contributors — BSD license
+
+//! Output of user-defined quantities
+use std::io::prelude::*;
+use std::io;
+use std::path::Path;
+use std::error;
+use std::fmt;
+
+use units;
+use utils;
+use sys::System;
+use checkpoint::{Checkpoint, CheckpointWriter, CheckpointReader, CheckpointError};
+use super::{Output, OutputFile};
+
+/// Possible errors when creating a `CustomOutput`
+#[derive(Debug)]
+pub enum CustomOutputError {
+    /// Error while creating the output file
+    Io(io::Error),
+    /// Error in the unit of a column
+    Unit(units::ParseError),
+    /// Error in the expression of a column
+    Expr(String),
+}
+
+impl From<io::Error> for CustomOutputError {
+    fn from(err: io::Error) -> CustomOutputError {
+        CustomOutputError::Io(err)
+    }
+}
+
+impl From<units::ParseError> for CustomOutputError {
+    fn from(err: units::ParseError) -> CustomOutputError {
+        CustomOutputError::Unit(err)
+    }
+}
+
+impl fmt::Display for CustomOutputError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            CustomOutputError::Io(ref err) => err.fmt(f),
+            CustomOutputError::Unit(ref err) => err.fmt(f),
+            CustomOutputError::Expr(ref err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl error::Error for CustomOutputError {
+    fn description(&self) -> &str {
+        match *self {
+            CustomOutputError::Io(ref err) => err.description(),
+            CustomOutputError::Unit(ref err) => err.description(),
+            CustomOutputError::Expr(ref err) => err,
+        }
+    }
+
+    fn cause(&self) -> Option<&error::Error> {
+        match *self {
+            CustomOutputError::Io(ref err) => Some(err),
+            CustomOutputError::Unit(ref err) => Some(err),
+            CustomOutputError::Expr(..) => None,
+        }
+    }
+}
+
+/// Components of the potential energy
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum EnergyComponent {
+    Pair,
+    Tail,
+    Bond,
+    Angle,
+    Dihedral,
+    Coulomb,
+    Global,
+}
+
+/// Quantities which can be used in custom output expressions
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Quantity {
+    Step,
+    Natoms,
+    Volume,
+    Temperature,
+    Pressure,
+    PotentialEnergy,
+    KineticEnergy,
+    TotalEnergy,
+    Energy(EnergyComponent),
+    Stress(usize, usize),
+    Virial(usize, usize),
+    CellLength(usize),
+    CellAngle(usize),
+}
+
+impl Quantity {
+    fn parse(name: &str) -> Option<Quantity> {
+        let quantity = match name {
+            "step" => Quantity::Step,
+            "natoms" => Quantity::Natoms,
+            "volume" => Quantity::Volume,
+            "temperature" => Quantity::Temperature,
+            "pressure" => Quantity::Pressure,
+            "energy" | "energy.total" => Quantity::TotalEnergy,
+            "energy.potential" => Quantity::PotentialEnergy,
+            "energy.kinetic" => Quantity::KineticEnergy,
+            "energy.pair" => Quantity::Energy(EnergyComponent::Pair),
+            "energy.tail" => Quantity::Energy(EnergyComponent::Tail),
+            "energy.bond" => Quantity::Energy(EnergyComponent::Bond),
+            "energy.angle" => Quantity::Energy(EnergyComponent::Angle),
+            "energy.dihedral" => Quantity::Energy(EnergyComponent::Dihedral),
+            "energy.coulomb" => Quantity::Energy(EnergyComponent::Coulomb),
+            "energy.global" => Quantity::Energy(EnergyComponent::Global),
+            "cell.a" => Quantity::CellLength(0),
+            "cell.b" => Quantity::CellLength(1),
+            "cell.c" => Quantity::CellLength(2),
+            "cell.alpha" => Quantity::CellAngle(0),
+            "cell.beta" => Quantity::CellAngle(1),
+            "cell.gamma" => Quantity::CellAngle(2),
+            _ => {
+                if name.starts_with("stress.") {
+                    return tensor_component(&name[7..]).map(|(i, j)| Quantity::Stress(i, j));
+                } else if name.starts_with("virial.") {
+                    return tensor_component(&name[7..]).map(|(i, j)| Quantity::Virial(i, j));
+                } else {
+                    return None;
+                }
+            }
+        };
+        Some(quantity)
+    }
+
+    fn evaluate(&self, system: &System) -> f64 {
+        match *self {
+            Quantity::Step => system.step() as f64,
+            Quantity::Natoms => system.size() as f64,
+            Quantity::Volume => system.volume(),
+            Quantity::Temperature => system.temperature(),
+            Quantity::Pressure => system.pressure(),
+            Quantity::PotentialEnergy => system.potential_energy(),
+            Quantity::KineticEnergy => system.kinetic_energy(),
+            Quantity::TotalEnergy => system.total_energy(),
+            Quantity::Energy(component) => {
+                let evaluator = system.energy_evaluator();
+                match component {
+                    EnergyComponent::Pair => evaluator.pairs(),
+                    EnergyComponent::Tail => evaluator.pairs_tail(),
+                    EnergyComponent::Bond => evaluator.bonds(),
+                    EnergyComponent::Angle => evaluator.angles(),
+                    EnergyComponent::Dihedral => evaluator.dihedrals(),
+                    EnergyComponent::Coulomb => evaluator.coulomb(),
+                    EnergyComponent::Global => evaluator.global(),
+                }
+            }
+            Quantity::Stress(i, j) => system.stress()[i][j],
+            Quantity::Virial(i, j) => system.virial()[i][j],
+            Quantity::CellLength(i) => {
+                let cell = system.cell();
+                [cell.a(), cell.b(), cell.c()][i]
+            }
+            Quantity::CellAngle(i) => {
+                let cell = system.cell();
+                // Angles are given in degrees by the cell, convert them back
+                // to internal units
+                let angle = [cell.alpha(), cell.beta(), cell.gamma()][i];
+                utils::unit_from(angle, "deg")
+            }
+        }
+    }
+}
+
+/// Get the indexes of a tensor component given as `xy`, `zz`, ...
+fn tensor_component(name: &str) -> Option<(usize, usize)> {
+    fn index(c: char) -> Option<usize> {
+        match c {
+            'x' => Some(0),
+            'y' => Some(1),
+            'z' => Some(2),
+            _ => None,
+        }
+    }
+
+    let chars = name.chars().collect::<Vec<_>>();
+    if chars.len() != 2 {
+        return None;
+    }
+    match (index(chars[0]), index(chars[1])) {
+        (Some(i), Some(j)) => Some((i, j)),
+        _ => None,
+    }
+}
+
+/// Arithmetic expression of quantities
+#[derive(Clone, Debug, PartialEq)]
+enum Expr {
+    Number(f64),
+    Quantity(Quantity),
+    Neg(Box<Expr>),
+    Add(Box<Expr>, Box<Expr>),
+    Sub(Box<Expr>, Box<Expr>),
+    Mul(Box<Expr>, Box<Expr>),
+    Div(Box<Expr>, Box<Expr>),
+}
+
+impl Expr {
+    fn evaluate(&self, system: &System) -> f64 {
+        match *self {
+            Expr::Number(value) => value,
+            Expr::Quantity(ref quantity) => quantity.evaluate(system),
+            Expr::Neg(ref expr) => -expr.evaluate(system),
+            Expr::Add(ref lhs, ref rhs) => lhs.evaluate(system) + rhs.evaluate(system),
+            Expr::Sub(ref lhs, ref rhs) => lhs.evaluate(system) - rhs.evaluate(system),
+            Expr::Mul(ref lhs, ref rhs) => lhs.evaluate(system) * rhs.evaluate(system),
+            Expr::Div(ref lhs, ref rhs) => lhs.evaluate(system) / rhs.evaluate(system),
+        }
+    }
+}
+
+/// Tokens in expressions
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Number(f64),
+    Name(String),
+    Plus,
+    Minus,
+    Mul,
+    Div,
+    LParen,
+    RParen,
+}
+
+fn tokenize(expr: &str) -> Result<Vec<Token>, CustomOutputError> {
+    let chars = expr.chars().collect::<Vec<_>>();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+            continue;
+        }
+
+        let token = match c {
+            '+' => Token::Plus,
+            '-' => Token::Minus,
+            '*' => Token::Mul,
+            '/' => Token::Div,
+            '(' => Token::LParen,
+            ')' => Token::RParen,
+            _ if c.is_digit(10) || c == '.' => {
+                let start = i;
+                while i < chars.len() && (chars[i].is_digit(10) || chars[i] == '.') {
+                    i += 1;
+                }
+                // Exponent part
+                if i < chars.len() && (chars[i] == 'e' || chars[i] == 'E') {
+                    i += 1;
+                    if i < chars.len() && (chars[i] == '+' || chars[i] == '-') {
+                        i += 1;
+                    }
+                    while i < chars.len() && chars[i].is_digit(10) {
+                        i += 1;
+                    }
+                }
+                let number = chars[start..i].iter().cloned().collect::<String>();
+                let value = try!(number.parse().map_err(|_| CustomOutputError::Expr(
+                    format!("invalid number '{}' in expression '{}'", number, expr)
+                )));
+                tokens.push(Token::Number(value));
+                continue;
+            }
+            _ if c.is_alphabetic() || c == '_' => {
+                let start = i;
+                while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_' || chars[i] == '.') {
+                    i += 1;
+                }
+                tokens.push(Token::Name(chars[start..i].iter().cloned().collect()));
+                continue;
+            }
+            _ => {
+                return Err(CustomOutputError::Expr(
+                    format!("unexpected character '{}' in expression '{}'", c, expr)
+                ));
+            }
+        };
+        tokens.push(token);
+        i += 1;
+    }
+    Ok(tokens)
+}
+
+/// Recursive descent parser for expressions
+struct Parser<'a> {
+    expr: &'a str,
+    tokens: Vec<Token>,
+    current: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.current)
+    }
+
+    fn error(&self, message: &str) -> CustomOutputError {
+        CustomOutputError::Expr(format!("{} in expression '{}'", message, self.expr))
+    }
+
+    /// expr := term (('+' | '-') term)*
+    fn expr(&mut self) -> Result<Expr, CustomOutputError> {
+        let mut lhs = try!(self.term());
+        loop {
+            match self.peek() {
+                Some(&Token::Plus) => {
+                    self.current += 1;
+                    lhs = Expr::Add(Box::new(lhs), Box::new(try!(self.term())));
+                }
+                Some(&Token::Minus) => {
+                    self.current += 1;
+                    lhs = Expr::Sub(Box::new(lhs), Box::new(try!(self.term())));
+                }
+                _ => return Ok(lhs),
+            }
+        }
+    }
+
+    /// term := factor (('*' | '/') factor)*
+    fn term(&mut self) -> Result<Expr, CustomOutputError> {
+        let mut lhs = try!(self.factor());
+        loop {
+            match self.peek() {
+                Some(&Token::Mul) => {
+                    self.current += 1;
+                    lhs = Expr::Mul(Box::new(lhs), Box::new(try!(self.factor())));
+                }
+                Some(&Token::Div) => {
+                    self.current += 1;
+                    lhs = Expr::Div(Box::new(lhs), Box::new(try!(self.factor())));
+                }
+                _ => return Ok(lhs),
+            }
+        }
+    }
+
+    /// factor := '-' factor | number | quantity | '(' expr ')'
+    fn factor(&mut self) -> Result<Expr, CustomOutputError> {
+        let token = match self.tokens.get(self.current) {
+            Some(token) => token.clone(),
+            None => return Err(self.error("unexpected end")),
+        };
+        self.current += 1;
+        match token {
+            Token::Minus => Ok(Expr::Neg(Box::new(try!(self.factor())))),
+            Token::Number(value) => Ok(Expr::Number(value)),
+            Token::Name(name) => {
+                match Quantity::parse(&name) {
+                    Some(quantity) => Ok(Expr::Quantity(quantity)),
+                    None => Err(self.error(&format!("unknown quantity '{}'", name))),
+                }
+            }
+            Token::LParen => {
+                let expr = try!(self.expr());
+                match self.peek() {
+                    Some(&Token::RParen) => {
+                        self.current += 1;
+                        Ok(expr)
+                    }
+                    _ => Err(self.error("missing closing parenthesis")),
+                }
+            }
+            _ => Err(self.error("unexpected operator")),
+        }
+    }
+}
+
+fn parse(expr: &str) -> Result<Expr, CustomOutputError> {
+    let mut parser = Parser {
+        expr: expr,
+        tokens: try!(tokenize(expr)),
+        current: 0,
+    };
+    let result = try!(parser.expr());
+    if parser.current != parser.tokens.len() {
+        return Err(parser.error("unexpected content at the end"));
+    }
+    Ok(result)
+}
+
+/// A quantity computed from the system, given as an arithmetic expression of
+/// the quantities available in `CustomOutput`, for example `"energy.potential
+/// / natoms"`.
+#[derive(Clone, Debug)]
+pub struct CustomQuantity {
+    expr: Expr,
+}
+
+impl CustomQuantity {
+    /// Parse a new `CustomQuantity` from the `expr` expression
+    pub fn new(expr: &str) -> Result<CustomQuantity, CustomOutputError> {
+        Ok(CustomQuantity {
+            expr: try!(parse(expr)),
+        })
+    }
+
+    /// Get the value of this quantity for the `system`, in internal units
+    pub fn evaluate(&self, system: &System) -> f64 {
+        self.expr.evaluate(system)
+    }
+}
+
+/// A single column in the custom output
+struct Column {
+    /// Description of this column, as given by the user
+    description: String,
+    /// Expression to evaluate
+    expr: Expr,
+    /// Conversion factor from internal units to the column unit
+    conversion: f64,
+    /// Header of this column
+    header: String,
+}
+
+impl Column {
+    fn new(description: &str) -> Result<Column, CustomOutputError> {
+        let mut split = description.splitn(2, ':');
+        let expr = split.next().expect("splitn returned nothing");
+        let unit = split.next().map(str::trim);
+
+        let mut header = expr.split_whitespace().collect::<String>();
+        let conversion = match unit {
+            Some(unit) => {
+                let conversion = try!(units::to(1.0, unit));
+                if unit.contains('/') || unit.contains('*') {
+                    header = format!("{}/({})", header, unit);
+                } else {
+                    header = format!("{}/{}", header, unit);
+                }
+                conversion
+            }
+            None => 1.0,
+        };
+
+        Ok(Column {
+            description: String::from(description),
+            expr: try!(parse(expr)),
+            conversion: conversion,
+            header: header,
+        })
+    }
+}
+
+/******************************************************************************/
+/// The `CustomOutput` writes user-selected quantities to a file, with one
+/// column for each quantity.
+///
+/// Each column is given as an arithmetic expression of quantities and
+/// numbers, using `+`, `-`, `*`, `/` and parentheses, optionally followed by
+/// the unit to use for this column after a colon: `"pressure:bar"`,
+/// `"energy.potential / natoms:kJ/mol"`. Without unit, the value is written
+/// in internal units.
+///
+/// The available quantities are:
+///
+/// - `step`: the current simulation step;
+/// - `natoms`: the number of particles in the system;
+/// - `volume`, `temperature` and `pressure`;
+/// - `energy` or `energy.total`, `energy.potential` and `energy.kinetic`;
+/// - the potential energy components: `energy.pair`, `energy.tail`,
+///   `energy.bond`, `energy.angle`, `energy.dihedral`, `energy.coulomb` and
+///   `energy.global`;
+/// - the stress and virial tensors components: `stress.xy`, `virial.zz`, ...;
+/// - the unit cell parameters: `cell.a`, `cell.b`, `cell.c`, `cell.alpha`,
+///   `cell.beta` and `cell.gamma`.
+pub struct CustomOutput {
+    file: OutputFile,
+    columns: Vec<Column>,
+}
+
+impl CustomOutput {
+    /// Create a new `CustomOutput` writing to `filename` the given
+    /// `columns`. The file is replaced if it already exists, unless the
+    /// simulation is restarted.
+    pub fn new<P: AsRef<Path>>(filename: P, columns: &[&str]) -> Result<CustomOutput, CustomOutputError> {
+        let columns = try!(columns.iter().map(|column| Column::new(column)).collect());
+        Ok(CustomOutput {
+            file: try!(OutputFile::create(filename.as_ref())),
+            columns: columns,
+        })
+    }
+
+    /// Get the path of the file written by this output
+    pub fn path(&self) -> &Path {
+        self.file.path()
+    }
+
+    /// Get the description of the columns written by this output
+    pub fn columns(&self) -> Vec<&str> {
+        self.columns.iter().map(|column| &*column.description).collect()
+    }
+}
+
+impl Output for CustomOutput {
+    fn set_restart(&mut self) {
+        self.file.set_restart();
+    }
+
+    fn setup(&mut self, _: &System) {
+        match self.file.start() {
+            Ok(true) => {}
+            // The header was already written before the checkpoint
+            Ok(false) => return,
+            Err(err) => fatal_error!("Could not write to file '{}': {}", self.file.path().display(), err),
+        }
+        if let Err(err) = writeln!(&mut self.file, "# Custom properties of the simulation") {
+            fatal_error!("Could not write to file '{}': {}", self.file.path().display(), err);
+        }
+        let header = self.columns.iter().map(|column| &*column.header).collect::<Vec<_>>().join(" ");
+        if let Err(err) = writeln!(&mut self.file, "# {}", header) {
+            fatal_error!("Could not write to file '{}': {}", self.file.path().display(), err);
+        }
+    }
+
+    fn write(&mut self, system: &System) {
+        let values = self.columns.iter().map(|column| {
+            (column.conversion * column.expr.evaluate(system)).to_string()
+        }).collect::<Vec<_>>();
+        if let Err(err) = writeln!(&mut self.file, "{}", values.join(" ")) {
+            error!("Could not write to file '{}': {}", self.file.path().display(), err);
+        }
+    }
+
+    fn save_state(&self, writer: &mut CheckpointWriter) {
+        self.file.save(writer);
+    }
+
+    fn restore_state(&mut self, reader: &mut CheckpointReader) -> Result<(), CheckpointError> {
+        self.file.restore(reader)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate tempfile;
+    use self::tempfile::NamedTempFile;
+
+    use std::io::prelude::*;
+
+    use super::*;
+    use super::{parse, Expr, Quantity};
+    use sys::*;
+    use types::*;
+    use energy::{Harmonic, PairInteraction};
+    use utils::{unit_from, unit_to};
+
+    fn testing_system() -> System {
+        let mut system = System::from_cell(UnitCell::cubic(10.0));
+        system.add_particle(Particle::new("F"));
+        system[0].position = Vector3D::zero();
+        system.add_particle(Particle::new("F"));
+        system[1].position = Vector3D::new(1.3, 0.0, 0.0);
+
+        let harmonic = Box::new(Harmonic{k: unit_from(300.0, "kJ/mol/A^2"), x0: 1.2});
+        system.interactions_mut().add_pair("F", "F", PairInteraction::new(harmonic, 5.0));
+        return system;
+    }
+
+    #[test]
+    fn parsing() {
+        assert_eq!(parse("pressure").unwrap(), Expr::Quantity(Quantity::Pressure));
+        assert_eq!(parse("stress.yz").unwrap(), Expr::Quantity(Quantity::Stress(1, 2)));
+        assert_eq!(parse("-2.5e-1").unwrap(), Expr::Neg(Box::new(Expr::Number(0.25))));
+        assert_eq!(
+            parse("1 + 2 * 3").unwrap(),
+            Expr::Add(
+                Box::new(Expr::Number(1.0)),
+                Box::new(Expr::Mul(Box::new(Expr::Number(2.0)), Box::new(Expr::Number(3.0))))
+            )
+        );
+
+        let system = testing_system();
+        assert_eq!(parse("(1 + 2) * 3 - 8 / 4").unwrap().evaluate(&system), 7.0);
+        assert_eq!(parse("natoms / (natoms + 2)").unwrap().evaluate(&system), 0.5);
+    }
+
+    #[test]
+    fn parsing_errors() {
+        assert!(parse("foo").is_err());
+        assert!(parse("stress.xw").is_err());
+        assert!(parse("(1 + 2").is_err());
+        assert!(parse("1 +").is_err());
+        assert!(parse("1 2").is_err());
+        assert!(parse("1 $ 2").is_err());
+
+        let tempfile = NamedTempFile::new().unwrap();
+        assert!(CustomOutput::new(tempfile.path(), &["pressure:foo"]).is_err());
+    }
+
+    #[test]
+    fn custom() {
+        let tempfile = NamedTempFile::new().unwrap();
+        let system = testing_system();
+        {
+            let columns = ["step", "energy.pair:kJ/mol", "stress.xx:bar", "cell.a:nm", "cell.gamma:deg", "2 * volume"];
+            let mut out = CustomOutput::new(tempfile.path(), &columns).unwrap();
+            out.setup(&system);
+            out.write(&system);
+            out.finish(&system);
+        }
+
+        let mut buffer = String::new();
+        let _ = tempfile.reopen().unwrap().read_to_string(&mut buffer).unwrap();
+        let lines = buffer.lines().collect::<Vec<_>>();
+        assert_eq!(lines[0], "# Custom properties of the simulation");
+        assert_eq!(lines[1], "# step energy.pair/(kJ/mol) stress.xx/bar cell.a/nm cell.gamma/deg 2*volume");
+
+        let values = lines[2].split_whitespace().map(|v| v.parse::<f64>().unwrap()).collect::<Vec<_>>();
+        assert_eq!(values[0], 0.0);
+        assert_relative_eq!(values[1], 1.5, max_relative=1e-12);
+        assert_relative_eq!(values[2], unit_to(system.stress()[0][0], "bar"), max_relative=1e-12);
+        assert_relative_eq!(values[3], 1.0, max_relative=1e-12);
+        assert_relative_eq!(values[4], 90.0, max_relative=1e-12);
+        assert_relative_eq!(values[5], 2000.0, max_relative=1e-12);
+    }
+}
@@ -0,0 +1,358 @@
+// Lumol, an extensible molecular simulation engine
+// Copyright (C) 2015-2016 Lumol's contributors — BSD license
+
+//! Thermodynamic properties computed from fluctuations
+use std::io::prelude::*;
+use std::io;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+
+use consts::K_BOLTZMANN;
+use utils;
+use sys::System;
+use sim::Ensemble;
+use checkpoint::{Checkpoint, CheckpointWriter, CheckpointReader, CheckpointError};
+use super::Output;
+
+/// Running means and covariances of the potential energy, the volume and the
+/// configurational enthalpy.
+struct Moments {
+    /// Number of samples
+    count: usize,
+    /// Running means
+    mean: [f64; 3],
+    /// Running sum of the products of the differences to the means
+    comoments: [[f64; 3]; 3],
+}
+
+/// Index of the potential energy in `Moments`
+const ENERGY: usize = 0;
+/// Index of the volume in `Moments`
+const VOLUME: usize = 1;
+/// Index of the configurational enthalpy in `Moments`
+const ENTHALPY: usize = 2;
+
+impl Moments {
+    fn new() -> Moments {
+        Moments {
+            count: 0,
+            mean: [0.0; 3],
+            comoments: [[0.0; 3]; 3],
+        }
+    }
+
+    fn add(&mut self, values: [f64; 3]) {
+        self.count += 1;
+        let mut delta = [0.0; 3];
+        for i in 0..3 {
+            delta[i] = values[i] - self.mean[i];
+            self.mean[i] += delta[i] / self.count as f64;
+        }
+        for i in 0..3 {
+            for j in 0..3 {
+                self.comoments[i][j] += delta[i] * (values[j] - self.mean[j]);
+            }
+        }
+    }
+
+    /// Get the covariance of the values at index `i` and `j`
+    fn covariance(&self, i: usize, j: usize) -> f64 {
+        if self.count < 2 {
+            0.0
+        } else {
+            self.comoments[i][j] / (self.count - 1) as f64
+        }
+    }
+}
+
+impl Checkpoint for Moments {
+    fn save(&self, writer: &mut CheckpointWriter) {
+        writer.write_usize(self.count);
+        writer.write_f64s(&self.mean);
+        for comoments in &self.comoments {
+            writer.write_f64s(comoments);
+        }
+    }
+
+    fn restore(&mut self, reader: &mut CheckpointReader) -> Result<(), CheckpointError> {
+        let count = try!(reader.read_usize());
+        let mut mean = [0.0; 3];
+        try!(read_array(reader, &mut mean));
+        let mut comoments = [[0.0; 3]; 3];
+        for values in &mut comoments {
+            try!(read_array(reader, values));
+        }
+        *self = Moments {
+            count: count,
+            mean: mean,
+            comoments: comoments,
+        };
+        Ok(())
+    }
+}
+
+/// Read the 3 `values` from a checkpoint
+fn read_array(reader: &mut CheckpointReader, values: &mut [f64; 3]) -> Result<(), CheckpointError> {
+    let restored = try!(reader.read_f64s());
+    if restored.len() != 3 {
+        return Err(CheckpointError::from("invalid moments in fluctuations checkpoint"));
+    }
+    values.copy_from_slice(&restored);
+    Ok(())
+}
+
+/// The `FluctuationsOutput` computes thermodynamic response functions from the
+/// fluctuations of the energy, volume and enthalpy during the simulation.
+/// The file is written at the end of the simulation.
+///
+/// The formulas depend on the ensemble sampled by the simulation propagator:
+///
+/// - in the NVT ensemble, the isochoric heat capacity is computed as `Cv =
+///   <δU²> / (kB T²) + 3/2 N kB`;
+/// - in the NPT ensemble, the isobaric heat capacity is computed as `Cp =
+///   <δH²> / (kB T²) + 3/2 N kB`, the isothermal compressibility as `κT =
+///   <δV²> / (kB T <V>)` and the thermal expansion coefficient as `αP = <δV
+///   δH> / (kB T² <V>)`.
+///
+/// Here `U` is the potential energy, `H = U + P V` the configurational
+/// enthalpy, and `T` and `P` the target temperature and pressure of the
+/// propagator. The kinetic energy contribution is added analytically, so the
+/// same formulas can be used for Monte-Carlo and molecular dynamics. The heat
+/// capacities are given per mole of particles. Other ensembles are not
+/// supported.
+pub struct FluctuationsOutput {
+    file: File,
+    path: PathBuf,
+    /// Ensemble sampled by the simulation
+    ensemble: Ensemble,
+    /// Number of particles in the system
+    natoms: usize,
+    /// Accumulated moments
+    moments: Moments,
+}
+
+impl FluctuationsOutput {
+    /// Create a new `FluctuationsOutput` writing to `filename`. The file is
+    /// replaced if it already exists.
+    pub fn new<P: AsRef<Path>>(filename: P) -> Result<FluctuationsOutput, io::Error> {
+        Ok(FluctuationsOutput {
+            file: try!(File::create(filename.as_ref())),
+            path: filename.as_ref().to_owned(),
+            ensemble: Ensemble::Unknown,
+            natoms: 0,
+            moments: Moments::new(),
+        })
+    }
+
+    /// Get the path of the file written by this output
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Get the fluctuation properties, as `(name, value, unit)` with the
+    /// value in the given unit.
+    fn properties(&self) -> Vec<(&'static str, f64, &'static str)> {
+        let kinetic = 1.5 * K_BOLTZMANN;
+        let natoms = self.natoms as f64;
+        match self.ensemble {
+            Ensemble::NVT(temperature) => {
+                let kt2 = K_BOLTZMANN * temperature * temperature;
+                let cv = self.moments.covariance(ENERGY, ENERGY) / kt2 / natoms + kinetic;
+                vec![("HeatCapacity", utils::unit_to(cv, "J/mol/K"), "J/mol/K")]
+            }
+            Ensemble::NPT(temperature, _) => {
+                let kt = K_BOLTZMANN * temperature;
+                let kt2 = kt * temperature;
+                let volume = self.moments.mean[VOLUME];
+                let cp = self.moments.covariance(ENTHALPY, ENTHALPY) / kt2 / natoms + kinetic;
+                let compressibility = self.moments.covariance(VOLUME, VOLUME) / (kt * volume);
+                let expansion = self.moments.covariance(VOLUME, ENTHALPY) / (kt2 * volume);
+                vec![
+                    ("HeatCapacity", utils::unit_to(cp, "J/mol/K"), "J/mol/K"),
+                    ("Compressibility", utils::unit_to(compressibility, "bar^-1"), "bar^-1"),
+                    ("ThermalExpansion", utils::unit_to(expansion, "K^-1"), "K^-1"),
+                ]
+            }
+            Ensemble::NVE | Ensemble::Unknown => Vec::new(),
+        }
+    }
+
+    fn write_properties(&mut self) -> Result<(), io::Error> {
+        let properties = self.properties();
+        match self.ensemble {
+            Ensemble::NVT(temperature) => try!(writeln!(
+                &mut self.file, "# Fluctuation properties in the NVT ensemble at T = {} K",
+                utils::unit_to(temperature, "K")
+            )),
+            Ensemble::NPT(temperature, pressure) => try!(writeln!(
+                &mut self.file, "# Fluctuation properties in the NPT ensemble at T = {} K and P = {} bar",
+                utils::unit_to(temperature, "K"), utils::unit_to(pressure, "bar")
+            )),
+            Ensemble::NVE | Ensemble::Unknown => return Ok(()),
+        }
+        try!(writeln!(&mut self.file, "# Computed from {} samples", self.moments.count));
+        try!(writeln!(&mut self.file, "# Property Value Unit"));
+        for (name, value, unit) in properties {
+            try!(writeln!(&mut self.file, "{} {} {}", name, value, unit));
+        }
+        Ok(())
+    }
+}
+
+impl Output for FluctuationsOutput {
+    fn set_ensemble(&mut self, ensemble: Ensemble) {
+        self.ensemble = ensemble;
+    }
+
+    fn setup(&mut self, system: &System) {
+        match self.ensemble {
+            Ensemble::NVT(_) | Ensemble::NPT(_, _) => {}
+            Ensemble::NVE | Ensemble::Unknown => {
+                warn!("Fluctuation properties are only computed in the NVT and NPT ensembles");
+            }
+        }
+        self.natoms = system.size();
+        self.moments = Moments::new();
+    }
+
+    fn write(&mut self, system: &System) {
+        let energy = system.potential_energy();
+        let volume = system.volume();
+        let enthalpy = match self.ensemble {
+            Ensemble::NPT(_, pressure) => energy + pressure * volume,
+            _ => energy,
+        };
+        self.moments.add([energy, volume, enthalpy]);
+    }
+
+    fn finish(&mut self, _: &System) {
+        for (name, value, unit) in self.properties() {
+            info!("{} = {} {}", name, value, unit);
+        }
+
+        if let Err(err) = self.write_properties() {
+            error!("Could not write to file '{}': {}", self.path.display(), err);
+        }
+    }
+
+    fn save_state(&self, writer: &mut CheckpointWriter) {
+        self.moments.save(writer);
+    }
+
+    fn restore_state(&mut self, reader: &mut CheckpointReader) -> Result<(), CheckpointError> {
+        self.moments.restore(reader)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate tempfile;
+    use self::tempfile::NamedTempFile;
+
+    use std::io::prelude::*;
+
+    use super::*;
+    use consts::K_BOLTZMANN;
+    use sys::*;
+    use sim::Ensemble;
+    use types::{Matrix3, One};
+    use utils::{unit_from, unit_to};
+
+    fn read_file(file: &NamedTempFile) -> Vec<String> {
+        let mut buffer = String::new();
+        let _ = file.reopen().unwrap().read_to_string(&mut buffer).unwrap();
+        buffer.lines().map(String::from).collect()
+    }
+
+    fn value(line: &str) -> f64 {
+        line.split_whitespace().nth(1).unwrap().parse().unwrap()
+    }
+
+    fn testing_system() -> System {
+        let mut system = System::from_cell(UnitCell::cubic(10.0));
+        system.add_particle(Particle::new("Ar"));
+        system.add_particle(Particle::new("Ar"));
+        system[1].position[0] = 3.0;
+        system.interactions_mut().add_pair("Ar", "Ar", ::energy::PairInteraction::new(
+            Box::new(::energy::Harmonic{k: unit_from(100.0, "kJ/mol/A^2"), x0: 3.0}), 8.0
+        ));
+        return system;
+    }
+
+    #[test]
+    fn nvt() {
+        let tempfile = NamedTempFile::new().unwrap();
+        let mut system = testing_system();
+        let temperature = unit_from(300.0, "K");
+        {
+            let mut out = FluctuationsOutput::new(tempfile.path()).unwrap();
+            out.set_ensemble(Ensemble::NVT(temperature));
+            out.setup(&system);
+            out.write(&system);
+            system[1].position[0] = 3.1;
+            out.write(&system);
+            out.finish(&system);
+        }
+
+        let lines = read_file(&tempfile);
+        assert_eq!(lines[0], "# Fluctuation properties in the NVT ensemble at T = 300 K");
+        assert_eq!(lines[1], "# Computed from 2 samples");
+        assert_eq!(lines[2], "# Property Value Unit");
+
+        // Energies are 0 and 1/2 k 0.1^2
+        let energy = 0.5 * unit_from(100.0, "kJ/mol/A^2") * 0.01;
+        let variance = energy * energy / 2.0;
+        let expected = variance / (K_BOLTZMANN * temperature * temperature) / 2.0 + 1.5 * K_BOLTZMANN;
+        assert!(lines[3].starts_with("HeatCapacity "));
+        assert!(lines[3].ends_with(" J/mol/K"));
+        assert_relative_eq!(value(&lines[3]), unit_to(expected, "J/mol/K"), max_relative=1e-9);
+    }
+
+    #[test]
+    fn npt() {
+        let tempfile = NamedTempFile::new().unwrap();
+        let mut system = testing_system();
+        let temperature = unit_from(300.0, "K");
+        let pressure = unit_from(1000.0, "bar");
+        {
+            let mut out = FluctuationsOutput::new(tempfile.path()).unwrap();
+            out.set_ensemble(Ensemble::NPT(temperature, pressure));
+            out.setup(&system);
+            out.write(&system);
+            system.cell_mut().scale_mut(1.1 * Matrix3::one());
+            out.write(&system);
+            out.finish(&system);
+        }
+
+        let lines = read_file(&tempfile);
+        assert_eq!(lines[0], "# Fluctuation properties in the NPT ensemble at T = 300 K and P = 1000 bar");
+        assert!(lines[3].starts_with("HeatCapacity "));
+        assert!(lines[4].starts_with("Compressibility "));
+        assert!(lines[5].starts_with("ThermalExpansion "));
+
+        let v1 = 1000.0;
+        let v2 = 1331.0;
+        let mean = 0.5 * (v1 + v2);
+        let variance = (v1 - v2) * (v1 - v2) / 2.0;
+        let kt = K_BOLTZMANN * temperature;
+        let expected = variance / (kt * mean);
+        assert_relative_eq!(value(&lines[4]), unit_to(expected, "bar^-1"), max_relative=1e-9);
+        // The energy does not change, so the enthalpy only changes with PV
+        let expected = pressure * variance / (kt * temperature * mean);
+        assert_relative_eq!(value(&lines[5]), unit_to(expected, "K^-1"), max_relative=1e-9);
+    }
+
+    #[test]
+    fn unsupported_ensemble() {
+        let tempfile = NamedTempFile::new().unwrap();
+        let system = testing_system();
+        {
+            let mut out = FluctuationsOutput::new(tempfile.path()).unwrap();
+            out.set_ensemble(Ensemble::NVE);
+            out.setup(&system);
+            out.write(&system);
+            out.finish(&system);
+        }
+        assert!(read_file(&tempfile).is_empty());
+    }
+}
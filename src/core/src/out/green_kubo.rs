@@ -0,0 +1,501 @@
+// Lumol, an extensible molecular simulation engine
+// Copyright (C) 2015-2016 Lumol's contributors — BSD license
+
+//! Outputs computing time correlation functions and the associated transport
+//! coefficients through Green-Kubo relations.
+use std::io::prelude::*;
+use std::io;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+
+use consts::K_BOLTZMANN;
+use utils;
+use sys::System;
+use checkpoint::{CheckpointWriter, CheckpointReader, CheckpointError};
+use super::Output;
+use super::correlator::{Correlator, integrate};
+
+/// Number of points in each level of the correlators
+const POINTS: usize = 16;
+/// Averaging between levels in the correlators
+const AVERAGING: usize = 2;
+
+/// Shared implementation of the Green-Kubo outputs
+struct GreenKubo {
+    file: File,
+    path: PathBuf,
+    /// Number of levels in the correlator
+    levels: usize,
+    /// Correlator, created when the observable dimension is known
+    correlator: Option<Correlator>,
+    /// Simulation timestep
+    timestep: f64,
+    /// Step of the first and last samples
+    steps: Option<(u64, u64)>,
+    /// Number of samples
+    samples: usize,
+    /// Sum of the temperature over all samples
+    temperature: f64,
+    /// Sum of the volume over all samples
+    volume: f64,
+}
+
+impl GreenKubo {
+    fn new<P: AsRef<Path>>(filename: P, timestep: f64, levels: usize) -> Result<GreenKubo, io::Error> {
+        assert!(levels > 0, "the number of levels must be positive in Green-Kubo outputs");
+        Ok(GreenKubo {
+            file: try!(File::create(filename.as_ref())),
+            path: filename.as_ref().to_owned(),
+            levels: levels,
+            correlator: None,
+            timestep: timestep,
+            steps: None,
+            samples: 0,
+            temperature: 0.0,
+            volume: 0.0,
+        })
+    }
+
+    fn setup(&mut self, dimension: usize) {
+        self.correlator = Some(Correlator::new(dimension, POINTS, AVERAGING, self.levels));
+        self.steps = None;
+        self.samples = 0;
+        self.temperature = 0.0;
+        self.volume = 0.0;
+    }
+
+    fn add(&mut self, system: &System, value: &[f64]) {
+        let correlator = self.correlator.as_mut().expect("missing call to setup");
+        correlator.add(value);
+
+        let step = system.step();
+        self.steps = match self.steps {
+            Some((first, _)) => Some((first, step)),
+            None => Some((step, step)),
+        };
+        self.samples += 1;
+        self.temperature += system.temperature();
+        self.volume += system.volume();
+    }
+
+    fn save_state(&self, writer: &mut CheckpointWriter) {
+        let correlator = self.correlator.as_ref().expect("missing call to setup");
+        writer.write_section(correlator);
+        writer.write_bool(self.steps.is_some());
+        let (first, last) = self.steps.unwrap_or((0, 0));
+        writer.write_u64(first);
+        writer.write_u64(last);
+        writer.write_usize(self.samples);
+        writer.write_f64(self.temperature);
+        writer.write_f64(self.volume);
+    }
+
+    fn restore_state(&mut self, reader: &mut CheckpointReader) -> Result<(), CheckpointError> {
+        {
+            let correlator = self.correlator.as_mut().expect("missing call to setup");
+            try!(reader.read_section(correlator));
+        }
+        let has_steps = try!(reader.read_bool());
+        let first = try!(reader.read_u64());
+        let last = try!(reader.read_u64());
+        self.steps = if has_steps {Some((first, last))} else {None};
+        self.samples = try!(reader.read_usize());
+        self.temperature = try!(reader.read_f64());
+        self.volume = try!(reader.read_f64());
+        Ok(())
+    }
+
+    /// Average temperature over the samples
+    fn temperature(&self) -> f64 {
+        self.temperature / self.samples as f64
+    }
+
+    /// Average volume over the samples
+    fn volume(&self) -> f64 {
+        self.volume / self.samples as f64
+    }
+
+    /// Get the correlation function, as pairs of time and value, scaled by
+    /// `scaling`
+    fn correlation(&self, scaling: f64) -> Vec<(f64, f64)> {
+        let interval = match self.steps {
+            Some((first, last)) if self.samples > 1 => {
+                (last - first) as f64 / (self.samples - 1) as f64
+            }
+            _ => return Vec::new(),
+        };
+
+        let correlator = self.correlator.as_ref().expect("missing call to setup");
+        correlator.correlation().iter().map(|&(lag, value)| {
+            (lag as f64 * interval * self.timestep, scaling * value)
+        }).collect()
+    }
+
+    /// Write the `correlation` function to the file, after the `header`
+    /// lines. The correlation function values are converted to `unit`.
+    fn write(&mut self, header: &[String], correlation: &[(f64, f64)], unit: &str) -> Result<(), io::Error> {
+        for line in header {
+            try!(writeln!(&mut self.file, "# {}", line));
+        }
+        for &(time, value) in correlation {
+            try!(writeln!(&mut self.file, "{} {}", utils::unit_to(time, "fs"), utils::unit_to(value, unit)));
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self, header: &[String], correlation: &[(f64, f64)], unit: &str) {
+        if let Err(err) = self.write(header, correlation, unit) {
+            error!("Could not write to file '{}': {}", self.path.display(), err);
+        }
+    }
+}
+
+/******************************************************************************/
+/// The `VacfOutput` computes the velocity autocorrelation function `<v(0) ·
+/// v(t)>`, averaged over all the particles, and the corresponding
+/// self-diffusion coefficient `D = 1/3 ∫ <v(0) · v(t)> dt`. The file is
+/// written at the end of the simulation.
+pub struct VacfOutput {
+    green_kubo: GreenKubo,
+    natoms: usize,
+}
+
+impl VacfOutput {
+    /// Create a new `VacfOutput` writing to `filename`, for a simulation
+    /// with the given `timestep`. The correlation function is computed using
+    /// a multiple-tau correlator with `levels` levels. The file is replaced
+    /// if it already exists.
+    ///
+    /// # Panics
+    ///
+    /// If `levels` is zero.
+    pub fn new<P: AsRef<Path>>(filename: P, timestep: f64, levels: usize) -> Result<VacfOutput, io::Error> {
+        Ok(VacfOutput {
+            green_kubo: try!(GreenKubo::new(filename, timestep, levels)),
+            natoms: 0,
+        })
+    }
+
+    /// Get the path of the file written by this output
+    pub fn path(&self) -> &Path {
+        &self.green_kubo.path
+    }
+
+    /// Get the timestep of the simulation
+    pub fn timestep(&self) -> f64 {
+        self.green_kubo.timestep
+    }
+
+    /// Get the number of levels in the correlator
+    pub fn levels(&self) -> usize {
+        self.green_kubo.levels
+    }
+}
+
+impl Output for VacfOutput {
+    fn setup(&mut self, system: &System) {
+        self.natoms = system.size();
+        self.green_kubo.setup(3 * system.size());
+    }
+
+    fn write(&mut self, system: &System) {
+        if system.size() != self.natoms {
+            fatal_error!("The number of particles changed in velocity autocorrelation function");
+        }
+        let mut velocities = Vec::with_capacity(3 * system.size());
+        for particle in system {
+            velocities.extend_from_slice(&[particle.velocity[0], particle.velocity[1], particle.velocity[2]]);
+        }
+        self.green_kubo.add(system, &velocities);
+    }
+
+    fn finish(&mut self, _: &System) {
+        let correlation = self.green_kubo.correlation(1.0 / self.natoms as f64);
+        let diffusion = integrate(&correlation) / 3.0;
+        let header = [
+            String::from("Velocity autocorrelation function"),
+            format!("Self-diffusion coefficient: {} A^2/fs", utils::unit_to(diffusion, "A^2/fs")),
+            String::from("Time/fs VACF/(A^2/fs^2)"),
+        ];
+        self.green_kubo.finish(&header, &correlation, "A^2/fs^2");
+    }
+
+    fn save_state(&self, writer: &mut CheckpointWriter) {
+        self.green_kubo.save_state(writer);
+    }
+
+    fn restore_state(&mut self, reader: &mut CheckpointReader) -> Result<(), CheckpointError> {
+        self.green_kubo.restore_state(reader)
+    }
+}
+
+/******************************************************************************/
+/// The `ViscosityOutput` computes the autocorrelation function of the
+/// off-diagonal components of the stress tensor, averaged over the `xy`, `xz`
+/// and `yz` components, and the corresponding shear viscosity `η = V / (kB
+/// T) ∫ <σxy(0) σxy(t)> dt`. The volume and temperature are averaged over
+/// the simulation. The file is written at the end of the simulation.
+pub struct ViscosityOutput {
+    green_kubo: GreenKubo,
+}
+
+impl ViscosityOutput {
+    /// Create a new `ViscosityOutput` writing to `filename`, for a
+    /// simulation with the given `timestep`. The correlation function is
+    /// computed using a multiple-tau correlator with `levels` levels. The
+    /// file is replaced if it already exists.
+    ///
+    /// # Panics
+    ///
+    /// If `levels` is zero.
+    pub fn new<P: AsRef<Path>>(filename: P, timestep: f64, levels: usize) -> Result<ViscosityOutput, io::Error> {
+        Ok(ViscosityOutput {
+            green_kubo: try!(GreenKubo::new(filename, timestep, levels)),
+        })
+    }
+
+    /// Get the path of the file written by this output
+    pub fn path(&self) -> &Path {
+        &self.green_kubo.path
+    }
+
+    /// Get the timestep of the simulation
+    pub fn timestep(&self) -> f64 {
+        self.green_kubo.timestep
+    }
+
+    /// Get the number of levels in the correlator
+    pub fn levels(&self) -> usize {
+        self.green_kubo.levels
+    }
+}
+
+impl Output for ViscosityOutput {
+    fn setup(&mut self, _: &System) {
+        self.green_kubo.setup(3);
+    }
+
+    fn write(&mut self, system: &System) {
+        let stress = system.stress();
+        self.green_kubo.add(system, &[stress[0][1], stress[0][2], stress[1][2]]);
+    }
+
+    fn finish(&mut self, _: &System) {
+        let correlation = self.green_kubo.correlation(1.0 / 3.0);
+        let viscosity = if correlation.is_empty() {
+            0.0
+        } else {
+            let volume = self.green_kubo.volume();
+            let temperature = self.green_kubo.temperature();
+            volume / (K_BOLTZMANN * temperature) * integrate(&correlation)
+        };
+        let header = [
+            String::from("Stress autocorrelation function"),
+            format!("Shear viscosity: {} Pa*s", utils::unit_to(viscosity, "Pa*s")),
+            String::from("Time/fs SACF/bar^2"),
+        ];
+        self.green_kubo.finish(&header, &correlation, "bar^2");
+    }
+
+    fn save_state(&self, writer: &mut CheckpointWriter) {
+        self.green_kubo.save_state(writer);
+    }
+
+    fn restore_state(&mut self, reader: &mut CheckpointReader) -> Result<(), CheckpointError> {
+        self.green_kubo.restore_state(reader)
+    }
+}
+
+/******************************************************************************/
+/// The `ConductivityOutput` computes the autocorrelation function of the
+/// electric current `J = ∑ qi vi`, and the corresponding ionic conductivity
+/// `σ = 1 / (3 V kB T) ∫ <J(0) · J(t)> dt`. The volume and temperature are
+/// averaged over the simulation. The file is written at the end of the
+/// simulation.
+pub struct ConductivityOutput {
+    green_kubo: GreenKubo,
+}
+
+impl ConductivityOutput {
+    /// Create a new `ConductivityOutput` writing to `filename`, for a
+    /// simulation with the given `timestep`. The correlation function is
+    /// computed using a multiple-tau correlator with `levels` levels. The
+    /// file is replaced if it already exists.
+    ///
+    /// # Panics
+    ///
+    /// If `levels` is zero.
+    pub fn new<P: AsRef<Path>>(filename: P, timestep: f64, levels: usize) -> Result<ConductivityOutput, io::Error> {
+        Ok(ConductivityOutput {
+            green_kubo: try!(GreenKubo::new(filename, timestep, levels)),
+        })
+    }
+
+    /// Get the path of the file written by this output
+    pub fn path(&self) -> &Path {
+        &self.green_kubo.path
+    }
+
+    /// Get the timestep of the simulation
+    pub fn timestep(&self) -> f64 {
+        self.green_kubo.timestep
+    }
+
+    /// Get the number of levels in the correlator
+    pub fn levels(&self) -> usize {
+        self.green_kubo.levels
+    }
+}
+
+impl Output for ConductivityOutput {
+    fn setup(&mut self, _: &System) {
+        self.green_kubo.setup(3);
+    }
+
+    fn write(&mut self, system: &System) {
+        let mut current = [0.0; 3];
+        for particle in system {
+            for k in 0..3 {
+                current[k] += particle.charge * particle.velocity[k];
+            }
+        }
+        self.green_kubo.add(system, &current);
+    }
+
+    fn finish(&mut self, _: &System) {
+        let correlation = self.green_kubo.correlation(1.0);
+        let conductivity = if correlation.is_empty() {
+            0.0
+        } else {
+            let volume = self.green_kubo.volume();
+            let temperature = self.green_kubo.temperature();
+            integrate(&correlation) / (3.0 * volume * K_BOLTZMANN * temperature)
+        };
+        let header = [
+            String::from("Electric current autocorrelation function"),
+            format!("Ionic conductivity: {} S/m", utils::unit_to(conductivity, "C^2/(J*m*s)")),
+            String::from("Time/fs JACF/(e^2*A^2/fs^2)"),
+        ];
+        self.green_kubo.finish(&header, &correlation, "e^2*A^2/fs^2");
+    }
+
+    fn save_state(&self, writer: &mut CheckpointWriter) {
+        self.green_kubo.save_state(writer);
+    }
+
+    fn restore_state(&mut self, reader: &mut CheckpointReader) -> Result<(), CheckpointError> {
+        self.green_kubo.restore_state(reader)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate tempfile;
+    use self::tempfile::NamedTempFile;
+
+    use std::io::prelude::*;
+
+    use super::*;
+    use sys::*;
+    use types::*;
+    use utils::unit_from;
+
+    fn read_file(file: &NamedTempFile) -> Vec<String> {
+        let mut buffer = String::new();
+        let _ = file.reopen().unwrap().read_to_string(&mut buffer).unwrap();
+        buffer.lines().map(String::from).collect()
+    }
+
+    fn testing_system() -> System {
+        let mut system = System::from_cell(UnitCell::cubic(10.0));
+        system.add_particle(Particle::new("Na"));
+        system[0].charge = 1.0;
+        system[0].velocity = Vector3D::new(1e-3, 0.0, 0.0);
+        system.add_particle(Particle::new("Cl"));
+        system[1].charge = -1.0;
+        system[1].velocity = Vector3D::new(-1e-3, 0.0, 0.0);
+        return system;
+    }
+
+    #[test]
+    fn vacf() {
+        let tempfile = NamedTempFile::new().unwrap();
+        let mut system = testing_system();
+        {
+            let mut out = VacfOutput::new(tempfile.path(), 2.0, 4).unwrap();
+            out.setup(&system);
+            for _ in 0..100 {
+                out.write(&system);
+                system.increment_step();
+            }
+            out.finish(&system);
+        }
+
+        let lines = read_file(&tempfile);
+        assert_eq!(lines[0], "# Velocity autocorrelation function");
+        assert_eq!(lines[2], "# Time/fs VACF/(A^2/fs^2)");
+
+        // Constant velocities: the correlation is constant, and the
+        // diffusion coefficient grows linearly with the longest lag. The last
+        // level received 12 values, with a spacing of 8 samples.
+        let longest = 2.0 * (11 * 8) as f64;
+        let expected = 1e-6 * longest / 3.0;
+        let diffusion = lines[1].split_whitespace().nth(3).unwrap().parse::<f64>().unwrap();
+        assert_relative_eq!(diffusion, expected, max_relative=1e-12);
+
+        let first = lines[3].split_whitespace().map(|v| v.parse::<f64>().unwrap()).collect::<Vec<_>>();
+        assert_eq!(first[0], 0.0);
+        assert_relative_eq!(first[1], 1e-6, max_relative=1e-12);
+        let second = lines[4].split_whitespace().map(|v| v.parse::<f64>().unwrap()).collect::<Vec<_>>();
+        assert_eq!(second[0], 2.0);
+    }
+
+    #[test]
+    fn conductivity() {
+        let tempfile = NamedTempFile::new().unwrap();
+        let mut system = testing_system();
+        system.external_temperature(Some(unit_from(300.0, "K")));
+        {
+            let mut out = ConductivityOutput::new(tempfile.path(), 1.0, 2).unwrap();
+            out.setup(&system);
+            for _ in 0..50 {
+                out.write(&system);
+                system.increment_step();
+            }
+            out.finish(&system);
+        }
+
+        let lines = read_file(&tempfile);
+        assert_eq!(lines[0], "# Electric current autocorrelation function");
+        assert!(lines[1].starts_with("# Ionic conductivity: "));
+        // J = 2e-3 e A/fs
+        let first = lines[3].split_whitespace().map(|v| v.parse::<f64>().unwrap()).collect::<Vec<_>>();
+        assert_relative_eq!(first[1], 4e-6, max_relative=1e-12);
+    }
+
+    #[test]
+    fn viscosity() {
+        let tempfile = NamedTempFile::new().unwrap();
+        let mut system = testing_system();
+        system[0].velocity = Vector3D::new(1e-3, 1e-3, 0.0);
+        {
+            let mut out = ViscosityOutput::new(tempfile.path(), 1.0, 2).unwrap();
+            out.setup(&system);
+            for _ in 0..50 {
+                out.write(&system);
+                system.increment_step();
+            }
+            out.finish(&system);
+        }
+
+        let lines = read_file(&tempfile);
+        assert_eq!(lines[0], "# Stress autocorrelation function");
+        assert!(lines[1].starts_with("# Shear viscosity: "));
+        assert_eq!(lines[2], "# Time/fs SACF/bar^2");
+
+        // Only the xy component of the stress is not zero
+        let stress = system.stress()[0][1];
+        let first = lines[3].split_whitespace().map(|v| v.parse::<f64>().unwrap()).collect::<Vec<_>>();
+        assert_relative_eq!(first[1], utils::unit_to(stress * stress / 3.0, "bar^2"), max_relative=1e-12);
+    }
+}
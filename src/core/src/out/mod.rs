@@ -0,0 +1,1443 @@
+// Lumol, an extensible molecular simulation engine
+// Copyright (C) 2015-2016 Lumol's contributors — BSD license
+
+//! Saving properties of a system during a simulation
+use std::io::prelude::*;
+use std::io::{self, SeekFrom};
+use std::fs::{File, OpenOptions};
+use std::path::{Path, PathBuf};
+use std::f64::consts::PI;
+use std::collections::VecDeque;
+
+use utils;
+use types::{Vector3D, Zero};
+use sys::System;
+use sys::{Trajectory, TrajectoryError};
+use sys::NormalModes;
+use sim::Ensemble;
+use utils::AsAny;
+use checkpoint::{Checkpoint, CheckpointWriter, CheckpointReader, CheckpointError};
+
+mod correlator;
+pub use self::correlator::{Correlator, integrate};
+
+mod green_kubo;
+pub use self::green_kubo::{VacfOutput, ViscosityOutput, ConductivityOutput};
+
+mod structure;
+pub use self::structure::StructureFactorOutput;
+
+mod statistics;
+pub use self::statistics::{BlockAverage, Statistics};
+
+mod fluctuations;
+pub use self::fluctuations::FluctuationsOutput;
+
+mod custom;
+pub use self::custom::{CustomOutput, CustomOutputError, CustomQuantity};
+
+mod breakdown;
+pub use self::breakdown::EnergyBreakdownOutput;
+
+mod profiles;
+pub use self::profiles::DensityProfileOutput;
+
+/// The `Output` trait define the interface for all the quantities outputted by
+/// the simulation during the run. An Output can be a text or a binary data
+/// file, an image, a text log, …
+pub trait Output: AsAny {
+    /// Function called once at the beginning of the simulation, which allow
+    /// for some setup of the output if needed.
+    fn setup(&mut self, _: &System) {}
+
+    /// Function called once at the beginning of the simulation, before
+    /// `setup`, with the thermodynamic `ensemble` sampled by the simulation
+    /// propagator. The default implementation does nothing.
+    fn set_ensemble(&mut self, _: Ensemble) {}
+
+    /// Function called once at the beginning of the simulation, before
+    /// `setup`, when the simulation is restarted from a checkpoint. The data
+    /// already written to the output files should then be kept instead of
+    /// being replaced, and the state of the output is restored by
+    /// `restore_state` after `setup`. The default implementation does nothing.
+    fn set_restart(&mut self) {}
+
+    /// Write the output from the system.
+    fn write(&mut self, system: &System);
+
+    /// Function called once at the end of the simulation.
+    fn finish(&mut self, _: &System) {}
+
+    /// Save the internal state of this output in a checkpoint, to be able to
+    /// restart the simulation later. The default implementation saves nothing.
+    fn save_state(&self, _: &mut CheckpointWriter) {}
+
+    /// Restore the internal state of this output from a checkpoint written by
+    /// `save_state`. This function is called after `setup` when restarting a
+    /// simulation. The default implementation does nothing.
+    fn restore_state(&mut self, _: &mut CheckpointReader) -> Result<(), CheckpointError> {
+        Ok(())
+    }
+}
+
+impl_downcast!(Output);
+
+/// A file written by the outputs during the simulation. The file is replaced
+/// at the beginning of the simulation, and when restarting from a checkpoint
+/// it is truncated to the data written before the checkpoint and continued.
+struct OutputFile {
+    file: File,
+    path: PathBuf,
+    /// Are we restarting the simulation from a checkpoint?
+    restart: bool,
+    /// Number of bytes written to the file
+    size: u64,
+}
+
+impl OutputFile {
+    /// Open the file at `path` for writing, creating it if needed. The
+    /// existing content of the file is only removed by `start`.
+    fn create(path: &Path) -> Result<OutputFile, io::Error> {
+        let file = try!(OpenOptions::new().write(true).create(true).truncate(false).open(path));
+        Ok(OutputFile {
+            file: file,
+            path: path.to_owned(),
+            restart: false,
+            size: 0,
+        })
+    }
+
+    /// Get the path of this file
+    fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Keep the existing content of the file when starting the simulation
+    fn set_restart(&mut self) {
+        self.restart = true;
+    }
+
+    /// Start writing to the file at the beginning of the simulation. This
+    /// removes the existing content of the file and returns `true` if the
+    /// header of the file should be written, or does nothing and returns
+    /// `false` when restarting.
+    fn start(&mut self) -> Result<bool, io::Error> {
+        if self.restart {
+            return Ok(false);
+        }
+        try!(self.file.set_len(0));
+        let _ = try!(self.file.seek(SeekFrom::Start(0)));
+        self.size = 0;
+        Ok(true)
+    }
+}
+
+impl Checkpoint for OutputFile {
+    fn save(&self, writer: &mut CheckpointWriter) {
+        writer.write_u64(self.size);
+    }
+
+    fn restore(&mut self, reader: &mut CheckpointReader) -> Result<(), CheckpointError> {
+        let size = try!(reader.read_u64());
+        let current = try!(self.file.metadata()).len();
+        if current < size {
+            return Err(CheckpointError::from(format!(
+                "the file '{}' contains {} bytes, but {} bytes were written \
+                when the checkpoint was created", self.path.display(), current, size
+            )));
+        }
+        // Remove the data written after the checkpoint
+        try!(self.file.set_len(size));
+        let _ = try!(self.file.seek(SeekFrom::Start(size)));
+        self.size = size;
+        Ok(())
+    }
+}
+
+impl Write for OutputFile {
+    fn write(&mut self, buffer: &[u8]) -> io::Result<usize> {
+        let written = try!(self.file.write(buffer));
+        self.size += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+/******************************************************************************/
+/// The `TrajectoryOutput` allow to write the trajectory of the system to a
+/// file, using any format supported by the [Chemfiles][chemfiles] library.
+///
+/// [chemfiles]: http://chemfiles.github.io
+pub struct TrajectoryOutput {
+    file: Trajectory,
+    path: PathBuf,
+    /// Are we restarting the simulation from a checkpoint?
+    restart: bool,
+}
+
+impl TrajectoryOutput {
+    /// Create a new `TrajectoryOutput` writing to `filename`. The file is
+    /// replaced if it already exists, unless the simulation is restarted. In
+    /// this case, the new steps are added at the end of the file.
+    pub fn new<P>(path: P) -> Result<TrajectoryOutput, TrajectoryError> where P: AsRef<Path> {
+        // Some formats can not be opened in append mode, and are always
+        // replaced by the simulation
+        let file = match Trajectory::append(path.as_ref()) {
+            Ok(file) => file,
+            Err(_) => try!(Trajectory::create(path.as_ref())),
+        };
+        Ok(TrajectoryOutput{
+            file: file,
+            path: path.as_ref().to_owned(),
+            restart: false,
+        })
+    }
+
+    /// Get the path of the file written by this output
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Output for TrajectoryOutput {
+    fn set_restart(&mut self) {
+        self.restart = true;
+    }
+
+    fn setup(&mut self, _: &System) {
+        if !self.restart {
+            match Trajectory::create(&self.path) {
+                Ok(file) => self.file = file,
+                Err(err) => {
+                    fatal_error!("Could not create trajectory '{}': {}", self.path.display(), err);
+                }
+            }
+        }
+    }
+
+    fn write(&mut self, system: &System) {
+        match self.file.write(system) {
+            Ok(()) => (),
+            Err(err) => {
+                fatal_error!("Error in while writing trajectory: {}", err);
+            }
+        }
+    }
+}
+
+
+/******************************************************************************/
+/// The `CellOutput` write all the components of a cell to a file . The columns
+/// in the file contains the following values: `A B C α β γ`.
+pub struct CellOutput {
+    file: OutputFile,
+}
+
+impl CellOutput {
+    /// Create a new `CellOutput` writing to `filename`. The file is replaced if
+    /// it already exists, unless the simulation is restarted.
+    pub fn new<P: AsRef<Path>>(filename: P) -> Result<CellOutput, io::Error> {
+        Ok(CellOutput{
+            file: try!(OutputFile::create(filename.as_ref())),
+        })
+    }
+
+    /// Get the path of the file written by this output
+    pub fn path(&self) -> &Path {
+        self.file.path()
+    }
+}
+
+impl Output for CellOutput {
+    fn set_restart(&mut self) {
+        self.file.set_restart();
+    }
+
+    fn setup(&mut self, _: &System) {
+        match self.file.start() {
+            Ok(true) => {}
+            // The header was already written before the checkpoint
+            Ok(false) => return,
+            Err(err) => fatal_error!("Could not write to file '{}': {}", self.file.path().display(), err),
+        }
+        if let Err(err) = writeln!(&mut self.file, "# Unit cell of the simulation") {
+            // Do panic in early time
+            fatal_error!("Could not write to file '{}': {}", self.file.path().display(), err);
+        }
+        if let Err(err) = writeln!(&mut self.file, "# Step A/Å B/Å C/Å α/deg β/deg γ/deg") {
+            fatal_error!("Could not write to file '{}': {}", self.file.path().display(), err);
+        }
+    }
+
+    fn write(&mut self, system: &System) {
+        let cell = system.cell();
+        if let Err(err) = writeln!(
+            &mut self.file, "{} {} {} {} {} {} {}",
+            system.step(), cell.a(), cell.b(), cell.c(), cell.alpha(), cell.beta(), cell.gamma()
+        ) {
+            // Do not panic during the simulation
+            error!("Could not write to file '{}': {}", self.file.path().display(), err);
+        }
+    }
+
+    fn save_state(&self, writer: &mut CheckpointWriter) {
+        self.file.save(writer);
+    }
+
+    fn restore_state(&mut self, reader: &mut CheckpointReader) -> Result<(), CheckpointError> {
+        self.file.restore(reader)
+    }
+}
+
+/******************************************************************************/
+/// The `EnergyOutput` write the energy of the system to a text file, organized
+/// as: `PotentialEnergy     KineticEnergy     TotalEnergy`.
+///
+/// The average of each energy and its statistical error are reported at the
+/// end of the simulation, and can also be written to a summary file.
+pub struct EnergyOutput {
+    file: OutputFile,
+    statistics: Statistics,
+}
+
+impl EnergyOutput {
+    /// Create a new `EnergyOutput` writing to `filename`. The file is replaced
+    /// if it already exists, unless the simulation is restarted.
+    pub fn new<P: AsRef<Path>>(filename: P) -> Result<EnergyOutput, io::Error> {
+        Ok(EnergyOutput{
+            file: try!(OutputFile::create(filename.as_ref())),
+            statistics: Statistics::new(&[
+                ("Potential", "kJ/mol"), ("Kinetic", "kJ/mol"), ("Total", "kJ/mol")
+            ]),
+        })
+    }
+
+    /// Also write the statistics of the energy to the file at `path` at the
+    /// end of the simulation. The file is replaced if it already exists.
+    pub fn set_summary<P: AsRef<Path>>(&mut self, path: P) -> Result<(), io::Error> {
+        self.statistics.set_summary(path)
+    }
+
+    /// Get the statistics of the potential, kinetic and total energy
+    pub fn statistics(&self) -> &Statistics {
+        &self.statistics
+    }
+
+    /// Get the path of the file written by this output
+    pub fn path(&self) -> &Path {
+        self.file.path()
+    }
+
+    /// Get the path of the summary file, if the statistics are written to a
+    /// summary file.
+    pub fn summary(&self) -> Option<&Path> {
+        self.statistics.summary()
+    }
+}
+
+impl Output for EnergyOutput {
+    fn set_restart(&mut self) {
+        self.file.set_restart();
+    }
+
+    fn setup(&mut self, _: &System) {
+        match self.file.start() {
+            Ok(true) => {}
+            // The header was already written before the checkpoint
+            Ok(false) => return,
+            Err(err) => fatal_error!("Could not write to file '{}': {}", self.file.path().display(), err),
+        }
+        if let Err(err) = writeln!(&mut self.file, "# Energy of the simulation (kJ/mol)") {
+            fatal_error!("Could not write to file '{}': {}", self.file.path().display(), err);
+        }
+        if let Err(err) = writeln!(&mut self.file, "# Step Potential Kinetic Total") {
+            fatal_error!("Could not write to file '{}': {}", self.file.path().display(), err);
+        }
+    }
+
+    fn write(&mut self, system: &System) {
+        let potential = system.potential_energy();
+        let kinetic = system.kinetic_energy();
+        let total = system.total_energy();
+        self.statistics.add(&[potential, kinetic, total]);
+
+        let potential = utils::unit_to(potential, "kJ/mol");
+        let kinetic = utils::unit_to(kinetic, "kJ/mol");
+        let total = utils::unit_to(total, "kJ/mol");
+        if let Err(err) = writeln!(&mut self.file, "{} {} {} {}", system.step(), potential, kinetic, total) {
+            error!("Could not write to file '{}': {}", self.file.path().display(), err);
+        }
+    }
+
+    fn finish(&mut self, _: &System) {
+        self.statistics.report("Energy statistics");
+    }
+
+    fn save_state(&self, writer: &mut CheckpointWriter) {
+        self.file.save(writer);
+        self.statistics.save(writer);
+    }
+
+    fn restore_state(&mut self, reader: &mut CheckpointReader) -> Result<(), CheckpointError> {
+        try!(self.file.restore(reader));
+        self.statistics.restore(reader)
+    }
+}
+
+/******************************************************************************/
+/// The `PropertiesOutput` write various physical properties of the system to
+/// a file. These properties are:
+///
+/// - volume of the unit cell;
+/// - instant temperature;
+/// - instant pressure;
+///
+/// The average of each property and its statistical error are reported at the
+/// end of the simulation, and can also be written to a summary file.
+pub struct PropertiesOutput {
+    file: OutputFile,
+    statistics: Statistics,
+}
+
+impl PropertiesOutput {
+    /// Create a new `PropertiesOutput` writing to `filename`. The file is replaced
+    /// if it already exists, unless the simulation is restarted.
+    pub fn new<P: AsRef<Path>>(filename: P) -> Result<PropertiesOutput, io::Error> {
+        Ok(PropertiesOutput{
+            file: try!(OutputFile::create(filename.as_ref())),
+            statistics: Statistics::new(&[
+                ("Volume", "A^3"), ("Temperature", "K"), ("Pressure", "bar")
+            ]),
+        })
+    }
+
+    /// Also write the statistics of the properties to the file at `path` at
+    /// the end of the simulation. The file is replaced if it already exists.
+    pub fn set_summary<P: AsRef<Path>>(&mut self, path: P) -> Result<(), io::Error> {
+        self.statistics.set_summary(path)
+    }
+
+    /// Get the statistics of the volume, temperature and pressure
+    pub fn statistics(&self) -> &Statistics {
+        &self.statistics
+    }
+
+    /// Get the path of the file written by this output
+    pub fn path(&self) -> &Path {
+        self.file.path()
+    }
+
+    /// Get the path of the summary file, if the statistics are written to a
+    /// summary file.
+    pub fn summary(&self) -> Option<&Path> {
+        self.statistics.summary()
+    }
+}
+
+impl Output for PropertiesOutput {
+    fn set_restart(&mut self) {
+        self.file.set_restart();
+    }
+
+    fn setup(&mut self, _: &System) {
+        match self.file.start() {
+            Ok(true) => {}
+            // The header was already written before the checkpoint
+            Ok(false) => return,
+            Err(err) => fatal_error!("Could not write to file '{}': {}", self.file.path().display(), err),
+        }
+        if let Err(err) = writeln!(&mut self.file, "# Physical properties of the simulation") {
+            fatal_error!("Could not write to file '{}': {}", self.file.path().display(), err);
+        }
+        if let Err(err) = writeln!(&mut self.file, "# Step Volume/A^3 Temperature/K Pressure/bar") {
+            fatal_error!("Could not write to file '{}': {}", self.file.path().display(), err);
+        }
+    }
+
+    fn write(&mut self, system: &System) {
+        let volume = system.volume();
+        let temperature = system.temperature();
+        let pressure = system.pressure();
+        self.statistics.add(&[volume, temperature, pressure]);
+
+        let volume = utils::unit_to(volume, "A^3");
+        let temperature = utils::unit_to(temperature, "K");
+        let pressure = utils::unit_to(pressure, "bar");
+        if let Err(err) = writeln!(&mut self.file, "{} {} {} {}", system.step(), volume, temperature, pressure) {
+            error!("Could not write to file '{}': {}", self.file.path().display(), err);
+        }
+    }
+
+    fn finish(&mut self, _: &System) {
+        self.statistics.report("Properties statistics");
+    }
+
+    fn save_state(&self, writer: &mut CheckpointWriter) {
+        self.file.save(writer);
+        self.statistics.save(writer);
+    }
+
+    fn restore_state(&mut self, reader: &mut CheckpointReader) -> Result<(), CheckpointError> {
+        try!(self.file.restore(reader));
+        self.statistics.restore(reader)
+    }
+}
+
+/******************************************************************************/
+/// The `NormalModesOutput` computes the normal modes of the system at the end
+/// of the simulation, and write the corresponding frequencies to a file. The
+/// modes can also be written to a trajectory, as an animation of one
+/// oscillation of each mode.
+///
+/// The Hessian computation is expensive, and the normal modes are only
+/// meaningful for minimized structures, so nothing is done during the
+/// simulation.
+pub struct NormalModesOutput {
+    file: File,
+    path: PathBuf,
+    modes: Option<(Trajectory, PathBuf)>,
+    amplitude: f64,
+}
+
+impl NormalModesOutput {
+    /// Create a new `NormalModesOutput` writing the frequencies to
+    /// `filename`. The file is replaced if it already exists.
+    pub fn new<P: AsRef<Path>>(filename: P) -> Result<NormalModesOutput, io::Error> {
+        Ok(NormalModesOutput{
+            file: try!(File::create(filename.as_ref())),
+            path: filename.as_ref().to_owned(),
+            modes: None,
+            amplitude: utils::unit_from(0.5, "A"),
+        })
+    }
+
+    /// Also write all the modes to the trajectory at `path`, with the given
+    /// oscillation `amplitude`. The file is replaced if it already exists.
+    pub fn set_modes_output<P: AsRef<Path>>(&mut self, path: P, amplitude: f64) -> Result<(), TrajectoryError> {
+        self.modes = Some((try!(Trajectory::create(path.as_ref())), path.as_ref().to_owned()));
+        self.amplitude = amplitude;
+        Ok(())
+    }
+
+    /// Get the path of the file written by this output
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Get the path of the trajectory containing the modes and the amplitude
+    /// of the oscillations, if the modes are written to a trajectory.
+    pub fn modes(&self) -> Option<(&Path, f64)> {
+        self.modes.as_ref().map(|&(_, ref path)| (&**path, self.amplitude))
+    }
+}
+
+impl Output for NormalModesOutput {
+    fn setup(&mut self, _: &System) {
+        if let Err(err) = writeln!(&mut self.file, "# Normal modes of the final configuration") {
+            fatal_error!("Could not write to file '{}': {}", self.path.display(), err);
+        }
+        if let Err(err) = writeln!(&mut self.file, "# Mode Wavenumber/cm^-1") {
+            fatal_error!("Could not write to file '{}': {}", self.path.display(), err);
+        }
+    }
+
+    fn write(&mut self, _: &System) {}
+
+    fn finish(&mut self, system: &System) {
+        let modes = NormalModes::new(system);
+        for (i, wavenumber) in modes.wavenumbers().iter().enumerate() {
+            if let Err(err) = writeln!(&mut self.file, "{} {}", i, wavenumber) {
+                error!("Could not write to file '{}': {}", self.path.display(), err);
+            }
+        }
+
+        if let Some((ref mut trajectory, _)) = self.modes {
+            for i in 0..modes.modes().len() {
+                if let Err(err) = modes.write_mode(system, i, self.amplitude, trajectory) {
+                    error!("Error while writing normal modes trajectory: {}", err);
+                }
+            }
+        }
+    }
+}
+
+/******************************************************************************/
+/// The `RdfOutput` computes the radial distribution function `g(r)` between
+/// two kinds of particles, or between all the particles, averaged over the
+/// simulation. The file is written at the end of the simulation, and contains
+/// the following columns: `r g(r) N(r)`, where `N(r)` is the running
+/// coordination number, i.e. the average number of particles of the second
+/// kind at a distance smaller than `r` of a particle of the first kind.
+///
+/// The radial distribution function is normalized with the volume of the unit
+/// cell at each step, and can be used with simulations where the volume
+/// changes.
+pub struct RdfOutput {
+    file: File,
+    path: PathBuf,
+    /// Names of the particles to use, or `None` to use all the particles
+    pair: Option<(String, String)>,
+    /// Maximal distance in the histogram
+    max: f64,
+    /// Width of the bins in the histogram
+    width: f64,
+    /// Accumulated radial distribution function
+    rdf: Vec<f64>,
+    /// Accumulated number of neighbors per reference particle in each bin
+    neighbors: Vec<f64>,
+    /// Number of configurations used
+    samples: usize,
+}
+
+impl RdfOutput {
+    /// Create a new `RdfOutput` writing to `filename`, using all the pairs of
+    /// particles up to a distance of `max`, with bins of size `width`. The
+    /// file is replaced if it already exists.
+    ///
+    /// # Panics
+    ///
+    /// If `width` is not positive or if `max` is smaller than `width`.
+    pub fn new<P: AsRef<Path>>(filename: P, max: f64, width: f64) -> Result<RdfOutput, io::Error> {
+        assert!(width > 0.0, "the bin width must be positive in radial distribution function");
+        assert!(max >= width, "the maximal distance must be larger than the bin width in radial distribution function");
+        let nbins = (max / width).floor() as usize;
+        Ok(RdfOutput{
+            file: try!(File::create(filename.as_ref())),
+            path: filename.as_ref().to_owned(),
+            pair: None,
+            max: max,
+            width: width,
+            rdf: vec![0.0; nbins],
+            neighbors: vec![0.0; nbins],
+            samples: 0,
+        })
+    }
+
+    /// Only use the pairs of particles named `first` and `second`
+    pub fn set_pair<S: Into<String>>(&mut self, first: S, second: S) {
+        self.pair = Some((first.into(), second.into()));
+    }
+
+    /// Get the path of the file written by this output
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Get the maximal distance in the histogram
+    pub fn max(&self) -> f64 {
+        self.max
+    }
+
+    /// Get the width of the bins in the histogram
+    pub fn width(&self) -> f64 {
+        self.width
+    }
+
+    /// Get the names of the pair of particles used, or `None` if all the
+    /// particles are used.
+    pub fn pair(&self) -> Option<(&str, &str)> {
+        self.pair.as_ref().map(|&(ref a, ref b)| (&**a, &**b))
+    }
+
+    /// Check if the particle named `name` should be used as the first (if
+    /// `first` is true) or the second particle in a pair.
+    fn selected(&self, name: &str, first: bool) -> bool {
+        match self.pair {
+            Some((ref a, ref b)) => if first {name == a} else {name == b},
+            None => true,
+        }
+    }
+
+    fn write_rdf(&mut self) -> Result<(), io::Error> {
+        try!(writeln!(&mut self.file, "# Radial distribution function"));
+        match self.pair {
+            Some((ref a, ref b)) => try!(writeln!(&mut self.file, "# Between {} and {}", a, b)),
+            None => try!(writeln!(&mut self.file, "# Between all particles")),
+        }
+        try!(writeln!(&mut self.file, "# r/A g(r) N(r)"));
+
+        let samples = if self.samples == 0 {1.0} else {self.samples as f64};
+        let mut coordination = 0.0;
+        for (i, (rdf, neighbors)) in self.rdf.iter().zip(&self.neighbors).enumerate() {
+            coordination += neighbors / samples;
+            let r = utils::unit_to((i as f64 + 0.5) * self.width, "A");
+            try!(writeln!(&mut self.file, "{} {} {}", r, rdf / samples, coordination));
+        }
+        Ok(())
+    }
+}
+
+impl Output for RdfOutput {
+    fn setup(&mut self, system: &System) {
+        let cell = system.cell();
+        if cell.is_infinite() {
+            fatal_error!("Can not compute radial distribution function with an infinite cell");
+        }
+
+        let (a, b, c) = (cell.vect_a(), cell.vect_b(), cell.vect_c());
+        let volume = cell.volume();
+        let width = f64::min(volume / (b ^ c).norm(), f64::min(volume / (c ^ a).norm(), volume / (a ^ b).norm()));
+        let max = self.width * self.rdf.len() as f64;
+        if max > 0.5 * width {
+            warn!(
+                "The maximal distance in radial distribution function is larger \
+                than half the unit cell width, the result will be wrong at large distances"
+            );
+        }
+    }
+
+    fn write(&mut self, system: &System) {
+        let nbins = self.rdf.len();
+        let mut histogram = vec![0.0; nbins];
+        let mut nfirst = 0;
+        let mut nsecond = 0;
+        let mut ncommon = 0;
+        for i in 0..system.size() {
+            let first = self.selected(system[i].name(), true);
+            let second = self.selected(system[i].name(), false);
+            if first {nfirst += 1;}
+            if second {nsecond += 1;}
+            if first && second {ncommon += 1;}
+
+            for j in (i + 1)..system.size() {
+                let other_first = self.selected(system[j].name(), true);
+                let other_second = self.selected(system[j].name(), false);
+                // Number of times this pair should be counted
+                let count = (first && other_second) as usize + (second && other_first) as usize;
+                if count == 0 {
+                    continue;
+                }
+
+                let bin = (system.distance(i, j) / self.width).floor() as usize;
+                if bin < nbins {
+                    histogram[bin] += count as f64;
+                }
+            }
+        }
+
+        // Number of distinct pairs in the system
+        let npairs = (nfirst * nsecond - ncommon) as f64;
+        if npairs == 0.0 {
+            return;
+        }
+
+        let volume = system.volume();
+        for (i, count) in histogram.iter().enumerate() {
+            let r_min = i as f64 * self.width;
+            let r_max = r_min + self.width;
+            let shell = 4.0 / 3.0 * PI * (r_max * r_max * r_max - r_min * r_min * r_min);
+            self.rdf[i] += count * volume / (npairs * shell);
+            self.neighbors[i] += count / nfirst as f64;
+        }
+        self.samples += 1;
+    }
+
+    fn finish(&mut self, _: &System) {
+        if let Err(err) = self.write_rdf() {
+            error!("Could not write to file '{}': {}", self.path.display(), err);
+        }
+    }
+
+    fn save_state(&self, writer: &mut CheckpointWriter) {
+        writer.write_f64s(&self.rdf);
+        writer.write_f64s(&self.neighbors);
+        writer.write_usize(self.samples);
+    }
+
+    fn restore_state(&mut self, reader: &mut CheckpointReader) -> Result<(), CheckpointError> {
+        let rdf = try!(reader.read_f64s());
+        let neighbors = try!(reader.read_f64s());
+        if rdf.len() != self.rdf.len() || neighbors.len() != self.neighbors.len() {
+            return Err(CheckpointError::from("wrong number of bins in radial distribution function checkpoint"));
+        }
+        self.rdf = rdf;
+        self.neighbors = neighbors;
+        self.samples = try!(reader.read_usize());
+        Ok(())
+    }
+}
+
+/******************************************************************************/
+/// The `MsdOutput` computes the mean square displacement of the particles or
+/// of the molecules center of mass for each species, and the corresponding
+/// self-diffusion coefficients. The file is written at the end of the
+/// simulation, and contains the time lag followed by the mean square
+/// displacement of each species.
+///
+/// All the configurations passed to this output are used as time origins,
+/// and the mean square displacement is computed for time lags up to a given
+/// number of configurations. The positions are unwrapped using the particles
+/// `image`, so that the displacement across periodic boundaries is accounted
+/// for. The self-diffusion coefficients are obtained from the Einstein
+/// relation, `MSD(t) = 6 D t`, using a linear fit over the second half of the
+/// time lags.
+pub struct MsdOutput {
+    file: File,
+    path: PathBuf,
+    /// Simulation timestep, used to convert steps to time
+    timestep: f64,
+    /// Number of time lags to use
+    lags: usize,
+    /// Should we use the molecules center of mass instead of the particles
+    molecules: bool,
+    /// Names of the species
+    species: Vec<String>,
+    /// Index in `species` of each particle or molecule
+    kinds: Vec<usize>,
+    /// Number of particles or molecules in each species
+    counts: Vec<usize>,
+    /// Previous steps and positions used as time origins, the most recent
+    /// being at the back
+    origins: VecDeque<(u64, Vec<Vector3D>)>,
+    /// Accumulated mean square displacement for each time lag and species
+    msd: Vec<Vec<f64>>,
+    /// Number of samples for each time lag
+    samples: Vec<usize>,
+    /// Time corresponding to each time lag
+    times: Vec<f64>,
+}
+
+impl MsdOutput {
+    /// Create a new `MsdOutput` writing to `filename`, for a simulation with
+    /// the given `timestep`, and using up to `lags` time lags. The file is
+    /// replaced if it already exists.
+    ///
+    /// # Panics
+    ///
+    /// If `lags` is zero.
+    pub fn new<P: AsRef<Path>>(filename: P, timestep: f64, lags: usize) -> Result<MsdOutput, io::Error> {
+        assert!(lags > 0, "the number of time lags must be positive in mean square displacement");
+        Ok(MsdOutput{
+            file: try!(File::create(filename.as_ref())),
+            path: filename.as_ref().to_owned(),
+            timestep: timestep,
+            lags: lags,
+            molecules: false,
+            species: Vec::new(),
+            kinds: Vec::new(),
+            counts: Vec::new(),
+            origins: VecDeque::new(),
+            msd: vec![Vec::new(); lags + 1],
+            samples: vec![0; lags + 1],
+            times: vec![0.0; lags + 1],
+        })
+    }
+
+    /// Use the center of mass of the molecules instead of the particles if
+    /// `molecules` is `true`. The molecules species are named after the
+    /// particles they contain.
+    pub fn set_molecules(&mut self, molecules: bool) {
+        self.molecules = molecules;
+    }
+
+    /// Get the path of the file written by this output
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Get the timestep of the simulation
+    pub fn timestep(&self) -> f64 {
+        self.timestep
+    }
+
+    /// Get the maximal number of time lags
+    pub fn lags(&self) -> usize {
+        self.lags
+    }
+
+    /// Check if the center of mass of the molecules is used instead of the
+    /// particles.
+    pub fn molecules(&self) -> bool {
+        self.molecules
+    }
+
+    /// Get the unwrapped positions of the particles or of the molecules
+    /// center of mass.
+    fn positions(&self, system: &System) -> Vec<Vector3D> {
+        if self.molecules {
+            system.molecules().iter().map(|molecule| {
+                let mut com = Vector3D::zero();
+                let mut mass = 0.0;
+                for i in molecule.iter() {
+                    com += system[i].mass * system.unwrapped_position(i);
+                    mass += system[i].mass;
+                }
+                com / mass
+            }).collect()
+        } else {
+            (0..system.size()).map(|i| system.unwrapped_position(i)).collect()
+        }
+    }
+
+    /// Get the species name for all particles or molecules in the system
+    fn names(&self, system: &System) -> Vec<String> {
+        if self.molecules {
+            system.molecules().iter().map(|molecule| {
+                molecule.iter().map(|i| system[i].name()).collect::<Vec<_>>().join("-")
+            }).collect()
+        } else {
+            system.iter().map(|particle| String::from(particle.name())).collect()
+        }
+    }
+
+    /// Get the self-diffusion coefficient of the species `k`, or `None` if
+    /// there is not enough data
+    fn diffusion(&self, k: usize) -> Option<f64> {
+        let points = (self.lags / 2..self.lags + 1)
+            .filter(|&lag| self.samples[lag] > 0)
+            .map(|lag| (self.times[lag], self.msd[lag][k] / self.samples[lag] as f64))
+            .collect::<Vec<_>>();
+        if points.len() < 2 {
+            return None;
+        }
+
+        let n = points.len() as f64;
+        let mean_t = points.iter().fold(0.0, |sum, p| sum + p.0) / n;
+        let mean_msd = points.iter().fold(0.0, |sum, p| sum + p.1) / n;
+        let covariance = points.iter().fold(0.0, |sum, p| sum + (p.0 - mean_t) * (p.1 - mean_msd));
+        let variance = points.iter().fold(0.0, |sum, p| sum + (p.0 - mean_t) * (p.0 - mean_t));
+        Some(covariance / variance / 6.0)
+    }
+
+    fn write_msd(&mut self) -> Result<(), io::Error> {
+        try!(writeln!(&mut self.file, "# Mean square displacement"));
+        let mut diffusion = String::from("# Self-diffusion coefficients (A^2/fs):");
+        for (k, name) in self.species.iter().enumerate() {
+            match self.diffusion(k) {
+                Some(value) => diffusion.push_str(&format!(" {} = {}", name, utils::unit_to(value, "A^2/fs"))),
+                None => diffusion.push_str(&format!(" {} = ?", name)),
+            }
+        }
+        try!(writeln!(&mut self.file, "{}", diffusion));
+
+        let mut header = String::from("# Time/fs");
+        for name in &self.species {
+            header.push_str(&format!(" {}/A^2", name));
+        }
+        try!(writeln!(&mut self.file, "{}", header));
+
+        for lag in 0..self.lags + 1 {
+            if self.samples[lag] == 0 {
+                continue;
+            }
+            try!(write!(&mut self.file, "{}", utils::unit_to(self.times[lag], "fs")));
+            for msd in &self.msd[lag] {
+                let msd = msd / self.samples[lag] as f64;
+                try!(write!(&mut self.file, " {}", utils::unit_to(msd, "A^2")));
+            }
+            try!(writeln!(&mut self.file, ""));
+        }
+        Ok(())
+    }
+}
+
+impl Output for MsdOutput {
+    fn setup(&mut self, system: &System) {
+        let names = self.names(system);
+        self.species = names.clone();
+        self.species.sort();
+        self.species.dedup();
+
+        self.kinds = names.iter().map(|name| {
+            self.species.iter().position(|species| species == name).expect("missing species")
+        }).collect();
+        self.counts = vec![0; self.species.len()];
+        for &kind in &self.kinds {
+            self.counts[kind] += 1;
+        }
+
+        self.origins.clear();
+        for msd in &mut self.msd {
+            *msd = vec![0.0; self.species.len()];
+        }
+        for samples in &mut self.samples {
+            *samples = 0;
+        }
+    }
+
+    fn write(&mut self, system: &System) {
+        let positions = self.positions(system);
+        if positions.len() != self.kinds.len() {
+            warn!("The number of particles changed, resetting the mean square displacement");
+            self.setup(system);
+        }
+
+        let step = system.step();
+        for (lag, &(origin, ref previous)) in self.origins.iter().rev().enumerate() {
+            let lag = lag + 1;
+            for (i, (position, previous)) in positions.iter().zip(previous).enumerate() {
+                let kind = self.kinds[i];
+                self.msd[lag][kind] += (position - previous).norm2() / self.counts[kind] as f64;
+            }
+            self.samples[lag] += 1;
+            self.times[lag] = (step - origin) as f64 * self.timestep;
+        }
+        self.samples[0] += 1;
+
+        self.origins.push_back((step, positions));
+        if self.origins.len() > self.lags {
+            let _ = self.origins.pop_front();
+        }
+    }
+
+    fn finish(&mut self, _: &System) {
+        if let Err(err) = self.write_msd() {
+            error!("Could not write to file '{}': {}", self.path.display(), err);
+        }
+    }
+
+    fn save_state(&self, writer: &mut CheckpointWriter) {
+        writer.write_usize(self.origins.len());
+        for &(step, ref positions) in &self.origins {
+            writer.write_u64(step);
+            writer.write_vectors(positions);
+        }
+        for (msd, (&samples, &time)) in self.msd.iter().zip(self.samples.iter().zip(&self.times)) {
+            writer.write_f64s(msd);
+            writer.write_usize(samples);
+            writer.write_f64(time);
+        }
+    }
+
+    fn restore_state(&mut self, reader: &mut CheckpointReader) -> Result<(), CheckpointError> {
+        // Each origin takes at least 16 bytes in the checkpoint
+        let norigins = try!(reader.read_count(16));
+        if norigins > self.lags {
+            return Err(CheckpointError::from("too many time origins in mean square displacement checkpoint"));
+        }
+        self.origins.clear();
+        for _ in 0..norigins {
+            let step = try!(reader.read_u64());
+            let positions = try!(reader.read_vectors());
+            if positions.len() != self.kinds.len() {
+                return Err(CheckpointError::from(format!(
+                    "wrong number of {} in mean square displacement checkpoint: expected {}, got {}",
+                    if self.molecules {"molecules"} else {"particles"}, self.kinds.len(), positions.len()
+                )));
+            }
+            self.origins.push_back((step, positions));
+        }
+
+        for lag in 0..self.lags + 1 {
+            let msd = try!(reader.read_f64s());
+            if msd.len() != self.species.len() {
+                return Err(CheckpointError::from("wrong number of species in mean square displacement checkpoint"));
+            }
+            self.msd[lag] = msd;
+            self.samples[lag] = try!(reader.read_usize());
+            self.times[lag] = try!(reader.read_f64());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate tempfile;
+    use self::tempfile::{NamedTempFile, NamedTempFileOptions};
+
+    use std::io::prelude::*;
+    use std::fs::File;
+
+    use super::*;
+    use sys::*;
+    use types::*;
+    use energy::*;
+    use sim::Ensemble;
+    use utils::unit_from;
+
+    fn testing_system() -> System {
+        let mut system = System::from_cell(UnitCell::cubic(10.0));;
+
+        system.add_particle(Particle::new("F"));
+        system[0].position = Vector3D::zero();
+
+        system.add_particle(Particle::new("F"));
+        system[1].position = Vector3D::new(1.3, 0.0, 0.0);
+
+        let harmonic = Box::new(Harmonic{
+            k: unit_from(300.0, "kJ/mol/A^2"),
+            x0: unit_from(1.2, "A")}
+        );
+        system.interactions_mut().add_pair("F", "F",
+            PairInteraction::new(harmonic, 5.0)
+        );
+        return system;
+    }
+
+    fn check_file_content(mut file: File, content: &str) {
+        let mut buffer = String::new();
+        let _ = file.read_to_string(&mut buffer).unwrap();
+
+        assert_eq!(buffer, content);
+    }
+
+    #[test]
+    fn trajectory() {
+        let tempfile = NamedTempFileOptions::new().suffix(".xyz").create().unwrap();
+        let system = testing_system();
+        {
+            let mut out = TrajectoryOutput::new(tempfile.path()).unwrap();
+            out.setup(&system);
+            out.write(&system);
+            out.finish(&system);
+        }
+
+        let content = "2
+Written by the chemfiles library
+F 0 0 0
+F 1.3 0 0
+";
+        let file = tempfile.reopen().unwrap();
+        check_file_content(file, content);
+    }
+
+    #[test]
+    fn trajectory_restart() {
+        let tempfile = NamedTempFileOptions::new().suffix(".xyz").create().unwrap();
+        let system = testing_system();
+        let frame = "2
+Written by the chemfiles library
+F 0 0 0
+F 1.3 0 0
+";
+        for _ in 0..2 {
+            let mut out = TrajectoryOutput::new(tempfile.path()).unwrap();
+            out.setup(&system);
+            out.write(&system);
+        }
+        // The file is replaced by a new simulation
+        check_file_content(tempfile.reopen().unwrap(), frame);
+
+        {
+            let mut out = TrajectoryOutput::new(tempfile.path()).unwrap();
+            out.set_restart();
+            out.setup(&system);
+            out.write(&system);
+        }
+        check_file_content(tempfile.reopen().unwrap(), &(String::from(frame) + frame));
+    }
+
+    #[test]
+    fn energy() {
+        let tempfile = NamedTempFile::new().unwrap();
+        let system = testing_system();
+        {
+            let mut out = EnergyOutput::new(tempfile.path()).unwrap();
+            out.setup(&system);
+            out.write(&system);
+            out.finish(&system);
+        }
+
+        let content = "\
+# Energy of the simulation (kJ/mol)
+# Step Potential Kinetic Total
+0 1.5000000000000027 0 1.5000000000000027
+";
+
+        let file = tempfile.reopen().unwrap();
+        check_file_content(file, content);
+    }
+
+    #[test]
+    fn cell() {
+        let tempfile = NamedTempFile::new().unwrap();
+        let system = testing_system();
+        {
+            let mut out = CellOutput::new(tempfile.path()).unwrap();
+            out.setup(&system);
+            out.write(&system);
+            out.finish(&system);
+        }
+
+        let content = "\
+# Unit cell of the simulation
+# Step A/Å B/Å C/Å α/deg β/deg γ/deg
+0 10 10 10 90 90 90
+";
+
+        let file = tempfile.reopen().unwrap();
+        check_file_content(file, content);
+    }
+
+    #[test]
+    fn properties() {
+        let tempfile = NamedTempFile::new().unwrap();
+        let system = testing_system();
+        {
+            let mut out = PropertiesOutput::new(tempfile.path()).unwrap();
+            out.setup(&system);
+            out.write(&system);
+            out.finish(&system);
+        }
+
+        let content = "\
+# Physical properties of the simulation
+# Step Volume/A^3 Temperature/K Pressure/bar
+0 1000 0 -215.87004181115455
+";
+
+        let file = tempfile.reopen().unwrap();
+        check_file_content(file, content);
+    }
+
+    #[test]
+    fn energy_summary() {
+        let tempfile = NamedTempFile::new().unwrap();
+        let summary = NamedTempFile::new().unwrap();
+        let system = testing_system();
+        {
+            let mut out = EnergyOutput::new(tempfile.path()).unwrap();
+            out.set_summary(summary.path()).unwrap();
+            out.setup(&system);
+            out.write(&system);
+            out.write(&system);
+            assert_eq!(out.statistics().get(0).count(), 2);
+            assert_relative_eq!(out.statistics().get(0).mean(), 1.5000000000000027);
+            out.finish(&system);
+        }
+
+        let content = "\
+# Energy statistics
+# Quantity Mean Error StdDev BlockSize Samples Unit
+Potential 1.5000000000000027 0 0 1 2 kJ/mol
+Kinetic 0 0 0 1 2 kJ/mol
+Total 1.5000000000000027 0 0 1 2 kJ/mol
+";
+
+        let file = summary.reopen().unwrap();
+        check_file_content(file, content);
+    }
+
+    #[test]
+    fn normal_modes() {
+        let tempfile = NamedTempFile::new().unwrap();
+        let system = testing_system();
+        {
+            let mut out = NormalModesOutput::new(tempfile.path()).unwrap();
+            out.setup(&system);
+            out.write(&system);
+            out.finish(&system);
+        }
+
+        let mut buffer = String::new();
+        let _ = tempfile.reopen().unwrap().read_to_string(&mut buffer).unwrap();
+        let lines = buffer.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 8);
+        assert_eq!(lines[0], "# Normal modes of the final configuration");
+        assert_eq!(lines[1], "# Mode Wavenumber/cm^-1");
+        assert!(lines[7].starts_with("5 "));
+    }
+
+    #[test]
+    fn rdf() {
+        // Simple cubic lattice with a spacing of 1 A
+        let mut system = System::from_cell(UnitCell::cubic(6.0));
+        for i in 0..6 {
+            for j in 0..6 {
+                for k in 0..6 {
+                    let name = if (i + j + k) % 2 == 0 {"Ar"} else {"Kr"};
+                    let mut particle = Particle::new(name);
+                    particle.position = Vector3D::new(i as f64, j as f64, k as f64);
+                    system.add_particle(particle);
+                }
+            }
+        }
+
+        let tempfile = NamedTempFile::new().unwrap();
+        {
+            let mut out = RdfOutput::new(tempfile.path(), 2.0, 0.1).unwrap();
+            out.setup(&system);
+            out.write(&system);
+            out.write(&system);
+            out.finish(&system);
+        }
+
+        let mut buffer = String::new();
+        let _ = tempfile.reopen().unwrap().read_to_string(&mut buffer).unwrap();
+        let lines = buffer.lines().skip(3).map(|line| {
+            line.split_whitespace().map(|value| value.parse::<f64>().unwrap()).collect::<Vec<_>>()
+        }).collect::<Vec<_>>();
+        assert_eq!(lines.len(), 20);
+
+        assert_relative_eq!(lines[9][0], 0.95);
+        assert_eq!(lines[9][1], 0.0);
+        assert_eq!(lines[9][2], 0.0);
+        // First neighbors
+        let shell = 4.0 / 3.0 * PI * (1.1f64.powi(3) - 1.0);
+        assert_relative_eq!(lines[10][1], 6.0 * 216.0 / (215.0 * shell));
+        assert_eq!(lines[10][2], 6.0);
+        // Second neighbors
+        assert_eq!(lines[14][2], 18.0);
+
+        // Between different particles
+        let tempfile = NamedTempFile::new().unwrap();
+        {
+            let mut out = RdfOutput::new(tempfile.path(), 2.0, 0.1).unwrap();
+            out.set_pair("Ar", "Kr");
+            out.setup(&system);
+            out.write(&system);
+            out.finish(&system);
+        }
+
+        let mut buffer = String::new();
+        let _ = tempfile.reopen().unwrap().read_to_string(&mut buffer).unwrap();
+        let lines = buffer.lines().skip(3).map(|line| {
+            line.split_whitespace().map(|value| value.parse::<f64>().unwrap()).collect::<Vec<_>>()
+        }).collect::<Vec<_>>();
+        // All the first neighbors of Ar are Kr, and none of the second
+        assert_eq!(lines[10][2], 6.0);
+        assert_eq!(lines[14][2], 6.0);
+    }
+
+    #[test]
+    fn msd() {
+        let mut system = System::from_cell(UnitCell::cubic(10.0));
+        system.add_particle(Particle::new("Ar"));
+        system.add_particle(Particle::new("Kr"));
+        system.add_particle(Particle::new("Kr"));
+        system[2].position = Vector3D::new(5.0, 5.0, 5.0);
+
+        let tempfile = NamedTempFile::new().unwrap();
+        {
+            let mut out = MsdOutput::new(tempfile.path(), 2.0, 4).unwrap();
+            out.setup(&system);
+            for _ in 0..10 {
+                out.write(&system);
+                // Ar is moving at constant velocity through the periodic
+                // boundaries, and the first Kr oscillates
+                system[0].position[0] += 3.0;
+                system.wrap_molecule(0);
+                system[1].position[1] = if system.step() % 2 == 0 {1.0} else {0.0};
+                system.increment_step();
+            }
+            out.finish(&system);
+        }
+
+        let mut buffer = String::new();
+        let _ = tempfile.reopen().unwrap().read_to_string(&mut buffer).unwrap();
+        let lines = buffer.lines().collect::<Vec<_>>();
+        assert_eq!(lines[0], "# Mean square displacement");
+        assert!(lines[1].starts_with("# Self-diffusion coefficients (A^2/fs): Ar = "));
+        assert_eq!(lines[2], "# Time/fs Ar/A^2 Kr/A^2");
+
+        let values = lines[3..].iter().map(|line| {
+            line.split_whitespace().map(|value| value.parse::<f64>().unwrap()).collect::<Vec<_>>()
+        }).collect::<Vec<_>>();
+        assert_eq!(values.len(), 5);
+        for (lag, values) in values.iter().enumerate() {
+            let lag = lag as f64;
+            assert_eq!(values[0], 2.0 * lag);
+            assert_relative_eq!(values[1], 9.0 * lag * lag, epsilon=1e-10);
+            // Only one of the two Kr is moving, by 1 A every odd lag
+            let expected = if lag % 2.0 == 1.0 {0.5} else {0.0};
+            assert_relative_eq!(values[2], expected, epsilon=1e-10);
+        }
+    }
+
+    /// Move the particles in the `system` and increment the step, changing
+    /// the periodic images of the particles.
+    fn evolve(system: &mut System) {
+        let step = system.step() as f64;
+        for (i, particle) in system.iter_mut().enumerate() {
+            let delta = Vector3D::new(0.7 + 0.1 * i as f64, f64::sin(step + i as f64), -0.3);
+            particle.position += delta;
+            particle.velocity = 1e-3 * delta;
+        }
+        for i in 0..system.molecules().len() {
+            system.wrap_molecule(i);
+        }
+        system.increment_step();
+    }
+
+    /// Check that an output restored from a checkpoint in the middle of a
+    /// simulation writes the same file as an output running the whole
+    /// simulation, even if the first simulation continued after the
+    /// checkpoint.
+    fn check_restart<F: Fn(&Path) -> Box<Output>>(create: F) {
+        let mut system = System::from_cell(UnitCell::cubic(10.0));
+        for (i, &name) in ["Na", "Cl", "Na", "Cl"].iter().enumerate() {
+            let mut particle = Particle::new(name);
+            particle.position = Vector3D::new(1.5 * i as f64, 0.5 * i as f64, 0.0);
+            particle.charge = if name == "Na" {1.0} else {-1.0};
+            system.add_particle(particle);
+        }
+        let ensemble = Ensemble::NVT(unit_from(300.0, "K"));
+
+        let mut reference = NamedTempFile::new().unwrap();
+        // Existing files are replaced by new simulations
+        for _ in 0..1000 {
+            writeln!(reference, "previous simulation").unwrap();
+        }
+        {
+            let mut system = system.clone();
+            let mut output = create(reference.path());
+            output.set_ensemble(ensemble);
+            output.setup(&system);
+            for _ in 0..20 {
+                evolve(&mut system);
+                output.write(&system);
+            }
+            output.finish(&system);
+        }
+
+        let restarted = NamedTempFile::new().unwrap();
+        {
+            let mut system = system.clone();
+            let mut writer = CheckpointWriter::new();
+            {
+                let mut output = create(restarted.path());
+                output.set_ensemble(ensemble);
+                output.setup(&system);
+                for _ in 0..10 {
+                    evolve(&mut system);
+                    output.write(&system);
+                }
+                output.save_state(&mut writer);
+
+                let mut system = system.clone();
+                for _ in 0..3 {
+                    evolve(&mut system);
+                    output.write(&system);
+                }
+            }
+
+            let mut output = create(restarted.path());
+            output.set_ensemble(ensemble);
+            output.set_restart();
+            output.setup(&system);
+            output.restore_state(&mut CheckpointReader::new(writer.as_bytes())).unwrap();
+            for _ in 0..10 {
+                evolve(&mut system);
+                output.write(&system);
+            }
+            output.finish(&system);
+        }
+
+        let mut expected = String::new();
+        let _ = reference.reopen().unwrap().read_to_string(&mut expected).unwrap();
+        let mut content = String::new();
+        let _ = restarted.reopen().unwrap().read_to_string(&mut content).unwrap();
+        assert_eq!(content, expected);
+    }
+
+    #[test]
+    fn restart() {
+        check_restart(|path| Box::new(CellOutput::new(path).unwrap()));
+        check_restart(|path| Box::new(EnergyOutput::new(path).unwrap()));
+        check_restart(|path| Box::new(PropertiesOutput::new(path).unwrap()));
+        check_restart(|path| Box::new(EnergyBreakdownOutput::new(path).unwrap()));
+        check_restart(|path| Box::new(CustomOutput::new(path, &["step", "pressure / natoms"]).unwrap()));
+        check_restart(|path| Box::new(RdfOutput::new(path, 4.0, 0.5).unwrap()));
+        check_restart(|path| Box::new(MsdOutput::new(path, 1.0, 4).unwrap()));
+        check_restart(|path| Box::new(VacfOutput::new(path, 1.0, 3).unwrap()));
+        check_restart(|path| Box::new(ViscosityOutput::new(path, 1.0, 3).unwrap()));
+        check_restart(|path| Box::new(ConductivityOutput::new(path, 1.0, 3).unwrap()));
+        check_restart(|path| Box::new(StructureFactorOutput::new(path, 2.0, 0.5).unwrap()));
+        check_restart(|path| Box::new(FluctuationsOutput::new(path).unwrap()));
+        check_restart(|path| Box::new(DensityProfileOutput::new(path, 2, 5).unwrap()));
+    }
+}
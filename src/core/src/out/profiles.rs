@@ -0,0 +1,353 @@
+// Lumol, an extensible molecular simulation engine
+// Copyright (C) 2015-2016 Lumol's contributors — BSD license
+
+//! Density profiles along a unit cell axis
+use std::io::prelude::*;
+use std::io;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::f64::consts::PI;
+use std::cmp;
+
+use consts::ELCC;
+use utils;
+use sys::System;
+use checkpoint::{CheckpointWriter, CheckpointReader, CheckpointError};
+use super::Output;
+
+/// The `DensityProfileOutput` computes the number, mass and charge density
+/// profiles of each species along one axis of the unit cell, averaged over
+/// the simulation, and the electrostatic potential profile. The file is
+/// written at the end of the simulation.
+///
+/// The profiles are histograms of the fractional coordinate of the particles
+/// along the axis, so that they stay meaningful when the unit cell changes
+/// during the simulation. The first column of the file contains the
+/// fractional coordinate `s` of the bins centers, and the second one the
+/// corresponding distance `z = s L`, using the average thickness `L` of the
+/// cell along the axis. The next columns contain the number densities (in
+/// A^-3), the mass densities (in kg/m^3) and the charge densities (in e/A^3)
+/// of each species and of the whole system. The last column contains the
+/// electrostatic potential (in V), obtained by integrating twice the Poisson
+/// equation, `φ(z) = - 1/ε0 ∫ (z - z') ρq(z') dz'`, with `φ = 0` and a zero
+/// electric field at `s = 0`.
+pub struct DensityProfileOutput {
+    file: File,
+    path: PathBuf,
+    /// Index of the cell vector along which the profiles are computed
+    axis: usize,
+    /// Number of bins in the profiles
+    nbins: usize,
+    /// Names of the species
+    species: Vec<String>,
+    /// Accumulated number density of each species, in each bin
+    number: Vec<Vec<f64>>,
+    /// Accumulated mass density of each species, in each bin
+    mass: Vec<Vec<f64>>,
+    /// Accumulated charge density of each species, in each bin
+    charge: Vec<Vec<f64>>,
+    /// Accumulated thickness of the cell along the axis
+    thickness: f64,
+    /// Number of accumulated steps
+    steps: usize,
+}
+
+impl DensityProfileOutput {
+    /// Create a new `DensityProfileOutput` writing to `filename`, computing
+    /// the profiles along the cell vector with index `axis` (0 for `a`, 1 for
+    /// `b` and 2 for `c`), using `bins` bins. The file is replaced if it
+    /// already exists.
+    ///
+    /// # Panics
+    ///
+    /// If `axis` is larger than 2 or if `bins` is zero.
+    pub fn new<P: AsRef<Path>>(filename: P, axis: usize, bins: usize) -> Result<DensityProfileOutput, io::Error> {
+        assert!(axis < 3, "the axis must be 0, 1 or 2 in density profile");
+        assert!(bins > 0, "the number of bins must be positive in density profile");
+        Ok(DensityProfileOutput {
+            file: try!(File::create(filename.as_ref())),
+            path: filename.as_ref().to_owned(),
+            axis: axis,
+            nbins: bins,
+            species: Vec::new(),
+            number: Vec::new(),
+            mass: Vec::new(),
+            charge: Vec::new(),
+            thickness: 0.0,
+            steps: 0,
+        })
+    }
+
+    /// Get the path of the file written by this output
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Get the index of the cell vector used as axis for the profiles
+    pub fn axis(&self) -> usize {
+        self.axis
+    }
+
+    /// Get the number of bins in the profiles
+    pub fn bins(&self) -> usize {
+        self.nbins
+    }
+
+    /// Get the index of the species with the given `name`, adding it if
+    /// needed.
+    fn species_index(&mut self, name: &str) -> usize {
+        if let Some(index) = self.species.iter().position(|species| species == name) {
+            return index;
+        }
+        let nbins = self.nbins;
+        self.species.push(String::from(name));
+        self.number.push(vec![0.0; nbins]);
+        self.mass.push(vec![0.0; nbins]);
+        self.charge.push(vec![0.0; nbins]);
+        return self.species.len() - 1;
+    }
+
+    /// Get the averaged profiles, for each species and then for the whole
+    /// system
+    fn average(&self, profiles: &[Vec<f64>]) -> Vec<Vec<f64>> {
+        let nbins = self.nbins;
+        let steps = self.steps as f64;
+        let mut average = profiles.iter().map(|profile| {
+            profile.iter().map(|value| value / steps).collect::<Vec<_>>()
+        }).collect::<Vec<_>>();
+        let total = (0..nbins).map(|bin| {
+            average.iter().fold(0.0, |sum, profile| sum + profile[bin])
+        }).collect();
+        average.push(total);
+        return average;
+    }
+
+    /// Get the electrostatic potential corresponding to the averaged charge
+    /// density `charge`, in internal units.
+    fn potential(&self, charge: &[f64]) -> Vec<f64> {
+        let dz = self.thickness / self.steps as f64 / self.nbins as f64;
+        // ELCC is 4 π ε0 in internal units
+        let factor = 4.0 * PI / ELCC;
+        (0..charge.len()).map(|i| {
+            let integral = (0..i).fold(0.0, |sum, j| sum + (i - j) as f64 * dz * charge[j] * dz);
+            -factor * integral
+        }).collect()
+    }
+
+    fn write_profiles(&mut self) -> Result<(), io::Error> {
+        let number = self.average(&self.number);
+        let mass = self.average(&self.mass);
+        let charge = self.average(&self.charge);
+        let potential = self.potential(&charge[charge.len() - 1]);
+
+        let nbins = self.nbins;
+        let thickness = self.thickness / self.steps as f64;
+        let axis = ["a", "b", "c"][self.axis];
+        try!(writeln!(&mut self.file, "# Density profiles along the {} axis", axis));
+        try!(writeln!(
+            &mut self.file, "# Number density in A^-3, mass density in kg/m^3, charge density in e/A^3 and potential in V"
+        ));
+        try!(write!(&mut self.file, "# s z/A"));
+        for prefix in &["N", "M", "Q"] {
+            for species in &self.species {
+                try!(write!(&mut self.file, " {}({})", prefix, species));
+            }
+            try!(write!(&mut self.file, " {}", prefix));
+        }
+        try!(writeln!(&mut self.file, " Phi"));
+
+        for bin in 0..nbins {
+            let s = (bin as f64 + 0.5) / nbins as f64;
+            try!(write!(&mut self.file, "{} {}", s, utils::unit_to(s * thickness, "A")));
+            for profile in &number {
+                try!(write!(&mut self.file, " {}", utils::unit_to(profile[bin], "A^-3")));
+            }
+            for profile in &mass {
+                try!(write!(&mut self.file, " {}", utils::unit_to(profile[bin], "kg/m^3")));
+            }
+            for profile in &charge {
+                try!(write!(&mut self.file, " {}", utils::unit_to(profile[bin], "e/A^3")));
+            }
+            try!(writeln!(&mut self.file, " {}", utils::unit_to(potential[bin], "V")));
+        }
+        Ok(())
+    }
+}
+
+impl Output for DensityProfileOutput {
+    fn setup(&mut self, system: &System) {
+        if system.cell().is_infinite() {
+            fatal_error!("Can not compute density profiles with an infinite cell");
+        }
+
+        let mut names = system.iter().map(|particle| String::from(particle.name())).collect::<Vec<_>>();
+        names.sort();
+        names.dedup();
+        self.species = names;
+        let nspecies = self.species.len();
+        let nbins = self.nbins;
+        self.number = vec![vec![0.0; nbins]; nspecies];
+        self.mass = vec![vec![0.0; nbins]; nspecies];
+        self.charge = vec![vec![0.0; nbins]; nspecies];
+        self.thickness = 0.0;
+        self.steps = 0;
+    }
+
+    fn write(&mut self, system: &System) {
+        let cell = system.cell();
+        let nbins = self.nbins;
+        let volume = cell.volume() / nbins as f64;
+
+        // The thickness of the cell along the axis is the distance between
+        // the planes of the other two cell vectors
+        let (rec_a, rec_b, rec_c) = cell.reciprocal_vectors();
+        let reciprocal = [rec_a, rec_b, rec_c][self.axis];
+        self.thickness += 2.0 * PI / reciprocal.norm();
+        self.steps += 1;
+
+        for particle in system {
+            let s = cell.fractional(&particle.position)[self.axis];
+            let s = s - f64::floor(s);
+            let bin = cmp::min((s * nbins as f64).floor() as usize, nbins - 1);
+
+            let index = self.species_index(particle.name());
+            self.number[index][bin] += 1.0 / volume;
+            self.mass[index][bin] += particle.mass / volume;
+            self.charge[index][bin] += particle.charge / volume;
+        }
+    }
+
+    fn finish(&mut self, _: &System) {
+        if self.steps == 0 {
+            return;
+        }
+
+        if let Err(err) = self.write_profiles() {
+            error!("Could not write to file '{}': {}", self.path.display(), err);
+        }
+    }
+
+    fn save_state(&self, writer: &mut CheckpointWriter) {
+        writer.write_usize(self.species.len());
+        for (i, species) in self.species.iter().enumerate() {
+            writer.write_str(species);
+            writer.write_f64s(&self.number[i]);
+            writer.write_f64s(&self.mass[i]);
+            writer.write_f64s(&self.charge[i]);
+        }
+        writer.write_f64(self.thickness);
+        writer.write_usize(self.steps);
+    }
+
+    fn restore_state(&mut self, reader: &mut CheckpointReader) -> Result<(), CheckpointError> {
+        // Each species takes at least 32 bytes in the checkpoint
+        let nspecies = try!(reader.read_count(32));
+        self.species.clear();
+        self.number.clear();
+        self.mass.clear();
+        self.charge.clear();
+        for _ in 0..nspecies {
+            self.species.push(try!(reader.read_string()));
+            let number = try!(reader.read_f64s());
+            let mass = try!(reader.read_f64s());
+            let charge = try!(reader.read_f64s());
+            if number.len() != self.nbins || mass.len() != self.nbins || charge.len() != self.nbins {
+                return Err(CheckpointError::from("wrong number of bins in density profile checkpoint"));
+            }
+            self.number.push(number);
+            self.mass.push(mass);
+            self.charge.push(charge);
+        }
+        self.thickness = try!(reader.read_f64());
+        self.steps = try!(reader.read_usize());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate tempfile;
+    use self::tempfile::NamedTempFile;
+
+    use std::io::prelude::*;
+    use std::f64::consts::PI;
+
+    use super::*;
+    use consts::ELCC;
+    use sys::*;
+    use types::{Matrix3, One};
+    use utils::{unit_from, unit_to};
+
+    fn read_file(file: &NamedTempFile) -> Vec<String> {
+        let mut buffer = String::new();
+        let _ = file.reopen().unwrap().read_to_string(&mut buffer).unwrap();
+        buffer.lines().map(String::from).collect()
+    }
+
+    fn parse(line: &str) -> Vec<f64> {
+        line.split_whitespace().map(|value| value.parse::<f64>().unwrap()).collect()
+    }
+
+    fn testing_system() -> System {
+        let mut system = System::from_cell(UnitCell::cubic(10.0));
+        system.add_particle(Particle::new("Na"));
+        system[0].position[2] = 2.5;
+        system[0].charge = 1.0;
+        system.add_particle(Particle::new("Cl"));
+        system[1].position[2] = 17.5;
+        system[1].charge = -1.0;
+        return system;
+    }
+
+    #[test]
+    fn profiles() {
+        let tempfile = NamedTempFile::new().unwrap();
+        let mut system = testing_system();
+        {
+            let mut out = DensityProfileOutput::new(tempfile.path(), 2, 10).unwrap();
+            out.setup(&system);
+            out.write(&system);
+            // The profiles are computed in fractional coordinates
+            system.cell_mut().scale_mut(2.0 * Matrix3::one());
+            for particle in system.iter_mut() {
+                particle.position = 2.0 * particle.position;
+            }
+            out.write(&system);
+            out.finish(&system);
+        }
+
+        let lines = read_file(&tempfile);
+        assert_eq!(lines[0], "# Density profiles along the c axis");
+        assert_eq!(lines[2], "# s z/A N(Cl) N(Na) N M(Cl) M(Na) M Q(Cl) Q(Na) Q Phi");
+        assert_eq!(lines.len(), 13);
+
+        let values = lines.iter().skip(3).map(|line| parse(line)).collect::<Vec<_>>();
+        assert_relative_eq!(values[0][0], 0.05);
+        assert_relative_eq!(values[0][1], 0.75);
+
+        // Average of the density in bins of 100 A^3 and 800 A^3
+        let density = 0.5 * (1.0 / 100.0 + 1.0 / 800.0);
+        assert_relative_eq!(values[2][3], density, max_relative=1e-9);
+        assert_relative_eq!(values[2][4], density, max_relative=1e-9);
+        assert_eq!(values[2][2], 0.0);
+        assert_relative_eq!(values[7][2], density, max_relative=1e-9);
+        assert_relative_eq!(values[7][4], density, max_relative=1e-9);
+
+        let mass = unit_to(Particle::new("Na").mass * unit_from(density, "A^-3"), "kg/m^3");
+        assert_relative_eq!(values[2][6], mass, max_relative=1e-9);
+        assert_relative_eq!(values[2][10], density, max_relative=1e-9);
+        assert_relative_eq!(values[7][10], -density, max_relative=1e-9);
+
+        // Potential between two charged planes
+        let dz = 15.0 / 10.0;
+        let field = -4.0 * PI / ELCC * density * dz;
+        for bin in 0..3 {
+            assert_eq!(values[bin][11], 0.0);
+        }
+        let expected = unit_to(field * 5.0 * dz, "V");
+        assert_relative_eq!(values[7][11], expected, max_relative=1e-9);
+        assert_relative_eq!(values[9][11], expected, max_relative=1e-9);
+        let expected = unit_to(field * dz, "V");
+        assert_relative_eq!(values[3][11], expected, max_relative=1e-9);
+    }
+}
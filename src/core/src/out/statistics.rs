@@ -0,0 +1,415 @@
+// Lumol, an extensible molecular simulation engine
+// Copyright (C) 2015-2016 Lumol's contributors — BSD license
+
+//! Running averages and statistical error estimates of scalar quantities
+use std::io::prelude::*;
+use std::io;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+
+use utils;
+use checkpoint::{Checkpoint, CheckpointWriter, CheckpointReader, CheckpointError};
+
+/// Minimal number of blocks for a blocking level to be used in the error
+/// estimate
+const MIN_BLOCKS: usize = 16;
+
+/// Running statistics for one level of the blocking transformation
+#[derive(Clone, Debug)]
+struct Level {
+    /// Number of values in this level
+    count: usize,
+    /// Running mean of the values
+    mean: f64,
+    /// Running sum of the squared differences to the mean
+    m2: f64,
+    /// Value waiting for a partner to be averaged and passed to the next level
+    pending: Option<f64>,
+}
+
+impl Level {
+    fn new() -> Level {
+        Level {
+            count: 0,
+            mean: 0.0,
+            m2: 0.0,
+            pending: None,
+        }
+    }
+
+    fn add(&mut self, value: f64) {
+        self.count += 1;
+        let delta = value - self.mean;
+        self.mean += delta / self.count as f64;
+        self.m2 += delta * (value - self.mean);
+    }
+
+    /// Estimate of the standard error of the mean, assuming the values in
+    /// this level are uncorrelated.
+    fn error(&self) -> f64 {
+        if self.count < 2 {
+            return 0.0;
+        }
+        let n = self.count as f64;
+        f64::sqrt(self.m2 / (n * (n - 1.0)))
+    }
+}
+
+/// Running mean, variance and standard error of a scalar quantity.
+///
+/// The standard error of the mean is estimated with the blocking method of
+/// Flyvbjerg and Petersen (J. Chem. Phys. 91, 461 (1989)), which accounts for
+/// the correlations between successive values. The values are averaged in
+/// blocks of increasing size (1, 2, 4, ...) on the fly, and the block size is
+/// selected automatically as the first one where the error estimate reaches
+/// a plateau.
+#[derive(Clone, Debug)]
+pub struct BlockAverage {
+    /// All the levels of the blocking transformation, the first one
+    /// containing all the values.
+    levels: Vec<Level>,
+}
+
+impl BlockAverage {
+    /// Create a new empty `BlockAverage`
+    pub fn new() -> BlockAverage {
+        BlockAverage {
+            levels: vec![Level::new()],
+        }
+    }
+
+    /// Add a new `value` to this average
+    pub fn add(&mut self, value: f64) {
+        let mut value = value;
+        let mut index = 0;
+        loop {
+            self.levels[index].add(value);
+            match self.levels[index].pending.take() {
+                None => {
+                    self.levels[index].pending = Some(value);
+                    break;
+                }
+                Some(previous) => {
+                    value = 0.5 * (previous + value);
+                    index += 1;
+                    if index == self.levels.len() {
+                        self.levels.push(Level::new());
+                    }
+                }
+            }
+        }
+    }
+
+    /// Get the number of values in this average
+    pub fn count(&self) -> usize {
+        self.levels[0].count
+    }
+
+    /// Get the mean of all the values
+    pub fn mean(&self) -> f64 {
+        self.levels[0].mean
+    }
+
+    /// Get the variance of all the values
+    pub fn variance(&self) -> f64 {
+        let level = &self.levels[0];
+        if level.count < 2 {
+            0.0
+        } else {
+            level.m2 / (level.count - 1) as f64
+        }
+    }
+
+    /// Get the standard deviation of all the values
+    pub fn std_dev(&self) -> f64 {
+        f64::sqrt(self.variance())
+    }
+
+    /// Get the block averaged standard error of the mean. If there are not
+    /// enough values to reach a plateau in the error estimate, the largest
+    /// estimate is used.
+    pub fn error(&self) -> f64 {
+        self.levels[self.selected_level()].error()
+    }
+
+    /// Get the block size used for the error estimate in `error`
+    pub fn block_size(&self) -> usize {
+        1 << self.selected_level()
+    }
+
+    /// Get the index of the blocking level used for the error estimate
+    fn selected_level(&self) -> usize {
+        let usable = self.levels.iter().take_while(|level| level.count >= MIN_BLOCKS).count();
+        if usable == 0 {
+            return 0;
+        }
+
+        for index in 0..(usable - 1) {
+            let level = &self.levels[index];
+            let error = level.error();
+            // Uncertainty of the error estimate in this level
+            let uncertainty = error / f64::sqrt(2.0 * (level.count - 1) as f64);
+            if self.levels[index + 1].error() <= error + uncertainty {
+                return index;
+            }
+        }
+
+        (0..usable).fold(0, |max, index| {
+            if self.levels[index].error() > self.levels[max].error() {index} else {max}
+        })
+    }
+
+    /// Remove all the values in this average
+    pub fn clear(&mut self) {
+        self.levels = vec![Level::new()];
+    }
+}
+
+impl Default for BlockAverage {
+    fn default() -> BlockAverage {
+        BlockAverage::new()
+    }
+}
+
+impl Checkpoint for BlockAverage {
+    fn save(&self, writer: &mut CheckpointWriter) {
+        writer.write_usize(self.levels.len());
+        for level in &self.levels {
+            writer.write_usize(level.count);
+            writer.write_f64(level.mean);
+            writer.write_f64(level.m2);
+            writer.write_bool(level.pending.is_some());
+            writer.write_f64(level.pending.unwrap_or(0.0));
+        }
+    }
+
+    fn restore(&mut self, reader: &mut CheckpointReader) -> Result<(), CheckpointError> {
+        let size = try!(reader.read_usize());
+        if size == 0 {
+            return Err(CheckpointError::from("missing levels in block average"));
+        }
+        let mut levels = Vec::with_capacity(size);
+        for _ in 0..size {
+            let mut level = Level::new();
+            level.count = try!(reader.read_usize());
+            level.mean = try!(reader.read_f64());
+            level.m2 = try!(reader.read_f64());
+            let pending = try!(reader.read_bool());
+            let value = try!(reader.read_f64());
+            if pending {
+                level.pending = Some(value);
+            }
+            levels.push(level);
+        }
+        self.levels = levels;
+        Ok(())
+    }
+}
+
+/// Statistics for a set of named scalar quantities, reported at the end of a
+/// simulation in the logs and optionally in a summary file.
+pub struct Statistics {
+    /// Name, unit and average of all the quantities
+    quantities: Vec<(String, String, BlockAverage)>,
+    /// Summary file
+    summary: Option<(File, PathBuf)>,
+}
+
+impl Statistics {
+    /// Create new `Statistics` for the quantities with the given names and
+    /// units, as `(name, unit)` pairs.
+    pub fn new(quantities: &[(&str, &str)]) -> Statistics {
+        Statistics {
+            quantities: quantities.iter().map(|&(name, unit)| {
+                (String::from(name), String::from(unit), BlockAverage::new())
+            }).collect(),
+            summary: None,
+        }
+    }
+
+    /// Write the statistics to the file at `path` when reporting them. The
+    /// file is replaced if it already exists.
+    pub fn set_summary<P: AsRef<Path>>(&mut self, path: P) -> Result<(), io::Error> {
+        let file = try!(File::create(path.as_ref()));
+        self.summary = Some((file, path.as_ref().to_owned()));
+        Ok(())
+    }
+
+    /// Get the path of the summary file, if any
+    pub fn summary(&self) -> Option<&Path> {
+        self.summary.as_ref().map(|&(_, ref path)| &**path)
+    }
+
+    /// Add a new set of `values` for all the quantities, in internal units.
+    /// The values are converted to the quantities units.
+    ///
+    /// # Panics
+    ///
+    /// If the number of values is not the number of quantities.
+    pub fn add(&mut self, values: &[f64]) {
+        assert_eq!(values.len(), self.quantities.len(), "wrong number of values in statistics");
+        for (&mut (_, ref unit, ref mut average), &value) in self.quantities.iter_mut().zip(values) {
+            average.add(utils::unit_to(value, unit));
+        }
+    }
+
+    /// Get the average for the quantity at index `i`, in the quantity unit.
+    pub fn get(&self, i: usize) -> &BlockAverage {
+        &self.quantities[i].2
+    }
+
+    /// Remove all the values in these statistics
+    pub fn clear(&mut self) {
+        for &mut (_, _, ref mut average) in &mut self.quantities {
+            average.clear();
+        }
+    }
+
+    /// Report the statistics in the logs and in the summary file, using the
+    /// given `title`.
+    pub fn report(&mut self, title: &str) {
+        if self.quantities.is_empty() || self.quantities[0].2.count() == 0 {
+            return;
+        }
+
+        info!("{}", title);
+        for &(ref name, ref unit, ref average) in &self.quantities {
+            info!("    <{}> = {} ± {} {}", name, average.mean(), average.error(), unit);
+        }
+
+        if let Some((ref mut file, ref path)) = self.summary {
+            if let Err(err) = write_summary(file, title, &self.quantities) {
+                error!("Could not write to file '{}': {}", path.display(), err);
+            }
+        }
+    }
+}
+
+/// Only the averages are saved in the checkpoint of `Statistics`, the
+/// quantities and the summary file are not.
+impl Checkpoint for Statistics {
+    fn save(&self, writer: &mut CheckpointWriter) {
+        writer.write_usize(self.quantities.len());
+        for &(_, _, ref average) in &self.quantities {
+            average.save(writer);
+        }
+    }
+
+    fn restore(&mut self, reader: &mut CheckpointReader) -> Result<(), CheckpointError> {
+        if try!(reader.read_usize()) != self.quantities.len() {
+            return Err(CheckpointError::from("wrong number of quantities in statistics"));
+        }
+        for &mut (_, _, ref mut average) in &mut self.quantities {
+            try!(average.restore(reader));
+        }
+        Ok(())
+    }
+}
+
+fn write_summary(file: &mut File, title: &str, quantities: &[(String, String, BlockAverage)]) -> Result<(), io::Error> {
+    try!(writeln!(file, "# {}", title));
+    try!(writeln!(file, "# Quantity Mean Error StdDev BlockSize Samples Unit"));
+    for &(ref name, ref unit, ref average) in quantities {
+        try!(writeln!(
+            file, "{} {} {} {} {} {} {}",
+            name, average.mean(), average.error(), average.std_dev(),
+            average.block_size(), average.count(), unit
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use checkpoint::{Checkpoint, CheckpointWriter, CheckpointReader};
+    use rand::{Rng, SeedableRng, XorShiftRng};
+
+    #[test]
+    fn mean_variance() {
+        let mut average = BlockAverage::new();
+        for &value in &[1.0, 2.0, 3.0, 4.0] {
+            average.add(value);
+        }
+        assert_eq!(average.count(), 4);
+        assert_eq!(average.mean(), 2.5);
+        assert_relative_eq!(average.variance(), 5.0 / 3.0);
+        assert_relative_eq!(average.error(), f64::sqrt(5.0 / 12.0));
+
+        average.clear();
+        assert_eq!(average.count(), 0);
+        assert_eq!(average.error(), 0.0);
+    }
+
+    #[test]
+    fn uncorrelated() {
+        let mut rng = XorShiftRng::from_seed([2015, 42, 3, 12]);
+        let mut average = BlockAverage::new();
+        let n = 100000;
+        for _ in 0..n {
+            average.add(rng.next_f64());
+        }
+
+        assert_relative_eq!(average.mean(), 0.5, epsilon=1e-2);
+        assert_relative_eq!(average.variance(), 1.0 / 12.0, max_relative=1e-2);
+        let expected = f64::sqrt(1.0 / 12.0 / n as f64);
+        assert_relative_eq!(average.error(), expected, max_relative=0.1);
+        assert!(average.block_size() <= 4);
+    }
+
+    #[test]
+    fn correlated() {
+        // Discrete AR(1) process with unit variance
+        let decay: f64 = 0.9;
+        let mut rng = XorShiftRng::from_seed([2015, 42, 3, 12]);
+        let mut average = BlockAverage::new();
+        let mut value = 0.0;
+        let n = 200000;
+        for _ in 0..n {
+            let noise = f64::sqrt(12.0) * (rng.next_f64() - 0.5);
+            value = decay * value + f64::sqrt(1.0 - decay * decay) * noise;
+            average.add(value);
+        }
+
+        let naive = average.std_dev() / f64::sqrt(n as f64);
+        let expected = naive * f64::sqrt((1.0 + decay) / (1.0 - decay));
+        assert_relative_eq!(average.error(), expected, max_relative=0.3);
+        assert!(average.block_size() > 1);
+    }
+
+    #[test]
+    fn statistics() {
+        let mut statistics = Statistics::new(&[("Distance", "nm"), ("Angle", "deg")]);
+        statistics.add(&[10.0, 0.0]);
+        statistics.add(&[30.0, 1.0]);
+        assert_relative_eq!(statistics.get(0).mean(), 2.0);
+        assert_relative_eq!(statistics.get(1).mean(), 0.5 * 180.0 / ::std::f64::consts::PI);
+
+        statistics.clear();
+        assert_eq!(statistics.get(0).count(), 0);
+    }
+
+    #[test]
+    fn checkpoint() {
+        let mut rng = XorShiftRng::from_seed([2015, 42, 3, 12]);
+        let mut average = BlockAverage::new();
+        for _ in 0..1001 {
+            average.add(rng.next_f64());
+        }
+
+        let mut writer = CheckpointWriter::new();
+        average.save(&mut writer);
+        let mut restored = BlockAverage::new();
+        restored.restore(&mut CheckpointReader::new(writer.as_bytes())).unwrap();
+
+        for _ in 0..500 {
+            let value = rng.next_f64();
+            average.add(value);
+            restored.add(value);
+        }
+        assert_eq!(restored.count(), average.count());
+        assert_eq!(restored.mean(), average.mean());
+        assert_eq!(restored.error(), average.error());
+        assert_eq!(restored.block_size(), average.block_size());
+    }
+}
@@ -0,0 +1,352 @@
+// Lumol, an extensible molecular simulation engine
+// Copyright (C) 2015-2016 Lumol's contributors — BSD license
+
+//! Static structure factor output
+use std::io::prelude::*;
+use std::io;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::f64::consts::PI;
+
+use utils;
+use types::{Array3, Complex, Zero};
+use sys::System;
+use checkpoint::{CheckpointWriter, CheckpointReader, CheckpointError};
+use super::Output;
+
+/// The `StructureFactorOutput` computes the static structure factor `S(k)`,
+/// and the partial structure factors between all pairs of species, averaged
+/// over the simulation. The file is written at the end of the simulation,
+/// and contains the modulus of the wave vector `k`, the total structure
+/// factor and the partial structure factors.
+///
+/// The structure factors are computed on the reciprocal lattice of the unit
+/// cell at each step, and spherically averaged in bins of fixed width. The
+/// total structure factor is `S(k) = 1/N <ρ(k) ρ(-k)>`, where `ρ(k) = ∑
+/// exp(-i k·ri)`. The partial structure factors use the Ashcroft-Langreth
+/// definition, `Sab(k) = 1/sqrt(Na Nb) <ρa(k) ρb(-k)>`, so that `S(k) = ∑
+/// sqrt(xa xb) Sab(k)` where the sum runs over all the pairs of species and
+/// `xa` is the fraction of particles of species `a`.
+pub struct StructureFactorOutput {
+    file: File,
+    path: PathBuf,
+    /// Maximal modulus of the wave vectors
+    max: f64,
+    /// Width of the bins in the histogram
+    width: f64,
+    /// Names of the species
+    species: Vec<String>,
+    /// Index in `species` of each particle
+    kinds: Vec<usize>,
+    /// Number of particles in each species
+    counts: Vec<usize>,
+    /// Accumulated total structure factor in each bin
+    total: Vec<f64>,
+    /// Accumulated partial structure factors in each bin, for each pair of
+    /// species `a <= b` in lexicographic order
+    partials: Vec<Vec<f64>>,
+    /// Number of wave vectors accumulated in each bin
+    samples: Vec<usize>,
+    /// Fourier phases of the particles, cached allocation
+    phases: Array3<Complex>,
+}
+
+impl StructureFactorOutput {
+    /// Create a new `StructureFactorOutput` writing to `filename`, using all
+    /// the wave vectors with a modulus up to `max`, with bins of size
+    /// `width`. The file is replaced if it already exists.
+    ///
+    /// # Panics
+    ///
+    /// If `width` is not positive or if `max` is smaller than `width`.
+    pub fn new<P: AsRef<Path>>(filename: P, max: f64, width: f64) -> Result<StructureFactorOutput, io::Error> {
+        assert!(width > 0.0, "the bin width must be positive in structure factor");
+        assert!(max >= width, "the maximal wave vector must be larger than the bin width in structure factor");
+        let nbins = (max / width).floor() as usize;
+        Ok(StructureFactorOutput {
+            file: try!(File::create(filename.as_ref())),
+            path: filename.as_ref().to_owned(),
+            max: max,
+            width: width,
+            species: Vec::new(),
+            kinds: Vec::new(),
+            counts: Vec::new(),
+            total: vec![0.0; nbins],
+            partials: Vec::new(),
+            samples: vec![0; nbins],
+            phases: Array3::zeros((0, 0, 0)),
+        })
+    }
+
+    /// Get the path of the file written by this output
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Get the maximal modulus of the wave vectors
+    pub fn max(&self) -> f64 {
+        self.max
+    }
+
+    /// Get the width of the bins in the histogram
+    pub fn width(&self) -> f64 {
+        self.width
+    }
+
+    /// Get the pairs of species indexes, in the same order as `partials`
+    fn pairs(&self) -> Vec<(usize, usize)> {
+        let nspecies = self.species.len();
+        let mut pairs = Vec::new();
+        for a in 0..nspecies {
+            for b in a..nspecies {
+                pairs.push((a, b));
+            }
+        }
+        return pairs;
+    }
+
+    /// Get the phase `exp(-2 π i n s)` of the particle `i` along direction
+    /// `j`, for positive and negative values of `n`.
+    fn phase(&self, n: isize, i: usize, j: usize) -> Complex {
+        if n >= 0 {
+            self.phases[(n as usize, i, j)]
+        } else {
+            self.phases[((-n) as usize, i, j)].conj()
+        }
+    }
+
+    fn write_structure_factor(&mut self) -> Result<(), io::Error> {
+        try!(writeln!(&mut self.file, "# Static structure factor"));
+        try!(write!(&mut self.file, "# k/A^-1 S(k)"));
+        for (a, b) in self.pairs() {
+            try!(write!(&mut self.file, " S({}-{})", self.species[a], self.species[b]));
+        }
+        try!(writeln!(&mut self.file, ""));
+
+        for (bin, &samples) in self.samples.iter().enumerate() {
+            if samples == 0 {
+                continue;
+            }
+            let k = utils::unit_to((bin as f64 + 0.5) * self.width, "A^-1");
+            try!(write!(&mut self.file, "{} {}", k, self.total[bin] / samples as f64));
+            for partial in &self.partials {
+                try!(write!(&mut self.file, " {}", partial[bin] / samples as f64));
+            }
+            try!(writeln!(&mut self.file, ""));
+        }
+        Ok(())
+    }
+}
+
+impl Output for StructureFactorOutput {
+    fn setup(&mut self, system: &System) {
+        if system.cell().is_infinite() {
+            fatal_error!("Can not compute structure factor with an infinite cell");
+        }
+
+        let names = system.iter().map(|particle| String::from(particle.name())).collect::<Vec<_>>();
+        self.species = names.clone();
+        self.species.sort();
+        self.species.dedup();
+
+        self.kinds = names.iter().map(|name| {
+            self.species.iter().position(|species| species == name).expect("missing species")
+        }).collect();
+        self.counts = vec![0; self.species.len()];
+        for &kind in &self.kinds {
+            self.counts[kind] += 1;
+        }
+
+        let nbins = self.total.len();
+        self.total = vec![0.0; nbins];
+        self.partials = vec![vec![0.0; nbins]; self.pairs().len()];
+        self.samples = vec![0; nbins];
+    }
+
+    fn write(&mut self, system: &System) {
+        if system.size() != self.kinds.len() {
+            warn!("The number of particles changed, resetting the structure factor");
+            self.setup(system);
+        }
+
+        // Largest reciprocal lattice indexes giving a wave vector with modulus
+        // smaller than `max`, using n = k · a / 2π
+        let cell = system.cell();
+        let nbins = self.total.len();
+        let max = self.width * nbins as f64;
+        let na = (max * cell.vect_a().norm() / (2.0 * PI)).floor() as isize;
+        let nb = (max * cell.vect_b().norm() / (2.0 * PI)).floor() as isize;
+        let nc = (max * cell.vect_c().norm() / (2.0 * PI)).floor() as isize;
+        let nmax = *[na, nb, nc].iter().max().expect("empty array") as usize;
+        utils::fourier_phases(system, nmax + 1, &mut self.phases);
+
+        let (rec_a, rec_b, rec_c) = cell.reciprocal_vectors();
+        let pairs = self.pairs();
+        let natoms = system.size() as f64;
+        let mut rho = vec![Complex::zero(); self.species.len()];
+        for ia in 0..(na + 1) {
+            for ib in -nb..(nb + 1) {
+                for ic in -nc..(nc + 1) {
+                    // ρ(-k) is the conjugate of ρ(k), so we only need half of
+                    // the wave vectors, and k = 0 is not used.
+                    if ia == 0 && (ib < 0 || (ib == 0 && ic <= 0)) {
+                        continue;
+                    }
+
+                    let k = ia as f64 * rec_a + ib as f64 * rec_b + ic as f64 * rec_c;
+                    let bin = (k.norm() / self.width).floor() as usize;
+                    if bin >= nbins {
+                        continue;
+                    }
+
+                    for value in &mut rho {
+                        *value = Complex::zero();
+                    }
+                    for i in 0..system.size() {
+                        let phase = self.phase(ia, i, 0) * self.phase(ib, i, 1) * self.phase(ic, i, 2);
+                        rho[self.kinds[i]] = rho[self.kinds[i]] + phase;
+                    }
+
+                    let total = rho.iter().fold(Complex::zero(), |sum, &value| sum + value);
+                    self.total[bin] += total.norm2() / natoms;
+                    for (partial, &(a, b)) in self.partials.iter_mut().zip(&pairs) {
+                        let normalization = f64::sqrt((self.counts[a] * self.counts[b]) as f64);
+                        partial[bin] += (rho[a] * rho[b].conj()).real() / normalization;
+                    }
+                    self.samples[bin] += 1;
+                }
+            }
+        }
+    }
+
+    fn finish(&mut self, _: &System) {
+        if let Err(err) = self.write_structure_factor() {
+            error!("Could not write to file '{}': {}", self.path.display(), err);
+        }
+    }
+
+    fn save_state(&self, writer: &mut CheckpointWriter) {
+        writer.write_f64s(&self.total);
+        writer.write_usize(self.partials.len());
+        for partial in &self.partials {
+            writer.write_f64s(partial);
+        }
+        for &samples in &self.samples {
+            writer.write_usize(samples);
+        }
+    }
+
+    fn restore_state(&mut self, reader: &mut CheckpointReader) -> Result<(), CheckpointError> {
+        let nbins = self.total.len();
+        let total = try!(reader.read_f64s());
+        if total.len() != nbins {
+            return Err(CheckpointError::from("wrong number of bins in structure factor checkpoint"));
+        }
+        if try!(reader.read_usize()) != self.partials.len() {
+            return Err(CheckpointError::from("wrong number of species in structure factor checkpoint"));
+        }
+        for partial in &mut self.partials {
+            *partial = try!(reader.read_f64s());
+            if partial.len() != nbins {
+                return Err(CheckpointError::from("wrong number of bins in structure factor checkpoint"));
+            }
+        }
+        self.total = total;
+        for samples in &mut self.samples {
+            *samples = try!(reader.read_usize());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate tempfile;
+    use self::tempfile::NamedTempFile;
+
+    use std::io::prelude::*;
+
+    use super::*;
+    use sys::*;
+    use types::*;
+
+    fn read_file(file: &NamedTempFile) -> Vec<String> {
+        let mut buffer = String::new();
+        let _ = file.reopen().unwrap().read_to_string(&mut buffer).unwrap();
+        buffer.lines().map(String::from).collect()
+    }
+
+    fn parse(line: &str) -> Vec<f64> {
+        line.split_whitespace().map(|value| value.parse::<f64>().unwrap()).collect()
+    }
+
+    /// Simple cubic lattice with a spacing of 1 A, with alternating Ar and
+    /// Kr particles
+    fn lattice() -> System {
+        let mut system = System::from_cell(UnitCell::cubic(6.0));
+        for i in 0..6 {
+            for j in 0..6 {
+                for k in 0..6 {
+                    let name = if (i + j + k) % 2 == 0 {"Ar"} else {"Kr"};
+                    let mut particle = Particle::new(name);
+                    particle.position = Vector3D::new(i as f64, j as f64, k as f64);
+                    system.add_particle(particle);
+                }
+            }
+        }
+        return system;
+    }
+
+    #[test]
+    fn bragg_peaks() {
+        let system = lattice();
+        let tempfile = NamedTempFile::new().unwrap();
+        {
+            let mut out = StructureFactorOutput::new(tempfile.path(), 7.0, 0.1).unwrap();
+            out.setup(&system);
+            out.write(&system);
+            out.write(&system);
+            out.finish(&system);
+        }
+
+        let lines = read_file(&tempfile);
+        assert_eq!(lines[0], "# Static structure factor");
+        assert_eq!(lines[1], "# k/A^-1 S(k) S(Ar-Ar) S(Ar-Kr) S(Kr-Kr)");
+
+        let values = lines.iter().skip(2).map(|line| parse(line)).collect::<Vec<_>>();
+        // The first wave vectors are not on the lattice reciprocal lattice
+        assert_relative_eq!(values[0][0], 1.05);
+        assert_relative_eq!(values[0][1], 0.0, epsilon=1e-9);
+
+        // The bin containing 2π contains the (6, 0, 0) Bragg peaks, with
+        // S(k) = N, and the (4, 4, 2) wave vectors with S(k) = 0.
+        let peak = values.iter().find(|value| value[0] == 6.25).unwrap();
+        assert_relative_eq!(peak[1], 216.0 * 3.0 / 15.0, max_relative=1e-9);
+    }
+
+    #[test]
+    fn partials() {
+        let mut system = lattice();
+        // Break the symmetry of the lattice
+        for (i, particle) in system.iter_mut().enumerate() {
+            let shift = 0.1 * f64::sin(i as f64);
+            particle.position += Vector3D::new(shift, -shift, 0.5 * shift);
+        }
+
+        let tempfile = NamedTempFile::new().unwrap();
+        {
+            let mut out = StructureFactorOutput::new(tempfile.path(), 4.0, 0.2).unwrap();
+            out.setup(&system);
+            out.write(&system);
+            out.finish(&system);
+        }
+
+        let lines = read_file(&tempfile);
+        for line in lines.iter().skip(2) {
+            let values = parse(line);
+            // Same number of Ar and Kr: S = (Saa + Sbb) / 2 + Sab
+            let expected = 0.5 * (values[2] + values[4]) + values[3];
+            assert_relative_eq!(values[1], expected, epsilon=1e-9, max_relative=1e-9);
+        }
+    }
+}
@@ -0,0 +1,295 @@
+// Lumol, an extensible molecular simulation engine
+// Copyright (C) 2015-2016 Lumol's contributors — BSD license
+
+//! Seedable random number generation.
+//!
+//! All the random numbers used in Lumol come from a `Random` generator. The
+//! generator is fully determined by an algorithm and a seed, and its internal
+//! state can be saved and restored at any time, making simulations exactly
+//! reproducible.
+use rand::Rng;
+
+/// Seed used by all the random number generators when no other seed is given.
+pub const DEFAULT_SEED: u64 = 42;
+
+/// Available algorithms for random number generation.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RngAlgorithm {
+    /// Marsaglia's xorshift128 algorithm. This is a very fast generator, with
+    /// a period of 2^128 - 1.
+    XorShift,
+    /// O'Neill's PCG32 algorithm (XSH-RR variant). This generator is slightly
+    /// slower than `XorShift`, but has better statistical properties.
+    Pcg32,
+}
+
+impl RngAlgorithm {
+    /// Get the name of this algorithm, as used in input files.
+    pub fn name(&self) -> &'static str {
+        match *self {
+            RngAlgorithm::XorShift => "xorshift",
+            RngAlgorithm::Pcg32 => "pcg32",
+        }
+    }
+}
+
+/// Complete internal state of a `Random` generator. Restoring a generator
+/// from this state will produce the exact same sequence of numbers.
+#[derive(Clone, Debug, PartialEq)]
+pub enum RngState {
+    /// State of a xorshift128 generator
+    XorShift([u32; 4]),
+    /// State of a PCG32 generator
+    Pcg32 {
+        /// Current state of the underlying linear congruential generator
+        state: u64,
+        /// Increment of the underlying linear congruential generator. This
+        /// value is always odd.
+        increment: u64,
+    },
+}
+
+/// Random number generator used in simulations.
+///
+/// # Example
+///
+/// ```
+/// # extern crate rand;
+/// # extern crate lumol;
+/// use rand::Rng;
+/// use lumol::rng::{Random, RngAlgorithm};
+///
+/// # fn main() {
+/// let mut rng = Random::new(RngAlgorithm::XorShift, 1234);
+/// let state = rng.state().clone();
+///
+/// let first = rng.next_f64();
+/// let mut restored = Random::from_state(state);
+/// assert_eq!(restored.next_f64(), first);
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct Random {
+    state: RngState,
+    /// Seed used to create this generator, if any
+    seed: Option<u64>,
+}
+
+impl Random {
+    /// Create a new random number generator using the given `algorithm`,
+    /// and initialized from `seed`. Two generators created with the same
+    /// algorithm and seed produce the same sequence of numbers.
+    pub fn new(algorithm: RngAlgorithm, seed: u64) -> Random {
+        let mut seeder = SplitMix64(seed);
+        let state = match algorithm {
+            RngAlgorithm::XorShift => {
+                let (a, b) = (seeder.next(), seeder.next());
+                let mut words = [a as u32, (a >> 32) as u32, b as u32, (b >> 32) as u32];
+                if words.iter().all(|&w| w == 0) {
+                    // xorshift can not work with an all zero state
+                    words[0] = 0x9e37_79b9;
+                }
+                RngState::XorShift(words)
+            }
+            RngAlgorithm::Pcg32 => {
+                let initial = seeder.next();
+                let increment = (seeder.next() << 1) | 1;
+                let mut rng = Random {
+                    state: RngState::Pcg32{state: 0, increment: increment},
+                    seed: Some(seed),
+                };
+                let _ = rng.next_u32();
+                if let RngState::Pcg32{ref mut state, ..} = rng.state {
+                    *state = state.wrapping_add(initial);
+                }
+                let _ = rng.next_u32();
+                return rng;
+            }
+        };
+        Random {
+            state: state,
+            seed: Some(seed),
+        }
+    }
+
+    /// Create a random number generator from a previously saved `state`.
+    ///
+    /// # Panics
+    ///
+    /// If the state is not a valid state for the corresponding algorithm, i.e.
+    /// if a xorshift state is all zeros or a PCG32 increment is even.
+    pub fn from_state(state: RngState) -> Random {
+        match state {
+            RngState::XorShift(ref words) => {
+                assert!(
+                    !words.iter().all(|&w| w == 0),
+                    "xorshift random number generator state can not be all zeros"
+                );
+            }
+            RngState::Pcg32{increment, ..} => {
+                assert!(
+                    increment % 2 == 1,
+                    "PCG32 random number generator increment must be odd"
+                );
+            }
+        }
+        Random {
+            state: state,
+            seed: None,
+        }
+    }
+
+    /// Get the algorithm used by this generator
+    pub fn algorithm(&self) -> RngAlgorithm {
+        match self.state {
+            RngState::XorShift(..) => RngAlgorithm::XorShift,
+            RngState::Pcg32{..} => RngAlgorithm::Pcg32,
+        }
+    }
+
+    /// Get the current internal state of this generator. This state can be
+    /// saved, and later used with `Random::from_state` to continue the
+    /// sequence of random numbers.
+    pub fn state(&self) -> &RngState {
+        &self.state
+    }
+
+    /// Get the seed used to create this generator with `Random::new`, or
+    /// `None` if the generator was created from a state with
+    /// `Random::from_state`.
+    pub fn seed(&self) -> Option<u64> {
+        self.seed
+    }
+}
+
+impl Default for Random {
+    fn default() -> Random {
+        Random::new(RngAlgorithm::XorShift, DEFAULT_SEED)
+    }
+}
+
+impl Rng for Random {
+    fn next_u32(&mut self) -> u32 {
+        match self.state {
+            RngState::XorShift(ref mut s) => {
+                let t = s[0] ^ (s[0] << 11);
+                s[0] = s[1];
+                s[1] = s[2];
+                s[2] = s[3];
+                s[3] = s[3] ^ (s[3] >> 19) ^ (t ^ (t >> 8));
+                s[3]
+            }
+            RngState::Pcg32{ref mut state, increment} => {
+                let old = *state;
+                *state = old.wrapping_mul(6_364_136_223_846_793_005).wrapping_add(increment);
+                let xorshifted = (((old >> 18) ^ old) >> 27) as u32;
+                let rotation = (old >> 59) as u32;
+                xorshifted.rotate_right(rotation)
+            }
+        }
+    }
+}
+
+/// Derive a new seed from `seed` for the independent `stream`. This allow to
+/// create multiple generators producing different sequences of numbers from
+/// a single seed, by using a different stream for each generator.
+pub fn derive_seed(seed: u64, stream: u64) -> u64 {
+    let mut seeder = SplitMix64(seed ^ SplitMix64(stream).next());
+    seeder.next()
+}
+
+/// SplitMix64 generator, used to expand a single 64-bit seed into the full
+/// state of the other generators.
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{Rng, SeedableRng, XorShiftRng};
+
+    #[test]
+    fn reproducible() {
+        for &algorithm in &[RngAlgorithm::XorShift, RngAlgorithm::Pcg32] {
+            let mut a = Random::new(algorithm, 42);
+            let mut b = Random::new(algorithm, 42);
+            let mut c = Random::new(algorithm, 43);
+
+            let a = (0..100).map(|_| a.next_u32()).collect::<Vec<_>>();
+            let b = (0..100).map(|_| b.next_u32()).collect::<Vec<_>>();
+            let c = (0..100).map(|_| c.next_u32()).collect::<Vec<_>>();
+            assert_eq!(a, b);
+            assert!(a != c);
+        }
+    }
+
+    #[test]
+    fn restore_state() {
+        for &algorithm in &[RngAlgorithm::XorShift, RngAlgorithm::Pcg32] {
+            let mut rng = Random::new(algorithm, 1234);
+            for _ in 0..10 {
+                let _ = rng.next_f64();
+            }
+
+            let mut restored = Random::from_state(rng.state().clone());
+            assert_eq!(restored.algorithm(), algorithm);
+            assert_eq!(rng.seed(), Some(1234));
+            assert_eq!(restored.seed(), None);
+            for _ in 0..100 {
+                assert_eq!(rng.next_u64(), restored.next_u64());
+            }
+        }
+    }
+
+    #[test]
+    fn xorshift() {
+        let mut rng = Random::from_state(RngState::XorShift([2015, 42, 3, 12]));
+        let mut reference = XorShiftRng::from_seed([2015, 42, 3, 12]);
+        for _ in 0..100 {
+            assert_eq!(rng.next_u32(), reference.next_u32());
+        }
+    }
+
+    #[test]
+    fn pcg32() {
+        // Reference values from the PCG32 demo program, seeded with an
+        // initial state of 42 and a sequence of 54
+        let mut rng = Random::from_state(RngState::Pcg32{
+            state: 0x1857_06b8_2c2e_03f8,
+            increment: 0x6d,
+        });
+        let expected = [0xa15c_02b7, 0x7b47_f409, 0xba1d_3330, 0x83d2_f293, 0xbfa4_784b, 0xcbed_606e];
+        for &value in &expected {
+            assert_eq!(rng.next_u32(), value);
+        }
+    }
+
+    #[test]
+    fn derived_seeds() {
+        assert_eq!(derive_seed(42, 3), derive_seed(42, 3));
+        assert!(derive_seed(42, 3) != derive_seed(42, 4));
+        assert!(derive_seed(42, 3) != derive_seed(43, 3));
+        assert!(derive_seed(42, 0) != 42);
+    }
+
+    #[test]
+    #[should_panic]
+    fn zero_xorshift() {
+        let _ = Random::from_state(RngState::XorShift([0, 0, 0, 0]));
+    }
+
+    #[test]
+    #[should_panic]
+    fn even_pcg_increment() {
+        let _ = Random::from_state(RngState::Pcg32{state: 0, increment: 2});
+    }
+}
@@ -0,0 +1,140 @@
+// Lumol, an extensible molecular simulation engine
+// Copyright (C) 2015-2016 Lumol's contributors — BSD license
+
+//! Hooks to inspect and modify the system between the steps of a simulation
+use sys::System;
+
+/// Information about the current step of a simulation run, given to hooks.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct StepInfo {
+    /// Index of the current step in this run, starting at 0
+    pub index: usize,
+    /// Total number of steps requested for this run
+    pub nsteps: usize,
+}
+
+impl StepInfo {
+    /// Get the fraction of this run done at the beginning of the current
+    /// step, between 0 and 1. This is useful to gradually change a parameter
+    /// during a simulation.
+    pub fn progress(&self) -> f64 {
+        if self.nsteps == 0 {
+            1.0
+        } else {
+            self.index as f64 / self.nsteps as f64
+        }
+    }
+}
+
+/// Action requested by a hook to the simulation.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum HookAction {
+    /// Nothing to do, continue the simulation.
+    Continue,
+    /// The hook modified the system, in a way which may invalidate the
+    /// internal state of the propagator (positions, charges, interactions,
+    /// ...). The propagator is notified through `Propagator::system_changed`
+    /// before continuing the simulation.
+    Modified,
+    /// Stop the simulation now.
+    Stop,
+}
+
+/// A `Hook` is called before and after every step of a simulation, and can
+/// inspect and modify the system, or stop the simulation. This allow to
+/// script simulation protocols, such as gradual heating, without writing a
+/// new `Propagator`.
+///
+/// Hooks are not stored in checkpoints, and the step information given to
+/// them is relative to the current run.
+pub trait Hook {
+    /// Setup code, called at the beginning of every simulation run.
+    fn setup(&mut self, _: &System) {}
+
+    /// Function called before every step of the simulation. The default
+    /// implementation does nothing.
+    fn before_step(&mut self, _: &mut System, _: StepInfo) -> HookAction {
+        HookAction::Continue
+    }
+
+    /// Function called after every step of the simulation, once the outputs
+    /// have been written. The default implementation does nothing.
+    fn after_step(&mut self, _: &mut System, _: StepInfo) -> HookAction {
+        HookAction::Continue
+    }
+
+    /// Finish code, called at the end of every simulation run.
+    fn finish(&mut self, _: &System) {}
+}
+
+/// A `Hook` calling a function before every step of the simulation.
+pub struct BeforeStep<F> {
+    function: F,
+}
+
+impl<F> BeforeStep<F> where F: FnMut(&mut System, StepInfo) -> HookAction {
+    /// Create a new `BeforeStep` hook calling `function` before every step.
+    pub fn new(function: F) -> BeforeStep<F> {
+        BeforeStep {
+            function: function,
+        }
+    }
+}
+
+impl<F> Hook for BeforeStep<F> where F: FnMut(&mut System, StepInfo) -> HookAction {
+    fn before_step(&mut self, system: &mut System, info: StepInfo) -> HookAction {
+        (self.function)(system, info)
+    }
+}
+
+/// A `Hook` calling a function after every step of the simulation.
+pub struct AfterStep<F> {
+    function: F,
+}
+
+impl<F> AfterStep<F> where F: FnMut(&mut System, StepInfo) -> HookAction {
+    /// Create a new `AfterStep` hook calling `function` after every step.
+    pub fn new(function: F) -> AfterStep<F> {
+        AfterStep {
+            function: function,
+        }
+    }
+}
+
+impl<F> Hook for AfterStep<F> where F: FnMut(&mut System, StepInfo) -> HookAction {
+    fn after_step(&mut self, system: &mut System, info: StepInfo) -> HookAction {
+        (self.function)(system, info)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sys::{System, Particle, UnitCell};
+
+    #[test]
+    fn progress() {
+        assert_eq!(StepInfo{index: 0, nsteps: 10}.progress(), 0.0);
+        assert_eq!(StepInfo{index: 5, nsteps: 10}.progress(), 0.5);
+        assert_eq!(StepInfo{index: 0, nsteps: 0}.progress(), 1.0);
+    }
+
+    #[test]
+    fn closures() {
+        let mut system = System::from_cell(UnitCell::cubic(10.0));
+        system.add_particle(Particle::new("Ar"));
+        let info = StepInfo{index: 3, nsteps: 10};
+
+        let mut hook = BeforeStep::new(|system: &mut System, info: StepInfo| {
+            system[0].charge = info.index as f64;
+            HookAction::Modified
+        });
+        assert_eq!(hook.before_step(&mut system, info), HookAction::Modified);
+        assert_eq!(system[0].charge, 3.0);
+        assert_eq!(hook.after_step(&mut system, info), HookAction::Continue);
+
+        let mut hook = AfterStep::new(|_: &mut System, _: StepInfo| HookAction::Stop);
+        assert_eq!(hook.before_step(&mut system, info), HookAction::Continue);
+        assert_eq!(hook.after_step(&mut system, info), HookAction::Stop);
+    }
+}
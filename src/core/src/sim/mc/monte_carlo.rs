@@ -2,11 +2,13 @@
 // Copyright (C) 2015-2016 Lumol's contributors — BSD license
 
 //! Metropolis Monte-Carlo propagator implementation
-use rand::{self, SeedableRng};
+use rand::Rng;
 
 use consts::K_BOLTZMANN;
+use rng::{Random, RngState};
 use sys::{System, EnergyCache};
-use sim::{Propagator, TemperatureStrategy};
+use sim::{Propagator, TemperatureStrategy, Ensemble};
+use checkpoint::{CheckpointWriter, CheckpointReader, CheckpointError};
 
 use super::MCMove;
 
@@ -23,7 +25,7 @@ pub struct MonteCarlo {
     update_frequency: u64,
     /// Random number generator for the simulation. All random state will be
     /// taken from this.
-    rng: Box<rand::Rng>,
+    rng: Random,
     /// Cache for faster energy computation
     cache: EnergyCache,
     /// Flag checking if the moves frequencies has been converted to
@@ -32,16 +34,16 @@ pub struct MonteCarlo {
 }
 
 impl MonteCarlo {
-    /// Create a new Monte-Carlo propagator at temperature `T`.
+    /// Create a new Monte-Carlo propagator at temperature `T`, using a
+    /// xorshift random number generator with a fixed initial state.
     pub fn new(temperature: f64) -> MonteCarlo {
-        let mut rng = Box::new(rand::XorShiftRng::new_unseeded());
-        rng.reseed([2015u32, 42u32, 3u32, 12u32]);
+        let rng = Random::from_state(RngState::XorShift([2015, 42, 3, 12]));
         return MonteCarlo::from_rng(temperature, rng);
     }
 
     /// Create a Monte-Carlo propagator at temperature `T`, using the `rng`
     /// random number generator.
-    pub fn from_rng(temperature: f64, rng: Box<rand::Rng>) -> MonteCarlo {
+    pub fn from_rng(temperature: f64, rng: Random) -> MonteCarlo {
         assert!(temperature >= 0.0, "Monte-Carlo temperature must be positive");
         MonteCarlo {
             beta: 1.0 / (K_BOLTZMANN * temperature),
@@ -97,6 +99,32 @@ impl MonteCarlo {
         self.update_frequency = frequency;
     }
 
+    /// Get the number of times a move has to be called before its amplitude
+    /// is updated. Zero means that the amplitudes are never updated.
+    pub fn amplitude_update_frequency(&self) -> u64 {
+        self.update_frequency
+    }
+
+    /// Get the list of Monte-Carlo moves in this propagator, together with
+    /// their frequency and target acceptance.
+    pub fn moves(&self) -> Vec<(&MCMove, f64, Option<f64>)> {
+        let mut moves = Vec::new();
+        let mut previous = 0.0;
+        for (&(ref mcmove, ref counter), &frequency) in self.moves.iter().zip(&self.frequencies) {
+            // Frequencies are converted to cumulative probabilities when
+            // starting the simulation.
+            let frequency = if self.initialized {
+                let probability = frequency - previous;
+                previous = frequency;
+                probability
+            } else {
+                frequency
+            };
+            moves.push((&**mcmove, frequency, counter.target_acceptance()));
+        }
+        return moves;
+    }
+
     /// Get the temperature of the simulation
     pub fn temperature(&self) -> f64 {
         1.0 / (self.beta * K_BOLTZMANN)
@@ -107,6 +135,18 @@ impl MonteCarlo {
         self.beta = 1.0 / (temperature * K_BOLTZMANN);
     }
 
+    /// Get the random number generator used by this simulation. Its state can
+    /// be saved to later continue the simulation with the exact same
+    /// sequence of random numbers.
+    pub fn rng(&self) -> &Random {
+        &self.rng
+    }
+
+    /// Replace the random number generator used by this simulation.
+    pub fn set_rng(&mut self, rng: Random) {
+        self.rng = rng;
+    }
+
     fn normalize_frequencies(&mut self) {
         assert_eq!(self.frequencies.len(), self.moves.len());
         if self.frequencies.is_empty() {
@@ -142,6 +182,14 @@ impl Propagator for MonteCarlo {
         TemperatureStrategy::External(self.temperature())
     }
 
+    fn ensemble(&self) -> Ensemble {
+        let pressure = self.moves.iter().filter_map(|mcmove| mcmove.0.pressure()).next();
+        match pressure {
+            Some(pressure) => Ensemble::NPT(self.temperature(), pressure),
+            None => Ensemble::NVT(self.temperature()),
+        }
+    }
+
     fn setup(&mut self, system: &System) {
         self.normalize_frequencies();
         self.cache.init(system);
@@ -150,6 +198,10 @@ impl Propagator for MonteCarlo {
         }
     }
 
+    fn system_changed(&mut self, system: &System) {
+        self.cache.init(system);
+    }
+
     fn propagate(&mut self, system: &mut System) {
         let mcmove = {
             let probability = self.rng.next_f64();
@@ -208,6 +260,34 @@ impl Propagator for MonteCarlo {
                 mc_move.1.nattempted as f64 * 100.0);
         }
     }
+
+    fn save_state(&self, writer: &mut CheckpointWriter) {
+        writer.write_section(&self.rng);
+        writer.write_usize(self.moves.len());
+        for &(ref mcmove, ref counter) in &self.moves {
+            writer.write_u64(counter.ncalled);
+            writer.write_u64(counter.naccepted);
+            writer.write_u64(counter.nattempted);
+            mcmove.save_state(writer);
+        }
+        writer.write_section(&self.cache);
+    }
+
+    fn restore_state(&mut self, reader: &mut CheckpointReader) -> Result<(), CheckpointError> {
+        try!(reader.read_section(&mut self.rng));
+        if try!(reader.read_usize()) != self.moves.len() {
+            return Err(CheckpointError::from(
+                "the number of moves in the checkpoint does not match the simulation"
+            ));
+        }
+        for &mut (ref mut mcmove, ref mut counter) in &mut self.moves {
+            counter.ncalled = try!(reader.read_u64());
+            counter.naccepted = try!(reader.read_u64());
+            counter.nattempted = try!(reader.read_u64());
+            try!(mcmove.restore_state(reader));
+        }
+        reader.read_section(&mut self.cache)
+    }
 }
 
 /// This struct keeps track of the number of times a move was called
@@ -249,6 +329,11 @@ impl MoveCounter {
         self.target_acceptance = target_acceptance;
     }
 
+    /// Get the target acceptance for the move counter, if any.
+    pub fn target_acceptance(&self) -> Option<f64> {
+        self.target_acceptance
+    }
+
     /// Compute a scaling factor according to the desired acceptance.
     pub fn compute_scaling_factor(&self) -> Option<f64> {
         // Check if there exists an target_acceptance
@@ -274,16 +359,17 @@ impl Default for MoveCounter {
 
 #[cfg(test)]
 mod tests {
-    use sim::mc::{MonteCarlo, MCMove, MoveCounter};
-    use sim::Propagator;
-    use sys::{System, EnergyCache};
-    use rand::Rng;
+    use sim::mc::{MonteCarlo, MCMove, MoveCounter, Resize};
+    use sim::{Propagator, Ensemble};
+    use sys::{System, EnergyCache, Particle, UnitCell};
+    use energy::{LennardJones, PairInteraction};
+    use rng::Random;
 
     struct DummyMove;
     impl MCMove for DummyMove {
         fn describe(&self) -> &str {"dummy"}
         fn setup(&mut self, _: &System) {}
-        fn prepare(&mut self, _: &mut System, _: &mut Box<Rng>) -> bool {true}
+        fn prepare(&mut self, _: &mut System, _: &mut Random) -> bool {true}
         fn cost(&self, _: &System, _: f64, _: &mut EnergyCache) -> f64 {0.0}
         fn apply(&mut self, _: &mut System) {}
         fn restore(&mut self, _: &mut System) {}
@@ -311,6 +397,21 @@ mod tests {
         assert_eq!(mc.frequencies[2], 1.0);
     }
 
+    #[test]
+    fn moves() {
+        let mut mc = MonteCarlo::new(100.0);
+        mc.add(Box::new(DummyMove), 3.0);
+        mc.add_move_with_acceptance(Box::new(DummyMove), 1.0, 0.5);
+
+        let frequencies = mc.moves().iter().map(|m| (m.1, m.2)).collect::<Vec<_>>();
+        assert_eq!(frequencies, vec![(3.0, None), (1.0, Some(0.5))]);
+        assert!(mc.moves()[0].0.downcast_ref::<DummyMove>().is_some());
+
+        mc.setup(&System::new());
+        let frequencies = mc.moves().iter().map(|m| (m.1, m.2)).collect::<Vec<_>>();
+        assert_eq!(frequencies, vec![(0.75, None), (0.25, Some(0.5))]);
+    }
+
     #[test]
     #[should_panic]
     fn add_after_init() {
@@ -344,6 +445,16 @@ mod tests {
         assert_eq!(mc.moves[0].1.target_acceptance, None);
     }
 
+    #[test]
+    fn ensemble() {
+        let mut mc = MonteCarlo::new(100.0);
+        mc.add(Box::new(DummyMove), 1.0);
+        assert_eq!(mc.ensemble(), Ensemble::NVT(100.0));
+
+        mc.add(Box::new(Resize::new(5.0, 10.0)), 1.0);
+        assert_eq!(mc.ensemble(), Ensemble::NPT(100.0, 5.0));
+    }
+
     #[test]
     fn scaling_factor() {
         let mut counter = MoveCounter::new(Some(0.5));
@@ -354,4 +465,23 @@ mod tests {
         counter.naccepted = 0;
         assert_eq!(counter.compute_scaling_factor(), Some(0.8));
     }
+
+    #[test]
+    fn system_changed() {
+        let mut system = System::from_cell(UnitCell::cubic(20.0));
+        system.add_particle(Particle::new("Ar"));
+        system.add_particle(Particle::new("Ar"));
+        system[1].position[0] = 3.5;
+        let lj = LennardJones{sigma: 3.4, epsilon: 1.0};
+        system.interactions_mut().add_pair("Ar", "Ar", PairInteraction::new(Box::new(lj), 8.0));
+
+        let mut mc = MonteCarlo::new(100.0);
+        mc.add(Box::new(DummyMove), 1.0);
+        mc.setup(&system);
+        assert_eq!(mc.cache.energy(), system.potential_energy());
+
+        system[1].position[0] = 4.0;
+        mc.system_changed(&system);
+        assert_eq!(mc.cache.energy(), system.potential_energy());
+    }
 }
@@ -10,11 +10,14 @@
 //! In all this module, beta refers to the Boltzmann factor 1/(kB T)
 use rand::Rng;
 
+use rng::Random;
 use sys::{System, EnergyCache};
+use utils::AsAny;
+use checkpoint::{CheckpointWriter, CheckpointReader, CheckpointError};
 
 /// The `MCMove` trait correspond to the set of methods used in Monte-Carlo
 /// simulations.
-pub trait MCMove {
+pub trait MCMove: AsAny {
     /// Give a short description of this move
     fn describe(&self) -> &str;
 
@@ -27,7 +30,7 @@ pub trait MCMove {
     ///
     /// This function should return true is we can perform the move, and false
     /// otherwise.
-    fn prepare(&mut self, system: &mut System, rng: &mut Box<Rng>) -> bool;
+    fn prepare(&mut self, system: &mut System, rng: &mut Random) -> bool;
 
     /// Get the cost of performing this move on `system`. For example in
     /// simple NVT simulations, this cost is the energetic difference between
@@ -51,8 +54,27 @@ pub trait MCMove {
 
     /// Update the sample range for displacements.
     fn update_amplitude(&mut self, scaling_factor: Option<f64>);
+
+    /// Get the target pressure of this move, if it changes the volume of the
+    /// system. The default implementation returns `None`.
+    fn pressure(&self) -> Option<f64> {
+        None
+    }
+
+    /// Save the internal state of this move in a checkpoint, to be able to
+    /// restart the simulation later. The default implementation saves nothing.
+    fn save_state(&self, _: &mut CheckpointWriter) {}
+
+    /// Restore the internal state of this move from a checkpoint written by
+    /// `save_state`. This function is called after `setup` when restarting a
+    /// simulation. The default implementation does nothing.
+    fn restore_state(&mut self, _: &mut CheckpointReader) -> Result<(), CheckpointError> {
+        Ok(())
+    }
 }
 
+impl_downcast!(MCMove);
+
 /// Select a random molecule in the system using `rng` as random number
 /// generator. If `moltype` is `None`, any molecule can be chosen. If `moltype`
 /// is `Some(molecule_type)`, then a molecule with matching type is selected.
@@ -60,7 +82,7 @@ pub trait MCMove {
 /// This function returns `None` if no matching molecule was found, and
 /// `Some(molid)` with `molid` the index of the molecule if a molecule was
 /// selected.
-fn select_molecule(system: &System, moltype: Option<u64>, rng: &mut Box<Rng>) -> Option<usize> {
+fn select_molecule(system: &System, moltype: Option<u64>, rng: &mut Random) -> Option<usize> {
     if let Some(moltype) = moltype {
         // Pick a random molecule with matching moltype
         let mols = system.molecules_with_moltype(moltype);
@@ -2,7 +2,7 @@
 // Copyright (C) 2015-2016 G. Fraux — BSD license
 
 use rand::distributions::{Sample, Range};
-use rand::Rng;
+use rng::Random;
 
 use std::f64;
 use std::mem;
@@ -11,6 +11,7 @@ use super::MCMove;
 
 use types::{Matrix3, One};
 use sys::{System, EnergyCache};
+use checkpoint::{CheckpointWriter, CheckpointReader, CheckpointError};
 
 /// Monte-Carlo move that changes the size of the simulation cell
 pub struct Resize {
@@ -39,6 +40,11 @@ impl Resize {
             rc_max: 0.0,
         }
     }
+
+    /// Get the current maximum displacement of this move
+    pub fn delta(&self) -> f64 {
+        self.delta
+    }
 }
 
 impl MCMove for Resize {
@@ -46,6 +52,10 @@ impl MCMove for Resize {
         "resizing of the cell"
     }
 
+    fn pressure(&self) -> Option<f64> {
+        Some(self.pressure)
+    }
+
     fn setup(&mut self, system: &System) {
         // check if the cell is infinite
         if system.cell().is_infinite() {
@@ -62,7 +72,7 @@ impl MCMove for Resize {
                             .fold(f64::NAN, f64::max)
     }
 
-    fn prepare(&mut self, system: &mut System, rng: &mut Box<Rng>) -> bool {
+    fn prepare(&mut self, system: &mut System, rng: &mut Random) -> bool {
         let delta = self.range.sample(rng);
 
         // Copy the system: the proposed state will be stored here
@@ -138,4 +148,14 @@ impl MCMove for Resize {
             self.range = Range::new(-self.delta, self.delta);
         }
     }
+
+    fn save_state(&self, writer: &mut CheckpointWriter) {
+        writer.write_f64(self.delta);
+    }
+
+    fn restore_state(&mut self, reader: &mut CheckpointReader) -> Result<(), CheckpointError> {
+        self.delta = try!(reader.read_f64());
+        self.range = Range::new(-self.delta, self.delta);
+        Ok(())
+    }
 }
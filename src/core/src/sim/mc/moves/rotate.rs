@@ -1,21 +1,27 @@
 // Lumol, an extensible molecular simulation engine
 // Copyright (C) 2015-2016 G. Fraux — BSD license
 use rand::distributions::{Normal, Range, Sample};
-use rand::Rng;
+use rng::Random;
 
 use std::usize;
 use std::f64;
+use std::path::{Path, PathBuf};
 
 use super::MCMove;
 use super::select_molecule;
 
 use types::{Matrix3, Vector3D};
-use sys::{System, EnergyCache};
+use sys::{System, EnergyCache, TrajectoryError};
+use sys::{read_molecule, molecule_type};
+use checkpoint::{CheckpointWriter, CheckpointReader, CheckpointError};
 
 /// Monte-Carlo move for rotating a rigid molecule
 pub struct Rotate {
     /// Type of molecule to rotate. `None` means all molecules.
     moltype: Option<u64>,
+    /// Path to the file containing a molecule of the rotated type, if
+    /// the move was created from this file
+    molecule: Option<PathBuf>,
     /// Index of the molecule to rotate
     molid: usize,
     /// New positions of the atom in the rotated molecule
@@ -41,11 +47,22 @@ impl Rotate {
         Rotate::create(theta, Some(moltype))
     }
 
+    /// Create a new `Rotate` move, with maximum angular displacement of `theta`,
+    /// rotating only molecules with the same type as the molecule in the
+    /// file at `path`.
+    pub fn with_molecule<P: AsRef<Path>>(theta: f64, path: P) -> Result<Rotate, TrajectoryError> {
+        let (molecule, particles) = try!(read_molecule(path.as_ref()));
+        let mut rotate = Rotate::with_moltype(theta, molecule_type(&molecule, &particles));
+        rotate.molecule = Some(path.as_ref().to_owned());
+        Ok(rotate)
+    }
+
     // Factorizing the constructors
     fn create(theta: f64, moltype: Option<u64>) -> Rotate {
         assert!(theta > 0.0, "theta must be positive in Rotate move");
         Rotate {
             moltype: moltype,
+            molecule: None,
             molid: usize::MAX,
             newpos: Vec::new(),
             axis_rng: Normal::new(0.0, 1.0),
@@ -53,6 +70,23 @@ impl Rotate {
             range: Range::new(-theta, theta),
         }
     }
+
+    /// Get the current maximum angular displacement of this move
+    pub fn theta(&self) -> f64 {
+        self.theta
+    }
+
+    /// Get the type of molecules rotated by this move, or `None` if all the
+    /// molecules are rotated.
+    pub fn moltype(&self) -> Option<u64> {
+        self.moltype
+    }
+
+    /// Get the path to the file containing the molecule used to select the
+    /// rotated molecules, if this move was created with `Rotate::with_molecule`.
+    pub fn molecule(&self) -> Option<&Path> {
+        self.molecule.as_ref().map(|path| path.as_path())
+    }
 }
 
 impl Default for Rotate {
@@ -68,7 +102,7 @@ impl MCMove for Rotate {
 
     fn setup(&mut self, _: &System) { }
 
-    fn prepare(&mut self, system: &mut System, rng: &mut Box<Rng>) -> bool {
+    fn prepare(&mut self, system: &mut System, rng: &mut Random) -> bool {
         if let Some(id) = select_molecule(system, self.moltype, rng) {
             self.molid = id;
         } else {
@@ -126,6 +160,16 @@ impl MCMove for Rotate {
             }
         }
     }
+
+    fn save_state(&self, writer: &mut CheckpointWriter) {
+        writer.write_f64(self.theta);
+    }
+
+    fn restore_state(&mut self, reader: &mut CheckpointReader) -> Result<(), CheckpointError> {
+        self.theta = try!(reader.read_f64());
+        self.range = Range::new(-self.theta, self.theta);
+        Ok(())
+    }
 }
 
 /// Rotate the particles at `positions` with the center-of-mass position
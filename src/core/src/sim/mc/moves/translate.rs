@@ -2,21 +2,27 @@
 // Copyright (C) 2015-2016 G. Fraux — BSD license
 
 use rand::distributions::{Sample, Range};
-use rand::Rng;
+use rng::Random;
 
 use std::usize;
 use std::f64;
+use std::path::{Path, PathBuf};
 
 use super::MCMove;
 use super::select_molecule;
 
 use types::Vector3D;
-use sys::{System, EnergyCache};
+use sys::{System, EnergyCache, TrajectoryError};
+use sys::{read_molecule, molecule_type};
+use checkpoint::{CheckpointWriter, CheckpointReader, CheckpointError};
 
 /// Monte-Carlo move for translating a molecule
 pub struct Translate {
     /// Type of molecule to translate. `None` means all molecules.
     moltype: Option<u64>,
+    /// Path to the file containing a molecule of the translated type, if
+    /// the move was created from this file
+    molecule: Option<PathBuf>,
     /// Index of the molecule to translate
     molid: usize,
     /// New positions of the atom in the translated molecule
@@ -42,12 +48,23 @@ impl Translate {
         Translate::create(dr, Some(moltype))
     }
 
+    /// Create a new `Translate` move, with maximum displacement of `dr`.
+    /// Translating only molecules with the same type as the molecule in the
+    /// file at `path`.
+    pub fn with_molecule<P: AsRef<Path>>(dr: f64, path: P) -> Result<Translate, TrajectoryError> {
+        let (molecule, particles) = try!(read_molecule(path.as_ref()));
+        let mut translate = Translate::with_moltype(dr, molecule_type(&molecule, &particles));
+        translate.molecule = Some(path.as_ref().to_owned());
+        Ok(translate)
+    }
+
     /// Factorizing the constructors
     fn create(dr: f64, moltype: Option<u64>) -> Translate {
         assert!(dr > 0.0, "dr must be positive in Translate move");
         let dr = dr / f64::sqrt(3.0);
         Translate {
             moltype: moltype,
+            molecule: None,
             molid: usize::MAX,
             newpos: Vec::new(),
             dr: dr,
@@ -55,6 +72,23 @@ impl Translate {
             range: Range::new(-dr, dr),
         }
     }
+
+    /// Get the current maximum displacement of this move
+    pub fn delta(&self) -> f64 {
+        self.dr * f64::sqrt(3.0)
+    }
+
+    /// Get the type of molecules translated by this move, or `None` if all
+    /// the molecules are translated.
+    pub fn moltype(&self) -> Option<u64> {
+        self.moltype
+    }
+
+    /// Get the path to the file containing the molecule used to select the
+    /// translated molecules, if this move was created with `Translate::with_molecule`.
+    pub fn molecule(&self) -> Option<&Path> {
+        self.molecule.as_ref().map(|path| path.as_path())
+    }
 }
 
 impl Default for Translate {
@@ -80,7 +114,7 @@ impl MCMove for Translate {
         }
     }
 
-    fn prepare(&mut self, system: &mut System, rng: &mut Box<Rng>) -> bool {
+    fn prepare(&mut self, system: &mut System, rng: &mut Random) -> bool {
         if let Some(id) = select_molecule(system, self.moltype, rng) {
             self.molid = id;
         } else {
@@ -141,4 +175,14 @@ impl MCMove for Translate {
             }
         };
     }
+
+    fn save_state(&self, writer: &mut CheckpointWriter) {
+        writer.write_f64(self.dr);
+    }
+
+    fn restore_state(&mut self, reader: &mut CheckpointReader) -> Result<(), CheckpointError> {
+        self.dr = try!(reader.read_f64());
+        self.range = Range::new(-self.dr, self.dr);
+        Ok(())
+    }
 }
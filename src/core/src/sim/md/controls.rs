@@ -8,9 +8,11 @@ use types::{Matrix3, Vector3D, Zero};
 use sys::System;
 use sys::veloc;
 use sim::Alternator;
+use utils::AsAny;
+use checkpoint::{Checkpoint, CheckpointWriter, CheckpointReader, CheckpointError};
 
 /// Trait for controlling some parameters in a system during a simulation.
-pub trait Control {
+pub trait Control: AsAny {
     /// Function called once at the beginning of the simulation, which allow
     /// for some setup of the control algorithm if needed.
     fn setup(&mut self, _: &System) {}
@@ -20,10 +22,29 @@ pub trait Control {
 
     /// Function called once at the end of the simulation.
     fn finish(&mut self, _: &System) {}
+
+    /// Save the internal state of this control algorithm in a checkpoint, to be
+    /// able to restart the simulation later. The default implementation saves
+    /// nothing.
+    fn save_state(&self, _: &mut CheckpointWriter) {}
+
+    /// Restore the internal state of this control algorithm from a checkpoint
+    /// written by `save_state`. This function is called after `setup` when
+    /// restarting a simulation. The default implementation does nothing.
+    fn restore_state(&mut self, _: &mut CheckpointReader) -> Result<(), CheckpointError> {
+        Ok(())
+    }
 }
 
+impl_downcast!(Control);
+
 /// Trait for controls usable as thermostats
-pub trait Thermostat: Control {}
+pub trait Thermostat: Control {
+    /// Get the target temperature of this thermostat
+    fn temperature(&self) -> f64;
+}
+
+impl_downcast!(Thermostat);
 
 /******************************************************************************/
 /// Velocity rescaling thermostat.
@@ -54,6 +75,11 @@ impl RescaleThermostat {
     pub fn with_tolerance(temperature: f64, tol: f64) -> RescaleThermostat {
         RescaleThermostat{temperature: temperature, tol: tol}
     }
+
+    /// Get the tolerance in temperature of this thermostat
+    pub fn tolerance(&self) -> f64 {
+        self.tol
+    }
 }
 
 impl Control for RescaleThermostat {
@@ -65,7 +91,11 @@ impl Control for RescaleThermostat {
     }
 }
 
-impl Thermostat for RescaleThermostat {}
+impl Thermostat for RescaleThermostat {
+    fn temperature(&self) -> f64 {
+        self.temperature
+    }
+}
 
 /******************************************************************************/
 /// Berendsen thermostat.
@@ -91,6 +121,12 @@ impl BerendsenThermostat {
         assert!(tau >= 0.0, "The timestep must be positive in berendsen thermostat.");
         BerendsenThermostat{temperature: temperature, tau: tau}
     }
+
+    /// Get the timestep of this thermostat, as a multiplicative factor of the
+    /// integrator timestep.
+    pub fn tau(&self) -> f64 {
+        self.tau
+    }
 }
 
 impl Control for BerendsenThermostat {
@@ -102,17 +138,31 @@ impl Control for BerendsenThermostat {
         }
     }
 }
-impl Thermostat for BerendsenThermostat {}
+impl Thermostat for BerendsenThermostat {
+    fn temperature(&self) -> f64 {
+        self.temperature
+    }
+}
 
 /******************************************************************************/
 
-impl<T> Control for Alternator<T> where T: Control {
+impl<T> Control for Alternator<T> where T: Control + 'static {
 
     fn control(&mut self, system: &mut System) {
         if self.can_run() {
             self.as_mut().control(system)
         }
     }
+
+    fn save_state(&self, writer: &mut CheckpointWriter) {
+        Checkpoint::save(self, writer);
+        self.as_ref().save_state(writer);
+    }
+
+    fn restore_state(&mut self, reader: &mut CheckpointReader) -> Result<(), CheckpointError> {
+        try!(Checkpoint::restore(self, reader));
+        self.as_mut().restore_state(reader)
+    }
 }
 
 /// Remove global translation from the system
@@ -2,17 +2,52 @@
 // Copyright (C) 2015-2016 Lumol's contributors — BSD license
 use types::{Vector3D, Matrix3, One, Zero};
 use sys::System;
+use utils::AsAny;
+use checkpoint::{CheckpointWriter, CheckpointReader, CheckpointError};
 
 /// The `Integrator` trait define integrator interface for molecular dynamics.
 /// An integrator is an algorithm responsible for propagating the equations of
 /// motion in the system.
-pub trait Integrator {
+pub trait Integrator: AsAny {
     /// Setup the integrator. This function is called once by every simulation
     /// run.
     fn setup(&mut self, _: &System) {}
     /// Integrate the equations of motion. This is called at every step of the
     /// simulation.
     fn integrate(&mut self, system: &mut System);
+    /// Get the target pressure of this integrator, if it controls the
+    /// pressure of the system. The default implementation returns `None`.
+    fn pressure(&self) -> Option<f64> {
+        None
+    }
+
+    /// Save the internal state of this integrator in a checkpoint, to be able
+    /// to restart the simulation later. The default implementation saves
+    /// nothing.
+    fn save_state(&self, _: &mut CheckpointWriter) {}
+
+    /// Restore the internal state of this integrator from a checkpoint written
+    /// by `save_state`. This function is called after `setup` when restarting a
+    /// simulation. The default implementation does nothing.
+    fn restore_state(&mut self, _: &mut CheckpointReader) -> Result<(), CheckpointError> {
+        Ok(())
+    }
+}
+
+impl_downcast!(Integrator);
+
+/// Restore the per-particle `values` of an integrator from a checkpoint,
+/// checking that the number of particles did not change.
+fn restore_vectors(reader: &mut CheckpointReader, values: &mut Vec<Vector3D>) -> Result<(), CheckpointError> {
+    let restored = try!(reader.read_vectors());
+    if restored.len() != values.len() {
+        return Err(CheckpointError::from(format!(
+            "wrong number of particles in integrator checkpoint: expected {}, got {}",
+            values.len(), restored.len()
+        )));
+    }
+    *values = restored;
+    Ok(())
 }
 
 /// Velocity-Verlet integrator. This one is reversible and symplectic.
@@ -31,6 +66,11 @@ impl VelocityVerlet {
             accelerations: Vec::new(),
         }
     }
+
+    /// Get the timestep of this integrator
+    pub fn timestep(&self) -> f64 {
+        self.timestep
+    }
 }
 
 impl Integrator for VelocityVerlet {
@@ -38,6 +78,14 @@ impl Integrator for VelocityVerlet {
         self.accelerations = vec![Vector3D::zero(); system.size()];
     }
 
+    fn save_state(&self, writer: &mut CheckpointWriter) {
+        writer.write_vectors(&self.accelerations);
+    }
+
+    fn restore_state(&mut self, reader: &mut CheckpointReader) -> Result<(), CheckpointError> {
+        restore_vectors(reader, &mut self.accelerations)
+    }
+
     fn integrate(&mut self, system: &mut System) {
         let dt = self.timestep;
 
@@ -73,6 +121,11 @@ impl Verlet {
             prevpos: Vec::new(),
         }
     }
+
+    /// Get the timestep of this integrator
+    pub fn timestep(&self) -> f64 {
+        self.timestep
+    }
 }
 
 impl Integrator for Verlet {
@@ -86,6 +139,14 @@ impl Integrator for Verlet {
         }
     }
 
+    fn save_state(&self, writer: &mut CheckpointWriter) {
+        writer.write_vectors(&self.prevpos);
+    }
+
+    fn restore_state(&mut self, reader: &mut CheckpointReader) -> Result<(), CheckpointError> {
+        restore_vectors(reader, &mut self.prevpos)
+    }
+
     fn integrate(&mut self, system: &mut System) {
         let dt = self.timestep;
         let dt2 = self.timestep * self.timestep;
@@ -124,6 +185,11 @@ impl LeapFrog {
             accelerations: Vec::new(),
         }
     }
+
+    /// Get the timestep of this integrator
+    pub fn timestep(&self) -> f64 {
+        self.timestep
+    }
 }
 
 impl Integrator for LeapFrog {
@@ -131,6 +197,14 @@ impl Integrator for LeapFrog {
         self.accelerations = vec![Vector3D::zero(); system.size()];
     }
 
+    fn save_state(&self, writer: &mut CheckpointWriter) {
+        writer.write_vectors(&self.accelerations);
+    }
+
+    fn restore_state(&mut self, reader: &mut CheckpointReader) -> Result<(), CheckpointError> {
+        restore_vectors(reader, &mut self.accelerations)
+    }
+
     fn integrate(&mut self, system: &mut System) {
         let dt = self.timestep;
         let dt2 = self.timestep * self.timestep;
@@ -182,6 +256,16 @@ impl BerendsenBarostat {
             eta: 1.0,
         }
     }
+
+    /// Get the timestep of this integrator
+    pub fn timestep(&self) -> f64 {
+        self.timestep
+    }
+
+    /// Get the barostat time scale, expressed in units of the timestep
+    pub fn tau(&self) -> f64 {
+        self.tau
+    }
 }
 
 impl Integrator for BerendsenBarostat {
@@ -189,6 +273,21 @@ impl Integrator for BerendsenBarostat {
         self.accelerations = vec![Vector3D::zero(); system.size()];
     }
 
+    fn pressure(&self) -> Option<f64> {
+        Some(self.pressure)
+    }
+
+    fn save_state(&self, writer: &mut CheckpointWriter) {
+        writer.write_vectors(&self.accelerations);
+        writer.write_f64(self.eta);
+    }
+
+    fn restore_state(&mut self, reader: &mut CheckpointReader) -> Result<(), CheckpointError> {
+        try!(restore_vectors(reader, &mut self.accelerations));
+        self.eta = try!(reader.read_f64());
+        Ok(())
+    }
+
     fn integrate(&mut self, system: &mut System) {
         let dt = self.timestep;
 
@@ -248,6 +347,21 @@ impl AnisoBerendsenBarostat {
     pub fn hydrostatic(timestep: f64, pressure: f64, tau: f64) -> AnisoBerendsenBarostat {
         AnisoBerendsenBarostat::new(timestep, pressure * Matrix3::one(), tau)
     }
+
+    /// Get the timestep of this integrator
+    pub fn timestep(&self) -> f64 {
+        self.timestep
+    }
+
+    /// Get the target stress matrix of this barostat
+    pub fn stress(&self) -> Matrix3 {
+        self.stress
+    }
+
+    /// Get the barostat time scale, expressed in units of the timestep
+    pub fn tau(&self) -> f64 {
+        self.tau
+    }
 }
 
 impl Integrator for AnisoBerendsenBarostat {
@@ -255,6 +369,27 @@ impl Integrator for AnisoBerendsenBarostat {
         self.accelerations = vec![Vector3D::zero(); system.size()];
     }
 
+    fn pressure(&self) -> Option<f64> {
+        // Only hydrostatic stress correspond to a pressure
+        let pressure = self.stress[0][0];
+        if self.stress == pressure * Matrix3::one() {
+            Some(pressure)
+        } else {
+            None
+        }
+    }
+
+    fn save_state(&self, writer: &mut CheckpointWriter) {
+        writer.write_vectors(&self.accelerations);
+        writer.write_matrix(&self.eta);
+    }
+
+    fn restore_state(&mut self, reader: &mut CheckpointReader) -> Result<(), CheckpointError> {
+        try!(restore_vectors(reader, &mut self.accelerations));
+        self.eta = try!(reader.read_matrix());
+        Ok(())
+    }
+
     fn integrate(&mut self, system: &mut System) {
         let dt = self.timestep;
 
@@ -2,7 +2,8 @@
 // Copyright (C) 2015-2016 Lumol's contributors — BSD license
 
 use sys::System;
-use sim::{Propagator, TemperatureStrategy};
+use sim::{Propagator, TemperatureStrategy, Ensemble};
+use checkpoint::{CheckpointWriter, CheckpointReader, CheckpointError};
 
 use super::{Integrator, Control, Thermostat};
 use super::VelocityVerlet;
@@ -43,6 +44,21 @@ impl MolecularDynamics {
     pub fn set_thermostat(&mut self, thermostat: Box<Thermostat>) {
         self.thermostat = Some(thermostat);
     }
+
+    /// Get the integrator used by this simulation
+    pub fn integrator(&self) -> &Integrator {
+        &*self.integrator
+    }
+
+    /// Get the thermostat used by this simulation, if any
+    pub fn thermostat(&self) -> Option<&Thermostat> {
+        self.thermostat.as_ref().map(|thermostat| &**thermostat)
+    }
+
+    /// Get the control algorithms used by this simulation
+    pub fn controls(&self) -> &[Box<Control>] {
+        &self.controls
+    }
 }
 
 impl Propagator for MolecularDynamics {
@@ -50,6 +66,17 @@ impl Propagator for MolecularDynamics {
         TemperatureStrategy::Velocities
     }
 
+    fn ensemble(&self) -> Ensemble {
+        let temperature = self.thermostat.as_ref().map(|thermostat| thermostat.temperature());
+        match (temperature, self.integrator.pressure()) {
+            (None, None) => Ensemble::NVE,
+            (Some(temperature), None) => Ensemble::NVT(temperature),
+            (Some(temperature), Some(pressure)) => Ensemble::NPT(temperature, pressure),
+            // Isoenthalpic-isobaric ensemble
+            (None, Some(_)) => Ensemble::Unknown,
+        }
+    }
+
     fn setup(&mut self, system: &System) {
         self.integrator.setup(system);
         for control in &mut self.controls {
@@ -74,4 +101,37 @@ impl Propagator for MolecularDynamics {
             control.finish(system);
         }
     }
+
+    fn save_state(&self, writer: &mut CheckpointWriter) {
+        self.integrator.save_state(writer);
+        writer.write_bool(self.thermostat.is_some());
+        if let Some(ref thermostat) = self.thermostat {
+            thermostat.save_state(writer);
+        }
+        writer.write_usize(self.controls.len());
+        for control in &self.controls {
+            control.save_state(writer);
+        }
+    }
+
+    fn restore_state(&mut self, reader: &mut CheckpointReader) -> Result<(), CheckpointError> {
+        try!(self.integrator.restore_state(reader));
+        if try!(reader.read_bool()) != self.thermostat.is_some() {
+            return Err(CheckpointError::from(
+                "the thermostat in the checkpoint does not match the simulation"
+            ));
+        }
+        if let Some(ref mut thermostat) = self.thermostat {
+            try!(thermostat.restore_state(reader));
+        }
+        if try!(reader.read_usize()) != self.controls.len() {
+            return Err(CheckpointError::from(
+                "the number of controls in the checkpoint does not match the simulation"
+            ));
+        }
+        for control in &mut self.controls {
+            try!(control.restore_state(reader));
+        }
+        Ok(())
+    }
 }
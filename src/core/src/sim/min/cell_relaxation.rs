@@ -0,0 +1,529 @@
+// Lumol, an extensible molecular simulation engine
+// Copyright (C) 2015-2016 Lumol's contributors — BSD license
+
+use utils;
+use sys::{System, UnitCell, CellShape};
+use types::{Matrix3, Vector3D, Zero, One};
+use super::{Minimizer, Tolerance};
+use super::configuration::dot;
+use super::conjugate_gradient::polak_ribiere;
+use super::line_search::{line_search, LinePoint};
+use checkpoint::{CheckpointWriter, CheckpointReader, CheckpointError};
+
+use std::f64;
+
+/// Parameter for the curvature condition in the line search
+const CURVATURE: f64 = 0.1;
+
+/// Possible deformations of the unit cell during a `CellRelaxation`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CellDeformation {
+    /// Scale all the cell vectors by the same factor, keeping the cell shape
+    Isotropic,
+    /// Scale independently the three cell lengths, keeping the cell angles.
+    /// This can only be used with orthorhombic cells.
+    Orthorhombic,
+    /// Change both lengths and angles of the cell. This can only be used with
+    /// triclinic cells.
+    Triclinic,
+}
+
+impl CellDeformation {
+    /// Project the `matrix` on the space of deformations allowed here
+    fn project(&self, matrix: Matrix3) -> Matrix3 {
+        match *self {
+            CellDeformation::Isotropic => matrix.trace() / 3.0 * Matrix3::one(),
+            CellDeformation::Orthorhombic => Matrix3::new(
+                matrix[0][0], 0.0, 0.0,
+                0.0, matrix[1][1], 0.0,
+                0.0, 0.0, matrix[2][2]
+            ),
+            // Only use the symmetric part, to prevent rotations of the cell
+            CellDeformation::Triclinic => 0.5 * (matrix + matrix.transposed()),
+        }
+    }
+}
+
+/// Energy, forces and virial of the system in a given configuration
+struct State {
+    /// Atomic positions
+    positions: Vec<Vector3D>,
+    /// Unit cell
+    cell: UnitCell,
+    /// Potential energy
+    energy: f64,
+    /// Atomic forces
+    forces: Vec<Vector3D>,
+    /// Virial tensor
+    virial: Matrix3,
+}
+
+impl State {
+    fn new(system: &System) -> State {
+        State {
+            positions: system.iter().map(|particle| particle.position).collect(),
+            cell: *system.cell(),
+            energy: system.potential_energy(),
+            forces: system.forces(),
+            virial: system.virial(),
+        }
+    }
+
+    fn matches(&self, system: &System) -> bool {
+        *system.cell() == self.cell && self.positions.len() == system.size() &&
+        system.iter().zip(&self.positions).all(|(particle, &position)| {
+            particle.position == position
+        })
+    }
+}
+
+/// Minimization of the enthalpy with respect to both the atomic positions
+/// and the unit cell, under a constant external stress.
+///
+/// The atomic positions and the cell are optimized together, using a
+/// Polak-Ribière conjugate gradient on the enthalpy. The gradient with
+/// respect to the cell comes from the difference between the virial stress
+/// of the system and the external stress. The deformation of the cell can be
+/// restricted with a `CellDeformation`.
+///
+/// The `energy` returned to the `Minimization` is the enthalpy `U + PV`, and
+/// `force2` include the generalized force acting on the cell, so that the
+/// minimization only converges when the internal stress matches the external
+/// one.
+pub struct CellRelaxation {
+    /// Allowed deformations of the cell
+    deformation: CellDeformation,
+    /// Hydrostatic part of the external stress
+    pressure: f64,
+    /// Traceless part of the external stress
+    deviatoric: Matrix3,
+    /// Initial step length for the line search
+    step: f64,
+    /// Maximal displacement of an atom at the first step of a line search
+    max_displacement: f64,
+    /// Generalized forces at the beginning of the previous iteration
+    previous: Vec<Vector3D>,
+    /// Search direction used in the previous iteration
+    direction: Vec<Vector3D>,
+    /// State of the system at the end of the previous iteration
+    state: Option<State>,
+}
+
+impl CellRelaxation {
+    /// Create a new `CellRelaxation` minimizer, using the given external
+    /// `pressure` and allowed cell `deformation`.
+    pub fn new(pressure: f64, deformation: CellDeformation) -> CellRelaxation {
+        CellRelaxation::with_stress(pressure * Matrix3::one(), deformation)
+    }
+
+    /// Create a new `CellRelaxation` minimizer, using the given external
+    /// `stress` tensor and allowed cell `deformation`. The stress tensor use
+    /// the same convention as `System::stress`, positive diagonal values
+    /// corresponding to a positive pressure.
+    pub fn with_stress(stress: Matrix3, deformation: CellDeformation) -> CellRelaxation {
+        let stress = 0.5 * (stress + stress.transposed());
+        let pressure = stress.trace() / 3.0;
+        CellRelaxation {
+            deformation: deformation,
+            pressure: pressure,
+            deviatoric: stress - pressure * Matrix3::one(),
+            step: utils::unit_from(0.1, "fs^2/u"),
+            max_displacement: utils::unit_from(0.1, "A"),
+            previous: Vec::new(),
+            direction: Vec::new(),
+            state: None,
+        }
+    }
+
+    /// Get the allowed deformations of the cell
+    pub fn deformation(&self) -> CellDeformation {
+        self.deformation
+    }
+
+    /// Get the hydrostatic pressure corresponding to the external stress
+    pub fn pressure(&self) -> f64 {
+        self.pressure
+    }
+
+    /// Get the external stress tensor, or `None` if the external stress is
+    /// hydrostatic and fully described by the `pressure`.
+    pub fn stress(&self) -> Option<Matrix3> {
+        if self.deviatoric == Matrix3::zero() {
+            None
+        } else {
+            Some(self.deviatoric + self.pressure * Matrix3::one())
+        }
+    }
+
+    /// Forget about the previous iterations, and restart from the steepest
+    /// descent direction.
+    fn reset(&mut self) {
+        self.previous.clear();
+        self.direction.clear();
+    }
+
+    /// Get the enthalpy and the generalized forces for the `state`, deformed
+    /// by `strain` from a reference configuration with volume
+    /// `reference_volume`. The cell strain is scaled by `length` to get the
+    /// same units for atomic and cell degrees of freedom.
+    ///
+    /// The generalized forces contains the atomic forces, followed by the
+    /// three rows of the force acting on the cell.
+    fn generalized(&self, state: &State, strain: &Matrix3, reference_volume: f64, length: f64) -> (f64, Vec<Vector3D>) {
+        let deformation = Matrix3::one() + *strain;
+        let volume = state.cell.volume();
+        let mut enthalpy = state.energy + self.pressure * volume;
+        for i in 0..3 {
+            for j in 0..3 {
+                enthalpy += reference_volume * self.deviatoric[i][j] * strain[i][j];
+            }
+        }
+
+        let transposed = deformation.transposed();
+        let mut forces = state.forces.iter().map(|&force| transposed * force).collect::<Vec<_>>();
+
+        let stress = state.virial - self.pressure * volume * Matrix3::one();
+        let cell_force = stress * deformation.inverse().transposed() - reference_volume * self.deviatoric;
+        let cell_force = self.deformation.project(cell_force / length);
+        for i in 0..3 {
+            forces.push(Vector3D::new(cell_force[i][0], cell_force[i][1], cell_force[i][2]));
+        }
+
+        return (enthalpy, forces);
+    }
+}
+
+impl Minimizer for CellRelaxation {
+    fn setup(&mut self, system: &System) {
+        let shape = system.cell().shape();
+        assert!(shape != CellShape::Infinite, "Can not relax an infinite cell");
+        assert!(
+            shape == CellShape::Triclinic || self.deformation != CellDeformation::Triclinic,
+            "Can not use triclinic cell relaxation with an orthorhombic cell"
+        );
+        // Scaling the cartesian axes would change the angles of a triclinic
+        // cell
+        assert!(
+            shape != CellShape::Triclinic || self.deformation != CellDeformation::Orthorhombic,
+            "Can not use orthorhombic cell relaxation with a triclinic cell"
+        );
+        self.reset();
+        self.state = None;
+    }
+
+    fn energy_changed(&mut self) {
+        self.state = None;
+    }
+
+    fn save_state(&self, writer: &mut CheckpointWriter) {
+        writer.write_f64(self.step);
+        writer.write_vectors(&self.previous);
+        writer.write_vectors(&self.direction);
+        writer.write_bool(self.state.is_some());
+        if let Some(ref state) = self.state {
+            writer.write_vectors(&state.positions);
+            writer.write_section(&state.cell);
+            writer.write_f64(state.energy);
+            writer.write_vectors(&state.forces);
+            writer.write_matrix(&state.virial);
+        }
+    }
+
+    fn restore_state(&mut self, reader: &mut CheckpointReader) -> Result<(), CheckpointError> {
+        self.step = try!(reader.read_f64());
+        self.previous = try!(reader.read_vectors());
+        self.direction = try!(reader.read_vectors());
+        self.state = None;
+        if try!(reader.read_bool()) {
+            let positions = try!(reader.read_vectors());
+            let mut cell = UnitCell::new();
+            try!(reader.read_section(&mut cell));
+            let energy = try!(reader.read_f64());
+            let forces = try!(reader.read_vectors());
+            let virial = try!(reader.read_matrix());
+            if forces.len() != positions.len() {
+                return Err(CheckpointError::from("invalid cell relaxation state in checkpoint"));
+            }
+            self.state = Some(State {
+                positions: positions,
+                cell: cell,
+                energy: energy,
+                forces: forces,
+                virial: virial,
+            });
+        }
+        Ok(())
+    }
+
+    fn energy(&self, system: &System) -> f64 {
+        // Enthalpy of the undeformed system, the deviatoric part of the
+        // stress only contributes through the strain.
+        system.potential_energy() + self.pressure * system.volume()
+    }
+
+    fn minimize(&mut self, system: &mut System) -> Tolerance {
+        let state = match self.state.take() {
+            Some(state) => {
+                if state.matches(system) {
+                    state
+                } else {
+                    self.reset();
+                    State::new(system)
+                }
+            }
+            None => State::new(system),
+        };
+
+        let natoms = state.positions.len();
+        let volume = state.cell.volume();
+        let length = f64::cbrt(natoms as f64 * volume);
+        let (enthalpy, forces) = self.generalized(&state, &Matrix3::zero(), volume, length);
+        let force2 = forces.iter().map(|&f| f.norm2()).fold(f64::NAN, f64::max);
+
+        let (direction, conjugate) = polak_ribiere(&forces, &self.previous, &self.direction);
+        let max_direction = direction.iter().map(|d| d.norm()).fold(0.0, f64::max);
+        if max_direction == 0.0 {
+            self.state = Some(state);
+            return Tolerance {
+                energy: enthalpy,
+                force2: force2,
+            };
+        }
+
+        let cell_direction = Matrix3::new(
+            direction[natoms][0], direction[natoms][1], direction[natoms][2],
+            direction[natoms + 1][0], direction[natoms + 1][1], direction[natoms + 1][2],
+            direction[natoms + 2][0], direction[natoms + 2][1], direction[natoms + 2][2],
+        ) / length;
+
+        let initial = f64::min(self.step, self.max_displacement / max_direction);
+        let start = LinePoint{step: 0.0, energy: enthalpy, slope: -dot(&forces, &direction)};
+        let mut last = None;
+        let result = {
+            let evaluate = |step: f64| {
+                let strain = step * cell_direction;
+                let deformation = Matrix3::one() + strain;
+                system.set_cell(state.cell.scale(deformation));
+                for (i, particle) in system.iter_mut().enumerate() {
+                    particle.position = deformation * (state.positions[i] + step * direction[i]);
+                }
+
+                let new_state = State::new(system);
+                let (energy, forces) = self.generalized(&new_state, &strain, volume, length);
+                last = Some(new_state);
+                LinePoint {
+                    step: step,
+                    energy: energy,
+                    slope: -dot(&forces, &direction),
+                }
+            };
+            line_search(evaluate, start, initial, CURVATURE)
+        };
+
+        match result {
+            Some(point) => {
+                self.step = point.step;
+                self.previous = forces;
+                self.direction = direction;
+                let state = last.expect("missing state after line search");
+                let volume = state.cell.volume();
+                let length = f64::cbrt(natoms as f64 * volume);
+                let (enthalpy, forces) = self.generalized(&state, &Matrix3::zero(), volume, length);
+                self.state = Some(state);
+                return Tolerance {
+                    energy: enthalpy,
+                    force2: forces.iter().map(|&f| f.norm2()).fold(f64::NAN, f64::max),
+                };
+            }
+            None => {
+                // No lower enthalpy along this direction, go back to the
+                // initial configuration and restart from the steepest descent
+                system.set_cell(state.cell);
+                for (i, particle) in system.iter_mut().enumerate() {
+                    particle.position = state.positions[i];
+                }
+                self.state = Some(state);
+                self.reset();
+                self.step /= 2.0;
+                if conjugate {
+                    return self.minimize(system);
+                }
+                return Tolerance {
+                    energy: enthalpy,
+                    force2: force2,
+                };
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sys::{System, UnitCell, Particle};
+    use types::{Matrix3, Vector3D, One};
+    use energy::*;
+    use sim::{Propagator, Minimization};
+    use utils::unit_from;
+
+    fn testing_system(cell: UnitCell) -> System {
+        // Face-centered cubic crystal, with 2x2x2 conventional cells
+        let basis = [
+            Vector3D::new(0.0, 0.0, 0.0), Vector3D::new(0.5, 0.5, 0.0),
+            Vector3D::new(0.5, 0.0, 0.5), Vector3D::new(0.0, 0.5, 0.5),
+        ];
+        let mut system = System::from_cell(cell);
+        for i in 0..2 {
+            for j in 0..2 {
+                for k in 0..2 {
+                    for atom in &basis {
+                        let fractional = (Vector3D::new(i as f64, j as f64, k as f64) + *atom) / 2.0;
+                        let mut particle = Particle::new("Ar");
+                        particle.position = cell.cartesian(&fractional);
+                        system.add_particle(particle);
+                    }
+                }
+            }
+        }
+
+        let lj = Box::new(LennardJones{
+            sigma: unit_from(3.4, "A"),
+            epsilon: unit_from(1.0, "kJ/mol"),
+        });
+        system.interactions_mut().add_pair("Ar", "Ar", PairInteraction::new(lj, 5.0));
+        return system;
+    }
+
+    fn static_stress(system: &System) -> Matrix3 {
+        system.virial() / system.volume()
+    }
+
+    #[test]
+    fn isotropic() {
+        let mut system = testing_system(UnitCell::cubic(10.8));
+        let pressure = unit_from(1000.0, "bar");
+
+        let relaxation = CellRelaxation::new(pressure, CellDeformation::Isotropic);
+        let mut minization = Minimization::new(Box::new(relaxation));
+        minization.setup(&system);
+        for _ in 0..200 {
+            minization.propagate(&mut system);
+        }
+        assert!(minization.converged());
+
+        let cell = system.cell();
+        assert_relative_eq!(cell.a(), cell.b(), epsilon=1e-9);
+        assert_relative_eq!(cell.a(), cell.c(), epsilon=1e-9);
+        let stress = static_stress(&system);
+        assert_relative_eq!(stress.trace() / 3.0, pressure, epsilon=1e-3 * pressure);
+    }
+
+    #[test]
+    fn orthorhombic() {
+        let mut system = testing_system(UnitCell::ortho(10.6, 10.8, 11.0));
+        let stress = Matrix3::new(
+            unit_from(-500.0, "bar"), 0.0, 0.0,
+            0.0, unit_from(0.0, "bar"), 0.0,
+            0.0, 0.0, unit_from(500.0, "bar"),
+        );
+
+        let relaxation = CellRelaxation::with_stress(stress, CellDeformation::Orthorhombic);
+        let mut minization = Minimization::new(Box::new(relaxation));
+        minization.setup(&system);
+        for _ in 0..500 {
+            minization.propagate(&mut system);
+        }
+        assert!(minization.converged());
+
+        let tolerance = unit_from(1.0, "bar");
+        let internal = static_stress(&system);
+        for i in 0..3 {
+            assert_relative_eq!(internal[i][i], stress[i][i], epsilon=tolerance);
+        }
+        assert_eq!(system.cell().alpha(), 90.0);
+    }
+
+    #[test]
+    fn triclinic() {
+        let mut system = testing_system(UnitCell::triclinic(10.8, 10.8, 10.8, 90.0, 93.0, 88.0));
+        let pressure = unit_from(100.0, "bar");
+
+        let relaxation = CellRelaxation::new(pressure, CellDeformation::Triclinic);
+        let mut minization = Minimization::new(Box::new(relaxation));
+        minization.setup(&system);
+        for _ in 0..500 {
+            minization.propagate(&mut system);
+        }
+        assert!(minization.converged());
+
+        let tolerance = unit_from(1.0, "bar");
+        let stress = static_stress(&system);
+        for i in 0..3 {
+            for j in 0..3 {
+                let expected = if i == j {pressure} else {0.0};
+                assert_relative_eq!(stress[i][j], expected, epsilon=tolerance);
+            }
+        }
+        assert_relative_eq!(system.cell().alpha(), 90.0, epsilon=1e-2);
+        assert_relative_eq!(system.cell().beta(), 90.0, epsilon=1e-2);
+        assert_relative_eq!(system.cell().gamma(), 90.0, epsilon=1e-2);
+    }
+
+    #[test]
+    fn enthalpy() {
+        let mut system = testing_system(UnitCell::cubic(10.8));
+        let pressure = unit_from(1000.0, "bar");
+        let relaxation = CellRelaxation::new(pressure, CellDeformation::Isotropic);
+        let enthalpy = system.potential_energy() + pressure * system.volume();
+        assert_eq!(relaxation.energy(&system), enthalpy);
+
+        // The first step compares two enthalpies, and converges with a
+        // tolerance smaller than the PV term.
+        let tolerance = Tolerance {
+            energy: 0.5 * pressure * system.volume(),
+            force2: 0.0,
+        };
+        let mut minization = Minimization::with_tolerance(Box::new(relaxation), tolerance);
+        minization.setup(&system);
+        minization.propagate(&mut system);
+        assert!(minization.converged());
+    }
+
+    #[test]
+    #[should_panic]
+    fn infinite_cell() {
+        let system = testing_system(UnitCell::new());
+        let mut relaxation = CellRelaxation::new(0.0, CellDeformation::Isotropic);
+        relaxation.setup(&system);
+    }
+
+    #[test]
+    #[should_panic]
+    fn triclinic_orthorhombic_cell() {
+        let system = testing_system(UnitCell::cubic(10.8));
+        let mut relaxation = CellRelaxation::new(0.0, CellDeformation::Triclinic);
+        relaxation.setup(&system);
+    }
+
+    #[test]
+    #[should_panic]
+    fn orthorhombic_triclinic_cell() {
+        let system = testing_system(UnitCell::triclinic(10.8, 10.8, 10.8, 90.0, 93.0, 88.0));
+        let mut relaxation = CellRelaxation::new(0.0, CellDeformation::Orthorhombic);
+        relaxation.setup(&system);
+    }
+
+    #[test]
+    fn projection() {
+        let matrix = Matrix3::new(1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 8.0, 9.0);
+        assert_eq!(CellDeformation::Isotropic.project(matrix), 5.0 * Matrix3::one());
+        assert_eq!(
+            CellDeformation::Orthorhombic.project(matrix),
+            Matrix3::new(1.0, 0.0, 0.0, 0.0, 5.0, 0.0, 0.0, 0.0, 9.0)
+        );
+        assert_eq!(
+            CellDeformation::Triclinic.project(matrix),
+            Matrix3::new(1.0, 3.0, 5.0, 3.0, 5.0, 7.0, 5.0, 7.0, 9.0)
+        );
+    }
+}
@@ -0,0 +1,103 @@
+// Lumol, an extensible molecular simulation engine
+// Copyright (C) 2015-2016 Lumol's contributors — BSD license
+
+//! Helpers shared by the minimizers working directly with atomic positions.
+use sys::System;
+use types::Vector3D;
+use checkpoint::{Checkpoint, CheckpointWriter, CheckpointReader, CheckpointError};
+
+use std::f64;
+
+/// Positions, energy and forces of a system at the end of a minimization
+/// step. This allow to reuse the energy and forces in the next step, as long
+/// as the system was not modified in between.
+pub struct Configuration {
+    /// Positions of the atoms
+    pub positions: Vec<Vector3D>,
+    /// Potential energy of the system
+    pub energy: f64,
+    /// Forces acting on the atoms
+    pub forces: Vec<Vector3D>,
+}
+
+impl Configuration {
+    /// Create a new empty configuration, which will not match any system
+    pub fn new() -> Configuration {
+        Configuration {
+            positions: Vec::new(),
+            energy: 0.0,
+            forces: Vec::new(),
+        }
+    }
+
+    /// Forget about this configuration, so that the next call to `update`
+    /// recomputes energy and forces.
+    pub fn clear(&mut self) {
+        self.positions.clear();
+        self.forces.clear();
+    }
+
+    /// Forget about the energy and forces in this configuration, while
+    /// keeping the positions. The next call to `update` will recompute them
+    /// even if the atoms did not move.
+    pub fn invalidate(&mut self) {
+        self.forces.clear();
+    }
+
+    /// Make sure this configuration matches the current state of the
+    /// `system`, recomputing the energy and the forces if needed. This
+    /// function returns `false` if the atoms were moved since the last time
+    /// the configuration was set.
+    pub fn update(&mut self, system: &System) -> bool {
+        let unchanged = self.positions.len() == system.size() &&
+            system.iter().zip(&self.positions).all(|(particle, &position)| {
+                particle.position == position
+            });
+
+        if !unchanged || self.forces.len() != self.positions.len() {
+            self.set(system, system.potential_energy(), system.forces());
+        }
+        return unchanged;
+    }
+
+    /// Set this configuration from the current positions in the `system`,
+    /// and the corresponding `energy` and `forces`.
+    pub fn set(&mut self, system: &System, energy: f64, forces: Vec<Vector3D>) {
+        self.positions = system.iter().map(|particle| particle.position).collect();
+        self.energy = energy;
+        self.forces = forces;
+    }
+
+    /// Get the maximal squared norm of the forces in this configuration
+    pub fn max_force2(&self) -> f64 {
+        self.forces.iter().map(|&f| f.norm2()).fold(f64::NAN, f64::max)
+    }
+}
+
+impl Checkpoint for Configuration {
+    fn save(&self, writer: &mut CheckpointWriter) {
+        writer.write_vectors(&self.positions);
+        writer.write_f64(self.energy);
+        writer.write_vectors(&self.forces);
+    }
+
+    fn restore(&mut self, reader: &mut CheckpointReader) -> Result<(), CheckpointError> {
+        let positions = try!(reader.read_vectors());
+        let energy = try!(reader.read_f64());
+        let forces = try!(reader.read_vectors());
+        // The forces are removed by `invalidate`, but are otherwise set
+        // together with the positions
+        if !forces.is_empty() && forces.len() != positions.len() {
+            return Err(CheckpointError::from("invalid minimizer configuration in checkpoint"));
+        }
+        self.positions = positions;
+        self.energy = energy;
+        self.forces = forces;
+        Ok(())
+    }
+}
+
+/// Scalar product of two sets of vectors
+pub fn dot(a: &[Vector3D], b: &[Vector3D]) -> f64 {
+    a.iter().zip(b).fold(0.0, |sum, (&u, &v)| sum + u * v)
+}
@@ -0,0 +1,196 @@
+// Lumol, an extensible molecular simulation engine
+// Copyright (C) 2015-2016 Lumol's contributors — BSD license
+
+use utils;
+use sys::System;
+use types::Vector3D;
+use super::{Minimizer, Tolerance};
+use super::configuration::{Configuration, dot};
+use super::line_search::search_along;
+use checkpoint::{Checkpoint, CheckpointWriter, CheckpointReader, CheckpointError};
+
+/// Parameter for the curvature condition in the line search. Conjugate
+/// gradient methods need a fairly accurate line search.
+const CURVATURE: f64 = 0.1;
+
+/// Polak-Ribière nonlinear conjugate gradient minimization algorithm.
+///
+/// At each step, this method searches a minimum of the energy along a
+/// direction made from the current forces and the previous direction. This
+/// converges much faster than the steepest descent for most systems.
+pub struct ConjugateGradient {
+    /// Initial step length for the line search
+    step: f64,
+    /// Maximal displacement of an atom at the first step of a line search
+    max_displacement: f64,
+    /// Forces at the beginning of the previous iteration
+    previous: Vec<Vector3D>,
+    /// Search direction used in the previous iteration
+    direction: Vec<Vector3D>,
+    /// Configuration at the end of the previous iteration
+    configuration: Configuration,
+}
+
+impl Default for ConjugateGradient {
+    fn default() -> ConjugateGradient {
+        ConjugateGradient::new()
+    }
+}
+
+impl ConjugateGradient {
+    /// Create a new `ConjugateGradient` minimizer
+    pub fn new() -> ConjugateGradient {
+        ConjugateGradient {
+            step: utils::unit_from(0.1, "fs^2/u"),
+            max_displacement: utils::unit_from(0.1, "A"),
+            previous: Vec::new(),
+            direction: Vec::new(),
+            configuration: Configuration::new(),
+        }
+    }
+
+    /// Forget about the previous iterations, and restart from the steepest
+    /// descent direction.
+    fn reset(&mut self) {
+        self.previous.clear();
+        self.direction.clear();
+    }
+}
+
+impl Minimizer for ConjugateGradient {
+    fn setup(&mut self, _: &System) {
+        self.reset();
+        self.configuration.clear();
+    }
+
+    fn energy_changed(&mut self) {
+        self.configuration.invalidate();
+    }
+
+    fn save_state(&self, writer: &mut CheckpointWriter) {
+        writer.write_f64(self.step);
+        writer.write_vectors(&self.previous);
+        writer.write_vectors(&self.direction);
+        self.configuration.save(writer);
+    }
+
+    fn restore_state(&mut self, reader: &mut CheckpointReader) -> Result<(), CheckpointError> {
+        self.step = try!(reader.read_f64());
+        self.previous = try!(reader.read_vectors());
+        self.direction = try!(reader.read_vectors());
+        self.configuration.restore(reader)
+    }
+
+    fn minimize(&mut self, system: &mut System) -> Tolerance {
+        if !self.configuration.update(system) {
+            self.reset();
+        }
+
+        let (direction, conjugate) = polak_ribiere(&self.configuration.forces, &self.previous, &self.direction);
+        let max_direction = direction.iter().map(|d| d.norm()).fold(0.0, f64::max);
+        if max_direction == 0.0 {
+            // All the forces are zero, we are already at a minimum
+            return Tolerance {
+                energy: self.configuration.energy,
+                force2: self.configuration.max_force2(),
+            };
+        }
+
+        let initial = f64::min(self.step, self.max_displacement / max_direction);
+        match search_along(system, &self.configuration, &direction, initial, CURVATURE) {
+            Some((point, forces)) => {
+                self.step = point.step;
+                self.previous = self.configuration.forces.clone();
+                self.direction = direction;
+                self.configuration.set(system, point.energy, forces);
+            }
+            None => {
+                // No lower energy along this direction, restart from the
+                // steepest descent
+                self.reset();
+                self.step /= 2.0;
+                if conjugate {
+                    return self.minimize(system);
+                }
+            }
+        }
+
+        return Tolerance {
+            energy: self.configuration.energy,
+            force2: self.configuration.max_force2(),
+        }
+    }
+}
+
+/// Get a new search direction from the current `forces`, the `previous`
+/// forces and the previous `direction` using the Polak-Ribière formula. The
+/// boolean is `true` if the direction is not the steepest descent one.
+pub fn polak_ribiere(forces: &[Vector3D], previous: &[Vector3D], direction: &[Vector3D]) -> (Vec<Vector3D>, bool) {
+    let mut new_direction = forces.to_vec();
+    if previous.len() != forces.len() || direction.len() != forces.len() {
+        return (new_direction, false);
+    }
+
+    let mut previous_force2 = 0.0;
+    let mut numerator = 0.0;
+    for (&force, &previous) in forces.iter().zip(previous) {
+        previous_force2 += previous * previous;
+        numerator += force * (force - previous);
+    }
+
+    // Restart with the steepest descent direction when beta becomes negative
+    let beta = numerator / previous_force2;
+    if !(beta > 0.0 && beta.is_finite()) {
+        return (new_direction, false);
+    }
+
+    for (d, &previous) in new_direction.iter_mut().zip(direction) {
+        *d += beta * previous;
+    }
+
+    if dot(forces, &new_direction) > 0.0 {
+        (new_direction, true)
+    } else {
+        // Not a descent direction, use the forces instead
+        (forces.to_vec(), false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sys::{System, UnitCell, Particle};
+    use types::{Vector3D, Zero};
+    use energy::*;
+    use sim::{Propagator, Minimization};
+
+    fn testing_system() -> System {
+        let mut system = System::from_cell(UnitCell::cubic(20.0));
+        system.add_particle(Particle::new("Cl"));
+        system[0].position = Vector3D::zero();
+        system.add_particle(Particle::new("Cl"));
+        system[1].position = Vector3D::new(0.0, 0.0, 2.0);
+        system.add_particle(Particle::new("Cl"));
+        system[2].position = Vector3D::new(0.0, 1.5, 0.5);
+
+        system.interactions_mut().add_pair("Cl", "Cl",
+            PairInteraction::new(Box::new(Harmonic{x0: 2.3, k: 0.1}), 10.0)
+        );
+        return system;
+    }
+
+    #[test]
+    fn minization() {
+        let mut system = testing_system();
+
+        let mut minization = Minimization::new(Box::new(ConjugateGradient::new()));
+        minization.setup(&system);
+        for _ in 0..100 {
+            minization.propagate(&mut system);
+        }
+        assert!(minization.converged());
+        assert_relative_eq!(system.distance(0, 1), 2.3, epsilon=1e-3);
+        assert_relative_eq!(system.distance(0, 2), 2.3, epsilon=1e-3);
+        assert_relative_eq!(system.distance(1, 2), 2.3, epsilon=1e-3);
+    }
+}
@@ -0,0 +1,244 @@
+// Lumol, an extensible molecular simulation engine
+// Copyright (C) 2015-2016 Lumol's contributors — BSD license
+
+use utils;
+use sys::System;
+use types::{Vector3D, Zero};
+use super::{Minimizer, Tolerance};
+use super::configuration::{Configuration, dot};
+use checkpoint::{Checkpoint, CheckpointWriter, CheckpointReader, CheckpointError};
+
+/// Number of steps with a positive power before increasing the timestep
+const N_MIN: usize = 5;
+/// Increase factor for the timestep
+const F_INC: f64 = 1.1;
+/// Decrease factor for the timestep
+const F_DEC: f64 = 0.5;
+/// Initial value of the mixing parameter
+const ALPHA_START: f64 = 0.1;
+/// Decrease factor for the mixing parameter
+const F_ALPHA: f64 = 0.99;
+
+/// Fast inertial relaxation engine minimization algorithm.
+///
+/// This method uses fictitious molecular dynamics, where the velocities are
+/// progressively aligned with the forces, and set to zero as soon as the
+/// system goes uphill. The timestep adapts to the energy landscape. This
+/// method is very robust, and is well suited to relax bad initial structures.
+///
+/// All the atoms have a mass of 1 u in the fictitious dynamics, and the
+/// displacement of each atom is limited to 0.1 Å at each step.
+///
+/// See Bitzek et al., Phys. Rev. Lett. 97, 170201 (2006).
+pub struct FIRE {
+    /// Current timestep
+    timestep: f64,
+    /// Initial timestep
+    initial_timestep: f64,
+    /// Maximal timestep
+    max_timestep: f64,
+    /// Maximal displacement of an atom in a single step
+    max_displacement: f64,
+    /// Mixing parameter between the velocities and the forces
+    alpha: f64,
+    /// Number of steps since the power was last negative
+    positive_steps: usize,
+    /// Fictitious velocities
+    velocities: Vec<Vector3D>,
+    /// Configuration at the end of the previous iteration
+    configuration: Configuration,
+}
+
+impl Default for FIRE {
+    fn default() -> FIRE {
+        FIRE::new()
+    }
+}
+
+impl FIRE {
+    /// Create a new `FIRE` minimizer with an initial timestep of 1 fs, and a
+    /// maximal timestep of 10 fs.
+    pub fn new() -> FIRE {
+        FIRE::with_timestep(utils::unit_from(1.0, "fs"), utils::unit_from(10.0, "fs"))
+    }
+
+    /// Create a new `FIRE` minimizer with the given initial `timestep` and
+    /// `max_timestep`.
+    ///
+    /// # Panics
+    ///
+    /// If any timestep is not positive, or if the maximal timestep is smaller
+    /// than the initial one.
+    pub fn with_timestep(timestep: f64, max_timestep: f64) -> FIRE {
+        assert!(timestep > 0.0, "FIRE timestep must be positive");
+        assert!(max_timestep >= timestep, "FIRE maximal timestep must be larger than the initial timestep");
+        FIRE {
+            timestep: timestep,
+            initial_timestep: timestep,
+            max_timestep: max_timestep,
+            max_displacement: utils::unit_from(0.1, "A"),
+            alpha: ALPHA_START,
+            positive_steps: 0,
+            velocities: Vec::new(),
+            configuration: Configuration::new(),
+        }
+    }
+
+    /// Get the initial timestep of this minimizer
+    pub fn timestep(&self) -> f64 {
+        self.initial_timestep
+    }
+
+    /// Get the maximal timestep of this minimizer
+    pub fn max_timestep(&self) -> f64 {
+        self.max_timestep
+    }
+
+    /// Reset the dynamics to its initial state
+    fn reset(&mut self, natoms: usize) {
+        self.timestep = self.initial_timestep;
+        self.alpha = ALPHA_START;
+        self.positive_steps = 0;
+        self.velocities = vec![Vector3D::zero(); natoms];
+    }
+}
+
+impl Minimizer for FIRE {
+    fn setup(&mut self, system: &System) {
+        self.reset(system.size());
+        self.configuration.clear();
+    }
+
+    fn energy_changed(&mut self) {
+        self.configuration.invalidate();
+    }
+
+    fn save_state(&self, writer: &mut CheckpointWriter) {
+        writer.write_f64(self.timestep);
+        writer.write_f64(self.alpha);
+        writer.write_usize(self.positive_steps);
+        writer.write_vectors(&self.velocities);
+        self.configuration.save(writer);
+    }
+
+    fn restore_state(&mut self, reader: &mut CheckpointReader) -> Result<(), CheckpointError> {
+        self.timestep = try!(reader.read_f64());
+        self.alpha = try!(reader.read_f64());
+        self.positive_steps = try!(reader.read_usize());
+        let velocities = try!(reader.read_vectors());
+        if velocities.len() != self.velocities.len() {
+            return Err(CheckpointError::from(format!(
+                "wrong number of particles in FIRE checkpoint: expected {}, got {}",
+                self.velocities.len(), velocities.len()
+            )));
+        }
+        self.velocities = velocities;
+        self.configuration.restore(reader)
+    }
+
+    fn minimize(&mut self, system: &mut System) -> Tolerance {
+        if !self.configuration.update(system) || self.velocities.len() != system.size() {
+            self.reset(system.size());
+        }
+
+        let forces = &self.configuration.forces;
+        let power = dot(forces, &self.velocities);
+        if power > 0.0 {
+            let velocity_norm = dot(&self.velocities, &self.velocities).sqrt();
+            let force_norm = dot(forces, forces).sqrt();
+            let mixing = self.alpha * velocity_norm / force_norm;
+            for (velocity, &force) in self.velocities.iter_mut().zip(forces) {
+                *velocity = (1.0 - self.alpha) * *velocity + mixing * force;
+            }
+
+            if self.positive_steps > N_MIN {
+                self.timestep = f64::min(self.timestep * F_INC, self.max_timestep);
+                self.alpha *= F_ALPHA;
+            }
+            self.positive_steps += 1;
+        } else {
+            for velocity in &mut self.velocities {
+                *velocity = Vector3D::zero();
+            }
+            self.timestep *= F_DEC;
+            self.alpha = ALPHA_START;
+            self.positive_steps = 0;
+        }
+
+        let dt = self.timestep;
+        for (velocity, &force) in self.velocities.iter_mut().zip(forces) {
+            *velocity += dt * force;
+        }
+
+        let max_velocity = self.velocities.iter().map(|v| v.norm()).fold(0.0, f64::max);
+        let scaling = if max_velocity * dt > self.max_displacement {
+            self.max_displacement / (max_velocity * dt)
+        } else {
+            1.0
+        };
+
+        for (i, particle) in system.iter_mut().enumerate() {
+            particle.position += scaling * dt * self.velocities[i];
+        }
+
+        let energy = system.potential_energy();
+        let forces = system.forces();
+        self.configuration.set(system, energy, forces);
+
+        return Tolerance {
+            energy: self.configuration.energy,
+            force2: self.configuration.max_force2(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sys::{System, UnitCell, Particle};
+    use types::{Vector3D, Zero};
+    use energy::*;
+    use sim::{Propagator, Minimization};
+
+    fn testing_system() -> System {
+        let mut system = System::from_cell(UnitCell::cubic(20.0));
+        system.add_particle(Particle::new("Cl"));
+        system[0].position = Vector3D::zero();
+        system.add_particle(Particle::new("Cl"));
+        system[1].position = Vector3D::new(0.0, 0.0, 2.0);
+        system.add_particle(Particle::new("Cl"));
+        system[2].position = Vector3D::new(0.0, 1.5, 0.5);
+
+        system.interactions_mut().add_pair("Cl", "Cl",
+            PairInteraction::new(Box::new(Harmonic{x0: 2.3, k: 0.1}), 10.0)
+        );
+        return system;
+    }
+
+    #[test]
+    fn minization() {
+        let mut system = testing_system();
+
+        let mut minization = Minimization::new(Box::new(FIRE::new()));
+        minization.setup(&system);
+        for _ in 0..500 {
+            minization.propagate(&mut system);
+        }
+        assert!(minization.converged());
+        assert_relative_eq!(system.distance(0, 1), 2.3, epsilon=1e-3);
+        assert_relative_eq!(system.distance(0, 2), 2.3, epsilon=1e-3);
+        assert_relative_eq!(system.distance(1, 2), 2.3, epsilon=1e-3);
+    }
+
+    #[test]
+    #[should_panic]
+    fn negative_timestep() {
+        let _ = FIRE::with_timestep(-1.0, 10.0);
+    }
+
+    #[test]
+    #[should_panic]
+    fn small_max_timestep() {
+        let _ = FIRE::with_timestep(2.0, 1.0);
+    }
+}
@@ -0,0 +1,265 @@
+// Lumol, an extensible molecular simulation engine
+// Copyright (C) 2015-2016 Lumol's contributors — BSD license
+
+use utils;
+use sys::System;
+use types::Vector3D;
+use super::{Minimizer, Tolerance};
+use super::configuration::{Configuration, dot};
+use super::line_search::search_along;
+use checkpoint::{Checkpoint, CheckpointWriter, CheckpointReader, CheckpointError};
+
+use std::collections::VecDeque;
+
+/// Parameter for the curvature condition in the line search. Quasi-Newton
+/// methods work well with an inexact line search.
+const CURVATURE: f64 = 0.9;
+
+/// Change of positions and forces during one iteration of L-BFGS
+struct Correction {
+    /// Displacement of the atoms
+    positions: Vec<Vector3D>,
+    /// Change in the gradient of the energy, i.e. opposite of the change in
+    /// forces
+    gradient: Vec<Vector3D>,
+    /// Inverse of the scalar product between `positions` and `gradient`
+    rho: f64,
+}
+
+/// Limited-memory Broyden–Fletcher–Goldfarb–Shanno minimization algorithm.
+///
+/// This quasi-Newton method builds an approximation of the inverse Hessian
+/// of the energy from the last positions and forces, and uses it to find the
+/// search direction. Only a fixed number of previous iterations is stored,
+/// making this method usable for large systems.
+pub struct LBFGS {
+    /// Number of previous iterations to use
+    history: usize,
+    /// Stored corrections, the most recent one being at the back
+    corrections: VecDeque<Correction>,
+    /// Initial step length for the line search, when there is no history
+    step: f64,
+    /// Maximal displacement of an atom at the first step of a line search
+    max_displacement: f64,
+    /// Configuration at the end of the previous iteration
+    configuration: Configuration,
+}
+
+impl Default for LBFGS {
+    fn default() -> LBFGS {
+        LBFGS::new()
+    }
+}
+
+impl LBFGS {
+    /// Create a new `LBFGS` minimizer using the 10 last iterations to
+    /// approximate the Hessian.
+    pub fn new() -> LBFGS {
+        LBFGS::with_history(10)
+    }
+
+    /// Create a new `LBFGS` minimizer using the `history` last iterations to
+    /// approximate the Hessian.
+    ///
+    /// # Panics
+    ///
+    /// If `history` is zero.
+    pub fn with_history(history: usize) -> LBFGS {
+        assert!(history > 0, "L-BFGS history size must be positive");
+        LBFGS {
+            history: history,
+            corrections: VecDeque::with_capacity(history),
+            step: utils::unit_from(0.1, "fs^2/u"),
+            max_displacement: utils::unit_from(0.1, "A"),
+            configuration: Configuration::new(),
+        }
+    }
+
+    /// Get the number of previous iterations used to approximate the Hessian
+    pub fn history(&self) -> usize {
+        self.history
+    }
+
+    /// Get the search direction using the two-loop recursion, i.e. the
+    /// approximated inverse Hessian applied to the forces.
+    fn direction(&self) -> Vec<Vector3D> {
+        let mut direction = self.configuration.forces.clone();
+        let mut alphas = Vec::with_capacity(self.corrections.len());
+        for correction in self.corrections.iter().rev() {
+            let alpha = correction.rho * dot(&correction.positions, &direction);
+            for (d, &y) in direction.iter_mut().zip(&correction.gradient) {
+                *d -= alpha * y;
+            }
+            alphas.push(alpha);
+        }
+
+        if let Some(last) = self.corrections.back() {
+            let gamma = 1.0 / (last.rho * dot(&last.gradient, &last.gradient));
+            for d in &mut direction {
+                *d *= gamma;
+            }
+        }
+
+        for (correction, alpha) in self.corrections.iter().zip(alphas.into_iter().rev()) {
+            let beta = correction.rho * dot(&correction.gradient, &direction);
+            for (d, &s) in direction.iter_mut().zip(&correction.positions) {
+                *d += (alpha - beta) * s;
+            }
+        }
+        return direction;
+    }
+}
+
+impl Minimizer for LBFGS {
+    fn setup(&mut self, _: &System) {
+        self.corrections.clear();
+        self.configuration.clear();
+    }
+
+    fn energy_changed(&mut self) {
+        self.configuration.invalidate();
+    }
+
+    fn save_state(&self, writer: &mut CheckpointWriter) {
+        writer.write_f64(self.step);
+        writer.write_usize(self.corrections.len());
+        for correction in &self.corrections {
+            writer.write_vectors(&correction.positions);
+            writer.write_vectors(&correction.gradient);
+            writer.write_f64(correction.rho);
+        }
+        self.configuration.save(writer);
+    }
+
+    fn restore_state(&mut self, reader: &mut CheckpointReader) -> Result<(), CheckpointError> {
+        self.step = try!(reader.read_f64());
+        // Each correction takes at least 24 bytes in the checkpoint
+        let count = try!(reader.read_count(24));
+        if count > self.history {
+            return Err(CheckpointError::from("too many corrections in L-BFGS checkpoint"));
+        }
+        self.corrections.clear();
+        for _ in 0..count {
+            self.corrections.push_back(Correction {
+                positions: try!(reader.read_vectors()),
+                gradient: try!(reader.read_vectors()),
+                rho: try!(reader.read_f64()),
+            });
+        }
+        self.configuration.restore(reader)
+    }
+
+    fn minimize(&mut self, system: &mut System) -> Tolerance {
+        if !self.configuration.update(system) {
+            self.corrections.clear();
+        }
+
+        let mut direction = self.direction();
+        if !(dot(&self.configuration.forces, &direction) > 0.0) {
+            // The Hessian approximation is not positive definite anymore,
+            // use the steepest descent direction instead
+            self.corrections.clear();
+            direction = self.configuration.forces.clone();
+        }
+
+        let max_direction = direction.iter().map(|d| d.norm()).fold(0.0, f64::max);
+        if max_direction == 0.0 {
+            // All the forces are zero, we are already at a minimum
+            return Tolerance {
+                energy: self.configuration.energy,
+                force2: self.configuration.max_force2(),
+            };
+        }
+
+        // With a good Hessian approximation, the natural step length is 1
+        let step = if self.corrections.is_empty() {self.step} else {1.0};
+        let initial = f64::min(step, self.max_displacement / max_direction);
+
+        let had_history = !self.corrections.is_empty();
+        match search_along(system, &self.configuration, &direction, initial, CURVATURE) {
+            Some((point, forces)) => {
+                let positions = direction.iter().map(|&d| point.step * d).collect::<Vec<_>>();
+                let gradient = forces.iter().zip(&self.configuration.forces)
+                                     .map(|(&new, &old)| old - new)
+                                     .collect::<Vec<_>>();
+                let curvature = dot(&positions, &gradient);
+                // Only keep corrections preserving a positive definite
+                // Hessian approximation
+                if curvature > 0.0 {
+                    if self.corrections.len() == self.history {
+                        let _ = self.corrections.pop_front();
+                    }
+                    self.corrections.push_back(Correction {
+                        positions: positions,
+                        gradient: gradient,
+                        rho: 1.0 / curvature,
+                    });
+                }
+
+                if !had_history {
+                    self.step = point.step;
+                }
+                self.configuration.set(system, point.energy, forces);
+            }
+            None => {
+                // No lower energy along this direction, restart from the
+                // steepest descent
+                self.corrections.clear();
+                if had_history {
+                    return self.minimize(system);
+                }
+                self.step /= 2.0;
+            }
+        }
+
+        return Tolerance {
+            energy: self.configuration.energy,
+            force2: self.configuration.max_force2(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sys::{System, UnitCell, Particle};
+    use types::{Vector3D, Zero};
+    use energy::*;
+    use sim::{Propagator, Minimization};
+
+    fn testing_system() -> System {
+        let mut system = System::from_cell(UnitCell::cubic(20.0));
+        system.add_particle(Particle::new("Cl"));
+        system[0].position = Vector3D::zero();
+        system.add_particle(Particle::new("Cl"));
+        system[1].position = Vector3D::new(0.0, 0.0, 2.0);
+        system.add_particle(Particle::new("Cl"));
+        system[2].position = Vector3D::new(0.0, 1.5, 0.5);
+
+        system.interactions_mut().add_pair("Cl", "Cl",
+            PairInteraction::new(Box::new(Harmonic{x0: 2.3, k: 0.1}), 10.0)
+        );
+        return system;
+    }
+
+    #[test]
+    fn minization() {
+        let mut system = testing_system();
+
+        let mut minization = Minimization::new(Box::new(LBFGS::new()));
+        minization.setup(&system);
+        for _ in 0..100 {
+            minization.propagate(&mut system);
+        }
+        assert!(minization.converged());
+        assert_relative_eq!(system.distance(0, 1), 2.3, epsilon=1e-3);
+        assert_relative_eq!(system.distance(0, 2), 2.3, epsilon=1e-3);
+        assert_relative_eq!(system.distance(1, 2), 2.3, epsilon=1e-3);
+    }
+
+    #[test]
+    #[should_panic]
+    fn zero_history() {
+        let _ = LBFGS::with_history(0);
+    }
+}
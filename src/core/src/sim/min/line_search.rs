@@ -0,0 +1,211 @@
+// Lumol, an extensible molecular simulation engine
+// Copyright (C) 2015-2016 Lumol's contributors — BSD license
+
+//! Line search along a direction, shared by the minimizers needing one.
+use sys::System;
+use types::Vector3D;
+use super::configuration::{Configuration, dot};
+
+use std::f64;
+
+/// Sufficient decrease parameter for the Armijo condition
+const ARMIJO: f64 = 1e-4;
+/// Maximal number of energy evaluations in each phase of the line search
+const MAX_EVALUATIONS: usize = 20;
+
+/// Value of the energy and of its derivative at a given step length along
+/// the search direction.
+#[derive(Clone, Copy, Debug)]
+pub struct LinePoint {
+    /// Step length along the search direction
+    pub step: f64,
+    /// Potential energy at this step
+    pub energy: f64,
+    /// Derivative of the energy with respect to the step length
+    pub slope: f64,
+}
+
+/// Search a step length satisfying the strong Wolfe conditions, following
+/// the algorithm 3.5 from Nocedal & Wright "Numerical Optimization".
+///
+/// The `evaluate` function is called with a step length, and must move the
+/// system to this step before returning the energy and the slope. `start` is
+/// the point at step zero, which must have a negative slope, `initial` is the
+/// first step length to try, and `curvature` the parameter for the curvature
+/// condition.
+///
+/// On success, the last call to `evaluate` was done with the returned step,
+/// so the system is already at the corresponding configuration. If no step
+/// decreasing the energy can be found, `None` is returned and the system is
+/// left in an unspecified configuration.
+pub fn line_search<F>(mut evaluate: F, start: LinePoint, initial: f64, curvature: f64) -> Option<LinePoint>
+    where F: FnMut(f64) -> LinePoint
+{
+    debug_assert!(start.step == 0.0 && start.slope < 0.0);
+    let mut previous = start;
+    let mut step = initial;
+    for i in 0..MAX_EVALUATIONS {
+        let current = evaluate(step);
+        if !current.energy.is_finite() {
+            // Probably two atoms on top of each other, try a smaller step
+            step = 0.5 * (previous.step + step);
+            continue;
+        }
+
+        if !armijo(&start, &current) || (i > 0 && current.energy >= previous.energy) {
+            return zoom(&mut evaluate, &start, previous, current, curvature);
+        }
+
+        if current.slope.abs() <= -curvature * start.slope {
+            return Some(current);
+        }
+
+        if current.slope >= 0.0 {
+            return zoom(&mut evaluate, &start, current, previous, curvature);
+        }
+
+        previous = current;
+        step *= 2.0;
+    }
+
+    if previous.energy < start.energy {
+        Some(evaluate(previous.step))
+    } else {
+        None
+    }
+}
+
+/// Search a configuration of lower energy by moving the atoms in the
+/// `system` from the `start` configuration along `direction`, which must be a
+/// descent direction. The search begins with a step length of `initial`.
+///
+/// On success, the system is left in the new configuration, and the final
+/// point of the line search is returned together with the new forces.
+/// Else, the atoms are moved back to their starting positions.
+pub fn search_along(system: &mut System, start: &Configuration, direction: &[Vector3D], initial: f64, curvature: f64) -> Option<(LinePoint, Vec<Vector3D>)> {
+    let slope = -dot(&start.forces, direction);
+    let origin = LinePoint{step: 0.0, energy: start.energy, slope: slope};
+
+    let mut forces = Vec::new();
+    let result = {
+        let evaluate = |step: f64| {
+            for (i, particle) in system.iter_mut().enumerate() {
+                particle.position = start.positions[i] + step * direction[i];
+            }
+            forces = system.forces();
+            LinePoint {
+                step: step,
+                energy: system.potential_energy(),
+                slope: -dot(&forces, direction),
+            }
+        };
+        line_search(evaluate, origin, initial, curvature)
+    };
+
+    match result {
+        Some(point) => Some((point, forces)),
+        None => {
+            for (i, particle) in system.iter_mut().enumerate() {
+                particle.position = start.positions[i];
+            }
+            None
+        }
+    }
+}
+
+/// Check the sufficient decrease condition at `point`
+fn armijo(start: &LinePoint, point: &LinePoint) -> bool {
+    point.energy <= start.energy + ARMIJO * point.step * start.slope
+}
+
+/// Refine the step length in the interval between `low` and `high`. `low` is
+/// always the point with the lowest energy satisfying the Armijo condition.
+fn zoom<F>(evaluate: &mut F, start: &LinePoint, mut low: LinePoint, mut high: LinePoint, curvature: f64) -> Option<LinePoint>
+    where F: FnMut(f64) -> LinePoint
+{
+    for _ in 0..MAX_EVALUATIONS {
+        let width = (high.step - low.step).abs();
+        if width <= f64::EPSILON * f64::max(low.step, high.step) {
+            break;
+        }
+
+        let current = evaluate(interpolate(&low, &high));
+        if !armijo(start, &current) || !(current.energy < low.energy) {
+            high = current;
+        } else {
+            if current.slope.abs() <= -curvature * start.slope {
+                return Some(current);
+            }
+            if current.slope * (high.step - low.step) >= 0.0 {
+                high = low;
+            }
+            low = current;
+        }
+    }
+
+    // Accept the best point we found if it decreases the energy
+    if low.step > 0.0 && low.energy < start.energy {
+        Some(evaluate(low.step))
+    } else {
+        None
+    }
+}
+
+/// Get the minimum of the cubic interpolating energy and slope at `a` and
+/// `b`, falling back to bisection if this minimum is not well inside the
+/// interval.
+fn interpolate(a: &LinePoint, b: &LinePoint) -> f64 {
+    let d1 = a.slope + b.slope - 3.0 * (a.energy - b.energy) / (a.step - b.step);
+    let d2 = (d1 * d1 - a.slope * b.slope).sqrt() * (b.step - a.step).signum();
+    let step = b.step - (b.step - a.step) * (b.slope + d2 - d1) / (b.slope - a.slope + 2.0 * d2);
+
+    let (min, max) = if a.step < b.step {(a.step, b.step)} else {(b.step, a.step)};
+    let margin = 0.1 * (max - min);
+    if step.is_finite() && step > min + margin && step < max - margin {
+        step
+    } else {
+        0.5 * (a.step + b.step)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn quadratic(step: f64) -> LinePoint {
+        // E(x) = (x - 3)^2, along the direction d = 1 starting at x = 0
+        LinePoint {
+            step: step,
+            energy: (step - 3.0) * (step - 3.0),
+            slope: 2.0 * (step - 3.0),
+        }
+    }
+
+    #[test]
+    fn bracketing() {
+        // The initial step is too small, and must be increased
+        let point = line_search(quadratic, quadratic(0.0), 0.1, 0.1).unwrap();
+        assert!(point.slope.abs() <= 0.1 * 6.0);
+        assert!(point.energy < 9.0);
+    }
+
+    #[test]
+    fn zooming() {
+        // The initial step is too large, and must be reduced
+        let point = line_search(quadratic, quadratic(0.0), 100.0, 0.1).unwrap();
+        assert!(point.slope.abs() <= 0.1 * 6.0);
+        assert_relative_eq!(point.step, 3.0, epsilon=0.3);
+    }
+
+    #[test]
+    fn no_descent() {
+        // Only increasing energy along this direction
+        let increasing = |step: f64| LinePoint {
+            step: step,
+            energy: step * step + 10.0 * step,
+            slope: -1.0,
+        };
+        let start = LinePoint{step: 0.0, energy: 0.0, slope: -1.0};
+        assert!(line_search(increasing, start, 1.0, 0.1).is_none());
+    }
+}
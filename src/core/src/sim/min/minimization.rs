@@ -5,6 +5,8 @@
 use utils;
 use sys::System;
 use sim::{Propagator, TemperatureStrategy};
+use utils::AsAny;
+use checkpoint::{CheckpointWriter, CheckpointReader, CheckpointError};
 
 use std::f64;
 
@@ -20,15 +22,40 @@ pub struct Tolerance {
 ///
 /// A minimizer is an algorithm responsible for finding new configurations of
 /// lower energy.
-pub trait Minimizer {
+pub trait Minimizer: AsAny {
     /// Setup the minimizer. This function is called once at the begining of
     /// every simulation run.
     fn setup(&mut self, _: &System) {}
     /// Find a new configuration of lower energy, and return the corresponding
     /// values for energy and forces.
     fn minimize(&mut self, system: &mut System) -> Tolerance;
+    /// Get the energy of the `system` as minimized by this minimizer, i.e. the
+    /// same quantity as the `energy` returned by `minimize`. The default
+    /// implementation returns the potential energy of the system.
+    fn energy(&self, system: &System) -> f64 {
+        system.potential_energy()
+    }
+    /// Function called when the energy or the forces acting on the system
+    /// changed without the particles moving, for example because the
+    /// interactions were modified between two steps. Minimizers keeping the
+    /// energy or the forces from one step to the next must recompute them.
+    fn energy_changed(&mut self) {}
+
+    /// Save the internal state of this minimizer in a checkpoint, to be able
+    /// to restart the minimization later. The default implementation saves
+    /// nothing.
+    fn save_state(&self, _: &mut CheckpointWriter) {}
+
+    /// Restore the internal state of this minimizer from a checkpoint written
+    /// by `save_state`. This function is called after `setup` when restarting a
+    /// simulation. The default implementation does nothing.
+    fn restore_state(&mut self, _: &mut CheckpointReader) -> Result<(), CheckpointError> {
+        Ok(())
+    }
 }
 
+impl_downcast!(Minimizer);
+
 /// Minimization propagator for simulations.
 ///
 /// The minimization stops when the energy difference between the previous and
@@ -62,9 +89,19 @@ impl Minimization {
         }
     }
 
+    /// Get the minimizer used by this minimization
+    pub fn minimizer(&self) -> &Minimizer {
+        &*self.minimizer
+    }
+
+    /// Get the energy and force tolerance of this minimization
+    pub fn tolerance(&self) -> &Tolerance {
+        &self.tolerance
+    }
+
     /// Check if the minimization has converged.
     pub fn converged(&self) -> bool {
-        self.is_converged
+        Propagator::converged(self)
     }
 }
 
@@ -73,10 +110,14 @@ impl Propagator for Minimization {
         TemperatureStrategy::None
     }
 
+    fn converged(&self) -> bool {
+        self.is_converged
+    }
+
     fn setup(&mut self, system: &System) {
         self.is_converged = false;
-        self.last_energy = system.potential_energy();
         self.minimizer.setup(system);
+        self.last_energy = self.minimizer.energy(system);
     }
 
     fn propagate(&mut self, system: &mut System) {
@@ -98,4 +139,16 @@ impl Propagator for Minimization {
 
         self.last_energy = result.energy;
     }
+
+    fn save_state(&self, writer: &mut CheckpointWriter) {
+        writer.write_bool(self.is_converged);
+        writer.write_f64(self.last_energy);
+        self.minimizer.save_state(writer);
+    }
+
+    fn restore_state(&mut self, reader: &mut CheckpointReader) -> Result<(), CheckpointError> {
+        self.is_converged = try!(reader.read_bool());
+        self.last_energy = try!(reader.read_f64());
+        self.minimizer.restore_state(reader)
+    }
 }
@@ -10,3 +10,18 @@ pub use self::minimization::Tolerance;
 
 mod steepest_descent;
 pub use self::steepest_descent::SteepestDescent;
+
+mod conjugate_gradient;
+pub use self::conjugate_gradient::ConjugateGradient;
+
+mod lbfgs;
+pub use self::lbfgs::LBFGS;
+
+mod fire;
+pub use self::fire::FIRE;
+
+mod cell_relaxation;
+pub use self::cell_relaxation::{CellRelaxation, CellDeformation};
+
+mod configuration;
+mod line_search;
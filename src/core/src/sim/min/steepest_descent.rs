@@ -4,6 +4,7 @@
 use utils;
 use sys::System;
 use super::{Minimizer, Tolerance};
+use checkpoint::{CheckpointWriter, CheckpointReader, CheckpointError};
 
 use std::f64;
 
@@ -67,6 +68,15 @@ impl Minimizer for SteepestDescent {
             force2: forces.iter().map(|&f| f.norm2()).fold(f64::NAN, f64::max)
         }
     }
+
+    fn save_state(&self, writer: &mut CheckpointWriter) {
+        writer.write_f64(self.gamma);
+    }
+
+    fn restore_state(&mut self, reader: &mut CheckpointReader) -> Result<(), CheckpointError> {
+        self.gamma = try!(reader.read_f64());
+        Ok(())
+    }
 }
 
 #[cfg(test)]
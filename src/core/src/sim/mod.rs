@@ -5,16 +5,26 @@
 mod propagator;
 pub use self::propagator::Propagator;
 pub use self::propagator::TemperatureStrategy;
+pub use self::propagator::Ensemble;
 
 pub mod md;
 pub mod mc;
 pub mod min;
 
 mod simulations;
-pub use self::simulations::Simulation;
+pub use self::simulations::{Simulation, RunInfo};
 pub use self::md::MolecularDynamics;
 pub use self::mc::MonteCarlo;
 pub use self::min::Minimization;
 
+mod neb;
+pub use self::neb::NudgedElasticBand;
+
+pub mod stop;
+pub use self::stop::StopCondition;
+
+pub mod hooks;
+pub use self::hooks::{Hook, HookAction, StepInfo};
+
 mod utils;
 pub use self::utils::Alternator;
@@ -0,0 +1,609 @@
+// Lumol, an extensible molecular simulation engine
+// Copyright (C) 2015-2016 Lumol's contributors — BSD license
+
+//! Nudged elastic band calculations of minimum energy paths
+use std::cell::RefCell;
+use std::io::prelude::*;
+use std::io;
+use std::fs::File;
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use utils;
+use sys::{System, Trajectory, TrajectoryError};
+use types::{Matrix3, Vector3D, Zero};
+use energy::{GlobalPotential, GlobalCache};
+use sim::{Propagator, TemperatureStrategy};
+use sim::min::Minimizer;
+use checkpoint::{CheckpointWriter, CheckpointReader, CheckpointError};
+
+/// Additional forces acting on an image of the band, to transform the true
+/// forces into the nudged elastic band forces.
+///
+/// These forces are constant for a given step, and the corresponding energy
+/// is linear in the displacement from the `reference` positions. The
+/// minimizers can then use both energy and forces during a step.
+#[derive(Clone, Default)]
+struct BandCorrection {
+    /// Positions of the atoms when the correction was computed
+    reference: Vec<Vector3D>,
+    /// Additional forces acting on the atoms
+    forces: Vec<Vector3D>,
+}
+
+/// Global potential adding the `BandCorrection` to an image
+#[derive(Clone)]
+struct BandForces {
+    correction: Rc<RefCell<BandCorrection>>,
+}
+
+impl GlobalPotential for BandForces {
+    fn energy(&mut self, system: &System) -> f64 {
+        let correction = self.correction.borrow();
+        if correction.forces.len() != system.size() {
+            return 0.0;
+        }
+
+        let mut energy = 0.0;
+        for (i, particle) in system.iter().enumerate() {
+            energy -= correction.forces[i] * (particle.position - correction.reference[i]);
+        }
+        return energy;
+    }
+
+    fn forces(&mut self, system: &System) -> Vec<Vector3D> {
+        let correction = self.correction.borrow();
+        if correction.forces.len() != system.size() {
+            return vec![Vector3D::zero(); system.size()];
+        }
+        correction.forces.clone()
+    }
+
+    fn virial(&mut self, _: &System) -> Matrix3 {
+        Matrix3::zero()
+    }
+}
+
+impl GlobalCache for BandForces {
+    fn move_particles_cost(&mut self, _: &System, _: &[usize], _: &[Vector3D]) -> f64 {
+        unreachable!("Nudged elastic band forces can not be used with Monte Carlo")
+    }
+
+    fn update(&mut self) {
+        unreachable!("Nudged elastic band forces can not be used with Monte Carlo")
+    }
+}
+
+/// Nudged elastic band propagator, finding the minimum energy path between
+/// two configurations.
+///
+/// The path is represented by a chain of images of the system, the first and
+/// last images being fixed to the initial and final configurations. The
+/// intermediate images are connected by springs, and moved using a
+/// `Minimizer` with the nudged elastic band forces: the component of the
+/// true forces perpendicular to the path, and the component of the spring
+/// forces parallel to the path. The tangent to the path is estimated using
+/// the improved tangent from Henkelman & Jónsson, J. Chem. Phys. 113, 9978
+/// (2000).
+///
+/// With the climbing image option, the highest energy image is not affected
+/// by the springs, and climbs up along the path to the saddle point.
+///
+/// At each step, the simulated system is updated with the positions of the
+/// highest energy image.
+pub struct NudgedElasticBand {
+    /// Positions of the atoms in the initial configuration
+    initial: Vec<Vector3D>,
+    /// Positions of the atoms in the final configuration
+    last: Vec<Vector3D>,
+    /// Files containing the initial and final configurations, if known
+    files: Option<(PathBuf, PathBuf)>,
+    /// Images of the system along the band, including the end points
+    images: Vec<System>,
+    /// Minimizers for the intermediate images
+    minimizers: Vec<Box<Minimizer>>,
+    /// Band forces corrections for the intermediate images
+    corrections: Vec<Rc<RefCell<BandCorrection>>>,
+    /// Spring constant between images
+    spring: f64,
+    /// Should we use the climbing image method
+    climbing: bool,
+    /// Tolerance on the squared norm of the nudged elastic band forces
+    tolerance: f64,
+    /// Did the band converge
+    is_converged: bool,
+    /// Path to the energy profile output file
+    profile: Option<PathBuf>,
+    /// Trajectory output for the band, and the corresponding path
+    band: Option<(PathBuf, Trajectory)>,
+}
+
+impl NudgedElasticBand {
+    /// Create a new `NudgedElasticBand` between the `initial` and `last`
+    /// positions. One intermediate image is created for each of the
+    /// `minimizers`, which are used to update the corresponding images.
+    ///
+    /// # Panics
+    ///
+    /// If `initial` and `last` do not have the same size, or if there are no
+    /// minimizers.
+    pub fn new(initial: Vec<Vector3D>, last: Vec<Vector3D>, minimizers: Vec<Box<Minimizer>>) -> NudgedElasticBand {
+        assert_eq!(
+            initial.len(), last.len(),
+            "initial and final configurations must have the same size in nudged elastic band"
+        );
+        assert!(!minimizers.is_empty(), "nudged elastic band needs at least one intermediate image");
+        NudgedElasticBand {
+            initial: initial,
+            last: last,
+            files: None,
+            images: Vec::new(),
+            minimizers: minimizers,
+            corrections: Vec::new(),
+            spring: utils::unit_from(5.0, "kJ/mol/A^2"),
+            climbing: false,
+            tolerance: utils::unit_from(1e-2, "kJ^2/mol^2/A^2"),
+            is_converged: false,
+            profile: None,
+            band: None,
+        }
+    }
+
+    /// Set the spring constant between images to `spring`
+    pub fn set_spring(&mut self, spring: f64) {
+        self.spring = spring;
+    }
+
+    /// Enable or disable the climbing image method
+    pub fn set_climbing(&mut self, climbing: bool) {
+        self.climbing = climbing;
+    }
+
+    /// Set the tolerance on the maximal squared norm of the nudged elastic
+    /// band force acting on any atom in the band.
+    pub fn set_tolerance(&mut self, force2: f64) {
+        self.tolerance = force2;
+    }
+
+    /// Write the energy profile along the band to the file at `path` at the
+    /// end of the simulation. The file is replaced if it already exists.
+    pub fn set_profile_output<P: AsRef<Path>>(&mut self, path: P) -> Result<(), io::Error> {
+        let _ = try!(File::create(path.as_ref()));
+        self.profile = Some(path.as_ref().to_owned());
+        Ok(())
+    }
+
+    /// Write all the images in the band to the trajectory at `path` at the
+    /// end of the simulation. The file is replaced if it already exists.
+    pub fn set_band_output<P: AsRef<Path>>(&mut self, path: P) -> Result<(), TrajectoryError> {
+        self.band = Some((path.as_ref().to_owned(), try!(Trajectory::create(path.as_ref()))));
+        Ok(())
+    }
+
+    /// Set the files containing the `initial` and `last` configurations used
+    /// to create this propagator. The files are not read again, and are only
+    /// used to describe the propagator, for example in input files.
+    pub fn set_configuration_files<P: AsRef<Path>>(&mut self, initial: P, last: P) {
+        self.files = Some((initial.as_ref().to_owned(), last.as_ref().to_owned()));
+    }
+
+    /// Get the files containing the initial and final configurations, if they
+    /// were set with `set_configuration_files`.
+    pub fn configuration_files(&self) -> Option<(&Path, &Path)> {
+        self.files.as_ref().map(|&(ref initial, ref last)| (initial.as_path(), last.as_path()))
+    }
+
+    /// Get the spring constant between images
+    pub fn spring(&self) -> f64 {
+        self.spring
+    }
+
+    /// Check if the climbing image method is used
+    pub fn climbing(&self) -> bool {
+        self.climbing
+    }
+
+    /// Get the tolerance on the maximal squared norm of the nudged elastic
+    /// band force acting on any atom in the band.
+    pub fn tolerance(&self) -> f64 {
+        self.tolerance
+    }
+
+    /// Get the number of atoms in the initial and final configurations
+    pub fn size(&self) -> usize {
+        self.initial.len()
+    }
+
+    /// Get the minimizers used to update the intermediate images
+    pub fn minimizers(&self) -> &[Box<Minimizer>] {
+        &self.minimizers
+    }
+
+    /// Get the path of the energy profile output file, if any
+    pub fn profile_output(&self) -> Option<&Path> {
+        self.profile.as_ref().map(|path| path.as_path())
+    }
+
+    /// Get the path of the band trajectory output file, if any
+    pub fn band_output(&self) -> Option<&Path> {
+        self.band.as_ref().map(|&(ref path, _)| path.as_path())
+    }
+
+    /// Get the images in the band, including the initial and final
+    /// configurations.
+    pub fn images(&self) -> &[System] {
+        &self.images
+    }
+
+    /// Get the energy profile along the band, as pairs of reaction
+    /// coordinate and potential energy for all images. The reaction
+    /// coordinate is the cumulated distance between images.
+    pub fn profile(&self) -> Vec<(f64, f64)> {
+        let mut profile = Vec::with_capacity(self.images.len());
+        let mut coordinate = 0.0;
+        for (i, image) in self.images.iter().enumerate() {
+            if i > 0 {
+                coordinate += norm(&displacement(&self.images[i - 1], image));
+            }
+            profile.push((coordinate, self.true_energy(i)));
+        }
+        return profile;
+    }
+
+    /// Get the potential energy of the image `i`, without the band forces
+    fn true_energy(&self, i: usize) -> f64 {
+        match self.correction(i) {
+            Some(correction) => {
+                let backup = mem::replace(&mut *correction.borrow_mut(), BandCorrection::default());
+                let energy = self.images[i].potential_energy();
+                *correction.borrow_mut() = backup;
+                energy
+            }
+            None => self.images[i].potential_energy()
+        }
+    }
+
+    /// Get the band correction of image `i`, if any
+    fn correction(&self, i: usize) -> Option<&Rc<RefCell<BandCorrection>>> {
+        if i == 0 || i == self.images.len() - 1 {
+            None
+        } else {
+            Some(&self.corrections[i - 1])
+        }
+    }
+
+    /// Get the tangent to the band at the intermediate image `i`, given the
+    /// energies of all the images.
+    fn tangent(&self, i: usize, energies: &[f64]) -> Vec<Vector3D> {
+        let forward = displacement(&self.images[i], &self.images[i + 1]);
+        let backward = displacement(&self.images[i - 1], &self.images[i]);
+
+        let (previous, current, next) = (energies[i - 1], energies[i], energies[i + 1]);
+        let (forward_weight, backward_weight) = if next > current && current > previous {
+            (1.0, 0.0)
+        } else if next < current && current < previous {
+            (0.0, 1.0)
+        } else {
+            let max = f64::max((next - current).abs(), (previous - current).abs());
+            let min = f64::min((next - current).abs(), (previous - current).abs());
+            if next > previous {(max, min)} else {(min, max)}
+        };
+
+        let mut tangent = forward.iter().zip(&backward)
+                                 .map(|(&f, &b)| forward_weight * f + backward_weight * b)
+                                 .collect::<Vec<_>>();
+        let tangent_norm = norm(&tangent);
+        if tangent_norm > 0.0 {
+            for t in &mut tangent {
+                *t /= tangent_norm;
+            }
+        }
+        return tangent;
+    }
+
+    fn write_outputs(&mut self) {
+        let profile = self.profile();
+        if let Some(ref path) = self.profile {
+            if let Err(err) = write_profile(path, &profile) {
+                error!("Could not write to file '{}': {}", path.display(), err);
+            }
+        }
+
+        if let Some((_, ref mut band)) = self.band {
+            for image in &self.images {
+                if let Err(err) = band.write(image) {
+                    error!("Error while writing band trajectory: {}", err);
+                }
+            }
+        }
+    }
+}
+
+impl Propagator for NudgedElasticBand {
+    fn temperature_strategy(&self) -> TemperatureStrategy {
+        TemperatureStrategy::None
+    }
+
+    fn converged(&self) -> bool {
+        self.is_converged
+    }
+
+    fn setup(&mut self, system: &System) {
+        assert_eq!(
+            self.initial.len(), system.size(),
+            "initial and final configurations must have the same size as the system in nudged elastic band"
+        );
+
+        let nimages = self.minimizers.len() + 2;
+        self.images.clear();
+        self.corrections.clear();
+        for i in 0..nimages {
+            let t = i as f64 / (nimages - 1) as f64;
+            let mut image = system.clone();
+            for (k, particle) in image.iter_mut().enumerate() {
+                let mut delta = self.last[k] - self.initial[k];
+                system.cell().vector_image(&mut delta);
+                particle.position = self.initial[k] + t * delta;
+            }
+
+            if i != 0 && i != nimages - 1 {
+                let correction = Rc::new(RefCell::new(BandCorrection::default()));
+                image.interactions_mut().add_global(Box::new(BandForces {
+                    correction: correction.clone()
+                }));
+                self.corrections.push(correction);
+            }
+            self.images.push(image);
+        }
+
+        for (minimizer, image) in self.minimizers.iter_mut().zip(&self.images[1..]) {
+            minimizer.setup(image);
+        }
+        self.is_converged = false;
+    }
+
+    fn propagate(&mut self, system: &mut System) {
+        if self.is_converged {
+            return;
+        }
+
+        let nimages = self.images.len();
+        let mut energies = vec![0.0; nimages];
+        let mut forces = vec![Vec::new(); nimages];
+        for i in 0..nimages {
+            if let Some(correction) = self.correction(i) {
+                *correction.borrow_mut() = BandCorrection::default();
+            }
+            energies[i] = self.images[i].potential_energy();
+            forces[i] = self.images[i].forces();
+        }
+
+        let highest = (1..nimages - 1).fold(1, |highest, i| {
+            if energies[i] > energies[highest] {i} else {highest}
+        });
+
+        let mut max_force2 = 0.0;
+        for i in 1..nimages - 1 {
+            let tangent = self.tangent(i, &energies);
+            let parallel = forces[i].iter().zip(&tangent).fold(0.0, |sum, (&f, &t)| sum + f * t);
+
+            let correction = if self.climbing && i == highest {
+                tangent.iter().map(|&t| -2.0 * parallel * t).collect::<Vec<_>>()
+            } else {
+                let forward = norm(&displacement(&self.images[i], &self.images[i + 1]));
+                let backward = norm(&displacement(&self.images[i - 1], &self.images[i]));
+                let spring = self.spring * (forward - backward);
+                tangent.iter().map(|&t| (spring - parallel) * t).collect::<Vec<_>>()
+            };
+
+            for (&force, &extra) in forces[i].iter().zip(&correction) {
+                let force2 = (force + extra).norm2();
+                if force2 > max_force2 {
+                    max_force2 = force2;
+                }
+            }
+
+            *self.corrections[i - 1].borrow_mut() = BandCorrection {
+                reference: self.images[i].iter().map(|particle| particle.position).collect(),
+                forces: correction,
+            };
+        }
+
+        if max_force2 < self.tolerance {
+            self.is_converged = true;
+            info!("Nudged elastic band converged on force tolerance");
+        } else {
+            for i in 1..nimages - 1 {
+                self.minimizers[i - 1].energy_changed();
+                let _ = self.minimizers[i - 1].minimize(&mut self.images[i]);
+            }
+        }
+
+        for (particle, image) in system.iter_mut().zip(self.images[highest].iter()) {
+            particle.position = image.position;
+        }
+    }
+
+    fn finish(&mut self, _: &System) {
+        self.write_outputs();
+    }
+
+    fn save_state(&self, writer: &mut CheckpointWriter) {
+        // The band corrections are recomputed at the beginning of each step,
+        // and do not need to be saved
+        writer.write_bool(self.is_converged);
+        writer.write_usize(self.images.len());
+        for image in &self.images {
+            writer.write_section(image.cell());
+            let positions = image.iter().map(|particle| particle.position).collect::<Vec<_>>();
+            writer.write_vectors(&positions);
+        }
+        for minimizer in &self.minimizers {
+            minimizer.save_state(writer);
+        }
+    }
+
+    fn restore_state(&mut self, reader: &mut CheckpointReader) -> Result<(), CheckpointError> {
+        self.is_converged = try!(reader.read_bool());
+        let nimages = try!(reader.read_usize());
+        if nimages != self.images.len() {
+            return Err(CheckpointError::from(format!(
+                "wrong number of images in nudged elastic band checkpoint: expected {}, got {}",
+                self.images.len(), nimages
+            )));
+        }
+        for image in &mut self.images {
+            try!(reader.read_section(image.cell_mut()));
+            let positions = try!(reader.read_vectors());
+            if positions.len() != image.size() {
+                return Err(CheckpointError::from(format!(
+                    "wrong number of particles in nudged elastic band checkpoint: expected {}, got {}",
+                    image.size(), positions.len()
+                )));
+            }
+            for (particle, position) in image.iter_mut().zip(positions) {
+                particle.position = position;
+            }
+        }
+        for minimizer in &mut self.minimizers {
+            try!(minimizer.restore_state(reader));
+        }
+        Ok(())
+    }
+}
+
+/// Get the displacement of all atoms between the images `a` and `b`, using
+/// the minimal image convention.
+fn displacement(a: &System, b: &System) -> Vec<Vector3D> {
+    a.iter().zip(b.iter()).map(|(pa, pb)| {
+        let mut delta = pb.position - pa.position;
+        a.cell().vector_image(&mut delta);
+        delta
+    }).collect()
+}
+
+/// Get the norm of a set of vectors, seen as a single vector
+fn norm(vectors: &[Vector3D]) -> f64 {
+    vectors.iter().fold(0.0, |sum, v| sum + v.norm2()).sqrt()
+}
+
+fn write_profile(path: &Path, profile: &[(f64, f64)]) -> Result<(), io::Error> {
+    let mut file = try!(File::create(path));
+    try!(writeln!(&mut file, "# Energy profile along the nudged elastic band"));
+    try!(writeln!(&mut file, "# Image Coordinate/A Energy/kJ/mol"));
+    let reference = profile.first().map_or(0.0, |&(_, energy)| energy);
+    for (i, &(coordinate, energy)) in profile.iter().enumerate() {
+        let coordinate = utils::unit_to(coordinate, "A");
+        let energy = utils::unit_to(energy - reference, "kJ/mol");
+        try!(writeln!(&mut file, "{} {} {}", i, coordinate, energy));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sys::{System, UnitCell, Particle};
+    use types::Vector3D;
+    use energy::*;
+    use sim::Propagator;
+    use sim::min::{Minimizer, FIRE};
+
+    /// Two fixed atoms A, and a B atom going from one side of them to the
+    /// other, having to pass between them. The saddle point is at the middle
+    /// of the two A atoms.
+    fn testing_system() -> System {
+        let mut system = System::from_cell(UnitCell::cubic(20.0));
+        system.add_particle(Particle::new("A"));
+        system[0].position = Vector3D::new(0.0, -1.6, 0.0);
+        system.add_particle(Particle::new("A"));
+        system[1].position = Vector3D::new(0.0, 1.6, 0.0);
+        system.add_particle(Particle::new("B"));
+        system[2].position = Vector3D::new(-2.0, 0.0, 0.0);
+
+        system.interactions_mut().add_pair("A", "A",
+            PairInteraction::new(Box::new(Harmonic{x0: 3.2, k: 10.0}), 8.0)
+        );
+        system.interactions_mut().add_pair("A", "B",
+            PairInteraction::new(Box::new(LennardJones{sigma: 2.0, epsilon: 0.1}), 8.0)
+        );
+        return system;
+    }
+
+    fn neb(system: &System, climbing: bool) -> NudgedElasticBand {
+        let initial = system.iter().map(|p| p.position).collect::<Vec<_>>();
+        let mut last = initial.clone();
+        last[2] = Vector3D::new(2.0, 0.0, 0.0);
+
+        let mut minimizers: Vec<Box<Minimizer>> = Vec::new();
+        for _ in 0..5 {
+            minimizers.push(Box::new(FIRE::new()));
+        }
+        let mut neb = NudgedElasticBand::new(initial, last, minimizers);
+        neb.set_climbing(climbing);
+        neb.set_spring(0.01);
+        neb.set_tolerance(1e-8);
+        return neb;
+    }
+
+    #[test]
+    fn parameters() {
+        let system = testing_system();
+        let mut neb = neb(&system, true);
+        assert_eq!(neb.spring(), 0.01);
+        assert_eq!(neb.tolerance(), 1e-8);
+        assert!(neb.climbing());
+        assert_eq!(neb.minimizers().len(), 5);
+        assert_eq!(neb.size(), system.size());
+
+        assert_eq!(neb.configuration_files(), None);
+        neb.set_configuration_files("initial.xyz", "final.xyz");
+        assert_eq!(neb.configuration_files(), Some((Path::new("initial.xyz"), Path::new("final.xyz"))));
+        assert_eq!(neb.profile_output(), None);
+        assert_eq!(neb.band_output(), None);
+    }
+
+    #[test]
+    fn setup() {
+        let system = testing_system();
+        let mut neb = neb(&system, false);
+        neb.setup(&system);
+
+        assert_eq!(neb.images().len(), 7);
+        assert_eq!(neb.images()[0][2].position, Vector3D::new(-2.0, 0.0, 0.0));
+        assert_eq!(neb.images()[6][2].position, Vector3D::new(2.0, 0.0, 0.0));
+        assert_relative_eq!(neb.images()[3][2].position, Vector3D::new(0.0, 0.0, 0.0), epsilon=1e-12);
+
+        let profile = neb.profile();
+        assert_eq!(profile.len(), 7);
+        assert_eq!(profile[0].0, 0.0);
+        assert_relative_eq!(profile[6].0, 4.0, epsilon=1e-12);
+    }
+
+    #[test]
+    fn climbing_image() {
+        let mut system = testing_system();
+        let mut neb = neb(&system, true);
+        neb.setup(&system);
+        for _ in 0..2000 {
+            neb.propagate(&mut system);
+        }
+        assert!(neb.converged());
+
+        // The saddle point is between the two A atoms
+        let images = neb.images();
+        let saddle = images[3][2].position;
+        assert_relative_eq!(saddle[0], 0.0, epsilon=1e-3);
+        assert_relative_eq!(saddle[1], 0.0, epsilon=1e-3);
+        assert_relative_eq!(system[2].position, saddle);
+
+        // End points did not move
+        assert_eq!(images[0][2].position, Vector3D::new(-2.0, 0.0, 0.0));
+        assert_eq!(images[6][2].position, Vector3D::new(2.0, 0.0, 0.0));
+
+        let profile = neb.profile();
+        let highest = profile.iter().map(|&(_, e)| e).fold(f64::NAN, f64::max);
+        assert_eq!(highest, profile[3].1);
+    }
+}
@@ -3,6 +3,8 @@
 
 //! A propagator is responsible for updating the system during a simulation
 use sys::System;
+use utils::AsAny;
+use checkpoint::{CheckpointWriter, CheckpointReader, CheckpointError};
 
 /// Possible temperature computation strategies. Different propagators needs
 /// different ways to compute the temperature: Monte-Carlo temperature is a
@@ -17,10 +19,30 @@ pub enum TemperatureStrategy {
     External(f64),
 }
 
+/// Thermodynamic ensemble sampled by a propagator, together with the
+/// corresponding target temperature and pressure. This is used by the outputs
+/// to select the right formulas for ensemble-dependent properties.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Ensemble {
+    /// The ensemble is not known, or the propagator does not sample a
+    /// thermodynamic ensemble (for example energy minimization).
+    Unknown,
+    /// Microcanonical ensemble, at constant number of particles, volume and
+    /// energy.
+    NVE,
+    /// Canonical ensemble, at constant number of particles, volume and
+    /// temperature. The value is the target temperature.
+    NVT(f64),
+    /// Isothermal-isobaric ensemble, at constant number of particles,
+    /// pressure and temperature. The values are the target temperature and
+    /// pressure.
+    NPT(f64, f64),
+}
+
 /// The propagator trait is the main algorithm of a simulation, i.e. the one
 /// which update the system. The main function here is `propagate`, which
 /// should propagate the simulation for one step.
-pub trait Propagator {
+pub trait Propagator: AsAny {
     /// Setup code, preparing all the meta-information needed about the
     /// simulation.
     fn setup(&mut self, _: &System) {}
@@ -28,9 +50,42 @@ pub trait Propagator {
     /// Get the temperature computation strategy for this propagator
     fn temperature_strategy(&self) -> TemperatureStrategy;
 
+    /// Get the thermodynamic ensemble sampled by this propagator. The
+    /// default implementation returns `Ensemble::Unknown`.
+    fn ensemble(&self) -> Ensemble {
+        Ensemble::Unknown
+    }
+
     /// Propagate the system for one simulation step.
     fn propagate(&mut self, system: &mut System);
 
+    /// Notify the propagator that the system was modified outside of
+    /// `propagate`, for example by a simulation `Hook`. Propagators caching
+    /// information about the system should update it here. The default
+    /// implementation does nothing.
+    fn system_changed(&mut self, _: &System) {}
+
+    /// Check if the propagator has converged, for propagators searching for
+    /// a specific configuration such as energy minimization. The default
+    /// implementation returns `false`.
+    fn converged(&self) -> bool {
+        false
+    }
+
     /// Finish the simulation, and maybe output some information about it
     fn finish(&mut self, _: &System) {}
+
+    /// Save the internal state of this propagator in a checkpoint, to be able
+    /// to restart the simulation later. The default implementation saves
+    /// nothing.
+    fn save_state(&self, _: &mut CheckpointWriter) {}
+
+    /// Restore the internal state of this propagator from a checkpoint written
+    /// by `save_state`. This function is called after `setup` when restarting a
+    /// simulation. The default implementation does nothing.
+    fn restore_state(&mut self, _: &mut CheckpointReader) -> Result<(), CheckpointError> {
+        Ok(())
+    }
 }
+
+impl_downcast!(Propagator);
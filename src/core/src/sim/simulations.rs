@@ -1,11 +1,19 @@
 // Lumol, an extensible molecular simulation engine
 // Copyright (C) 2015-2016 Lumol's contributors — BSD license
 
+use std::path::{Path, PathBuf};
+
 use sys::System;
 
 use sim::Propagator;
 use sim::TemperatureStrategy;
+use sim::Ensemble;
+use sim::StopCondition;
+use sim::{Hook, HookAction, StepInfo};
+use sim::hooks::{BeforeStep, AfterStep};
+use sim::stop;
 use out::Output;
+use checkpoint::{self, CheckpointWriter, CheckpointReader, CheckpointError};
 
 /// Writing an output at a given frequency
 struct OutputFrequency {
@@ -37,6 +45,14 @@ impl Output for OutputFrequency {
         self.output.setup(system);
     }
 
+    fn set_ensemble(&mut self, ensemble: Ensemble) {
+        self.output.set_ensemble(ensemble);
+    }
+
+    fn set_restart(&mut self) {
+        self.output.set_restart();
+    }
+
     fn write(&mut self, system: &System) {
         if system.step() % self.frequency == 0 {
             self.output.write(system);
@@ -46,6 +62,27 @@ impl Output for OutputFrequency {
     fn finish(&mut self, system: &System) {
         self.output.finish(system);
     }
+
+    fn save_state(&self, writer: &mut CheckpointWriter) {
+        self.output.save_state(writer);
+    }
+
+    fn restore_state(&mut self, reader: &mut CheckpointReader) -> Result<(), CheckpointError> {
+        self.output.restore_state(reader)
+    }
+}
+
+/// Information about a simulation run stored in checkpoints, used to know
+/// where to restart a multi-stage simulation.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RunInfo {
+    /// Index of the simulation stage which wrote the checkpoint
+    pub stage: usize,
+    /// Step at which the run should end
+    pub end: u64,
+    /// Was the run finished when the checkpoint was written, either because
+    /// all the steps were done or because a stop condition was met?
+    pub finished: bool,
 }
 
 /// The Simulation struct holds all the needed algorithms for running the
@@ -53,7 +90,20 @@ impl Output for OutputFrequency {
 /// simulation.
 pub struct Simulation {
     propagator: Box<Propagator>,
-    outputs: Vec<OutputFrequency>
+    outputs: Vec<OutputFrequency>,
+    /// Path and frequency for checkpoints
+    checkpoint: Option<(PathBuf, u64)>,
+    /// State of the propagator and the outputs read from a checkpoint, to be
+    /// restored at the beginning of the next run.
+    pending: Option<(Vec<u8>, Vec<Vec<u8>>)>,
+    /// Conditions to stop the simulation early
+    stop_conditions: Vec<Box<StopCondition>>,
+    /// Hooks called before and after every step
+    hooks: Vec<Box<Hook>>,
+    /// Was the last run interrupted by a stop condition?
+    interrupted: bool,
+    /// Information about the current run, stored in checkpoints
+    info: RunInfo,
 }
 
 impl Simulation {
@@ -62,10 +112,159 @@ impl Simulation {
         Simulation {
             propagator: propagator,
             outputs: Vec::new(),
+            checkpoint: None,
+            pending: None,
+            stop_conditions: Vec::new(),
+            hooks: Vec::new(),
+            interrupted: false,
+            info: RunInfo {
+                stage: 0,
+                end: 0,
+                finished: false,
+            },
+        }
+    }
+
+    /// Write a checkpoint of the simulation to the file at `path` every
+    /// `frequency` steps, and at the end of the simulation. The file is
+    /// replaced every time a new checkpoint is written.
+    ///
+    /// # Panics
+    ///
+    /// If `frequency` is zero.
+    pub fn set_checkpoint<P: AsRef<Path>>(&mut self, path: P, frequency: u64) {
+        assert!(frequency > 0, "checkpoint frequency must be positive");
+        self.checkpoint = Some((path.as_ref().to_owned(), frequency));
+    }
+
+    /// Get the path of the checkpoint file written by this simulation and the
+    /// frequency at which it is written, if checkpoints are enabled.
+    pub fn checkpoint(&self) -> Option<(&Path, u64)> {
+        self.checkpoint.as_ref().map(|&(ref path, frequency)| (path.as_path(), frequency))
+    }
+
+    /// Set the index of this simulation in a multi-stage simulation. This
+    /// index is stored in checkpoints, to be able to restart the right stage.
+    pub fn set_stage(&mut self, stage: usize) {
+        self.info.stage = stage;
+    }
+
+    /// Write a checkpoint of this simulation running on the `system` to the
+    /// file at `path`. The checkpoint contains the system, information about
+    /// the current run, the state of the propagator and the state of the
+    /// outputs.
+    pub fn save_checkpoint<P: AsRef<Path>>(&self, system: &System, path: P) -> Result<(), CheckpointError> {
+        let mut writer = CheckpointWriter::new();
+        writer.write_section(system);
+        writer.write_usize(self.info.stage);
+        writer.write_u64(self.info.end);
+        writer.write_bool(self.info.finished);
+
+        let mut propagator = CheckpointWriter::new();
+        self.propagator.save_state(&mut propagator);
+        writer.write_bytes(propagator.as_bytes());
+
+        writer.write_usize(self.outputs.len());
+        for output in &self.outputs {
+            let mut state = CheckpointWriter::new();
+            output.save_state(&mut state);
+            writer.write_bytes(state.as_bytes());
         }
+
+        checkpoint::write_file(path, writer.as_bytes())
+    }
+
+    /// Restore only the `system` from the checkpoint at `path`, ignoring the
+    /// state of the propagator and the outputs, and get information about
+    /// the run which wrote the checkpoint. This allow to start a new
+    /// simulation from the final state of a previous one.
+    pub fn restore_system<P: AsRef<Path>>(system: &mut System, path: P) -> Result<RunInfo, CheckpointError> {
+        let data = try!(checkpoint::read_file(path));
+        let mut reader = CheckpointReader::new(&data);
+        try!(reader.read_section(system));
+        read_run_info(&mut reader)
+    }
+
+    /// Restart this simulation from the checkpoint at `path`. The `system` is
+    /// restored immediately, and the state of the propagator and the outputs
+    /// is restored at the beginning of the next call to `run`. The simulation
+    /// must be configured with the same propagator and outputs as the one
+    /// which wrote the checkpoint.
+    ///
+    /// The interactions are not stored in checkpoints, and the ones already
+    /// in the `system` are used.
+    pub fn restart<P: AsRef<Path>>(&mut self, system: &mut System, path: P) -> Result<(), CheckpointError> {
+        let data = try!(checkpoint::read_file(path));
+        let mut reader = CheckpointReader::new(&data);
+        try!(reader.read_section(system));
+        let _ = try!(read_run_info(&mut reader));
+
+        let propagator = try!(reader.read_bytes()).to_vec();
+        let noutputs = try!(reader.read_usize());
+        if noutputs != self.outputs.len() {
+            return Err(CheckpointError::Format(format!(
+                "the checkpoint contains {} outputs, but the simulation has {}",
+                noutputs, self.outputs.len()
+            )));
+        }
+        let mut outputs = Vec::with_capacity(noutputs);
+        for _ in 0..noutputs {
+            outputs.push(try!(reader.read_bytes()).to_vec());
+        }
+        if !reader.is_empty() {
+            return Err(CheckpointError::from("unexpected data at the end of the checkpoint"));
+        }
+
+        self.pending = Some((propagator, outputs));
+        Ok(())
+    }
+
+    /// Add a new `StopCondition` to this simulation. The simulation will stop
+    /// after the first step where any of the conditions is met, even if all
+    /// the steps are not done.
+    pub fn add_stop_condition(&mut self, condition: Box<StopCondition>) {
+        self.stop_conditions.push(condition);
     }
 
-    /// Run the simulation on System for `nsteps` steps.
+    /// Get the stop conditions of this simulation
+    pub fn stop_conditions(&self) -> &[Box<StopCondition>] {
+        &self.stop_conditions
+    }
+
+    /// Add a new `Hook` to this simulation. Hooks are called before and
+    /// after every step, in the order they were added.
+    pub fn add_hook(&mut self, hook: Box<Hook>) {
+        self.hooks.push(hook);
+    }
+
+    /// Call `function` before every step of this simulation. The function
+    /// can modify the system, and stop the simulation by returning
+    /// `HookAction::Stop`.
+    pub fn before_step<F>(&mut self, function: F)
+        where F: FnMut(&mut System, StepInfo) -> HookAction + 'static
+    {
+        self.add_hook(Box::new(BeforeStep::new(function)));
+    }
+
+    /// Call `function` after every step of this simulation, once the outputs
+    /// have been written. The function can modify the system, and stop the
+    /// simulation by returning `HookAction::Stop`.
+    pub fn after_step<F>(&mut self, function: F)
+        where F: FnMut(&mut System, StepInfo) -> HookAction + 'static
+    {
+        self.add_hook(Box::new(AfterStep::new(function)));
+    }
+
+    /// Check if the last run of this simulation was interrupted by a stop
+    /// condition coming from outside the simulation, such as a time limit, or
+    /// by a call to `sim::stop::request_interrupt`. In this case, no other
+    /// simulation should run afterward.
+    pub fn interrupted(&self) -> bool {
+        self.interrupted
+    }
+
+    /// Run the simulation on System for `nsteps` steps, or until one of the
+    /// stop conditions is met or one of the hooks stops the simulation.
     pub fn run(&mut self, system: &mut System, nsteps: usize) {
         match self.propagator.temperature_strategy() {
             TemperatureStrategy::External(temperature) => {
@@ -76,16 +275,95 @@ impl Simulation {
         }
 
         self.setup(system);
-        for _ in 0..nsteps {
+        self.info.end = system.step() + nsteps as u64;
+        self.info.finished = false;
+        for index in 0..nsteps {
+            let info = StepInfo {
+                index: index,
+                nsteps: nsteps,
+            };
+            if self.call_hooks(system, info, true) {
+                break;
+            }
+
             self.propagator.propagate(system);
             system.increment_step();
             for output in &mut self.outputs {
                 output.write(system);
             }
+            let stop = self.call_hooks(system, info, false);
+
+            if let Some((ref path, frequency)) = self.checkpoint {
+                if system.step() % frequency == 0 {
+                    self.write_checkpoint(system, path);
+                }
+            }
+
+            if stop || self.should_stop(system) {
+                break;
+            }
+        }
+
+        self.info.finished = !self.interrupted;
+        if let Some((ref path, _)) = self.checkpoint {
+            self.write_checkpoint(system, path);
         }
         self.finish(system);
     }
 
+    /// Check the stop conditions and the interruption requests, and log the
+    /// reason to stop if needed
+    fn should_stop(&mut self, system: &System) -> bool {
+        if stop::interrupt_requested() {
+            info!("Stopping the simulation at step {}: interruption requested", system.step());
+            self.interrupted = true;
+            return true;
+        }
+
+        for condition in &mut self.stop_conditions {
+            if condition.check(system, &*self.propagator) {
+                info!("Stopping the simulation at step {}: {}", system.step(), condition.describe());
+                self.interrupted = condition.interrupt();
+                return true;
+            }
+        }
+        return false;
+    }
+
+    /// Call all the hooks `before` or after the current step, notifying the
+    /// propagator if the system was modified. This returns `true` if any hook
+    /// asked to stop the simulation.
+    fn call_hooks(&mut self, system: &mut System, info: StepInfo, before: bool) -> bool {
+        let mut modified = false;
+        let mut stop = false;
+        for hook in &mut self.hooks {
+            let action = if before {
+                hook.before_step(system, info)
+            } else {
+                hook.after_step(system, info)
+            };
+            match action {
+                HookAction::Continue => {}
+                HookAction::Modified => modified = true,
+                HookAction::Stop => stop = true,
+            }
+        }
+
+        if modified {
+            self.propagator.system_changed(system);
+        }
+        if stop {
+            info!("Stopping the simulation at step {}: requested by a hook", system.step());
+        }
+        return stop;
+    }
+
+    fn write_checkpoint(&self, system: &System, path: &Path) {
+        if let Err(err) = self.save_checkpoint(system, path) {
+            error!("Could not write checkpoint to '{}': {}", path.display(), err);
+        }
+    }
+
     /// Add a new `Output` algorithm in the outputs list
     pub fn add_output(&mut self, output: Box<Output>) {
         self.outputs.push(OutputFrequency::new(output));
@@ -98,11 +376,49 @@ impl Simulation {
         self.outputs.push(OutputFrequency::with_frequency(output, frequency));
     }
 
+    /// Get the propagator used by this simulation
+    pub fn propagator(&self) -> &Propagator {
+        &*self.propagator
+    }
+
+    /// Get the outputs of this simulation, together with the frequency at
+    /// which they are used.
+    pub fn outputs(&self) -> Vec<(&Output, u64)> {
+        self.outputs.iter().map(|output| (&*output.output, output.frequency)).collect()
+    }
+
     fn setup(&mut self, system: &mut System) {
+        self.interrupted = false;
+        stop::clear_interrupt();
         self.propagator.setup(system);
+        let ensemble = self.propagator.ensemble();
+        let restart = self.pending.is_some();
         for output in &mut self.outputs {
+            output.set_ensemble(ensemble);
+            if restart {
+                output.set_restart();
+            }
             output.setup(system);
         }
+
+        for condition in &mut self.stop_conditions {
+            condition.setup(system);
+        }
+
+        for hook in &mut self.hooks {
+            hook.setup(system);
+        }
+
+        if let Some((propagator, outputs)) = self.pending.take() {
+            if let Err(err) = restore(&propagator, |reader| self.propagator.restore_state(reader)) {
+                fatal_error!("Could not restore the propagator from checkpoint: {}", err);
+            }
+            for (output, state) in self.outputs.iter_mut().zip(&outputs) {
+                if let Err(err) = restore(state, |reader| output.restore_state(reader)) {
+                    fatal_error!("Could not restore an output from checkpoint: {}", err);
+                }
+            }
+        }
     }
 
     fn finish(&mut self, system: &mut System) {
@@ -110,5 +426,255 @@ impl Simulation {
         for output in &mut self.outputs {
             output.finish(system);
         }
+        for hook in &mut self.hooks {
+            hook.finish(system);
+        }
+    }
+}
+
+/// Read the information about a simulation run from a checkpoint
+fn read_run_info(reader: &mut CheckpointReader) -> Result<RunInfo, CheckpointError> {
+    Ok(RunInfo {
+        stage: try!(reader.read_usize()),
+        end: try!(reader.read_u64()),
+        finished: try!(reader.read_bool()),
+    })
+}
+
+/// Restore the state of a propagator or an output from the checkpoint `data`
+/// using the `restore_state` function, checking that all the data is used.
+fn restore<F>(data: &[u8], restore_state: F) -> Result<(), CheckpointError>
+    where F: FnOnce(&mut CheckpointReader) -> Result<(), CheckpointError>
+{
+    let mut reader = CheckpointReader::new(data);
+    try!(restore_state(&mut reader));
+    if reader.is_empty() {
+        Ok(())
+    } else {
+        Err(CheckpointError::from("unexpected data in checkpoint"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate tempfile;
+    use self::tempfile::NamedTempFile;
+
+    use super::*;
+    use sys::*;
+    use sim::{MolecularDynamics, MonteCarlo, Minimization};
+    use sim::mc::Translate;
+    use sim::min::{Minimizer, Tolerance, SteepestDescent, ConjugateGradient, LBFGS, FIRE};
+    use sim::min::{CellRelaxation, CellDeformation};
+    use sim::NudgedElasticBand;
+    use sim::stop::{Converged, StopFunction, WallClockLimit};
+    use sim::{HookAction, StepInfo};
+    use energy::{LennardJones, PairInteraction};
+    use types::Vector3D;
+    use utils::unit_from;
+
+    use std::rc::Rc;
+    use std::cell::Cell;
+
+    fn testing_system() -> System {
+        let mut system = System::from_cell(UnitCell::cubic(10.0));
+        for i in 0..8 {
+            let mut particle = Particle::new("Ar");
+            particle.position = Vector3D::new(
+                (i % 2) as f64 * 4.0, ((i / 2) % 2) as f64 * 4.0, (i / 4) as f64 * 4.0
+            ) + 0.1 * i as f64 * Vector3D::new(1.0, -0.5, 0.3);
+            particle.velocity = 1e-3 * Vector3D::new(i as f64, 1.0 - i as f64, 0.5);
+            system.add_particle(particle);
+        }
+
+        let lj = LennardJones{sigma: 3.4, epsilon: unit_from(1.0, "kJ/mol")};
+        system.interactions_mut().add_pair("Ar", "Ar", PairInteraction::new(Box::new(lj), 4.9));
+        return system;
+    }
+
+    fn check_restart<F: Fn() -> Simulation>(simulation: F) {
+        let checkpoint = NamedTempFile::new().unwrap();
+
+        let mut reference = testing_system();
+        simulation().run(&mut reference, 100);
+
+        let mut system = testing_system();
+        let mut first = simulation();
+        assert_eq!(first.checkpoint(), None);
+        first.set_checkpoint(checkpoint.path(), 30);
+        assert_eq!(first.checkpoint(), Some((checkpoint.path(), 30)));
+        first.set_stage(3);
+        first.run(&mut system, 50);
+
+        let mut restored = System::new();
+        *restored.interactions_mut() = system.interactions().clone();
+        let info = Simulation::restore_system(&mut restored, checkpoint.path()).unwrap();
+        assert_eq!(info, RunInfo{stage: 3, end: 50, finished: true});
+
+        let mut restarted = testing_system();
+        let mut second = simulation();
+        second.restart(&mut restarted, checkpoint.path()).unwrap();
+        assert_eq!(restarted.step(), 50);
+        second.run(&mut restarted, 50);
+
+        assert_eq!(restarted.step(), reference.step());
+        for (particle, expected) in restarted.iter().zip(reference.iter()) {
+            assert_eq!(particle.position, expected.position);
+            assert_eq!(particle.velocity, expected.velocity);
+        }
+    }
+
+    #[test]
+    fn restart_md() {
+        check_restart(|| {
+            Simulation::new(Box::new(MolecularDynamics::new(unit_from(1.0, "fs"))))
+        });
+    }
+
+    #[test]
+    fn restart_mc() {
+        check_restart(|| {
+            let mut mc = MonteCarlo::new(unit_from(300.0, "K"));
+            mc.add(Box::new(Translate::new(0.5)), 1.0);
+            Simulation::new(Box::new(mc))
+        });
+    }
+
+    #[test]
+    fn restart_minimization() {
+        // Use a zero tolerance to run all the steps
+        fn minimization(minimizer: Box<Minimizer>) -> Simulation {
+            let tolerance = Tolerance {energy: 0.0, force2: 0.0};
+            Simulation::new(Box::new(Minimization::with_tolerance(minimizer, tolerance)))
+        }
+
+        check_restart(|| minimization(Box::new(SteepestDescent::new())));
+        check_restart(|| minimization(Box::new(ConjugateGradient::new())));
+        check_restart(|| minimization(Box::new(LBFGS::new())));
+        check_restart(|| minimization(Box::new(FIRE::new())));
+        check_restart(|| {
+            minimization(Box::new(CellRelaxation::new(unit_from(1.0, "bar"), CellDeformation::Isotropic)))
+        });
+    }
+
+    #[test]
+    fn restart_neb() {
+        check_restart(|| {
+            let system = testing_system();
+            let initial = system.iter().map(|particle| particle.position).collect::<Vec<_>>();
+            let last = initial.iter().map(|&position| position + Vector3D::new(0.5, 0.2, 0.0)).collect();
+            let mut minimizers: Vec<Box<Minimizer>> = Vec::new();
+            for _ in 0..3 {
+                minimizers.push(Box::new(FIRE::new()));
+            }
+            let mut neb = NudgedElasticBand::new(initial, last, minimizers);
+            // Use a zero tolerance to run all the steps
+            neb.set_tolerance(0.0);
+            Simulation::new(Box::new(neb))
+        });
+    }
+
+    #[test]
+    fn restart_errors() {
+        let checkpoint = NamedTempFile::new().unwrap();
+        let mut system = testing_system();
+        let simulation = Simulation::new(Box::new(MolecularDynamics::new(1.0)));
+        simulation.save_checkpoint(&system, checkpoint.path()).unwrap();
+
+        let mut restored = System::new();
+        *restored.interactions_mut() = system.interactions().clone();
+        let info = Simulation::restore_system(&mut restored, checkpoint.path()).unwrap();
+        assert_eq!(restored.size(), system.size());
+        assert_eq!(info, RunInfo{stage: 0, end: 0, finished: false});
+
+        // The interactions must be the same as the ones used to write the
+        // checkpoint
+        let mut restored = System::new();
+        assert!(Simulation::restore_system(&mut restored, checkpoint.path()).is_err());
+        assert_eq!(restored.size(), 0);
+
+        let lj = LennardJones{sigma: 3.4, epsilon: unit_from(1.1, "kJ/mol")};
+        restored.interactions_mut().add_pair("Ar", "Ar", PairInteraction::new(Box::new(lj), 4.9));
+        assert!(Simulation::restore_system(&mut restored, checkpoint.path()).is_err());
+
+        let mut other = Simulation::new(Box::new(MolecularDynamics::new(1.0)));
+        other.add_output_with_frequency(Box::new(::out::CellOutput::new(NamedTempFile::new().unwrap().path()).unwrap()), 7);
+        assert_eq!(other.outputs().len(), 1);
+        assert_eq!(other.outputs()[0].1, 7);
+        assert!(other.restart(&mut system, checkpoint.path()).is_err());
+    }
+
+    #[test]
+    fn stop_conditions() {
+        let mut system = testing_system();
+        let mut simulation = Simulation::new(Box::new(MolecularDynamics::new(1.0)));
+        simulation.add_stop_condition(Box::new(
+            StopFunction::new("step 10", |system: &System| system.step() >= 10)
+        ));
+        assert_eq!(simulation.stop_conditions().len(), 1);
+        simulation.run(&mut system, 100);
+        assert_eq!(system.step(), 10);
+        assert!(!simulation.interrupted());
+
+        let mut simulation = Simulation::new(Box::new(MolecularDynamics::new(1.0)));
+        simulation.add_stop_condition(Box::new(WallClockLimit::new(1e-12)));
+        simulation.run(&mut system, 100);
+        assert_eq!(system.step(), 11);
+        assert!(simulation.interrupted());
+    }
+
+    #[test]
+    fn stop_converged() {
+        let mut system = testing_system();
+        let minimization = Minimization::new(Box::new(SteepestDescent::new()));
+        let mut simulation = Simulation::new(Box::new(minimization));
+        simulation.add_stop_condition(Box::new(Converged));
+        simulation.run(&mut system, 100_000);
+        assert!(system.step() < 100_000);
+        assert!(simulation.propagator().converged());
+    }
+
+    #[test]
+    fn hooks() {
+        let mut system = testing_system();
+        let mut simulation = Simulation::new(Box::new(MolecularDynamics::new(1.0)));
+
+        let calls = Rc::new(Cell::new(0));
+        let before = calls.clone();
+        simulation.before_step(move |system: &mut System, info: StepInfo| {
+            assert_eq!(info.nsteps, 100);
+            assert_eq!(system.step(), info.index as u64);
+            before.set(before.get() + 1);
+            // Gradually scale the charges
+            for particle in system.iter_mut() {
+                particle.charge = info.progress();
+            }
+            HookAction::Modified
+        });
+        simulation.after_step(|system: &mut System, _: StepInfo| {
+            if system.step() == 5 {
+                HookAction::Stop
+            } else {
+                HookAction::Continue
+            }
+        });
+
+        simulation.run(&mut system, 100);
+        assert_eq!(system.step(), 5);
+        assert_eq!(calls.get(), 5);
+        assert_eq!(system[0].charge, 0.04);
+        assert!(!simulation.interrupted());
+
+        // Stopping before a step does not propagate the system
+        let mut simulation = Simulation::new(Box::new(MolecularDynamics::new(1.0)));
+        simulation.before_step(|_: &mut System, info: StepInfo| {
+            if info.index == 3 {
+                HookAction::Stop
+            } else {
+                HookAction::Continue
+            }
+        });
+        simulation.run(&mut system, 100);
+        assert_eq!(system.step(), 8);
     }
 }
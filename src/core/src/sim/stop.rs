@@ -0,0 +1,374 @@
+// Lumol, an extensible molecular simulation engine
+// Copyright (C) 2015-2016 Lumol's contributors — BSD license
+
+//! Conditions to stop a simulation before all the steps are done
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use sys::System;
+use sim::Propagator;
+use out::{CustomQuantity, CustomOutputError};
+use utils::AsAny;
+
+lazy_static!(
+    static ref INTERRUPT_REQUESTED: AtomicBool = AtomicBool::new(false);
+);
+
+/// Request all the running simulations to stop after the current step, as if
+/// they were interrupted by a stop condition. The outputs are finished, and a
+/// final checkpoint is written if needed. This function only sets a flag, and
+/// can be called from a signal handler or from another thread.
+///
+/// The request is cleared when a simulation starts running, so that it only
+/// applies to the simulations running when it was made.
+pub fn request_interrupt() {
+    INTERRUPT_REQUESTED.store(true, Ordering::SeqCst);
+}
+
+/// Check if an interruption of the simulations was requested with
+/// `request_interrupt`.
+pub fn interrupt_requested() -> bool {
+    INTERRUPT_REQUESTED.load(Ordering::SeqCst)
+}
+
+/// Clear any interruption request made with `request_interrupt`. This is
+/// called at the beginning of `Simulation::run`.
+pub fn clear_interrupt() {
+    INTERRUPT_REQUESTED.store(false, Ordering::SeqCst);
+}
+
+/// A `StopCondition` allow to stop a simulation before all the requested steps
+/// are done. Conditions are checked after every step of the simulation, once
+/// the outputs have been written.
+pub trait StopCondition: AsAny {
+    /// Setup code, called at the beginning of every simulation run.
+    fn setup(&mut self, _: &System) {}
+
+    /// Check if the simulation should stop, given the current `system` and
+    /// the simulation `propagator`.
+    fn check(&mut self, system: &System, propagator: &Propagator) -> bool;
+
+    /// Get a short description of this condition, used to explain why the
+    /// simulation stopped.
+    fn describe(&self) -> &str;
+
+    /// Check if this condition interrupts all the remaining simulations, and
+    /// not only the current one. This is used for conditions coming from
+    /// outside of the simulation, such as time limits. The default
+    /// implementation returns `false`.
+    fn interrupt(&self) -> bool {
+        false
+    }
+}
+
+impl_downcast!(StopCondition);
+
+/// Stop the simulation when the propagator has converged, for example when
+/// an energy minimization reached the requested tolerance.
+pub struct Converged;
+
+impl StopCondition for Converged {
+    fn check(&mut self, _: &System, propagator: &Propagator) -> bool {
+        propagator.converged()
+    }
+
+    fn describe(&self) -> &str {
+        "the propagator converged"
+    }
+}
+
+/// Stop the simulation when a wall-clock time limit is reached. The time is
+/// measured from the creation of the condition, so that the limit applies to
+/// all the simulations using it. This condition interrupts all the remaining
+/// simulations.
+pub struct WallClockLimit {
+    /// Starting time
+    start: Instant,
+    /// Time limit, in seconds
+    limit: f64,
+}
+
+impl WallClockLimit {
+    /// Create a new `WallClockLimit` stopping the simulation after `limit`
+    /// seconds.
+    ///
+    /// # Panics
+    ///
+    /// If `limit` is not positive.
+    pub fn new(limit: f64) -> WallClockLimit {
+        assert!(limit > 0.0, "wall-clock time limit must be positive");
+        WallClockLimit {
+            start: Instant::now(),
+            limit: limit,
+        }
+    }
+
+    /// Get the time limit, in seconds
+    pub fn limit(&self) -> f64 {
+        self.limit
+    }
+}
+
+impl StopCondition for WallClockLimit {
+    fn check(&mut self, _: &System, _: &Propagator) -> bool {
+        let elapsed = self.start.elapsed();
+        let elapsed = elapsed.as_secs() as f64 + 1e-9 * elapsed.subsec_nanos() as f64;
+        elapsed >= self.limit
+    }
+
+    fn describe(&self) -> &str {
+        "the wall-clock time limit was reached"
+    }
+
+    fn interrupt(&self) -> bool {
+        true
+    }
+}
+
+/// Stop the simulation when a file exists. This allow to cleanly stop a
+/// running simulation by creating the file. The file is not removed, and
+/// should be removed before running the simulation again. This condition
+/// interrupts all the remaining simulations.
+pub struct StopFile {
+    /// Path of the file to check
+    path: PathBuf,
+}
+
+impl StopFile {
+    /// Create a new `StopFile` stopping the simulation when the file at
+    /// `path` exists.
+    pub fn new<P: AsRef<Path>>(path: P) -> StopFile {
+        StopFile {
+            path: path.as_ref().to_owned(),
+        }
+    }
+
+    /// Get the path of the file checked by this condition
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl StopCondition for StopFile {
+    fn check(&mut self, _: &System, _: &Propagator) -> bool {
+        self.path.exists()
+    }
+
+    fn describe(&self) -> &str {
+        "the stop file exists"
+    }
+
+    fn interrupt(&self) -> bool {
+        true
+    }
+}
+
+/// Stop the simulation when a quantity goes above or below a threshold.
+pub struct Threshold {
+    /// The expression of the quantity to check
+    property: String,
+    /// The quantity to check
+    quantity: CustomQuantity,
+    /// Threshold value, in internal units
+    value: f64,
+    /// Should we stop above (`true`) or below (`false`) the threshold
+    above: bool,
+    /// Description of this condition
+    description: String,
+}
+
+impl Threshold {
+    /// Create a new `Threshold` stopping the simulation when `quantity` is
+    /// larger than `value`, in internal units.
+    pub fn above(quantity: &str, value: f64) -> Result<Threshold, CustomOutputError> {
+        Threshold::new(quantity, value, true)
+    }
+
+    /// Create a new `Threshold` stopping the simulation when `quantity` is
+    /// smaller than `value`, in internal units.
+    pub fn below(quantity: &str, value: f64) -> Result<Threshold, CustomOutputError> {
+        Threshold::new(quantity, value, false)
+    }
+
+    fn new(quantity: &str, value: f64, above: bool) -> Result<Threshold, CustomOutputError> {
+        let side = if above {"above"} else {"below"};
+        Ok(Threshold {
+            property: String::from(quantity),
+            quantity: try!(CustomQuantity::new(quantity)),
+            value: value,
+            above: above,
+            description: format!("'{}' went {} the threshold", quantity.trim(), side),
+        })
+    }
+
+    /// Get the expression of the quantity checked by this condition
+    pub fn property(&self) -> &str {
+        &self.property
+    }
+
+    /// Get the threshold value, in internal units
+    pub fn value(&self) -> f64 {
+        self.value
+    }
+
+    /// Check if this condition stops the simulation above (`true`) or below
+    /// (`false`) the threshold.
+    pub fn is_above(&self) -> bool {
+        self.above
+    }
+}
+
+impl StopCondition for Threshold {
+    fn check(&mut self, system: &System, _: &Propagator) -> bool {
+        let value = self.quantity.evaluate(system);
+        if self.above {
+            value > self.value
+        } else {
+            value < self.value
+        }
+    }
+
+    fn describe(&self) -> &str {
+        &self.description
+    }
+}
+
+/// Stop the simulation when a function returns `true`.
+pub struct StopFunction<F> {
+    /// The function to call
+    function: F,
+    /// Description of this condition
+    description: String,
+}
+
+impl<F> StopFunction<F> where F: FnMut(&System) -> bool {
+    /// Create a new `StopFunction` stopping the simulation when `function`
+    /// returns `true`, with the given `description`.
+    pub fn new<S: Into<String>>(description: S, function: F) -> StopFunction<F> {
+        StopFunction {
+            function: function,
+            description: description.into(),
+        }
+    }
+}
+
+impl<F> StopCondition for StopFunction<F> where F: FnMut(&System) -> bool + 'static {
+    fn check(&mut self, system: &System, _: &Propagator) -> bool {
+        (self.function)(system)
+    }
+
+    fn describe(&self) -> &str {
+        &self.description
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate tempfile;
+    use self::tempfile::NamedTempFile;
+
+    use super::*;
+    use sys::{System, Particle, UnitCell};
+    use sim::{Propagator, TemperatureStrategy};
+
+    struct Dummy {
+        converged: bool,
+    }
+
+    impl Propagator for Dummy {
+        fn temperature_strategy(&self) -> TemperatureStrategy {
+            TemperatureStrategy::None
+        }
+
+        fn propagate(&mut self, _: &mut System) {}
+
+        fn converged(&self) -> bool {
+            self.converged
+        }
+    }
+
+    fn testing_system() -> System {
+        let mut system = System::from_cell(UnitCell::cubic(10.0));
+        system.add_particle(Particle::new("Ar"));
+        system.add_particle(Particle::new("Ar"));
+        return system;
+    }
+
+    #[test]
+    fn converged() {
+        let system = testing_system();
+        let mut condition = Converged;
+        assert!(!condition.check(&system, &Dummy{converged: false}));
+        assert!(condition.check(&system, &Dummy{converged: true}));
+        assert!(!condition.interrupt());
+    }
+
+    #[test]
+    fn wall_clock() {
+        let system = testing_system();
+        let propagator = Dummy{converged: false};
+        let mut condition = WallClockLimit::new(1e6);
+        assert!(!condition.check(&system, &propagator));
+        assert!(condition.interrupt());
+        assert_eq!(condition.limit(), 1e6);
+
+        let mut condition = WallClockLimit::new(1e-9);
+        ::std::thread::sleep(::std::time::Duration::from_millis(1));
+        assert!(condition.check(&system, &propagator));
+    }
+
+    #[test]
+    fn stop_file() {
+        let system = testing_system();
+        let propagator = Dummy{converged: false};
+        let path = {
+            let file = NamedTempFile::new().unwrap();
+            let mut condition = StopFile::new(file.path());
+            assert!(condition.check(&system, &propagator));
+            file.path().to_owned()
+        };
+        let mut condition = StopFile::new(&path);
+        assert!(!condition.check(&system, &propagator));
+        assert_eq!(condition.path(), path);
+    }
+
+    #[test]
+    fn threshold() {
+        let mut system = testing_system();
+        let propagator = Dummy{converged: false};
+        system[1].velocity[0] = 1e-2;
+
+        let temperature = system.temperature();
+        assert!(temperature > 0.0);
+        let mut condition = Threshold::above("temperature", 0.5 * temperature).unwrap();
+        assert!(condition.check(&system, &propagator));
+        let mut condition = Threshold::above("temperature", 2.0 * temperature).unwrap();
+        assert!(!condition.check(&system, &propagator));
+        let mut condition = Threshold::below("temperature", 2.0 * temperature).unwrap();
+        assert!(condition.check(&system, &propagator));
+        assert_eq!(condition.describe(), "'temperature' went below the threshold");
+        assert_eq!(condition.property(), "temperature");
+        assert_eq!(condition.value(), 2.0 * temperature);
+        assert!(!condition.is_above());
+
+        let mut condition = Threshold::below("volume / natoms", 400.0).unwrap();
+        assert!(!condition.check(&system, &propagator));
+        system.add_particle(Particle::new("Ar"));
+        assert!(condition.check(&system, &propagator));
+
+        assert!(Threshold::above("foo", 0.0).is_err());
+    }
+
+    #[test]
+    fn function() {
+        let mut system = testing_system();
+        let propagator = Dummy{converged: false};
+        let mut condition = StopFunction::new("too many steps", |system: &System| system.step() >= 2);
+        assert!(!condition.check(&system, &propagator));
+        system.increment_step();
+        system.increment_step();
+        assert!(condition.check(&system, &propagator));
+        assert_eq!(condition.describe(), "too many steps");
+    }
+}
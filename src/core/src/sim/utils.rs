@@ -2,6 +2,7 @@
 // Copyright (C) 2015-2016 Lumol's contributors — BSD license
 
 //! Module for small utility structs
+use checkpoint::{Checkpoint, CheckpointWriter, CheckpointReader, CheckpointError};
 
 /// Helper struct that can wrap an algorithm to make
 /// it run only a fraction of the times it is called.
@@ -63,6 +64,11 @@ impl<T> Alternator<T> {
         self.count += 1;
         self.count % self.every == 0
     }
+
+    /// Get the number of calls between two runs of the algorithm
+    pub fn every(&self) -> u64 {
+        self.every
+    }
 }
 
 impl<T> AsRef<T> for Alternator<T> {
@@ -78,3 +84,16 @@ impl<T> AsMut<T> for Alternator<T> {
         &mut self.base
     }
 }
+
+/// The checkpoint of an `Alternator` only contains the number of calls, the
+/// base algorithm state should be saved separately.
+impl<T> Checkpoint for Alternator<T> {
+    fn save(&self, writer: &mut CheckpointWriter) {
+        writer.write_u64(self.count);
+    }
+
+    fn restore(&mut self, reader: &mut CheckpointReader) -> Result<(), CheckpointError> {
+        self.count = try!(reader.read_u64());
+        Ok(())
+    }
+}
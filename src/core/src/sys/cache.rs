@@ -11,6 +11,7 @@ use std::mem;
 
 use super::System;
 use types::{Vector3D, Array2};
+use checkpoint::{Checkpoint, CheckpointWriter, CheckpointReader, CheckpointError};
 
 /// Callback for updating a cache. It also take an `&mut System` argument for
 /// updating the cache inside the global potentials.
@@ -362,6 +363,49 @@ fn new_position<'a>(system: &'a System, i: usize, idxes: &[usize], newpos: &'a[V
     return &system[i].position;
 }
 
+/// The checkpoint of an `EnergyCache` contains all the cached energies. The
+/// cache must already be initialized for the same system when restoring it.
+impl Checkpoint for EnergyCache {
+    fn save(&self, writer: &mut CheckpointWriter) {
+        let (size, _) = self.pairs_cache.shape();
+        writer.write_usize(size);
+        for i in 0..size {
+            for j in 0..size {
+                writer.write_f64(self.pairs_cache[(i, j)]);
+            }
+        }
+        writer.write_f64(self.pairs);
+        writer.write_f64(self.pairs_tail);
+        writer.write_f64(self.bonds);
+        writer.write_f64(self.angles);
+        writer.write_f64(self.dihedrals);
+        writer.write_f64(self.coulomb);
+        writer.write_f64(self.global);
+    }
+
+    fn restore(&mut self, reader: &mut CheckpointReader) -> Result<(), CheckpointError> {
+        let size = try!(reader.read_usize());
+        if (size, size) != self.pairs_cache.shape() {
+            return Err(CheckpointError::from(
+                "the energy cache in the checkpoint does not match the system size"
+            ));
+        }
+        for i in 0..size {
+            for j in 0..size {
+                self.pairs_cache[(i, j)] = try!(reader.read_f64());
+            }
+        }
+        self.pairs = try!(reader.read_f64());
+        self.pairs_tail = try!(reader.read_f64());
+        self.bonds = try!(reader.read_f64());
+        self.angles = try!(reader.read_f64());
+        self.dihedrals = try!(reader.read_f64());
+        self.coulomb = try!(reader.read_f64());
+        self.global = try!(reader.read_f64());
+        Ok(())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -7,6 +7,7 @@
 use std::f64::consts::PI;
 
 use types::{Matrix3, Vector3D, Zero};
+use checkpoint::{Checkpoint, CheckpointWriter, CheckpointReader, CheckpointError};
 
 /// The shape of a cell determine how we will be able to compute the periodic
 /// boundaries condition.
@@ -417,6 +418,30 @@ fn angle(u: Vector3D, v: Vector3D) -> f64 {
     f64::acos(un*vn)
 }
 
+impl Checkpoint for UnitCell {
+    fn save(&self, writer: &mut CheckpointWriter) {
+        writer.write_u64(match self.shape {
+            CellShape::Infinite => 0,
+            CellShape::Orthorombic => 1,
+            CellShape::Triclinic => 2,
+        });
+        writer.write_matrix(&self.cell);
+        writer.write_matrix(&self.inv);
+    }
+
+    fn restore(&mut self, reader: &mut CheckpointReader) -> Result<(), CheckpointError> {
+        self.shape = match try!(reader.read_u64()) {
+            0 => CellShape::Infinite,
+            1 => CellShape::Orthorombic,
+            2 => CellShape::Triclinic,
+            other => return Err(CheckpointError::from(format!("invalid cell shape {} in checkpoint", other))),
+        };
+        self.cell = try!(reader.read_matrix());
+        self.inv = try!(reader.read_matrix());
+        Ok(())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -236,6 +236,13 @@ impl Trajectory {
         return Ok(Trajectory(trajectory));
     }
 
+    /// Open the file at `path` for writing new steps after the existing ones,
+    /// creating the file if it does not exist.
+    pub fn append<P: AsRef<Path>>(path: P) -> TrajectoryResult<Trajectory> {
+        let trajectory = try!(chemfiles::Trajectory::open(path, 'a'));
+        return Ok(Trajectory(trajectory));
+    }
+
     /// Read the next step of the trajectory
     pub fn read(&mut self) -> TrajectoryResult<System> {
         let mut frame = try!(chemfiles::Frame::new());
@@ -3,7 +3,7 @@
 
 //! Computing properties of a system
 use consts::K_BOLTZMANN;
-use types::{Matrix3, Vector3D, Zero, One};
+use types::{Matrix3, Vector3D, Array2, Zero, One};
 use sys::System;
 use std::f64::consts::PI;
 
@@ -299,6 +299,56 @@ impl Compute for Pressure {
     }
 }
 
+/******************************************************************************/
+/// Compute the Hessian of the potential energy of the system, i.e. the matrix
+/// of second derivatives of the energy with respect to the atomic positions.
+///
+/// The Hessian is a `3N x 3N` matrix, where the element at `(3 * i + α, 3 * j
+/// + β)` is the derivative of the energy with respect to the `α` coordinate
+/// of atom `i` and the `β` coordinate of atom `j`. It is computed using
+/// central finite differences of the forces, and is thus available for all
+/// the potentials.
+pub struct Hessian;
+
+/// Displacement used for the finite differences in the Hessian
+const HESSIAN_DELTA: f64 = 1e-4;
+
+impl Compute for Hessian {
+    type Output = Array2<f64>;
+    fn compute(&self, system: &System) -> Array2<f64> {
+        let natoms = system.size();
+        let mut hessian = Array2::zeros((3 * natoms, 3 * natoms));
+        let mut system = system.clone();
+        for i in 0..natoms {
+            for alpha in 0..3 {
+                let position = system[i].position;
+                system[i].position[alpha] = position[alpha] + HESSIAN_DELTA;
+                let forward = Forces.compute(&system);
+                system[i].position[alpha] = position[alpha] - HESSIAN_DELTA;
+                let backward = Forces.compute(&system);
+                system[i].position = position;
+
+                for j in 0..natoms {
+                    for beta in 0..3 {
+                        let delta = forward[j][beta] - backward[j][beta];
+                        hessian[(3 * i + alpha, 3 * j + beta)] = -delta / (2.0 * HESSIAN_DELTA);
+                    }
+                }
+            }
+        }
+
+        // Remove the numerical noise breaking the symmetry
+        for i in 0..3 * natoms {
+            for j in (i + 1)..3 * natoms {
+                let mean = 0.5 * (hessian[(i, j)] + hessian[(j, i)]);
+                hessian[(i, j)] = mean;
+                hessian[(j, i)] = mean;
+            }
+        }
+        return hessian;
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -394,6 +444,29 @@ mod test {
         assert_ulps_eq!(forces_tot.norm2(), 0.0);
     }
 
+    #[test]
+    fn hessian() {
+        let system = &test_pairs_system();
+        let hessian = Hessian.compute(system);
+        assert_eq!(hessian.shape(), (6, 6));
+
+        // Second derivative of the harmonic potential along the bond, and
+        // F/r perpendicular to it
+        let k = unit_from(300.0, "kJ/mol/A^2");
+        let perpendicular = k * (1.3 - 1.2) / 1.3;
+        assert_relative_eq!(hessian[(0, 0)], k, max_relative=1e-6);
+        assert_relative_eq!(hessian[(0, 3)], -k, max_relative=1e-6);
+        assert_relative_eq!(hessian[(1, 1)], perpendicular, max_relative=1e-6);
+        assert_relative_eq!(hessian[(2, 5)], -perpendicular, max_relative=1e-6);
+        assert_relative_eq!(hessian[(0, 1)], 0.0, epsilon=1e-9);
+
+        for i in 0..6 {
+            for j in 0..6 {
+                assert_eq!(hessian[(i, j)], hessian[(j, i)]);
+            }
+        }
+    }
+
     #[test]
     fn energy_pairs() {
         let system = &test_pairs_system();
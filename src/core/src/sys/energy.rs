@@ -7,6 +7,7 @@
 //! potential energy of an `System`.
 use sys::System;
 use std::f64::consts::PI;
+use std::collections::BTreeMap;
 
 /// An helper struct to evaluate energy components of a system.
 pub struct EnergyEvaluator<'a> {
@@ -22,6 +23,50 @@ impl<'a> EnergyEvaluator<'a> {
     }
 }
 
+/// Decomposition of the energy of a system in its different terms, as
+/// returned by `System::energy_breakdown`. All the energies are in internal
+/// units.
+#[derive(Clone, Debug, PartialEq)]
+pub struct EnergyBreakdown {
+    /// Energy of all the non-bonded pairs, without tail corrections
+    pub pairs: f64,
+    /// Energy of the non-bonded pairs inside the same molecule
+    pub intramolecular: f64,
+    /// Energy of the non-bonded pairs between different molecules
+    pub intermolecular: f64,
+    /// Energy of the non-bonded pairs for each pair of species. The key
+    /// contains the names of the two species, in lexicographic order.
+    pub species: BTreeMap<(String, String), f64>,
+    /// Long range corrections for the pairs energy
+    pub tail: f64,
+    /// Energy of the bonds
+    pub bonds: f64,
+    /// Energy of the angles
+    pub angles: f64,
+    /// Energy of the dihedral angles
+    pub dihedrals: f64,
+    /// Energy of the electrostatic interactions
+    pub coulomb: f64,
+    /// Energy of the global potentials
+    pub global: f64,
+    /// Kinetic energy
+    pub kinetic: f64,
+}
+
+impl EnergyBreakdown {
+    /// Get the potential energy, *i.e.* the sum of all the potential energy
+    /// terms.
+    pub fn potential(&self) -> f64 {
+        self.pairs + self.tail + self.bonds + self.angles + self.dihedrals + self.coulomb + self.global
+    }
+
+    /// Get the total energy, *i.e.* the sum of the potential and kinetic
+    /// energy.
+    pub fn total(&self) -> f64 {
+        self.potential() + self.kinetic
+    }
+}
+
 impl<'a> EnergyEvaluator<'a> {
     /// Compute the energy associated with the pair of particles `i, j` at
     /// distance `r`
@@ -50,6 +95,51 @@ impl<'a> EnergyEvaluator<'a> {
         return energy;
     }
 
+    /// Compute the energy of all the pairs in the system, and the split of
+    /// this energy in `(intramolecular, intermolecular, species)`
+    /// contributions. See `EnergyBreakdown` for the meaning of the species
+    /// contributions.
+    pub fn pairs_decomposition(&self) -> (f64, f64, BTreeMap<(String, String), f64>) {
+        let mut intramolecular = 0.0;
+        let mut intermolecular = 0.0;
+        let mut kinds = BTreeMap::new();
+        for i in 0..self.system.size() {
+            let kind_i = self.system[i].kind;
+            for j in (i+1)..self.system.size() {
+                let r = self.system.nearest_image(i, j).norm();
+                let energy = self.pair(r, i, j);
+                if self.system.are_in_same_molecule(i, j) {
+                    intramolecular += energy;
+                } else {
+                    intermolecular += energy;
+                }
+
+                let kind_j = self.system[j].kind;
+                let key = if kind_i <= kind_j {(kind_i, kind_j)} else {(kind_j, kind_i)};
+                *kinds.entry(key).or_insert(0.0) += energy;
+            }
+        }
+
+        // Particles with the same kind have the same name, so the names only
+        // need to be looked up once per kind.
+        let mut names = BTreeMap::new();
+        for particle in self.system.iter() {
+            let _ = names.entry(particle.kind).or_insert_with(|| particle.name());
+        }
+
+        let mut species = BTreeMap::new();
+        for ((kind_i, kind_j), energy) in kinds {
+            let (a, b) = (names[&kind_i], names[&kind_j]);
+            let key = if a <= b {
+                (String::from(a), String::from(b))
+            } else {
+                (String::from(b), String::from(a))
+            };
+            *species.entry(key).or_insert(0.0) += energy;
+        }
+        return (intramolecular, intermolecular, species);
+    }
+
     /// Compute the energy due to long range corrections for the pairs
     #[inline]
     pub fn pairs_tail(&self) -> f64 {
@@ -253,4 +343,32 @@ mod tests {
         let evaluator = EnergyEvaluator::new(&system);
         assert_ulps_eq!(evaluator.dihedrals(), unit_from(1250.0, "kJ/mol"), max_ulps=15);
     }
+
+    #[test]
+    fn breakdown() {
+        let mut system = testing_system();
+        system.add_particle(Particle::new("Cl"));
+        system[4].position = Vector3D::new(0.0, 2.0, 0.0);
+        system.interactions_mut().add_pair("F", "Cl", PairInteraction::new(
+            Box::new(Harmonic{k: unit_from(100.0, "kJ/mol/A^2"), x0: 1.0}), 5.0
+        ));
+
+        let breakdown = system.energy_breakdown();
+        let evaluator = EnergyEvaluator::new(&system);
+        assert_ulps_eq!(breakdown.pairs, evaluator.pairs());
+        assert_ulps_eq!(breakdown.intramolecular, unit_from(-258.3019360389957, "kJ/mol"));
+        assert_ulps_eq!(breakdown.intramolecular + breakdown.intermolecular, breakdown.pairs);
+
+        assert_eq!(breakdown.species.len(), 2);
+        let key = (String::from("Cl"), String::from("F"));
+        assert_ulps_eq!(breakdown.species[&key], breakdown.intermolecular);
+        let key = (String::from("F"), String::from("F"));
+        assert_ulps_eq!(breakdown.species[&key], breakdown.intramolecular);
+
+        assert_ulps_eq!(breakdown.bonds, unit_from(150.0, "kJ/mol"));
+        assert_eq!(breakdown.coulomb, 0.0);
+        assert_eq!(breakdown.kinetic, 0.0);
+        assert_ulps_eq!(breakdown.potential(), system.potential_energy());
+        assert_ulps_eq!(breakdown.total(), system.total_energy());
+    }
 }
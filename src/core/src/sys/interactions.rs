@@ -141,6 +141,12 @@ impl Interactions {
         self.kinds.all_kinds()
     }
 
+    /// Get the particle name corresponding to the given `kind`, or `None` if
+    /// this kind is unknown.
+    pub fn get_name(&self, kind: Kind) -> Option<String> {
+        self.kinds.name(kind)
+    }
+
     /// Add the `potential` pair interaction for the pair `(i, j)`
     pub fn add_pair(&mut self, i: &str, j: &str, potential: PairInteraction) {
         let (i, j) = (self.get_kind(i), self.get_kind(j));
@@ -216,6 +222,31 @@ impl Interactions {
                   .collect()       // collect into Vec<&PairInteraction>
     }
 
+    /// Get the list of all the pairs of particles kinds with pair
+    /// interactions. The interactions can be accessed with `pairs`.
+    pub fn pair_kinds(&self) -> Vec<(Kind, Kind)> {
+        self.pairs.keys().cloned().collect()
+    }
+
+    /// Get the list of all the pairs of particles kinds with bonded
+    /// interactions. The interactions can be accessed with `bonds`.
+    pub fn bond_kinds(&self) -> Vec<(Kind, Kind)> {
+        self.bonds.keys().cloned().collect()
+    }
+
+    /// Get the list of all the angles of particles kinds with angle
+    /// interactions. The interactions can be accessed with `angles`.
+    pub fn angle_kinds(&self) -> Vec<(Kind, Kind, Kind)> {
+        self.angles.keys().cloned().collect()
+    }
+
+    /// Get the list of all the dihedral angles of particles kinds with
+    /// dihedral interactions. The interactions can be accessed with
+    /// `dihedrals`.
+    pub fn dihedral_kinds(&self) -> Vec<(Kind, Kind, Kind, Kind)> {
+        self.dihedrals.keys().cloned().collect()
+    }
+
     /// Get all bonded interactions corresponding to the pair `(i, j)`
     pub fn bonds(&self, i: Kind, j: Kind) -> &[Box<BondPotential>] {
         let (i, j) = normalize_pair(i, j);
@@ -389,6 +420,8 @@ mod test {
         interactions.add_pair("H", "H", pair.clone());
         assert_eq!(interactions.pairs(Kind(0), Kind(0)).len(), 1);
         assert_eq!(interactions.all_pairs().len(), 2);
+        assert_eq!(interactions.pair_kinds(), vec![(Kind(0), Kind(0)), (Kind(0), Kind(1))]);
+        assert_eq!(interactions.get_name(Kind(1)), Some(String::from("O")));
     }
 
     #[test]
@@ -402,6 +435,7 @@ mod test {
         assert_eq!(interactions.bonds(Kind(0), Kind(0)).len(), 0);
         interactions.add_bond("H", "H", Box::new(Harmonic{x0: 0.0, k: 0.0}));
         assert_eq!(interactions.bonds(Kind(0), Kind(0)).len(), 1);
+        assert_eq!(interactions.bond_kinds(), vec![(Kind(0), Kind(0)), (Kind(0), Kind(1))]);
     }
 
     #[test]
@@ -418,6 +452,7 @@ mod test {
 
         interactions.add_angle("N", "N", "N", Box::new(Harmonic{x0: 0.0, k: 0.0}));
         assert_eq!(interactions.angles(Kind(3), Kind(3), Kind(3)).len(), 1);
+        assert_eq!(interactions.angle_kinds().len(), 3);
     }
 
     #[test]
@@ -438,6 +473,7 @@ mod test {
 
         interactions.add_dihedral("S", "S", "S", "S", Box::new(Harmonic{x0: 0.0, k: 0.0}));
         assert_eq!(interactions.dihedrals(Kind(4), Kind(4), Kind(4), Kind(4)).len(), 1);
+        assert_eq!(interactions.dihedral_kinds().len(), 4);
     }
 
     #[test]
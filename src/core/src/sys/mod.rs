@@ -24,9 +24,10 @@ pub use self::molecules::Molecule;
 pub use self::molecules::molecule_type;
 
 mod interactions;
+pub use self::interactions::Interactions;
 
 mod energy;
-pub use self::energy::EnergyEvaluator;
+pub use self::energy::{EnergyEvaluator, EnergyBreakdown};
 
 mod cache;
 pub use self::cache::EnergyCache;
@@ -40,5 +41,8 @@ pub use self::chfl::{Trajectory, TrajectoryError};
 pub use self::chfl::{guess_bonds, read_molecule};
 pub use self::chfl::ToChemfiles;
 
+mod modes;
+pub use self::modes::NormalModes;
+
 pub mod veloc;
 pub mod compute;
@@ -0,0 +1,248 @@
+// Lumol, an extensible molecular simulation engine
+// Copyright (C) 2015-2016 Lumol's contributors — BSD license
+
+//! Normal modes analysis of a system
+use std::f64::consts::PI;
+
+use consts::SPEED_OF_LIGHT;
+use types::{Array2, Vector3D};
+use sys::{System, Trajectory, TrajectoryError};
+
+/// Maximal number of sweeps in the Jacobi diagonalization
+const MAX_SWEEPS: usize = 100;
+/// Number of frames used to represent one oscillation of a mode
+const MODE_FRAMES: usize = 20;
+
+/// Normal modes of vibration of a system, obtained by diagonalization of the
+/// mass-weighted Hessian of the potential energy.
+///
+/// The normal modes are only meaningful for a system at a minimum of the
+/// potential energy, for example after a `Minimization`. At a true minimum,
+/// all the eigenvalues of the Hessian are positive (up to the translations and
+/// rotations modes, which have zero frequency). Negative eigenvalues
+/// correspond to imaginary frequencies, which are reported as negative
+/// frequencies.
+pub struct NormalModes {
+    /// Angular frequencies of the modes, sorted in increasing order
+    frequencies: Vec<f64>,
+    /// Cartesian displacements of the atoms for each mode, normalized to 1
+    modes: Vec<Vec<Vector3D>>,
+}
+
+impl NormalModes {
+    /// Compute the normal modes of the `system`
+    pub fn new(system: &System) -> NormalModes {
+        let natoms = system.size();
+        let mut hessian = system.hessian();
+        let masses = system.iter().map(|particle| particle.mass).collect::<Vec<_>>();
+        for i in 0..3 * natoms {
+            for j in 0..3 * natoms {
+                hessian[(i, j)] /= f64::sqrt(masses[i / 3] * masses[j / 3]);
+            }
+        }
+
+        let (eigenvalues, eigenvectors) = diagonalize(hessian);
+        let mut order = (0..3 * natoms).collect::<Vec<_>>();
+        order.sort_by(|&a, &b| eigenvalues[a].partial_cmp(&eigenvalues[b]).expect("Got a NaN eigenvalue"));
+
+        let mut frequencies = Vec::with_capacity(3 * natoms);
+        let mut modes = Vec::with_capacity(3 * natoms);
+        for k in order {
+            let value = eigenvalues[k];
+            frequencies.push(value.signum() * value.abs().sqrt());
+
+            let mut mode = (0..natoms).map(|i| {
+                let scale = 1.0 / masses[i].sqrt();
+                Vector3D::new(
+                    scale * eigenvectors[(3 * i, k)],
+                    scale * eigenvectors[(3 * i + 1, k)],
+                    scale * eigenvectors[(3 * i + 2, k)],
+                )
+            }).collect::<Vec<_>>();
+            let norm = mode.iter().fold(0.0, |sum, v| sum + v.norm2()).sqrt();
+            for v in &mut mode {
+                *v /= norm;
+            }
+            modes.push(mode);
+        }
+
+        NormalModes {
+            frequencies: frequencies,
+            modes: modes,
+        }
+    }
+
+    /// Get the angular frequencies of the modes in internal units, sorted in
+    /// increasing order. Imaginary frequencies are negative.
+    pub fn frequencies(&self) -> &[f64] {
+        &self.frequencies
+    }
+
+    /// Get the wavenumbers of the modes in cm^-1, sorted in increasing
+    /// order. Imaginary wavenumbers are negative.
+    pub fn wavenumbers(&self) -> Vec<f64> {
+        // Conversion from A^-1 to cm^-1
+        const CM: f64 = 1e8;
+        self.frequencies.iter().map(|&omega| CM * omega / (2.0 * PI * SPEED_OF_LIGHT)).collect()
+    }
+
+    /// Get the cartesian displacements of the atoms for all the modes, in the
+    /// same order as the frequencies. Each mode is normalized to 1.
+    pub fn modes(&self) -> &[Vec<Vector3D>] {
+        &self.modes
+    }
+
+    /// Check if the system is at a minimum of the potential energy, i.e. if
+    /// there are no imaginary frequencies larger than `threshold`. The
+    /// threshold accounts for the numerical noise in the frequencies of the
+    /// translations and rotations modes.
+    pub fn is_minimum(&self, threshold: f64) -> bool {
+        self.frequencies.iter().all(|&omega| omega > -threshold)
+    }
+
+    /// Write an animation of the `mode` of the `system` to the `trajectory`,
+    /// as one oscillation of the atoms with an `amplitude` in Angstroms.
+    pub fn write_mode(&self, system: &System, mode: usize, amplitude: f64, trajectory: &mut Trajectory) -> Result<(), TrajectoryError> {
+        let mut frame = system.clone();
+        for step in 0..MODE_FRAMES {
+            let scaling = amplitude * f64::sin(2.0 * PI * step as f64 / MODE_FRAMES as f64);
+            for (i, particle) in frame.iter_mut().enumerate() {
+                particle.position = system[i].position + scaling * self.modes[mode][i];
+            }
+            try!(trajectory.write(&frame));
+        }
+        Ok(())
+    }
+}
+
+/// Diagonalize the symmetric `matrix` using the cyclic Jacobi method. The
+/// eigenvalues are returned together with a matrix containing the
+/// corresponding eigenvectors as columns.
+fn diagonalize(mut matrix: Array2<f64>) -> (Vec<f64>, Array2<f64>) {
+    let n = matrix.shape().0;
+    let mut vectors = Array2::zeros((n, n));
+    for i in 0..n {
+        vectors[(i, i)] = 1.0;
+    }
+
+    for _ in 0..MAX_SWEEPS {
+        let mut diagonal = 0.0;
+        let mut off_diagonal = 0.0;
+        for p in 0..n {
+            diagonal += matrix[(p, p)] * matrix[(p, p)];
+            for q in (p + 1)..n {
+                off_diagonal += matrix[(p, q)] * matrix[(p, q)];
+            }
+        }
+        if off_diagonal <= 1e-30 * diagonal {
+            break;
+        }
+
+        for p in 0..n {
+            for q in (p + 1)..n {
+                let apq = matrix[(p, q)];
+                if apq == 0.0 {
+                    continue;
+                }
+
+                // Rotation cancelling the (p, q) element
+                let theta = (matrix[(q, q)] - matrix[(p, p)]) / (2.0 * apq);
+                let t = theta.signum() / (theta.abs() + f64::sqrt(theta * theta + 1.0));
+                let c = 1.0 / f64::sqrt(t * t + 1.0);
+                let s = t * c;
+
+                for k in 0..n {
+                    let (akp, akq) = (matrix[(k, p)], matrix[(k, q)]);
+                    matrix[(k, p)] = c * akp - s * akq;
+                    matrix[(k, q)] = s * akp + c * akq;
+                }
+                for k in 0..n {
+                    let (apk, aqk) = (matrix[(p, k)], matrix[(q, k)]);
+                    matrix[(p, k)] = c * apk - s * aqk;
+                    matrix[(q, k)] = s * apk + c * aqk;
+                }
+                for k in 0..n {
+                    let (vkp, vkq) = (vectors[(k, p)], vectors[(k, q)]);
+                    vectors[(k, p)] = c * vkp - s * vkq;
+                    vectors[(k, q)] = s * vkp + c * vkq;
+                }
+            }
+        }
+    }
+
+    let values = (0..n).map(|i| matrix[(i, i)]).collect();
+    return (values, vectors);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::diagonalize;
+    use sys::{System, UnitCell, Particle};
+    use types::{Array2, Vector3D};
+    use energy::{Harmonic, PairInteraction};
+    use utils::unit_from;
+
+    #[test]
+    fn jacobi() {
+        let mut matrix = Array2::zeros((3, 3));
+        matrix[(0, 0)] = 2.0;
+        matrix[(1, 1)] = 2.0;
+        matrix[(2, 2)] = 5.0;
+        matrix[(0, 1)] = 1.0;
+        matrix[(1, 0)] = 1.0;
+
+        let (mut values, vectors) = diagonalize(matrix.clone());
+        for k in 0..3 {
+            // A v = λ v
+            for i in 0..3 {
+                let av = (0..3).fold(0.0, |sum, j| sum + matrix[(i, j)] * vectors[(j, k)]);
+                assert_relative_eq!(av, values[k] * vectors[(i, k)], epsilon=1e-12);
+            }
+        }
+
+        values.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        assert_relative_eq!(values[0], 1.0, epsilon=1e-12);
+        assert_relative_eq!(values[1], 3.0, epsilon=1e-12);
+        assert_relative_eq!(values[2], 5.0, epsilon=1e-12);
+    }
+
+    fn diatomic(distance: f64) -> System {
+        let mut system = System::from_cell(UnitCell::new());
+        system.add_particle(Particle::new("H"));
+        system[0].position = Vector3D::new(0.0, 0.0, 0.0);
+        system.add_particle(Particle::new("Cl"));
+        system[1].position = Vector3D::new(distance, 0.0, 0.0);
+
+        system.interactions_mut().add_pair("H", "Cl", PairInteraction::new(
+            Box::new(Harmonic{k: unit_from(500.0, "kJ/mol/A^2"), x0: 1.3}), 10.0
+        ));
+        return system;
+    }
+
+    #[test]
+    fn harmonic_diatomic() {
+        let system = diatomic(1.3);
+        let modes = NormalModes::new(&system);
+        assert_eq!(modes.frequencies().len(), 6);
+        assert!(modes.is_minimum(1e-6));
+
+        // Only the stretching mode have a non-zero frequency
+        let mass = system[0].mass * system[1].mass / (system[0].mass + system[1].mass);
+        let expected = f64::sqrt(unit_from(500.0, "kJ/mol/A^2") / mass);
+        for &omega in &modes.frequencies()[..5] {
+            assert_relative_eq!(omega, 0.0, epsilon=1e-3 * expected);
+        }
+        assert_relative_eq!(modes.frequencies()[5], expected, max_relative=1e-6);
+
+        // Stretching mode is along the bond, with the heaviest atom moving less
+        let stretch = &modes.modes()[5];
+        assert_relative_eq!(stretch[0][1], 0.0, epsilon=1e-9);
+        assert_relative_eq!(stretch[0][2], 0.0, epsilon=1e-9);
+        assert!(stretch[0][0] * stretch[1][0] < 0.0);
+        assert!(stretch[0].norm() > stretch[1].norm());
+
+        let wavenumber = modes.wavenumbers()[5];
+        assert_relative_eq!(wavenumber, expected * 1e8 / (2.0 * PI * SPEED_OF_LIGHT), max_relative=1e-6);
+    }
+}
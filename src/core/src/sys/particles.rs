@@ -43,6 +43,9 @@ pub struct Particle {
     pub position: Vector3D,
     /// Particle velocity, if needed
     pub velocity: Vector3D,
+    /// Number of times the particle was moved across the periodic boundaries
+    /// along each unit cell vector. This is used to unwrap the positions.
+    pub image: [i32; 3],
 }
 
 
@@ -60,7 +63,8 @@ impl Particle {
             charge: 0.0,
             kind: ParticleKind::default(),
             position: Vector3D::zero(),
-            velocity: Vector3D::zero()
+            velocity: Vector3D::zero(),
+            image: [0; 3],
         }
     }
 
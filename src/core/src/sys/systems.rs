@@ -11,20 +11,24 @@ use std::slice;
 use std::cmp::{min, max};
 use std::iter::IntoIterator;
 use std::i8;
+use std::i32;
+use std::f64::consts::PI;
 use std::collections::BTreeMap;
 
-use energy::PairInteraction;
+use energy::{PairInteraction, PairRestriction};
 use energy::{BondPotential, AnglePotential, DihedralPotential};
-use types::{Vector3D, Matrix3, Zero};
+use types::{Vector3D, Matrix3, Array2, Zero};
 
 use super::{Particle, ParticleKind};
 use super::Molecule;
 use super::{CONNECT_12, CONNECT_13, CONNECT_14, CONNECT_FAR};
 use super::UnitCell;
 use super::interactions::Interactions;
-use super::EnergyEvaluator;
+use super::{EnergyEvaluator, EnergyBreakdown};
 use super::molecules::molecule_type;
 
+use checkpoint::{Checkpoint, CheckpointWriter, CheckpointReader, CheckpointError};
+
 /// Particles permutations:. Indexes are given in the `(old, new)` form.
 pub type Permutations = Vec<(usize, usize)>;
 
@@ -339,12 +343,26 @@ impl System {
         let mut com_wrapped = com;
         self.cell.wrap_vector(&mut com_wrapped);
         let delta = com_wrapped - com;
+        // Keep track of the periodic image, to be able to unwrap the positions
+        let shift = self.cell.fractional(&delta);
+        let shift = [shift[0].round() as i32, shift[1].round() as i32, shift[2].round() as i32];
         // iterate over all positions and move them accordingly
         for pi in self.molecule(molid) {
             self[pi].position += delta;
+            for k in 0..3 {
+                self[pi].image[k] -= shift[k];
+            }
         }
     }
 
+    /// Get the position of the particle `i` as if it was never wrapped in the
+    /// unit cell, using the current unit cell and the particle `image`.
+    pub fn unwrapped_position(&self, i: usize) -> Vector3D {
+        let image = self.particles[i].image;
+        let image = Vector3D::new(image[0] as f64, image[1] as f64, image[2] as f64);
+        self.particles[i].position + self.cell.cartesian(&image)
+    }
+
     /// Get an iterator over the `Particle` in this system
     #[inline] pub fn iter(&self) -> slice::Iter<Particle> {
         self.particles.iter()
@@ -597,7 +615,7 @@ impl System {
 /******************************************************************************/
 use sys::compute::Compute;
 use sys::compute::{PotentialEnergy, KineticEnergy, TotalEnergy};
-use sys::compute::Forces;
+use sys::compute::{Forces, Hessian};
 use sys::compute::Temperature;
 use sys::compute::Volume;
 use sys::compute::{Virial, Stress, Pressure};
@@ -611,6 +629,25 @@ impl System {
     pub fn potential_energy(&self) -> f64 {PotentialEnergy.compute(self)}
     /// Get the total energy of the system.
     pub fn total_energy(&self) -> f64 {TotalEnergy.compute(self)}
+    /// Get the decomposition of the energy of the system in its different
+    /// terms.
+    pub fn energy_breakdown(&self) -> EnergyBreakdown {
+        let evaluator = self.energy_evaluator();
+        let (intramolecular, intermolecular, species) = evaluator.pairs_decomposition();
+        EnergyBreakdown {
+            pairs: intramolecular + intermolecular,
+            intramolecular: intramolecular,
+            intermolecular: intermolecular,
+            species: species,
+            tail: evaluator.pairs_tail(),
+            bonds: evaluator.bonds(),
+            angles: evaluator.angles(),
+            dihedrals: evaluator.dihedrals(),
+            coulomb: evaluator.coulomb(),
+            global: evaluator.global(),
+            kinetic: self.kinetic_energy(),
+        }
+    }
 
     /// Use an external temperature for all the system properties. Calling this
     /// with `Some(temperature)` will replace all the computation of the
@@ -663,6 +700,12 @@ impl System {
     pub fn forces(&self) -> Vec<Vector3D> {
         Forces.compute(self)
     }
+
+    /// Get the Hessian of the potential energy of the system, as a `3N x 3N`
+    /// matrix. See `compute::Hessian` for more information.
+    pub fn hessian(&self) -> Array2<f64> {
+        Hessian.compute(self)
+    }
 }
 
 /******************************************************************************/
@@ -681,6 +724,273 @@ impl IndexMut<usize> for System {
     }
 }
 
+/// Compute a fingerprint of the `interactions`, used to check that a system is
+/// restored from a checkpoint with the same interactions that were used to
+/// write the checkpoint. The fingerprint contains the particles names and the
+/// energy of all the pair, bond, angle and dihedral potentials at fixed
+/// points. The coulombic and global potentials are checked by comparing the
+/// potential energy of the restored system with the one in the checkpoint.
+///
+/// The fingerprint is stored in checkpoints, so it only uses explicit bytes
+/// and a fixed hash algorithm, which do not change with the compiler version.
+fn interactions_fingerprint(interactions: &Interactions) -> u64 {
+    let name = |kind| interactions.get_name(kind).unwrap_or_default();
+    let distances = (1..41).map(|i| 0.5 * f64::from(i)).collect::<Vec<_>>();
+    let angles = (0..21).map(|i| PI * f64::from(i) / 10.0 - PI).collect::<Vec<_>>();
+
+    // Particles kinds depend on the order in which the particles are added
+    // to the system, so the potentials are sorted by particles names. The
+    // potentials are described by a tag for their type, followed by the
+    // other parameters not contained in the energy values.
+    let mut potentials = Vec::new();
+    for (i, j) in interactions.pair_kinds() {
+        let mut names = vec![name(i), name(j)];
+        names.sort();
+        for pair in interactions.pairs(i, j) {
+            let mut values = vec![pair.get_cutoff(), pair.tail_energy()];
+            values.extend(distances.iter().map(|&r| pair.energy(r)));
+            let (restriction, scaling) = restriction_tag(pair.restriction());
+            let description = vec![0, restriction, scaling, pair.is_shifted() as u64];
+            potentials.push((names.clone(), description, values));
+        }
+    }
+    for (i, j) in interactions.bond_kinds() {
+        let mut names = vec![name(i), name(j)];
+        names.sort();
+        for bond in interactions.bonds(i, j) {
+            let values = distances.iter().map(|&r| bond.energy(r)).collect();
+            potentials.push((names.clone(), vec![1], values));
+        }
+    }
+    for (i, j, k) in interactions.angle_kinds() {
+        let mut names = vec![name(i), name(j), name(k)];
+        if names[0] > names[2] {
+            names.reverse();
+        }
+        for angle in interactions.angles(i, j, k) {
+            let values = angles.iter().map(|&theta| angle.energy(theta)).collect();
+            potentials.push((names.clone(), vec![2], values));
+        }
+    }
+    for (i, j, k, m) in interactions.dihedral_kinds() {
+        let mut names = vec![name(i), name(j), name(k), name(m)];
+        let mut reversed = names.clone();
+        reversed.reverse();
+        if reversed < names {
+            names = reversed;
+        }
+        for dihedral in interactions.dihedrals(i, j, k, m) {
+            let values = angles.iter().map(|&phi| dihedral.energy(phi)).collect();
+            potentials.push((names.clone(), vec![3], values));
+        }
+    }
+    potentials.sort_by(|a, b| (&a.0, &a.1).cmp(&(&b.0, &b.1)));
+
+    let mut hasher = Fnv1a::new();
+    hasher.write_u64(potentials.len() as u64);
+    for (names, description, values) in potentials {
+        hasher.write_u64(names.len() as u64);
+        for name in names {
+            hasher.write_str(&name);
+        }
+        for tag in description {
+            hasher.write_u64(tag);
+        }
+        for value in values {
+            hasher.write_u64(value.to_bits());
+        }
+    }
+    hasher.write_u64(interactions.coulomb().is_some() as u64);
+    hasher.write_u64(interactions.globals().len() as u64);
+    hasher.finish()
+}
+
+/// Get an explicit tag for the `restriction`, together with the bits of the
+/// scaling factor for `PairRestriction::Scale14`.
+fn restriction_tag(restriction: PairRestriction) -> (u64, u64) {
+    match restriction {
+        PairRestriction::None => (0, 0),
+        PairRestriction::IntraMolecular => (1, 0),
+        PairRestriction::InterMolecular => (2, 0),
+        PairRestriction::Exclude12 => (3, 0),
+        PairRestriction::Exclude13 => (4, 0),
+        PairRestriction::Exclude14 => (5, 0),
+        PairRestriction::Scale14(scaling) => (6, scaling.to_bits()),
+    }
+}
+
+/// 64-bit FNV-1a hash function. Contrary to the hashers in the standard
+/// library, the algorithm is fixed and can be used for data stored in files.
+struct Fnv1a(u64);
+
+impl Fnv1a {
+    fn new() -> Fnv1a {
+        Fnv1a(0xcbf2_9ce4_8422_2325)
+    }
+
+    fn write_bytes(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 ^= u64::from(byte);
+            self.0 = self.0.wrapping_mul(0x0000_0100_0000_01b3);
+        }
+    }
+
+    /// Write the `value` as little-endian bytes
+    fn write_u64(&mut self, value: u64) {
+        let mut bytes = [0; 8];
+        for (i, byte) in bytes.iter_mut().enumerate() {
+            *byte = (value >> (8 * i)) as u8;
+        }
+        self.write_bytes(&bytes);
+    }
+
+    /// Write the length of the `string`, followed by its UTF-8 bytes
+    fn write_str(&mut self, string: &str) {
+        self.write_u64(string.len() as u64);
+        self.write_bytes(string.as_bytes());
+    }
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+/// The checkpoint of a system contains the unit cell, the current step, the
+/// particles and the molecules. The interactions are not saved, and the
+/// interactions already defined in the system are used when restoring it.
+/// The checkpoint also contains a fingerprint of the interactions and the
+/// potential energy of the system, and restoring it fails if the interactions
+/// in the system are not the same as the ones used to write the checkpoint.
+impl Checkpoint for System {
+    fn save(&self, writer: &mut CheckpointWriter) {
+        writer.write_u64(self.step);
+        writer.write_section(&self.cell);
+        writer.write_u64(interactions_fingerprint(&self.interactions));
+        writer.write_f64(self.potential_energy());
+
+        writer.write_usize(self.particles.len());
+        for particle in &self.particles {
+            writer.write_str(particle.name());
+            writer.write_f64(particle.mass);
+            writer.write_f64(particle.charge);
+            writer.write_vector(&particle.position);
+            writer.write_vector(&particle.velocity);
+            for &image in &particle.image {
+                writer.write_i64(i64::from(image));
+            }
+        }
+
+        writer.write_usize(self.molecules.len());
+        for molecule in &self.molecules {
+            writer.write_usize(molecule.start());
+            writer.write_usize(molecule.end());
+            let mut bonds = molecule.bonds().iter().map(|bond| (bond.i(), bond.j())).collect::<Vec<_>>();
+            bonds.sort();
+            writer.write_usize(bonds.len());
+            for (i, j) in bonds {
+                writer.write_usize(i);
+                writer.write_usize(j);
+            }
+        }
+    }
+
+    fn restore(&mut self, reader: &mut CheckpointReader) -> Result<(), CheckpointError> {
+        let step = try!(reader.read_u64());
+        let mut cell = UnitCell::new();
+        try!(reader.read_section(&mut cell));
+
+        if try!(reader.read_u64()) != interactions_fingerprint(&self.interactions) {
+            return Err(CheckpointError::from(
+                "the interactions are not the same as the ones used to write the checkpoint"
+            ));
+        }
+        let energy = try!(reader.read_f64());
+
+        // Each particle takes at least 96 bytes in the checkpoint
+        let natoms = try!(reader.read_count(96));
+        let mut particles = Vec::with_capacity(natoms);
+        // Only create one particle for each name, to get the particle kind
+        // and to warn about unknown particles only once.
+        let mut templates = BTreeMap::new();
+        for _ in 0..natoms {
+            let name = try!(reader.read_string());
+            let mut particle = templates.entry(name.clone()).or_insert_with(|| {
+                Particle::new(name)
+            }).clone();
+            particle.kind = self.interactions.get_kind(particle.name());
+            particle.mass = try!(reader.read_f64());
+            particle.charge = try!(reader.read_f64());
+            particle.position = try!(reader.read_vector());
+            particle.velocity = try!(reader.read_vector());
+            for image in &mut particle.image {
+                let value = try!(reader.read_i64());
+                if value < i64::from(i32::MIN) || value > i64::from(i32::MAX) {
+                    return Err(CheckpointError::from("invalid periodic image in checkpoint"));
+                }
+                *image = value as i32;
+            }
+            particles.push(particle);
+        }
+
+        // Each molecule takes at least 24 bytes in the checkpoint
+        let nmolecules = try!(reader.read_count(24));
+        let mut molecules = Vec::with_capacity(nmolecules);
+        let mut molids = vec![usize::max_value(); natoms];
+        for molid in 0..nmolecules {
+            let start = try!(reader.read_usize());
+            let end = try!(reader.read_usize());
+            if start >= end || end > natoms {
+                return Err(CheckpointError::from("invalid molecule in checkpoint"));
+            }
+
+            let mut molecule = Molecule::new(start);
+            for i in (start + 1)..end {
+                molecule.merge_with(Molecule::new(i));
+            }
+            let nbonds = try!(reader.read_count(16));
+            for _ in 0..nbonds {
+                let i = try!(reader.read_usize());
+                let j = try!(reader.read_usize());
+                if !molecule.contains(i) || !molecule.contains(j) || i == j {
+                    return Err(CheckpointError::from("invalid bond in checkpoint"));
+                }
+                molecule.add_bond(i, j);
+            }
+
+            for i in start..end {
+                if molids[i] != usize::max_value() {
+                    return Err(CheckpointError::from("overlapping molecules in checkpoint"));
+                }
+                molids[i] = molid;
+            }
+            molecules.push(molecule);
+        }
+        if molids.iter().any(|&molid| molid == usize::max_value()) {
+            return Err(CheckpointError::from("some particles are not in a molecule in checkpoint"));
+        }
+
+        let restored = System {
+            cell: cell,
+            particles: particles,
+            molecules: molecules,
+            molids: molids,
+            interactions: self.interactions.clone(),
+            step: step,
+            external_temperature: self.external_temperature,
+        };
+        let current = restored.potential_energy();
+        let tolerance = 1e-10 * f64::max(energy.abs(), 1.0);
+        if current != energy && !((current - energy).abs() < tolerance) {
+            return Err(CheckpointError::from(
+                "the energy of the restored system does not match the checkpoint, \
+                the interactions are not the same as the ones used to write the checkpoint"
+            ));
+        }
+        *self = restored;
+        Ok(())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use sys::*;
@@ -893,7 +1203,11 @@ mod tests {
         system.wrap_molecule(0);
         assert_eq!(system[0].position, Vector3D::new(3.0, 0.0, 0.0));
         assert_eq!(system[1].position, Vector3D::new(5.0, 0.0, 0.0));
-        assert_eq!(system.molecule_com(0), Vector3D::new(4.0, 0.0, 0.0))
+        assert_eq!(system.molecule_com(0), Vector3D::new(4.0, 0.0, 0.0));
+
+        assert_eq!(system[0].image, [-1, 0, 0]);
+        assert_eq!(system.unwrapped_position(0), Vector3D::new(-2.0, 0.0, 0.0));
+        assert_eq!(system.unwrapped_position(1), Vector3D::zero());
     }
 
     #[test]
@@ -940,6 +1254,45 @@ mod tests {
         assert!(system.molecule_type(1) != system.molecule_type(4));
     }
 
+    #[test]
+    fn fnv1a() {
+        use super::Fnv1a;
+        let mut hasher = Fnv1a::new();
+        assert_eq!(hasher.finish(), 0xcbf2_9ce4_8422_2325);
+        hasher.write_bytes(b"a");
+        assert_eq!(hasher.finish(), 0xaf63_dc4c_8601_ec8c);
+
+        let mut hasher = Fnv1a::new();
+        hasher.write_bytes(b"foobar");
+        assert_eq!(hasher.finish(), 0x8594_4171_f739_67e8);
+    }
+
+    #[test]
+    fn interactions_fingerprint() {
+        use super::interactions_fingerprint;
+        use energy::{PairInteraction, PairRestriction, LennardJones};
+
+        let fingerprint = |restriction| {
+            let mut system = System::new();
+            system.add_particle(Particle::new("Ar"));
+            let mut pair = PairInteraction::new(Box::new(LennardJones {
+                sigma: 3.4,
+                epsilon: 1e-3,
+            }), 10.0);
+            pair.set_restriction(restriction);
+            system.interactions_mut().add_pair("Ar", "Ar", pair);
+            interactions_fingerprint(system.interactions())
+        };
+
+        let empty = interactions_fingerprint(System::new().interactions());
+        assert!(fingerprint(PairRestriction::None) != empty);
+        assert_eq!(fingerprint(PairRestriction::None), fingerprint(PairRestriction::None));
+
+        assert!(fingerprint(PairRestriction::Exclude13) != fingerprint(PairRestriction::None));
+        assert!(fingerprint(PairRestriction::Exclude13) != fingerprint(PairRestriction::Exclude14));
+        assert!(fingerprint(PairRestriction::Scale14(0.5)) != fingerprint(PairRestriction::Scale14(0.8)));
+    }
+
     #[test]
     #[should_panic]
     fn negative_external_temperature() {
@@ -3,10 +3,10 @@
 
 //! This module provides some ways to initialize the velocities in a `System`
 use rand::distributions::{Range, Normal, Sample};
-use rand::Isaac64Rng;
-use rand::SeedableRng;
+use rand::{Rng, Isaac64Rng, SeedableRng};
 
 use consts::K_BOLTZMANN;
+use rng::Random;
 use types::Vector3D;
 use super::System;
 
@@ -26,13 +26,16 @@ pub trait InitVelocities {
     fn init(&mut self, system: &mut System);
     /// Set the seed of the random number generator. The default seed is 42.
     fn seed(&mut self, seed: u64);
+    /// Use `rng` as the random number generator for the initialization. The
+    /// default implementation does nothing.
+    fn set_rng(&mut self, _: Random) {}
 }
 
 /// Initialize the velocities from a Boltzmann distribution.
 pub struct BoltzmannVelocities {
     temperature: f64,
     dist: Normal,
-    rng: Isaac64Rng,
+    rng: Box<Rng>,
 }
 
 impl BoltzmannVelocities {
@@ -41,7 +44,7 @@ impl BoltzmannVelocities {
         BoltzmannVelocities{
             temperature: temperature,
             dist: Normal::new(0.0, f64::sqrt(K_BOLTZMANN * temperature)),
-            rng: Isaac64Rng::from_seed(&[42]),
+            rng: Box::new(Isaac64Rng::from_seed(&[42])),
         }
     }
 }
@@ -59,7 +62,11 @@ impl InitVelocities for BoltzmannVelocities {
     }
 
     fn seed(&mut self, seed: u64) {
-        self.rng.reseed(&[seed]);
+        self.rng = Box::new(Isaac64Rng::from_seed(&[seed]));
+    }
+
+    fn set_rng(&mut self, rng: Random) {
+        self.rng = Box::new(rng);
     }
 }
 
@@ -67,7 +74,7 @@ impl InitVelocities for BoltzmannVelocities {
 pub struct UniformVelocities {
     temperature: f64,
     dist: Range<f64>,
-    rng: Isaac64Rng,
+    rng: Box<Rng>,
 }
 
 impl UniformVelocities {
@@ -77,7 +84,7 @@ impl UniformVelocities {
         UniformVelocities{
             temperature: temperature,
             dist: Range::new(-factor, factor),
-            rng: Isaac64Rng::from_seed(&[42]),
+            rng: Box::new(Isaac64Rng::from_seed(&[42])),
         }
     }
 }
@@ -95,7 +102,11 @@ impl InitVelocities for UniformVelocities {
     }
 
     fn seed(&mut self, seed: u64) {
-        self.rng.reseed(&[seed]);
+        self.rng = Box::new(Isaac64Rng::from_seed(&[seed]));
+    }
+
+    fn set_rng(&mut self, rng: Random) {
+        self.rng = Box::new(rng);
     }
 }
 
@@ -43,6 +43,9 @@ lazy_static!(
         assert!(map.insert("fs", 1.0).is_none());
         assert!(map.insert("ps", 1e3).is_none());
         assert!(map.insert("ns", 1e6).is_none());
+        assert!(map.insert("s", 1e15).is_none());
+        assert!(map.insert("min", 60e15).is_none());
+        assert!(map.insert("h", 3600e15).is_none());
 
         // Mass units.
         assert!(map.insert("u", 1.0).is_none());
@@ -51,6 +54,10 @@ lazy_static!(
         assert!(map.insert("g", 1e-3 / U_IN_KG).is_none());
         assert!(map.insert("kg", 1.0 / U_IN_KG).is_none());
 
+        // Charge units.
+        assert!(map.insert("e", 1.0).is_none());
+        assert!(map.insert("C", 1.0 / 1.60217653e-19).is_none());
+
         // Temperature units.
         assert!(map.insert("K", 1.0).is_none());
         // Quantity of matter units.
@@ -68,6 +75,9 @@ lazy_static!(
         assert!(map.insert("H", 4.35974417e-18 * 1e-10 / U_IN_KG).is_none());
         assert!(map.insert("Ry", 4.35974417e-18 / 2.0 * 1e-10 / U_IN_KG).is_none());
 
+        // Electric potential unit.
+        assert!(map.insert("V", 1.60217653e-19 * 1e-10 / U_IN_KG).is_none());
+
         // Force unit.
         assert!(map.insert("N", 1e-20 / U_IN_KG).is_none());
 
@@ -486,5 +496,7 @@ mod test {
         assert_eq!(to(25.0, "m").unwrap(), 2.5e-9);
         assert_eq!(to(25.0, "bar").unwrap(), 4.1513469550000005e9);
         assert_eq!(to(25.0, "kJ/mol").unwrap(), 249999.99982494753);
+        assert_relative_eq!(to(25.0, "V").unwrap(), to(25.0, "J/C").unwrap());
+        assert_relative_eq!(to(from(2.0, "h").unwrap(), "min").unwrap(), 120.0);
     }
 }
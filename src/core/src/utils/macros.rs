@@ -36,6 +36,29 @@ macro_rules! impl_box_clone {
     );
 }
 
+/// A macro to get the concrete type of `Trait` objects, adding a
+/// `downcast_ref` function to them. `Trait` must inherit the `AsAny` trait,
+/// which is automatically implemented for all `'static` types.
+///
+/// Usage:
+///
+/// ```ignore
+/// trait Foo: AsAny {}
+///
+/// impl_downcast!(Foo);
+/// ```
+macro_rules! impl_downcast {
+    ($Trait: ident) => (
+        impl<'a> $Trait + 'a {
+            /// Get a reference to the concrete type of this object, if it is
+            /// a `T`, or `None` otherwise.
+            pub fn downcast_ref<T: $Trait + 'static>(&self) -> Option<&T> {
+                ::utils::AsAny::as_any(self).downcast_ref::<T>()
+            }
+        }
+    );
+}
+
 /// Implement $Lhs -- $Rhs arithmetic operations for all variation of by
 /// value, by reference and by mutable reference of $Rhs and $Lhs.
 macro_rules! impl_arithmetic {
@@ -2,6 +2,12 @@
 // Copyright (C) 2015-2016 Lumol's contributors — BSD license
 
 //! Various internal utilities, which do not have there own module
+use std::any::Any;
+use std::f64::consts::PI;
+
+use sys::System;
+use types::{Array3, Complex};
+
 #[macro_use]
 mod macros;
 
@@ -11,6 +17,20 @@ mod xyz;
 pub use self::xyz::system_from_xyz;
 
 
+#[doc(hidden)]
+/// This is an internal implementation detail for getting the concrete type of
+/// trait objects, see `impl_downcast!`.
+pub trait AsAny {
+    /// Get `self` as an `Any` reference.
+    fn as_any(&self) -> &Any;
+}
+
+impl<T: Any> AsAny for T {
+    fn as_any(&self) -> &Any {
+        self
+    }
+}
+
 /// Internal version of `units::from`, where the unit is assumed to be correct
 pub fn unit_from(value: f64, unit: &str) -> f64 {
     ::units::from(value, unit).expect("Internal unit error. This is a bug.")
@@ -20,3 +40,32 @@ pub fn unit_from(value: f64, unit: &str) -> f64 {
 pub fn unit_to(value: f64, unit: &str) -> f64 {
     ::units::to(value, unit).expect("Internal unit error. This is a bug.")
 }
+
+/// Compute the Fourier phases `exp(-2 π i n s)` of all the particles in the
+/// `system`, where `s` are the fractional coordinates of the particles, for
+/// `n` from 0 to `nmax - 1` along each of the three directions. The phase of
+/// particle `i` for `n` along direction `j` is stored in `phases[(n, i, j)]`.
+pub fn fourier_phases(system: &System, nmax: usize, phases: &mut Array3<Complex>) {
+    let natoms = system.size();
+    phases.resize_if_different((nmax, natoms, 3));
+
+    // Do the n=0, 1 cases first
+    for i in 0..natoms {
+        let si = system.cell().fractional(&system[i].position);
+        for j in 0..3 {
+            phases[(0, i, j)] = Complex::polar(1.0, 0.0);
+            if nmax > 1 {
+                phases[(1, i, j)] = Complex::polar(1.0, -2.0 * PI * si[j]);
+            }
+        }
+    }
+
+    // Use recursive definition for computing the factor for all the other values of n.
+    for n in 2..nmax {
+        for i in 0..natoms {
+            for j in 0..3 {
+                phases[(n, i, j)] = phases[(n - 1, i, j)] * phases[(1, i, j)];
+            }
+        }
+    }
+}
@@ -12,6 +12,8 @@ use chemfiles;
 use lumol::units::ParseError;
 use lumol::sys::TrajectoryError;
 
+use preprocess::Source;
+
 /// Custom `Result` type for input files
 pub type Result<T> = result::Result<T, Error>;
 
@@ -28,6 +30,36 @@ pub enum Error {
     Config(String),
     /// Unit parsing error
     Unit(ParseError),
+    /// Error associated with a specific key of the input file
+    Located(Box<Error>, Location),
+}
+
+/// Location of an error in an input file
+#[derive(Clone, Debug, PartialEq)]
+pub struct Location {
+    /// The key where the error happened
+    pub key: String,
+    /// Path to the input file, if known
+    pub path: Option<PathBuf>,
+    /// Line and column of the key in the input file, starting at 1. This is
+    /// `None` if the key could not be found, or if it is used multiple times
+    /// in the file.
+    pub position: Option<(usize, usize)>,
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> result::Result<(), fmt::Error> {
+        match (&self.path, self.position) {
+            (&Some(ref path), Some((line, column))) => {
+                write!(fmt, "{}:{}:{}", path.display(), line, column)
+            }
+            (&Some(ref path), None) => write!(fmt, "{}", path.display()),
+            (&None, Some((line, column))) => {
+                write!(fmt, "line {}, column {}", line, column)
+            }
+            (&None, None) => Ok(()),
+        }
+    }
 }
 
 impl From<(io::Error, PathBuf)> for Error {
@@ -77,6 +109,13 @@ impl fmt::Display for Error {
                     }
                 }
             }
+            Error::Located(ref error, ref location) => {
+                if location.path.is_none() && location.position.is_none() {
+                    error.to_string()
+                } else {
+                    format!("{}: {}", location, error)
+                }
+            }
             _ => String::from(self.description())
         };
         try!(write!(fmt, "{}", message));
@@ -91,6 +130,7 @@ impl error::Error for Error {
             Error::Io(ref err, _) => err.description(),
             Error::Trajectory(ref err) => err.description(),
             Error::Unit(ref err) => err.description(),
+            Error::Located(ref err, _) => err.description(),
         }
     }
 
@@ -100,6 +140,7 @@ impl error::Error for Error {
             Error::Io(ref err, _) => Some(err),
             Error::Trajectory(ref err) => Some(err),
             Error::Unit(ref err) => Some(err),
+            Error::Located(ref err, _) => err.cause(),
         }
     }
 }
@@ -113,3 +154,107 @@ pub fn toml_error_to_string(parser: &Parser) -> String {
     let plural = if errors.len() == 1 {""} else {"s"};
     return format!("TOML parsing error{}: {}", plural, errors);
 }
+
+/// Associate the `error` with the TOML `key` where it happened. The position
+/// of the key is found later, using `locate`.
+pub fn at_key<E: Into<Error>>(key: &str, error: E) -> Error {
+    Error::Located(Box::new(error.into()), Location {
+        key: String::from(key),
+        path: None,
+        position: None,
+    })
+}
+
+/// Find the position of the key associated with `error` in the TOML
+/// `sources` of an input file. The position is only set if the key is found
+/// once in all the sources, else the error is associated with the first
+/// source. Errors already located in another file are left unchanged.
+pub fn locate(error: Error, sources: &[Source]) -> Error {
+    match error {
+        Error::Located(error, mut location) => {
+            if location.path.is_none() && location.position.is_none() {
+                let mut found = Vec::new();
+                for source in sources {
+                    for position in find_key(&source.content, &location.key) {
+                        found.push((source, position));
+                    }
+                }
+
+                if found.len() == 1 {
+                    location.path = found[0].0.path.clone();
+                    location.position = Some(found[0].1);
+                } else if let Some(source) = sources.first() {
+                    location.path = source.path.clone();
+                }
+            }
+            Error::Located(error, location)
+        }
+        error => error,
+    }
+}
+
+/// Find all the places where `key` is defined in the TOML `source`, either
+/// as the last component of a table header, or as the key of a key/value
+/// pair. The positions are given as line and column, starting at 1.
+fn find_key(source: &str, key: &str) -> Vec<(usize, usize)> {
+    let mut positions = Vec::new();
+    for (line, content) in source.lines().enumerate() {
+        let chars = content.char_indices().collect::<Vec<_>>();
+        let trimmed = content.trim_left();
+        if trimmed.starts_with('[') {
+            // Table header, only the last component is defined here
+            let header = trimmed.trim_left_matches('[');
+            let header = header.split(']').next().unwrap_or("");
+            let last = header.rsplit('.').next().unwrap_or("");
+            let last = last.trim().trim_matches('"');
+            if last == key {
+                let offset = content.find(header).unwrap_or(0) + header.rfind(last).unwrap_or(0);
+                positions.push((line + 1, content[..offset].chars().count() + 1));
+            }
+            continue;
+        }
+
+        // Key/value pairs are found at the beginning of a line, or after '{'
+        // and ',' in inline tables.
+        let mut in_string = None;
+        let mut key_start = true;
+        let mut i = 0;
+        while i < chars.len() {
+            let (offset, c) = chars[i];
+            if let Some(quote) = in_string {
+                if c == quote {
+                    in_string = None;
+                } else if c == '\\' && quote == '"' {
+                    i += 1;
+                }
+            } else if c == '#' {
+                break;
+            } else if c.is_whitespace() {
+                // Whitespace does not change the key_start state
+            } else if key_start {
+                key_start = false;
+                let rest = &content[offset..];
+                let name = if c == '"' {
+                    rest[1..].split('"').next()
+                } else {
+                    rest.split(|c: char| !(c.is_alphanumeric() || c == '_' || c == '-')).next()
+                };
+                if let Some(name) = name {
+                    let length = if c == '"' {name.len() + 2} else {name.len()};
+                    if name == key && rest[length..].trim_left().starts_with('=') {
+                        positions.push((line + 1, i + 1));
+                    }
+                }
+                if c == '"' || c == '\'' {
+                    in_string = Some(c);
+                }
+            } else if c == '"' || c == '\'' {
+                in_string = Some(c);
+            } else if c == '{' || c == ',' {
+                key_start = true;
+            }
+            i += 1;
+        }
+    }
+    return positions;
+}
@@ -1,7 +1,9 @@
 // Lumol, an extensible molecular simulation engine
 // Copyright (C) 2015-2016 Lumol's contributors — BSD license
 use toml::{Table, Value};
-use error::{Error, Result};
+use std::{cmp, mem};
+
+use error::{Error, Result, at_key};
 
 
 /// Extract the table at the given `key`, from the `config` TOML table
@@ -10,7 +12,7 @@ pub fn table<'a>(key: &str, config: &'a Table, context: &str) -> Result<&'a Tabl
     let table = try!(config.get(key).ok_or(Error::from(
         format!("Missing '{}' key in {}", key, context)
     )));
-    return table.as_table().ok_or(Error::from(
+    return table.as_table().ok_or(at_key(key,
         format!("'{}' must be a table in {}", key, context)
     ));
 }
@@ -21,7 +23,7 @@ pub fn str<'a>(key: &str, config: &'a Table, context: &str) -> Result<&'a str> {
     let string = try!(config.get(key).ok_or(Error::from(
         format!("Missing '{}' key in {}", key, context)
     )));
-    return string.as_str().ok_or(Error::from(
+    return string.as_str().ok_or(at_key(key,
         format!("'{}' must be a string in {}", key, context)
     ));
 }
@@ -35,7 +37,7 @@ pub fn number(key: &str, config: &Table, context: &str) -> Result<f64> {
     match *number {
         ::toml::Value::Integer(v) => Ok(v as f64),
         ::toml::Value::Float(v) => Ok(v),
-        _ => Err(Error::from(
+        _ => Err(at_key(key,
             format!("'{}' must be a number in {}", key, context)
         ))
     }
@@ -50,13 +52,13 @@ pub fn uint(key: &str, config: &Table, context: &str) -> Result<u64> {
     match *number {
         ::toml::Value::Integer(v) => {
             if v < 0 {
-                Err(Error::from(format!(
+                Err(at_key(key, format!(
                     "'{}' must be a positive integer in {}", key, context)))
             } else {
                 Ok(v as u64)
             }
         },
-        _ => Err(Error::from(
+        _ => Err(at_key(key,
             format!("'{}' must be a positive integer in {}", key, context)
         ))
     }
@@ -68,7 +70,7 @@ pub fn slice<'a>(key: &str, config: &'a Table, context: &str) -> Result<&'a [Val
     let array = try!(config.get(key).ok_or(Error::from(
         format!("Missing '{}' key in {}", key, context)
     )));
-    return array.as_slice().ok_or(Error::from(
+    return array.as_slice().ok_or(at_key(key,
         format!("'{}' must be an array in {}", key, context)
     ))
 }
@@ -78,7 +80,55 @@ pub fn typ<'a>(config: &'a Table, context: &str) -> Result<&'a str> {
     let typ = try!(config.get("type").ok_or(Error::from(
         format!("Missing 'type' key in {}", context)
     )));
-    return typ.as_str().ok_or(Error::from(
+    return typ.as_str().ok_or(at_key("type",
         format!("'type' key must be a string in {}", context)
     ));
 }
+
+/// Check that all the keys in the `config` TOML table interpreted as a
+/// `context` are in the `known` list. The error for an unknown key suggests
+/// the closest known key, if any.
+pub fn check_keys(config: &Table, known: &[&str], context: &str) -> Result<()> {
+    for key in config.keys() {
+        if !known.contains(&key.as_str()) {
+            return Err(at_key(key, format!(
+                "Unknown key '{}' in {}{}", key, context, did_you_mean(key, known)
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Get a suggestion for a misspelled `name`, from the list of `candidates`.
+/// The suggestion is formatted to be appended to an error message, and empty
+/// if no candidate is close enough to `name`.
+pub fn did_you_mean(name: &str, candidates: &[&str]) -> String {
+    let max_distance = cmp::max(1, name.chars().count() / 3);
+    let closest = candidates.iter()
+                            .map(|candidate| (levenshtein(name, candidate), candidate))
+                            .filter(|&(distance, _)| distance <= max_distance)
+                            .min();
+    match closest {
+        Some((_, candidate)) => format!(", did you mean '{}'?", candidate),
+        None => String::new(),
+    }
+}
+
+/// Compute the Levenshtein distance between `a` and `b`, i.e. the minimal
+/// number of characters insertions, deletions and substitutions needed to
+/// transform `a` into `b`.
+fn levenshtein(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<_>>();
+    let mut previous = (0..b.len() + 1).collect::<Vec<_>>();
+    let mut current = vec![0; b.len() + 1];
+    for (i, ca) in a.chars().enumerate() {
+        current[0] = i + 1;
+        for (j, &cb) in b.iter().enumerate() {
+            let substitution = previous[j] + if ca == cb {0} else {1};
+            let insertion_deletion = cmp::min(previous[j + 1], current[j]) + 1;
+            current[j + 1] = cmp::min(substitution, insertion_deletion);
+        }
+        mem::swap(&mut previous, &mut current);
+    }
+    return previous[b.len()];
+}
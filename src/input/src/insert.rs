@@ -0,0 +1,58 @@
+// Lumol, an extensible molecular simulation engine
+// Copyright (C) 2015-2016 Lumol's contributors — BSD license
+
+//! Helpers to build TOML tables from Rust values, used by the `ToToml`
+//! implementations. This is the reverse of the `extract` module.
+use toml::{Table, Value};
+use std::path::Path;
+
+use error::{Error, Result};
+
+/// Insert the `value` at the given `key` in the `config` table, as a string
+/// without unit. The value is read back in internal units, and the formatting
+/// of `f64` ensure that it is read back exactly.
+pub fn quantity(key: &str, value: f64, config: &mut Table) {
+    let _ = config.insert(String::from(key), Value::String(format!("{}", value)));
+}
+
+/// Insert the string `value` at the given `key` in the `config` table
+pub fn str(key: &str, value: &str, config: &mut Table) {
+    let _ = config.insert(String::from(key), Value::String(String::from(value)));
+}
+
+/// Insert the integer `value` at the given `key` in the `config` table
+pub fn uint(key: &str, value: u64, config: &mut Table) {
+    let _ = config.insert(String::from(key), Value::Integer(value as i64));
+}
+
+/// Insert the float `value` at the given `key` in the `config` table
+pub fn float(key: &str, value: f64, config: &mut Table) {
+    let _ = config.insert(String::from(key), Value::Float(value));
+}
+
+/// Insert the boolean `value` at the given `key` in the `config` table
+pub fn bool(key: &str, value: bool, config: &mut Table) {
+    let _ = config.insert(String::from(key), Value::Boolean(value));
+}
+
+/// Insert the table `value` at the given `key` in the `config` table
+pub fn table(key: &str, value: Table, config: &mut Table) {
+    let _ = config.insert(String::from(key), Value::Table(value));
+}
+
+/// Insert the `path` at the given `key` in the `config` table, interpreted as
+/// a `context`. Paths which are not valid unicode are an error.
+pub fn path(key: &str, path: &Path, config: &mut Table, context: &str) -> Result<()> {
+    let path = try!(path.to_str().ok_or(Error::from(format!(
+        "Can not convert the path '{}' in {} to TOML", path.display(), context
+    ))));
+    str(key, path, config);
+    Ok(())
+}
+
+/// Create a new table with the given `typ` as type
+pub fn typ(typ: &str) -> Table {
+    let mut config = Table::new();
+    str("type", typ, &mut config);
+    return config;
+}
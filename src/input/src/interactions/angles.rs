@@ -7,9 +7,10 @@ use lumol::energy::{Harmonic, CosineHarmonic, Torsion, NullPotential};
 use lumol::energy::{AnglePotential, DihedralPotential};
 
 use error::{Error, Result};
-use FromToml;
+use {FromToml, ToToml};
 use extract;
-use super::InteractionsInput;
+use insert;
+use super::{InteractionsInput, check_keywords};
 
 impl InteractionsInput {
     /// Read the "angles" section from the potential configuration. This is an
@@ -87,6 +88,8 @@ impl InteractionsInput {
 }
 
 fn read_angle_potential(angle: &Table) -> Result<Box<AnglePotential>> {
+    const POTENTIALS: &'static[&'static str] = &["null", "harmonic", "cosine-harmonic"];
+    try!(check_keywords(angle, &["atoms"], POTENTIALS, "angle potential"));
     let potentials = angle.keys().cloned()
                     .filter(|key| key != "atoms")
                     .collect::<Vec<_>>();
@@ -109,9 +112,9 @@ fn read_angle_potential(angle: &Table) -> Result<Box<AnglePotential>> {
             "null" => Ok(Box::new(try!(NullPotential::from_toml(table)))),
             "harmonic" => Ok(Box::new(try!(Harmonic::from_toml(table)))),
             "cosine-harmonic" => Ok(Box::new(try!(CosineHarmonic::from_toml(table)))),
-            other => Err(
-                Error::from(format!("Unknown potential type '{}'", other))
-            ),
+            other => Err(Error::from(format!(
+                "Unknown potential type '{}'{}", other, extract::did_you_mean(other, POTENTIALS)
+            ))),
         }
     } else {
         Err(
@@ -121,6 +124,8 @@ fn read_angle_potential(angle: &Table) -> Result<Box<AnglePotential>> {
 }
 
 fn read_dihedral_potential(dihedral: &Table) -> Result<Box<DihedralPotential>> {
+    const POTENTIALS: &'static[&'static str] = &["null", "harmonic", "cosine-harmonic", "torsion"];
+    try!(check_keywords(dihedral, &["atoms"], POTENTIALS, "dihedral potential"));
     let potentials = dihedral.keys().cloned()
                     .filter(|key| key != "atoms")
                     .collect::<Vec<_>>();
@@ -144,9 +149,9 @@ fn read_dihedral_potential(dihedral: &Table) -> Result<Box<DihedralPotential>> {
             "harmonic" => Ok(Box::new(try!(Harmonic::from_toml(table)))),
             "cosine-harmonic" => Ok(Box::new(try!(CosineHarmonic::from_toml(table)))),
             "torsion" => Ok(Box::new(try!(Torsion::from_toml(table)))),
-            other => Err(
-                Error::from(format!("Unknown potential type '{}'", other))
-            ),
+            other => Err(Error::from(format!(
+                "Unknown potential type '{}'{}", other, extract::did_you_mean(other, POTENTIALS)
+            ))),
         }
     } else {
         Err(
@@ -154,3 +159,37 @@ fn read_dihedral_potential(dihedral: &Table) -> Result<Box<DihedralPotential>> {
         )
     }
 }
+
+/******************************************************************************/
+
+/// Write the angle `potential` in the `config` table.
+pub fn write_angle_potential(potential: &AnglePotential, config: &mut Table) -> Result<()> {
+    let (key, table) = if let Some(potential) = potential.downcast_ref::<NullPotential>() {
+        ("null", try!(potential.to_toml()))
+    } else if let Some(potential) = potential.downcast_ref::<Harmonic>() {
+        ("harmonic", try!(potential.to_toml()))
+    } else if let Some(potential) = potential.downcast_ref::<CosineHarmonic>() {
+        ("cosine-harmonic", try!(potential.to_toml()))
+    } else {
+        return Err(Error::from("Can not convert this angle potential to TOML"));
+    };
+    insert::table(key, table, config);
+    Ok(())
+}
+
+/// Write the dihedral `potential` in the `config` table.
+pub fn write_dihedral_potential(potential: &DihedralPotential, config: &mut Table) -> Result<()> {
+    let (key, table) = if let Some(potential) = potential.downcast_ref::<NullPotential>() {
+        ("null", try!(potential.to_toml()))
+    } else if let Some(potential) = potential.downcast_ref::<Harmonic>() {
+        ("harmonic", try!(potential.to_toml()))
+    } else if let Some(potential) = potential.downcast_ref::<CosineHarmonic>() {
+        ("cosine-harmonic", try!(potential.to_toml()))
+    } else if let Some(potential) = potential.downcast_ref::<Torsion>() {
+        ("torsion", try!(potential.to_toml()))
+    } else {
+        return Err(Error::from("Can not convert this dihedral potential to TOML"));
+    };
+    insert::table(key, table, config);
+    Ok(())
+}
@@ -1,13 +1,15 @@
 // Lumol, an extensible molecular simulation engine
 // Copyright (C) 2015-2016 Lumol's contributors — BSD license
-use toml::Value;
+use toml::{Table, Value};
 
 use lumol::sys::System;
 use lumol::energy::{Wolf, Ewald, CoulombicPotential};
 
 use error::{Error, Result};
-use FromToml;
-use super::read_restriction;
+use {FromToml, ToToml};
+use extract;
+use insert;
+use super::{read_restriction, write_restriction, check_keywords};
 use super::InteractionsInput;
 
 impl InteractionsInput {
@@ -25,6 +27,9 @@ impl InteractionsInput {
             Error::from("The 'coulomb' section must be a table")
         ));
 
+        const SOLVERS: &'static[&'static str] = &["wolf", "ewald"];
+        try!(check_keywords(coulomb, &["restriction"], SOLVERS, "'coulomb' section"));
+
         let solvers = coulomb.keys().cloned()
                              .filter(|key| key != "restriction")
                              .collect::<Vec<_>>();
@@ -41,7 +46,9 @@ impl InteractionsInput {
                 "wolf" => Box::new(try!(Wolf::from_toml(table))),
                 "ewald" => Box::new(try!(Ewald::from_toml(table))),
                 other => {
-                    return Err(Error::from(format!("Unknown coulomb solver '{}'", other)))
+                    return Err(Error::from(format!(
+                        "Unknown coulomb solver '{}'{}", other, extract::did_you_mean(other, SOLVERS)
+                    )))
                 },
             };
 
@@ -104,3 +111,20 @@ impl InteractionsInput {
         Ok(())
     }
 }
+
+/******************************************************************************/
+
+/// Write the coulombic `potential` in a new 'coulomb' table.
+pub fn write_coulomb(potential: &CoulombicPotential) -> Result<Table> {
+    let mut config = Table::new();
+    if let Some(wolf) = potential.downcast_ref::<Wolf>() {
+        insert::table("wolf", try!(wolf.to_toml()), &mut config);
+        write_restriction(wolf.restriction(), &mut config);
+    } else if let Some(ewald) = potential.downcast_ref::<Ewald>() {
+        insert::table("ewald", try!(ewald.to_toml()), &mut config);
+        write_restriction(ewald.restriction(), &mut config);
+    } else {
+        return Err(Error::from("Can not convert this coulombic potential to TOML"));
+    }
+    Ok(config)
+}
@@ -1,27 +1,36 @@
 // Lumol, an extensible molecular simulation engine
 // Copyright (C) 2015-2016 Lumol's contributors — BSD license
-use toml::{Parser, Table, Value};
+use toml::{Table, Value};
 
 use std::io::prelude::*;
 use std::fs::File;
 use std::path::PathBuf;
 
-use lumol::sys::System;
+use lumol::sys::{System, Interactions, ParticleKind};
 use lumol::energy::PairRestriction;
 
-use {Error, Result};
+use {Error, Result, ToToml};
 use validate;
-use error::toml_error_to_string;
+use extract;
+use insert;
+use error;
+use preprocess::{self, Source};
 
 mod toml;
 mod pairs;
 mod angles;
 mod coulomb;
 
+use self::pairs::write_bond_potential;
+use self::angles::{write_angle_potential, write_dihedral_potential};
+use self::coulomb::write_coulomb;
+
 /// An interaction input file for Lumol.
 pub struct InteractionsInput {
     /// The TOML configuration
     config: Table,
+    /// The TOML sources of the configuration, used to locate errors
+    sources: Vec<Source>,
 }
 
 impl InteractionsInput {
@@ -31,20 +40,27 @@ impl InteractionsInput {
         let mut file = try_io!(File::open(&path), path);
         let mut buffer = String::new();
         let _ = try_io!(file.read_to_string(&mut buffer), path);
-        return InteractionsInput::from_string(&buffer);
+        return InteractionsInput::parse(buffer, Some(path));
     }
 
     /// Read the interactions from a TOML formatted string.
     // TODO: use restricted privacy here
     #[doc(hidden)]
     pub fn from_string(string: &str) -> Result<InteractionsInput> {
-        let mut parser = Parser::new(string);
-        let config = try!(parser.parse().ok_or(
-            Error::TOML(toml_error_to_string(&parser))
-        ));
+        return InteractionsInput::parse(String::from(string), None);
+    }
 
-        try!(validate(&config));
-        return InteractionsInput::from_toml(config);
+    /// Parse the TOML `source`, read from the file at `path` if any.
+    fn parse(source: String, path: Option<PathBuf>) -> Result<InteractionsInput> {
+        let (config, sources) = try!(preprocess::parse(source, path));
+        if let Err(err) = validate(&config) {
+            return Err(error::locate(err, &sources));
+        }
+
+        Ok(InteractionsInput {
+            config: config,
+            sources: sources,
+        })
     }
 
     /// Read the interactions from a TOML table. This is an internal function,
@@ -52,13 +68,21 @@ impl InteractionsInput {
     // TODO: use restricted privacy here
     #[doc(hidden)]
     pub fn from_toml(config: Table) -> Result<InteractionsInput> {
-        Ok(InteractionsInput{
-            config: config
+        Ok(InteractionsInput {
+            config: config,
+            sources: Vec::new(),
         })
     }
 
     /// Read the interactions from this input into the `system`.
     pub fn read(&self, system: &mut System) -> Result<()> {
+        self.read_all(system).map_err(|err| error::locate(err, &self.sources))
+    }
+
+    fn read_all(&self, system: &mut System) -> Result<()> {
+        try!(extract::check_keys(&self.config, &[
+            "input", "global", "pairs", "bonds", "angles", "dihedrals", "coulomb", "charges"
+        ], "interactions"));
         try!(self.read_pairs(system));
         try!(self.read_bonds(system));
         try!(self.read_angles(system));
@@ -69,6 +93,102 @@ impl InteractionsInput {
     }
 }
 
+impl ToToml for Interactions {
+    /// Convert the interactions to a TOML table, which can be read back with
+    /// `InteractionsInput`. The charges are not part of the interactions, and
+    /// are not written.
+    fn to_toml(&self) -> Result<Table> {
+        if !self.globals().is_empty() {
+            return Err(Error::from("Can not convert global potentials to TOML"));
+        }
+
+        let name = |kind: ParticleKind| -> Result<Value> {
+            self.get_name(kind).map(Value::String).ok_or(Error::from(
+                format!("Missing name for particle kind {} in interactions", kind)
+            ))
+        };
+
+        let mut config = Table::new();
+        let mut input = Table::new();
+        insert::uint("version", 1, &mut input);
+        insert::table("input", input, &mut config);
+
+        let mut pairs = Vec::new();
+        for (i, j) in self.pair_kinds() {
+            for interaction in self.pairs(i, j) {
+                let mut pair = try!(interaction.to_toml());
+                let atoms = vec![try!(name(i)), try!(name(j))];
+                let _ = pair.insert(String::from("atoms"), Value::Array(atoms));
+                pairs.push(Value::Table(pair));
+            }
+        }
+
+        let mut bonds = Vec::new();
+        for (i, j) in self.bond_kinds() {
+            for potential in self.bonds(i, j) {
+                let mut bond = Table::new();
+                try!(write_bond_potential(&**potential, &mut bond));
+                let atoms = vec![try!(name(i)), try!(name(j))];
+                let _ = bond.insert(String::from("atoms"), Value::Array(atoms));
+                bonds.push(Value::Table(bond));
+            }
+        }
+
+        let mut angles = Vec::new();
+        for (i, j, k) in self.angle_kinds() {
+            for potential in self.angles(i, j, k) {
+                let mut angle = Table::new();
+                try!(write_angle_potential(&**potential, &mut angle));
+                let atoms = vec![try!(name(i)), try!(name(j)), try!(name(k))];
+                let _ = angle.insert(String::from("atoms"), Value::Array(atoms));
+                angles.push(Value::Table(angle));
+            }
+        }
+
+        let mut dihedrals = Vec::new();
+        for (i, j, k, m) in self.dihedral_kinds() {
+            for potential in self.dihedrals(i, j, k, m) {
+                let mut dihedral = Table::new();
+                try!(write_dihedral_potential(&**potential, &mut dihedral));
+                let atoms = vec![try!(name(i)), try!(name(j)), try!(name(k)), try!(name(m))];
+                let _ = dihedral.insert(String::from("atoms"), Value::Array(atoms));
+                dihedrals.push(Value::Table(dihedral));
+            }
+        }
+
+        for (key, values) in vec![("pairs", pairs), ("bonds", bonds), ("angles", angles), ("dihedrals", dihedrals)] {
+            if !values.is_empty() {
+                let _ = config.insert(String::from(key), Value::Array(values));
+            }
+        }
+
+        if let Some(coulomb) = self.coulomb() {
+            let coulomb = try!(write_coulomb(&**coulomb.borrow()));
+            insert::table("coulomb", coulomb, &mut config);
+        }
+
+        Ok(config)
+    }
+}
+
+/// Check the `config` table of a potential for misspelled `keywords`. The
+/// other keys are potential types, and are checked when reading the
+/// potential itself.
+fn check_keywords(config: &Table, keywords: &[&str], potentials: &[&str], context: &str) -> Result<()> {
+    for key in config.keys() {
+        if keywords.contains(&key.as_str()) || potentials.contains(&key.as_str()) {
+            continue;
+        }
+        let suggestion = extract::did_you_mean(key, keywords);
+        if !suggestion.is_empty() {
+            return Err(error::at_key(key, format!(
+                "Unknown key '{}' in {}{}", key, context, suggestion
+            )));
+        }
+    }
+    Ok(())
+}
+
 fn read_restriction(config: &Table) -> Result<Option<PairRestriction>> {
     let restriction = config.get("restriction");
     if restriction.is_none() {
@@ -107,3 +227,22 @@ fn read_restriction(config: &Table) -> Result<Option<PairRestriction>> {
         _ => Err(Error::from("Restriction must be a table or a string"))
     }
 }
+
+/// Write the `restriction` in the `config` table. `PairRestriction::None` is
+/// the default, and is not written.
+fn write_restriction(restriction: PairRestriction, config: &mut Table) {
+    let restriction = match restriction {
+        PairRestriction::None => return,
+        PairRestriction::IntraMolecular => Value::String(String::from("intramolecular")),
+        PairRestriction::InterMolecular => Value::String(String::from("intermolecular")),
+        PairRestriction::Exclude12 => Value::String(String::from("exclude12")),
+        PairRestriction::Exclude13 => Value::String(String::from("exclude13")),
+        PairRestriction::Exclude14 => Value::String(String::from("exclude14")),
+        PairRestriction::Scale14(scale) => {
+            let mut table = Table::new();
+            insert::float("scale14", scale, &mut table);
+            Value::Table(table)
+        }
+    };
+    let _ = config.insert(String::from("restriction"), restriction);
+}
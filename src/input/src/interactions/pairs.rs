@@ -11,9 +11,10 @@ use lumol::energy::{Buckingham, BornMayerHuggins};
 use lumol::energy::TableComputation;
 
 use error::{Error, Result};
-use {FromToml, FromTomlWithData};
+use {FromToml, FromTomlWithData, ToToml};
 use extract;
-use super::read_restriction;
+use insert;
+use super::{read_restriction, write_restriction, check_keywords};
 use super::InteractionsInput;
 
 /// Global settings for the pair interactions
@@ -29,6 +30,7 @@ impl<'a> GlobalInformation<'a> {
                 let global = try!(global.as_table().ok_or(Error::from(
                     "'global' section must be a table"
                 )));
+                try!(extract::check_keys(global, &["cutoff", "tail_correction"], "'global' section"));
                 let cutoff = global.get("cutoff");
                 let tail = if let Some(tail) = global.get("tail_correction") {
                     let tail = try!(tail.as_bool().ok_or(Error::from(
@@ -109,6 +111,9 @@ impl InteractionsInput {
                     PairInteraction::new(potential, cutoff)
                 }
                 Value::Table(ref table) => {
+                    if table.keys().any(|key| key != "shifted") {
+                        return Err(Error::from("'cutoff' table can only contain 'shifted' key"));
+                    }
                     let shifted = try!(table.get("shifted").ok_or(Error::from(
                         "'cutoff' table can only contain 'shifted' key"
                     )));
@@ -187,6 +192,10 @@ fn read_pair_potential(pair: &Table) -> Result<Box<PairPotential>> {
     const KEYWORDS: &'static[&'static str] = &[
         "restriction", "computation", "atoms", "cutoff", "tail_correction"
     ];
+    const POTENTIALS: &'static[&'static str] = &[
+        "null", "harmonic", "lj", "buckingham", "born"
+    ];
+    try!(check_keywords(pair, KEYWORDS, POTENTIALS, "pair potential"));
 
     let potentials = pair.keys().cloned()
                     .filter(|key| !KEYWORDS.contains(&key.as_ref()))
@@ -212,9 +221,9 @@ fn read_pair_potential(pair: &Table) -> Result<Box<PairPotential>> {
             "lj" => Ok(Box::new(try!(LennardJones::from_toml(table)))),
             "buckingham" => Ok(Box::new(try!(Buckingham::from_toml(table)))),
             "born" => Ok(Box::new(try!(BornMayerHuggins::from_toml(table)))),
-            other => Err(
-                Error::from(format!("Unknown potential type '{}'", other))
-            ),
+            other => Err(Error::from(format!(
+                "Unknown potential type '{}'{}", other, extract::did_you_mean(other, POTENTIALS)
+            ))),
         }
     } else {
         Err(
@@ -224,6 +233,8 @@ fn read_pair_potential(pair: &Table) -> Result<Box<PairPotential>> {
 }
 
 fn read_bond_potential(pair: &Table) -> Result<Box<BondPotential>> {
+    const POTENTIALS: &'static[&'static str] = &["null", "harmonic"];
+    try!(check_keywords(pair, &["atoms"], POTENTIALS, "bond potential"));
     let potentials = pair.keys().cloned()
                     .filter(|k| k != "atoms")
                     .collect::<Vec<_>>();
@@ -245,9 +256,9 @@ fn read_bond_potential(pair: &Table) -> Result<Box<BondPotential>> {
         match key {
             "null" => Ok(Box::new(try!(NullPotential::from_toml(table)))),
             "harmonic" => Ok(Box::new(try!(Harmonic::from_toml(table)))),
-            other => Err(
-                Error::from(format!("Unknown potential type '{}'", other))
-            ),
+            other => Err(Error::from(format!(
+                "Unknown potential type '{}'{}", other, extract::did_you_mean(other, POTENTIALS)
+            ))),
         }
     } else {
         Err(
@@ -273,3 +284,70 @@ fn read_pair_computation(computation: &Table, potential: Box<PairPotential>) ->
         None => unreachable!()
     }
 }
+
+/******************************************************************************/
+
+impl ToToml for PairInteraction {
+    fn to_toml(&self) -> Result<Table> {
+        let mut config = Table::new();
+        try!(write_pair_potential(self.potential(), &mut config));
+
+        if self.is_shifted() {
+            let mut cutoff = Table::new();
+            insert::quantity("shifted", self.get_cutoff(), &mut cutoff);
+            insert::table("cutoff", cutoff, &mut config);
+        } else {
+            insert::quantity("cutoff", self.get_cutoff(), &mut config);
+        }
+
+        if self.has_tail_corrections() {
+            insert::bool("tail_correction", true, &mut config);
+        }
+
+        write_restriction(self.restriction(), &mut config);
+        Ok(config)
+    }
+}
+
+/// Write the pair `potential` in the `config` table, together with the
+/// associated 'computation' table if needed.
+fn write_pair_potential(potential: &PairPotential, config: &mut Table) -> Result<()> {
+    if let Some(table) = potential.downcast_ref::<TableComputation>() {
+        let mut computation = Table::new();
+        insert::uint("n", table.size() as u64, &mut computation);
+        insert::quantity("max", table.max(), &mut computation);
+        let mut wrapper = Table::new();
+        insert::table("table", computation, &mut wrapper);
+        insert::table("computation", wrapper, config);
+        return write_pair_potential(table.potential(), config);
+    }
+
+    let (key, table) = if let Some(potential) = potential.downcast_ref::<NullPotential>() {
+        ("null", try!(potential.to_toml()))
+    } else if let Some(potential) = potential.downcast_ref::<Harmonic>() {
+        ("harmonic", try!(potential.to_toml()))
+    } else if let Some(potential) = potential.downcast_ref::<LennardJones>() {
+        ("lj", try!(potential.to_toml()))
+    } else if let Some(potential) = potential.downcast_ref::<Buckingham>() {
+        ("buckingham", try!(potential.to_toml()))
+    } else if let Some(potential) = potential.downcast_ref::<BornMayerHuggins>() {
+        ("born", try!(potential.to_toml()))
+    } else {
+        return Err(Error::from("Can not convert this pair potential to TOML"));
+    };
+    insert::table(key, table, config);
+    Ok(())
+}
+
+/// Write the bond `potential` in the `config` table.
+pub fn write_bond_potential(potential: &BondPotential, config: &mut Table) -> Result<()> {
+    let (key, table) = if let Some(potential) = potential.downcast_ref::<NullPotential>() {
+        ("null", try!(potential.to_toml()))
+    } else if let Some(potential) = potential.downcast_ref::<Harmonic>() {
+        ("harmonic", try!(potential.to_toml()))
+    } else {
+        return Err(Error::from("Can not convert this bond potential to TOML"));
+    };
+    insert::table(key, table, config);
+    Ok(())
+}
@@ -4,9 +4,12 @@
 //! Convert TOML values to Lumol types.
 use toml::Table;
 
+use std::f64::consts::PI;
+
 use error::{Error, Result};
-use FromToml;
-use FromTomlWithData;
+use {FromToml, FromTomlWithData, ToToml};
+use extract;
+use insert;
 
 use lumol::energy::{Harmonic, LennardJones, NullPotential, CosineHarmonic};
 use lumol::energy::{Torsion, Buckingham, BornMayerHuggins};
@@ -25,13 +28,15 @@ macro_rules! try_extract_parameter {
 }
 
 impl FromToml for NullPotential {
-    fn from_toml(_: &Table) -> Result<NullPotential> {
+    fn from_toml(table: &Table) -> Result<NullPotential> {
+        try!(extract::check_keys(table, &[], "null potential"));
         Ok(NullPotential)
     }
 }
 
 impl FromToml for Harmonic {
     fn from_toml(table: &Table) -> Result<Harmonic> {
+        try!(extract::check_keys(table, &["k", "x0"], "harmonic potential"));
         let k = try_extract_parameter!(table, "k", "harmonic potential");
         let x0 = try_extract_parameter!(table, "x0", "harmonic potential");
 
@@ -49,6 +54,7 @@ impl FromToml for Harmonic {
 
 impl FromToml for LennardJones {
     fn from_toml(table: &Table) -> Result<LennardJones> {
+        try!(extract::check_keys(table, &["sigma", "epsilon"], "Lennard-Jones potential"));
         let sigma = try_extract_parameter!(table, "sigma", "Lennard-Jones potential");
         let epsilon = try_extract_parameter!(table, "epsilon", "Lennard-Jones potential");
 
@@ -66,6 +72,7 @@ impl FromToml for LennardJones {
 
 impl FromToml for CosineHarmonic {
     fn from_toml(table: &Table) -> Result<CosineHarmonic> {
+        try!(extract::check_keys(table, &["k", "x0"], "cosine harmonic potential"));
         let k = try_extract_parameter!(table, "k", "cosine harmonic potential");
         let x0 = try_extract_parameter!(table, "x0", "cosine harmonic potential");
 
@@ -83,6 +90,7 @@ impl FromToml for CosineHarmonic {
 
 impl FromToml for Torsion {
     fn from_toml(table: &Table) -> Result<Torsion> {
+        try!(extract::check_keys(table, &["k", "n", "delta"], "torsion potential"));
         let k = try_extract_parameter!(table, "k", "torsion potential");
         let n = try_extract_parameter!(table, "n", "torsion potential");
         let delta = try_extract_parameter!(table, "delta", "torsion potential");
@@ -104,6 +112,7 @@ impl FromToml for Torsion {
 
 impl FromToml for Buckingham {
     fn from_toml(table: &Table) -> Result<Buckingham> {
+        try!(extract::check_keys(table, &["A", "C", "rho"], "Buckingham potential"));
         let a = try_extract_parameter!(table, "A", "Buckingham potential");
         let c = try_extract_parameter!(table, "C", "Buckingham potential");
         let rho = try_extract_parameter!(table, "rho", "Buckingham potential");
@@ -125,6 +134,7 @@ impl FromToml for Buckingham {
 
 impl FromToml for BornMayerHuggins {
     fn from_toml(table: &Table) -> Result<BornMayerHuggins> {
+        try!(extract::check_keys(table, &["A", "C", "D", "sigma", "rho"], "Born-Mayer-Huggins potential"));
         let a = try_extract_parameter!(table, "A", "Born-Mayer-Huggins potential");
         let c = try_extract_parameter!(table, "C", "Born-Mayer-Huggins potential");
         let d = try_extract_parameter!(table, "D", "Born-Mayer-Huggins potential");
@@ -158,6 +168,7 @@ impl FromTomlWithData for TableComputation {
         let table = try!(table["table"].as_table().ok_or(
             Error::from("'table' key in computation must be a TOML table")
         ));
+        try!(extract::check_keys(table, &["n", "max"], "table computation"));
         let n = try_extract_parameter!(table, "n", "table computation");
         let max = try_extract_parameter!(table, "max", "table computation");
         if let (Some(n), Some(max)) = (n.as_integer(), max.as_str()) {
@@ -175,6 +186,7 @@ impl FromTomlWithData for TableComputation {
 
 impl FromToml for Wolf {
     fn from_toml(table: &Table) -> Result<Wolf> {
+        try!(extract::check_keys(table, &["cutoff"], "wolf potential"));
         let cutoff = try_extract_parameter!(table, "cutoff", "wolf potential");
         if let Some(cutoff) = cutoff.as_str() {
             let cutoff = try!(::lumol::units::from_str(cutoff));
@@ -187,18 +199,115 @@ impl FromToml for Wolf {
 
 impl FromToml for Ewald {
     fn from_toml(table: &Table) -> Result<Ewald> {
+        try!(extract::check_keys(table, &["cutoff", "kmax", "alpha"], "ewald potential"));
         let cutoff = try_extract_parameter!(table, "cutoff", "ewald potential");
         let kmax = try_extract_parameter!(table, "kmax", "ewald potential");
 
         if let (Some(cutoff), Some(kmax)) = (cutoff.as_str(), kmax.as_integer()) {
             let cutoff = try!(::lumol::units::from_str(cutoff));
             if kmax < 0 {
-                Err(Error::from("'kmax' can not be negative in Ewald potential"))
-            } else {
-                Ok(Ewald::new(cutoff, kmax as usize))
+                return Err(Error::from("'kmax' can not be negative in Ewald potential"));
+            }
+            let mut ewald = Ewald::new(cutoff, kmax as usize);
+            if table.contains_key("alpha") {
+                let alpha = try!(extract::str("alpha", table, "Ewald potential"));
+                let alpha = try!(::lumol::units::from_str(alpha));
+                if alpha <= 0.0 {
+                    return Err(Error::from("'alpha' must be positive in Ewald potential"));
+                }
+                ewald.set_alpha(alpha);
             }
+            Ok(ewald)
         } else {
             Err(Error::from("'cutoff' must be a string and 'kmax' an integer in Ewald potential"))
         }
     }
 }
+
+/******************************************************************************/
+
+impl ToToml for NullPotential {
+    fn to_toml(&self) -> Result<Table> {
+        Ok(Table::new())
+    }
+}
+
+impl ToToml for Harmonic {
+    fn to_toml(&self) -> Result<Table> {
+        let mut table = Table::new();
+        insert::quantity("k", self.k, &mut table);
+        insert::quantity("x0", self.x0, &mut table);
+        Ok(table)
+    }
+}
+
+impl ToToml for LennardJones {
+    fn to_toml(&self) -> Result<Table> {
+        let mut table = Table::new();
+        insert::quantity("sigma", self.sigma, &mut table);
+        insert::quantity("epsilon", self.epsilon, &mut table);
+        Ok(table)
+    }
+}
+
+impl ToToml for CosineHarmonic {
+    fn to_toml(&self) -> Result<Table> {
+        let mut table = Table::new();
+        insert::quantity("k", self.k(), &mut table);
+        insert::quantity("x0", self.x0(), &mut table);
+        Ok(table)
+    }
+}
+
+impl ToToml for Torsion {
+    fn to_toml(&self) -> Result<Table> {
+        let mut table = Table::new();
+        insert::quantity("k", self.k, &mut table);
+        insert::quantity("delta", self.delta, &mut table);
+        insert::uint("n", self.n as u64, &mut table);
+        Ok(table)
+    }
+}
+
+impl ToToml for Buckingham {
+    fn to_toml(&self) -> Result<Table> {
+        let mut table = Table::new();
+        insert::quantity("A", self.a, &mut table);
+        insert::quantity("C", self.c, &mut table);
+        insert::quantity("rho", self.rho, &mut table);
+        Ok(table)
+    }
+}
+
+impl ToToml for BornMayerHuggins {
+    fn to_toml(&self) -> Result<Table> {
+        let mut table = Table::new();
+        insert::quantity("A", self.a, &mut table);
+        insert::quantity("C", self.c, &mut table);
+        insert::quantity("D", self.d, &mut table);
+        insert::quantity("sigma", self.sigma, &mut table);
+        insert::quantity("rho", self.rho, &mut table);
+        Ok(table)
+    }
+}
+
+impl ToToml for Wolf {
+    fn to_toml(&self) -> Result<Table> {
+        let mut table = Table::new();
+        insert::quantity("cutoff", self.cutoff(), &mut table);
+        Ok(table)
+    }
+}
+
+impl ToToml for Ewald {
+    fn to_toml(&self) -> Result<Table> {
+        let mut table = Table::new();
+        insert::quantity("cutoff", self.cutoff(), &mut table);
+        insert::uint("kmax", self.kmax() as u64, &mut table);
+        // Only write alpha if it is not the default value
+        if self.alpha() != 3.0 * PI / (self.cutoff() * 4.0) {
+            insert::quantity("alpha", self.alpha(), &mut table);
+        }
+        Ok(table)
+    }
+}
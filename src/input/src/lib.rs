@@ -44,6 +44,26 @@
 //! }
 //! ```
 //!
+//! The `ToToml` trait does the reverse conversion, to write interactions,
+//! propagators, Monte-Carlo moves and outputs built in Rust to TOML:
+//!
+//! ```no_run
+//! extern crate lumol;
+//! extern crate lumol_input;
+//! extern crate toml;
+//! use lumol::sys::System;
+//! use lumol_input::ToToml;
+//!
+//! fn main() {
+//!     let system = System::new();
+//!
+//!     // ... Build the system and the interactions by hand
+//!
+//!     let interactions = system.interactions().to_toml().unwrap();
+//!     println!("{}", toml::Value::Table(interactions));
+//! }
+//! ```
+//!
 #![warn(
     missing_docs, trivial_casts, unused_import_braces, variant_size_differences,
     unused_qualifications, unused_results
@@ -65,6 +85,7 @@
 extern crate log;
 extern crate toml;
 extern crate chemfiles;
+extern crate tempdir;
 extern crate lumol;
 
 use toml::Table;
@@ -81,13 +102,15 @@ macro_rules! try_io {
 }
 
 mod extract;
+mod insert;
 mod error;
+mod preprocess;
 mod interactions;
 mod simulations;
 
-pub use self::error::{Error, Result};
+pub use self::error::{Error, Result, Location};
 pub use self::interactions::InteractionsInput;
-pub use self::simulations::Input;
+pub use self::simulations::{Input, Config, Stage, LogConfig};
 
 /// Convert a TOML table to a Rust type.
 pub trait FromToml: Sized {
@@ -103,10 +126,20 @@ pub trait FromTomlWithData: Sized {
     fn from_toml(table: &Table, data: Self::Data) -> Result<Self>;
 }
 
+/// Convert a Rust type to a TOML table, which can be read back with the
+/// corresponding `FromToml` implementation.
+pub trait ToToml {
+    /// Do the conversion from Self to a TOML table.
+    fn to_toml(&self) -> Result<Table>;
+}
+
 fn validate(config: &Table) -> Result<()> {
     let input = try!(config.get("input").ok_or(
         Error::from("Missing 'input' table")
     ));
+    if let Some(input) = input.as_table() {
+        try!(extract::check_keys(input, &["version"], "'input' table"));
+    }
 
     let version = try!(input.lookup("version").ok_or(
         Error::from("Missing 'version' key in 'input' table")
@@ -0,0 +1,282 @@
+// Lumol, an extensible molecular simulation engine
+// Copyright (C) 2015-2016 Lumol's contributors — BSD license
+
+//! Preprocessing of the input files: inclusion of other files with the
+//! `include` key, and substitution of the variables from the `[variables]`
+//! table.
+use toml::{Parser, Table, Value};
+
+use std::collections::BTreeMap;
+use std::io::prelude::*;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+
+use error::{self, Error, Result, at_key, toml_error_to_string};
+use simulations::get_input_path;
+
+/// The TOML source of an input file, used to locate errors
+#[derive(Clone)]
+pub struct Source {
+    /// Path to the file, if the source was read from a file
+    pub path: Option<PathBuf>,
+    /// Content of the file
+    pub content: String,
+}
+
+/// Parse the TOML `content` of the input file at `path`, including the other
+/// files and substituting the variables. All the sources used to build the
+/// final configuration are returned with it, the first one being `content`.
+/// The errors are located in the corresponding source.
+pub fn parse(content: String, path: Option<PathBuf>) -> Result<(Table, Vec<Source>)> {
+    let mut sources = Vec::new();
+    let mut config = try!(read_includes(content, path, &mut Vec::new(), &mut sources));
+    if let Err(err) = substitute_variables(&mut config) {
+        return Err(error::locate(err, &sources));
+    }
+    Ok((config, sources))
+}
+
+/// Parse the TOML `content` of the file at `path`, and merge it with the
+/// files it includes. `stack` contains the files currently being included,
+/// and is used to detect cycles. The sources of all the files are added to
+/// `sources`.
+fn read_includes(content: String, path: Option<PathBuf>, stack: &mut Vec<PathBuf>, sources: &mut Vec<Source>) -> Result<Table> {
+    let config = {
+        let mut parser = Parser::new(&content);
+        match parser.parse() {
+            Some(config) => config,
+            None => {
+                let message = toml_error_to_string(&parser);
+                return Err(Error::TOML(match (stack.is_empty(), &path) {
+                    (false, &Some(ref path)) => format!("{} in '{}'", message, path.display()),
+                    _ => message,
+                }));
+            }
+        }
+    };
+
+    let index = sources.len();
+    sources.push(Source {
+        path: path.clone(),
+        content: content,
+    });
+
+    if let Some(ref path) = path {
+        stack.push(path.clone());
+    }
+    let result = include_files(config, path.as_ref(), stack, sources);
+    if path.is_some() {
+        let _ = stack.pop();
+    }
+    // Errors in the included files are already located
+    result.map_err(|err| error::locate(err, &sources[index..index + 1]))
+}
+
+/// Read the files included by `config`, from the file at `path`, and merge
+/// `config` on top of them.
+fn include_files(mut config: Table, path: Option<&PathBuf>, stack: &mut Vec<PathBuf>, sources: &mut Vec<Source>) -> Result<Table> {
+    let includes = match config.remove("include") {
+        Some(Value::Array(includes)) => includes,
+        Some(_) => return Err(at_key("include", "'include' must be an array of strings")),
+        None => return Ok(config),
+    };
+
+    let mut merged = Table::new();
+    for include in includes {
+        let include = try!(include.as_str().ok_or(
+            at_key("include", "'include' must be an array of strings")
+        ));
+        let include = match path {
+            Some(path) => get_input_path(path, include),
+            None => PathBuf::from(include),
+        };
+
+        if stack.iter().any(|path| same_file(path, &include)) {
+            let cycle = stack.iter()
+                             .chain(Some(&include))
+                             .map(|path| format!("'{}'", path.display()))
+                             .collect::<Vec<_>>();
+            return Err(at_key("include", format!(
+                "Cycle in included files: {}", cycle.join(" -> ")
+            )));
+        }
+
+        let content = try!(read_file(&include).map_err(|err| at_key("include", err)));
+        let included = try!(read_includes(content, Some(include), stack, sources));
+        merge(&mut merged, included);
+    }
+
+    merge(&mut merged, config);
+    return Ok(merged);
+}
+
+/// Read the whole content of the file at `path`
+fn read_file(path: &Path) -> Result<String> {
+    let mut file = try_io!(File::open(path), path.to_owned());
+    let mut buffer = String::new();
+    let _ = try_io!(file.read_to_string(&mut buffer), path.to_owned());
+    Ok(buffer)
+}
+
+/// Check if the paths `a` and `b` point to the same file
+fn same_file(a: &Path, b: &Path) -> bool {
+    match (a.canonicalize(), b.canonicalize()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a == b,
+    }
+}
+
+/// Merge the `other` table in `table`. Tables are merged recursively, arrays
+/// of tables are concatenated, and all other values in `other` replace the
+/// values in `table`.
+fn merge(table: &mut Table, other: Table) {
+    for (key, value) in other {
+        let value = match (table.get_mut(&key), value) {
+            (Some(&mut Value::Table(ref mut table)), Value::Table(other)) => {
+                merge(table, other);
+                continue;
+            }
+            (Some(&mut Value::Array(ref mut array)), Value::Array(other)) => {
+                if is_array_of_tables(array) && is_array_of_tables(&other) {
+                    array.extend(other);
+                    continue;
+                }
+                Value::Array(other)
+            }
+            (_, value) => value,
+        };
+        let _ = table.insert(key, value);
+    }
+}
+
+/// Check if the `array` only contains tables
+fn is_array_of_tables(array: &[Value]) -> bool {
+    array.iter().all(|value| value.as_table().is_some())
+}
+
+/// Substitute the variables defined in the `[variables]` table of `config`
+/// in all the strings of `config`, and remove this table.
+fn substitute_variables(config: &mut Table) -> Result<()> {
+    let definitions = match config.remove("variables") {
+        Some(Value::Table(definitions)) => definitions,
+        Some(_) => return Err(at_key("variables", "'variables' must be a table")),
+        None => return Ok(()),
+    };
+
+    let mut variables = Variables {
+        definitions: &definitions,
+        values: BTreeMap::new(),
+        stack: Vec::new(),
+    };
+
+    // Resolve all the variables first, to report errors in the definitions
+    // with the corresponding variable.
+    for name in definitions.keys() {
+        let _ = try!(variables.get(name).map_err(|err| at_key(name, err)));
+    }
+
+    for (key, value) in config.iter_mut() {
+        try!(substitute_value(key, value, &mut variables));
+    }
+    Ok(())
+}
+
+/// Substitute the variables in all the strings in `value`, associated with
+/// the given `key`.
+fn substitute_value(key: &str, value: &mut Value, variables: &mut Variables) -> Result<()> {
+    let substituted = match *value {
+        Value::String(ref string) => {
+            try!(variables.substitute(string).map_err(|err| at_key(key, err)))
+        }
+        Value::Array(ref mut array) => {
+            for value in array {
+                try!(substitute_value(key, value, variables));
+            }
+            return Ok(());
+        }
+        Value::Table(ref mut table) => {
+            for (key, value) in table.iter_mut() {
+                try!(substitute_value(key, value, variables));
+            }
+            return Ok(());
+        }
+        _ => return Ok(()),
+    };
+    *value = substituted;
+    Ok(())
+}
+
+/// Variables defined in an input file
+struct Variables<'a> {
+    /// Definitions of the variables, from the `[variables]` table
+    definitions: &'a Table,
+    /// Values of the variables resolved so far
+    values: BTreeMap<String, Value>,
+    /// Variables currently being resolved, used to detect cycles
+    stack: Vec<String>,
+}
+
+impl<'a> Variables<'a> {
+    /// Get the value of the variable with the given `name`
+    fn get(&mut self, name: &str) -> Result<Value> {
+        if let Some(value) = self.values.get(name) {
+            return Ok(value.clone());
+        }
+
+        if self.stack.iter().any(|variable| variable == name) {
+            let cycle = self.stack.iter()
+                                  .map(|variable| variable.as_str())
+                                  .chain(Some(name))
+                                  .collect::<Vec<_>>();
+            return Err(Error::from(format!(
+                "Cycle in the definition of variables: {}", cycle.join(" -> ")
+            )));
+        }
+
+        let value = match self.definitions.get(name) {
+            Some(&Value::String(ref string)) => {
+                self.stack.push(String::from(name));
+                let value = self.substitute(string);
+                let _ = self.stack.pop();
+                try!(value)
+            }
+            Some(value @ &Value::Integer(_)) |
+            Some(value @ &Value::Float(_)) |
+            Some(value @ &Value::Boolean(_)) => value.clone(),
+            Some(_) => return Err(Error::from(format!(
+                "Variable '{}' must be a string, a number or a boolean", name
+            ))),
+            None => return Err(Error::from(
+                format!("Undefined variable '{}'", name)
+            )),
+        };
+
+        let _ = self.values.insert(String::from(name), value.clone());
+        Ok(value)
+    }
+
+    /// Substitute all the references to variables in `string`. A string
+    /// containing a single reference, such as `"${n}"`, is replaced by the
+    /// value of the variable, keeping its type.
+    fn substitute(&mut self, string: &str) -> Result<Value> {
+        if string.starts_with("${") && string.ends_with('}') && string.find('}') == Some(string.len() - 1) {
+            return self.get(&string[2..string.len() - 1]);
+        }
+
+        let mut result = String::new();
+        let mut rest = string;
+        while let Some(start) = rest.find("${") {
+            result.push_str(&rest[..start]);
+            let length = try!(rest[start..].find('}').ok_or(Error::from(
+                format!("Missing '}}' after '${{' in '{}'", string)
+            )));
+            match try!(self.get(&rest[start + 2..start + length])) {
+                Value::String(value) => result.push_str(&value),
+                value => result.push_str(&value.to_string()),
+            }
+            rest = &rest[start + length + 1..];
+        }
+        result.push_str(rest);
+        Ok(Value::String(result))
+    }
+}
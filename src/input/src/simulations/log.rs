@@ -0,0 +1,118 @@
+// Lumol, an extensible molecular simulation engine
+// Copyright (C) 2015-2016 Lumol's contributors — BSD license
+use toml::Table;
+use std::fs;
+use std::path::PathBuf;
+
+use lumol::{Logger, LogLevel, LogLevelFilter, Target};
+
+use error::{Error, Result};
+use extract;
+use super::Input;
+
+/// Logging configuration, from the `[log]` section of an input file.
+pub struct LogConfig {
+    /// Where the log messages are written
+    pub target: Target,
+    /// Maximum log level, or `None` to use the default level
+    pub level: Option<LogLevel>,
+    /// Maximum log levels for specific modules, overriding `level`
+    pub modules: Vec<(String, LogLevelFilter)>,
+}
+
+impl Default for LogConfig {
+    fn default() -> LogConfig {
+        LogConfig {
+            target: Target::StdOut,
+            level: None,
+            modules: Vec::new(),
+        }
+    }
+}
+
+impl LogConfig {
+    /// Initialize the global logger using this configuration.
+    pub fn init(self) -> Result<()> {
+        let level = self.level.unwrap_or(Logger::default_level());
+        let path = match self.target {
+            Target::File(ref path) => PathBuf::from(path),
+            _ => PathBuf::new(),
+        };
+
+        // The output directory may not exist yet
+        if let Some(parent) = path.parent() {
+            try_io!(fs::create_dir_all(parent), parent.to_owned());
+        }
+        try_io!(Logger::init_with(self.target, level, self.modules), path);
+        Ok(())
+    }
+}
+
+impl Input {
+    /// Get the logging configuration from the `[log]` section of the input
+    /// file, or the default configuration if this section is missing.
+    pub fn read_log(&self) -> Result<LogConfig> {
+        self.log_config().map_err(|err| self.locate(err))
+    }
+
+    fn log_config(&self) -> Result<LogConfig> {
+        let mut log = LogConfig::default();
+        let config = match self.config.get("log") {
+            Some(config) => try!(config.as_table().ok_or(
+                Error::from("'log' must be a table")
+            )),
+            None => return Ok(log),
+        };
+        try!(extract::check_keys(config, &["target", "file", "level", "modules"], "log section"));
+
+        match (config.get("target"), config.get("file")) {
+            (Some(_), Some(_)) => return Err(Error::from(
+                "'target' and 'file' can not both be given in log section"
+            )),
+            (Some(_), None) => {
+                log.target = match try!(extract::str("target", config, "log section")) {
+                    "stdout" => Target::StdOut,
+                    "stderr" => Target::StdErr,
+                    other => return Err(Error::from(format!(
+                        "Unknown log target '{}', expected 'stdout' or 'stderr'", other
+                    ))),
+                };
+            }
+            (None, Some(_)) => {
+                let file = try!(extract::str("file", config, "log section"));
+                let path = self.output_path(file);
+                log.target = Target::File(path.to_string_lossy().into_owned());
+            }
+            (None, None) => {}
+        }
+
+        if config.contains_key("level") {
+            let level = try!(extract::str("level", config, "log section"));
+            log.level = Some(try!(level.parse().map_err(|_| Error::from(
+                format!("Invalid log level '{}' in log section", level)
+            ))));
+        }
+
+        if config.contains_key("modules") {
+            let modules = try!(extract::table("modules", config, "log section"));
+            log.modules = try!(read_modules(modules));
+        }
+
+        Ok(log)
+    }
+}
+
+/// Read the maximum log levels for specific modules
+fn read_modules(modules: &Table) -> Result<Vec<(String, LogLevelFilter)>> {
+    let mut result = Vec::new();
+    for (module, level) in modules {
+        let level = try!(level.as_str().ok_or(Error::from(format!(
+            "log level for module '{}' must be a string", module
+        ))));
+        let level = try!(level.parse().map_err(|_| Error::from(format!(
+            "Invalid log level '{}' for module '{}'", level, module
+        ))));
+        result.push((module.clone(), level));
+    }
+    Ok(result)
+}
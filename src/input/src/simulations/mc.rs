@@ -1,20 +1,23 @@
 // Lumol, an extensible molecular simulation engine
 // Copyright (C) 2015-2016 Lumol's contributors — BSD license
-use toml::Table;
+use toml::{Table, Value};
 use std::path::PathBuf;
 
-use lumol::sys::{read_molecule, molecule_type};
 use lumol::sim::mc::*;
 use lumol::units;
 
 use error::{Error, Result};
-use FromTomlWithData;
+use {FromTomlWithData, ToToml};
 use extract;
+use insert;
 use simulations::get_input_path;
 
 impl FromTomlWithData for MonteCarlo {
     type Data = PathBuf;
     fn from_toml(config: &Table, root: PathBuf) -> Result<MonteCarlo> {
+        try!(extract::check_keys(
+            config, &["type", "temperature", "update_frequency", "moves"], "Monte-Carlo propagator"
+        ));
         let temperature = try!(extract::str("temperature", config, "Monte-Carlo propagator"));
         let temperature = try!(units::from_str(temperature));
 
@@ -76,15 +79,15 @@ impl FromTomlWithData for MonteCarlo {
 impl FromTomlWithData for Translate {
     type Data = PathBuf;
     fn from_toml(config: &Table, root: PathBuf) -> Result<Translate> {
+        try!(extract::check_keys(config, &[
+            "type", "frequency", "target_acceptance", "delta", "molecule"
+        ], "Translate move"));
         let delta = try!(extract::str("delta", config, "Translate move"));
         let delta = try!(units::from_str(delta));
 
         if config.get("molecule").is_some() {
             let molfile = try!(extract::str("molecule", config, "Translate move"));
-            let molfile = get_input_path(root, molfile);
-            let (molecule, atoms) = try!(read_molecule(molfile));
-            let moltype = molecule_type(&molecule, &atoms);
-            Ok(Translate::with_moltype(delta, moltype))
+            Ok(try!(Translate::with_molecule(delta, get_input_path(root, molfile))))
         } else {
             Ok(Translate::new(delta))
         }
@@ -94,15 +97,15 @@ impl FromTomlWithData for Translate {
 impl FromTomlWithData for Rotate {
     type Data = PathBuf;
     fn from_toml(config: &Table, root: PathBuf) -> Result<Rotate> {
+        try!(extract::check_keys(config, &[
+            "type", "frequency", "target_acceptance", "delta", "molecule"
+        ], "Rotate move"));
         let delta = try!(extract::str("delta", config, "Rotate move"));
         let delta = try!(units::from_str(delta));
 
         if config.get("molecule").is_some() {
             let molfile = try!(extract::str("molecule", config, "Rotate move"));
-            let molfile = get_input_path(root, molfile);
-            let (molecule, atoms) = try!(read_molecule(molfile));
-            let moltype = molecule_type(&molecule, &atoms);
-            Ok(Rotate::with_moltype(delta, moltype))
+            Ok(try!(Rotate::with_molecule(delta, get_input_path(root, molfile))))
         } else {
             Ok(Rotate::new(delta))
         }
@@ -112,6 +115,9 @@ impl FromTomlWithData for Rotate {
 impl FromTomlWithData for Resize {
     type Data = PathBuf;
     fn from_toml(config: &Table, _: PathBuf) -> Result<Resize> {
+        try!(extract::check_keys(config, &[
+            "type", "frequency", "target_acceptance", "pressure", "delta"
+        ], "Resize move"));
         let pressure = try!(extract::str("pressure", config, "Resize move"));
         let pressure = try!(units::from_str(pressure));
 
@@ -121,3 +127,82 @@ impl FromTomlWithData for Resize {
         Ok(Resize::new(pressure, delta))
     }
 }
+
+/******************************************************************************/
+
+impl ToToml for MonteCarlo {
+    /// Convert the propagator to TOML. The random number generator is part of
+    /// the simulation table, and is written when converting the simulation.
+    fn to_toml(&self) -> Result<Table> {
+        let mut config = insert::typ("MonteCarlo");
+        insert::quantity("temperature", self.temperature(), &mut config);
+        if self.amplitude_update_frequency() != 0 {
+            insert::uint("update_frequency", self.amplitude_update_frequency(), &mut config);
+        }
+
+        let mut moves = Vec::new();
+        for (mc_move, frequency, target_acceptance) in self.moves() {
+            let mut mc_move = if let Some(translate) = mc_move.downcast_ref::<Translate>() {
+                try!(translate.to_toml())
+            } else if let Some(rotate) = mc_move.downcast_ref::<Rotate>() {
+                try!(rotate.to_toml())
+            } else if let Some(resize) = mc_move.downcast_ref::<Resize>() {
+                try!(resize.to_toml())
+            } else {
+                return Err(Error::from("Can not convert this Monte-Carlo move to TOML"));
+            };
+            insert::float("frequency", frequency, &mut mc_move);
+            if let Some(target_acceptance) = target_acceptance {
+                insert::float("target_acceptance", target_acceptance, &mut mc_move);
+            }
+            moves.push(Value::Table(mc_move));
+        }
+        let _ = config.insert(String::from("moves"), Value::Array(moves));
+
+        Ok(config)
+    }
+}
+
+impl ToToml for Translate {
+    fn to_toml(&self) -> Result<Table> {
+        let mut config = insert::typ("Translate");
+        insert::quantity("delta", self.delta(), &mut config);
+        match (self.moltype(), self.molecule()) {
+            (None, _) => {}
+            (Some(_), Some(molecule)) => {
+                try!(insert::path("molecule", molecule, &mut config, "Translate move"));
+            }
+            (Some(_), None) => return Err(Error::from(
+                "Can not convert Translate move restricted to a molecule type without molecule file to TOML"
+            )),
+        }
+        Ok(config)
+    }
+}
+
+impl ToToml for Rotate {
+    fn to_toml(&self) -> Result<Table> {
+        let mut config = insert::typ("Rotate");
+        insert::quantity("delta", self.theta(), &mut config);
+        match (self.moltype(), self.molecule()) {
+            (None, _) => {}
+            (Some(_), Some(molecule)) => {
+                try!(insert::path("molecule", molecule, &mut config, "Rotate move"));
+            }
+            (Some(_), None) => return Err(Error::from(
+                "Can not convert Rotate move restricted to a molecule type without molecule file to TOML"
+            )),
+        }
+        Ok(config)
+    }
+}
+
+impl ToToml for Resize {
+    fn to_toml(&self) -> Result<Table> {
+        let mut config = insert::typ("Resize");
+        let pressure = self.pressure().expect("Resize move always have a pressure");
+        insert::quantity("pressure", pressure, &mut config);
+        insert::quantity("delta", self.delta(), &mut config);
+        Ok(config)
+    }
+}
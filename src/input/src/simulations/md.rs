@@ -1,17 +1,21 @@
 // Lumol, an extensible molecular simulation engine
 // Copyright (C) 2015-2016 Lumol's contributors — BSD license
-use toml::Table;
+use toml::{Table, Value};
 
 use lumol::sim::md::*;
 use lumol::units;
 use lumol::sim::Alternator;
 
 use error::{Error, Result};
-use {FromToml, FromTomlWithData};
+use {FromToml, FromTomlWithData, ToToml};
 use extract;
+use insert;
 
 impl FromToml for MolecularDynamics {
     fn from_toml(config: &Table) -> Result<MolecularDynamics> {
+        try!(extract::check_keys(config, &[
+            "type", "timestep", "integrator", "thermostat", "controls"
+        ], "molecular dynamics propagator"));
         // Get the timestep of the simulation
         let timestep = try!(extract::str("timestep", config, "molecular dynamics propagator"));
         let timestep = try!(units::from_str(timestep));
@@ -100,21 +104,24 @@ impl FromToml for MolecularDynamics {
 
 impl FromTomlWithData for Verlet {
     type Data = f64;
-    fn from_toml(_: &Table, timestep: f64) -> Result<Verlet> {
+    fn from_toml(config: &Table, timestep: f64) -> Result<Verlet> {
+        try!(extract::check_keys(config, &["type"], "Verlet integrator"));
         Ok(Verlet::new(timestep))
     }
 }
 
 impl FromTomlWithData for VelocityVerlet {
     type Data = f64;
-    fn from_toml(_: &Table, timestep: f64) -> Result<VelocityVerlet> {
+    fn from_toml(config: &Table, timestep: f64) -> Result<VelocityVerlet> {
+        try!(extract::check_keys(config, &["type"], "VelocityVerlet integrator"));
         Ok(VelocityVerlet::new(timestep))
     }
 }
 
 impl FromTomlWithData for LeapFrog {
     type Data = f64;
-    fn from_toml(_: &Table, timestep: f64) -> Result<LeapFrog> {
+    fn from_toml(config: &Table, timestep: f64) -> Result<LeapFrog> {
+        try!(extract::check_keys(config, &["type"], "LeapFrog integrator"));
         Ok(LeapFrog::new(timestep))
     }
 }
@@ -122,6 +129,7 @@ impl FromTomlWithData for LeapFrog {
 impl FromTomlWithData for BerendsenBarostat {
     type Data = f64;
     fn from_toml(config: &Table, timestep: f64) -> Result<BerendsenBarostat> {
+        try!(extract::check_keys(config, &["type", "pressure", "timestep"], "Berendsen barostat"));
         let pressure = try!(extract::str("pressure", config, "Berendsen barostat"));
         let pressure = try!(units::from_str(pressure));
         let tau = try!(extract::number("timestep", config, "Berendsen barostat"));
@@ -133,6 +141,9 @@ impl FromTomlWithData for AnisoBerendsenBarostat {
     type Data = f64;
     fn from_toml(config: &Table, timestep: f64) -> Result<AnisoBerendsenBarostat> {
         // TODO: implement a way to give the stress matrix
+        try!(extract::check_keys(
+            config, &["type", "pressure", "timestep"], "anisotropic Berendsen barostat"
+        ));
         let pressure = try!(extract::str("pressure", config, "anisotropic Berendsen barostat"));
         let pressure = try!(units::from_str(pressure));
         let tau = try!(extract::number("timestep", config, "anisotropic Berendsen barostat"));
@@ -144,6 +155,7 @@ impl FromTomlWithData for AnisoBerendsenBarostat {
 
 impl FromToml for BerendsenThermostat {
     fn from_toml(config: &Table) -> Result<BerendsenThermostat> {
+        try!(extract::check_keys(config, &["type", "temperature", "timestep"], "Berendsen thermostat"));
         let temperature = try!(extract::str("temperature", config, "Berendsen thermostat"));
         let temperature = try!(units::from_str(temperature));
         let tau = try!(extract::number("timestep", config, "Berendsen thermostat"));
@@ -153,6 +165,7 @@ impl FromToml for BerendsenThermostat {
 
 impl FromToml for RescaleThermostat {
     fn from_toml(config: &Table) -> Result<RescaleThermostat> {
+        try!(extract::check_keys(config, &["type", "temperature", "tolerance"], "rescale thermostat"));
         let temperature = try!(extract::str("temperature", config, "Berendsen thermostat"));
         let temperature = try!(units::from_str(temperature));
 
@@ -171,6 +184,7 @@ impl FromToml for RescaleThermostat {
 
 impl FromToml for Alternator<RemoveTranslation> {
     fn from_toml(config: &Table) -> Result<Alternator<RemoveTranslation>> {
+        try!(extract::check_keys(config, &["type", "every"], "RemoveTranslation control"));
         let every = if config.contains_key("every") {
             try!(extract::uint("every", config, "RemoveTranslation control"))
         } else {
@@ -182,6 +196,7 @@ impl FromToml for Alternator<RemoveTranslation> {
 
 impl FromToml for Alternator<RemoveRotation> {
     fn from_toml(config: &Table) -> Result<Alternator<RemoveRotation>> {
+        try!(extract::check_keys(config, &["type", "every"], "RemoveRotation control"));
         let every = if config.contains_key("every") {
             try!(extract::uint("every", config, "RemoveRotation control"))
         } else {
@@ -190,3 +205,130 @@ impl FromToml for Alternator<RemoveRotation> {
         Ok(Alternator::new(every, RemoveRotation::new()))
     }
 }
+
+/******************************************************************************/
+
+impl ToToml for MolecularDynamics {
+    fn to_toml(&self) -> Result<Table> {
+        let mut config = insert::typ("MolecularDynamics");
+
+        let integrator = self.integrator();
+        let (timestep, integrator) = if let Some(verlet) = integrator.downcast_ref::<Verlet>() {
+            (verlet.timestep(), try!(verlet.to_toml()))
+        } else if let Some(verlet) = integrator.downcast_ref::<VelocityVerlet>() {
+            (verlet.timestep(), try!(verlet.to_toml()))
+        } else if let Some(leapfrog) = integrator.downcast_ref::<LeapFrog>() {
+            (leapfrog.timestep(), try!(leapfrog.to_toml()))
+        } else if let Some(barostat) = integrator.downcast_ref::<BerendsenBarostat>() {
+            (barostat.timestep(), try!(barostat.to_toml()))
+        } else if let Some(barostat) = integrator.downcast_ref::<AnisoBerendsenBarostat>() {
+            (barostat.timestep(), try!(barostat.to_toml()))
+        } else {
+            return Err(Error::from("Can not convert this integrator to TOML"));
+        };
+        insert::quantity("timestep", timestep, &mut config);
+        insert::table("integrator", integrator, &mut config);
+
+        if let Some(thermostat) = self.thermostat() {
+            let thermostat = if let Some(berendsen) = thermostat.downcast_ref::<BerendsenThermostat>() {
+                try!(berendsen.to_toml())
+            } else if let Some(rescale) = thermostat.downcast_ref::<RescaleThermostat>() {
+                try!(rescale.to_toml())
+            } else {
+                return Err(Error::from("Can not convert this thermostat to TOML"));
+            };
+            insert::table("thermostat", thermostat, &mut config);
+        }
+
+        let mut controls = Vec::new();
+        for control in self.controls() {
+            let control = if let Some(control) = control.downcast_ref::<Alternator<RemoveTranslation>>() {
+                try!(control.to_toml())
+            } else if let Some(control) = control.downcast_ref::<Alternator<RemoveRotation>>() {
+                try!(control.to_toml())
+            } else {
+                return Err(Error::from("Can not convert this control to TOML"));
+            };
+            controls.push(Value::Table(control));
+        }
+        if !controls.is_empty() {
+            let _ = config.insert(String::from("controls"), Value::Array(controls));
+        }
+
+        Ok(config)
+    }
+}
+
+impl ToToml for Verlet {
+    fn to_toml(&self) -> Result<Table> {
+        Ok(insert::typ("Verlet"))
+    }
+}
+
+impl ToToml for VelocityVerlet {
+    fn to_toml(&self) -> Result<Table> {
+        Ok(insert::typ("VelocityVerlet"))
+    }
+}
+
+impl ToToml for LeapFrog {
+    fn to_toml(&self) -> Result<Table> {
+        Ok(insert::typ("LeapFrog"))
+    }
+}
+
+impl ToToml for BerendsenBarostat {
+    fn to_toml(&self) -> Result<Table> {
+        let mut config = insert::typ("BerendsenBarostat");
+        let pressure = self.pressure().expect("Berendsen barostat always have a pressure");
+        insert::quantity("pressure", pressure, &mut config);
+        insert::float("timestep", self.tau(), &mut config);
+        Ok(config)
+    }
+}
+
+impl ToToml for AnisoBerendsenBarostat {
+    fn to_toml(&self) -> Result<Table> {
+        let pressure = try!(self.pressure().ok_or(Error::from(
+            "Can not convert anisotropic Berendsen barostat with a non hydrostatic stress to TOML"
+        )));
+        let mut config = insert::typ("AnisoBerendsenBarostat");
+        insert::quantity("pressure", pressure, &mut config);
+        insert::float("timestep", self.tau(), &mut config);
+        Ok(config)
+    }
+}
+
+impl ToToml for BerendsenThermostat {
+    fn to_toml(&self) -> Result<Table> {
+        let mut config = insert::typ("Berendsen");
+        insert::quantity("temperature", self.temperature(), &mut config);
+        insert::float("timestep", self.tau(), &mut config);
+        Ok(config)
+    }
+}
+
+impl ToToml for RescaleThermostat {
+    fn to_toml(&self) -> Result<Table> {
+        let mut config = insert::typ("Rescale");
+        insert::quantity("temperature", self.temperature(), &mut config);
+        insert::quantity("tolerance", self.tolerance(), &mut config);
+        Ok(config)
+    }
+}
+
+impl ToToml for Alternator<RemoveTranslation> {
+    fn to_toml(&self) -> Result<Table> {
+        let mut config = insert::typ("RemoveTranslation");
+        insert::uint("every", self.every(), &mut config);
+        Ok(config)
+    }
+}
+
+impl ToToml for Alternator<RemoveRotation> {
+    fn to_toml(&self) -> Result<Table> {
+        let mut config = insert::typ("RemoveRotation");
+        insert::uint("every", self.every(), &mut config);
+        Ok(config)
+    }
+}
@@ -1,26 +1,23 @@
 // Lumol, an extensible molecular simulation engine
 // Copyright (C) 2015-2016 Lumol's contributors — BSD license
-use toml::Table;
+use toml::{Table, Value};
 
 use lumol::sim::min::*;
+use lumol::types::Matrix3;
 use lumol::units;
 
 use error::{Error, Result};
-use FromToml;
+use {FromToml, ToToml};
 use extract;
+use insert;
 
 impl FromToml for Minimization {
     fn from_toml(config: &Table) -> Result<Minimization> {
+        try!(extract::check_keys(
+            config, &["type", "minimizer", "tolerance"], "minimization propagator"
+        ));
         let minimizer = try!(extract::table("minimizer", config, "minimization propagator"));
-
-        let minimizer: Box<Minimizer> = match try!(extract::typ(minimizer, "minimizer")) {
-            "SteepestDescent" => Box::new(try!(
-                SteepestDescent::from_toml(minimizer)
-            )),
-            other => return Err(Error::from(
-                format!("Unknown minimizer '{}'", other)
-            ))
-        };
+        let minimizer = try!(read_minimizer(minimizer));
 
         if let Some(tolerance) = config.get("tolerance") {
             let tolerance = try!(tolerance.as_table().ok_or(Error::from(
@@ -34,8 +31,34 @@ impl FromToml for Minimization {
     }
 }
 
+/// Read a minimizer from the `config` table
+pub fn read_minimizer(config: &Table) -> Result<Box<Minimizer>> {
+    let minimizer: Box<Minimizer> = match try!(extract::typ(config, "minimizer")) {
+        "SteepestDescent" => Box::new(try!(
+            SteepestDescent::from_toml(config)
+        )),
+        "ConjugateGradient" => Box::new(try!(
+            ConjugateGradient::from_toml(config)
+        )),
+        "LBFGS" => Box::new(try!(
+            LBFGS::from_toml(config)
+        )),
+        "FIRE" => Box::new(try!(
+            FIRE::from_toml(config)
+        )),
+        "CellRelaxation" => Box::new(try!(
+            CellRelaxation::from_toml(config)
+        )),
+        other => return Err(Error::from(
+            format!("Unknown minimizer '{}'", other)
+        ))
+    };
+    return Ok(minimizer);
+}
+
 impl FromToml for Tolerance {
     fn from_toml(config: &Table) -> Result<Tolerance> {
+        try!(extract::check_keys(config, &["energy", "force2"], "minimization tolerance"));
         let energy = try!(extract::str("energy", config, "minimization tolerance"));
         let force2 = try!(extract::str("force2", config, "minimization tolerance"));
 
@@ -49,7 +72,205 @@ impl FromToml for Tolerance {
 
 
 impl FromToml for SteepestDescent {
-    fn from_toml(_: &Table) -> Result<SteepestDescent> {
+    fn from_toml(config: &Table) -> Result<SteepestDescent> {
+        try!(extract::check_keys(config, &["type"], "steepest descent minimizer"));
         Ok(SteepestDescent::new())
     }
 }
+
+impl FromToml for ConjugateGradient {
+    fn from_toml(config: &Table) -> Result<ConjugateGradient> {
+        try!(extract::check_keys(config, &["type"], "conjugate gradient minimizer"));
+        Ok(ConjugateGradient::new())
+    }
+}
+
+impl FromToml for LBFGS {
+    fn from_toml(config: &Table) -> Result<LBFGS> {
+        try!(extract::check_keys(config, &["type", "history"], "LBFGS minimizer"));
+        if config.contains_key("history") {
+            let history = try!(extract::uint("history", config, "LBFGS minimizer"));
+            if history == 0 {
+                return Err(Error::from("'history' must be positive in LBFGS minimizer"));
+            }
+            Ok(LBFGS::with_history(history as usize))
+        } else {
+            Ok(LBFGS::new())
+        }
+    }
+}
+
+impl FromToml for FIRE {
+    fn from_toml(config: &Table) -> Result<FIRE> {
+        try!(extract::check_keys(config, &["type", "timestep", "max_timestep"], "FIRE minimizer"));
+        let timestep = if config.contains_key("timestep") {
+            let timestep = try!(extract::str("timestep", config, "FIRE minimizer"));
+            try!(units::from_str(timestep))
+        } else {
+            try!(units::from(1.0, "fs"))
+        };
+
+        let max_timestep = if config.contains_key("max_timestep") {
+            let max_timestep = try!(extract::str("max_timestep", config, "FIRE minimizer"));
+            try!(units::from_str(max_timestep))
+        } else {
+            10.0 * timestep
+        };
+
+        if timestep <= 0.0 {
+            return Err(Error::from("'timestep' must be positive in FIRE minimizer"));
+        }
+        if max_timestep < timestep {
+            return Err(Error::from(
+                "'max_timestep' must be larger than 'timestep' in FIRE minimizer"
+            ));
+        }
+        Ok(FIRE::with_timestep(timestep, max_timestep))
+    }
+}
+
+impl FromToml for CellRelaxation {
+    fn from_toml(config: &Table) -> Result<CellRelaxation> {
+        try!(extract::check_keys(
+            config, &["type", "cell", "pressure", "stress"], "cell relaxation minimizer"
+        ));
+        let deformation = if config.contains_key("cell") {
+            match try!(extract::str("cell", config, "cell relaxation minimizer")) {
+                "isotropic" => CellDeformation::Isotropic,
+                "orthorhombic" => CellDeformation::Orthorhombic,
+                "triclinic" => CellDeformation::Triclinic,
+                other => return Err(Error::from(
+                    format!("Unknown cell deformation '{}' in cell relaxation minimizer", other)
+                ))
+            }
+        } else {
+            CellDeformation::Isotropic
+        };
+
+        match (config.contains_key("pressure"), config.contains_key("stress")) {
+            (true, false) => {
+                let pressure = try!(extract::str("pressure", config, "cell relaxation minimizer"));
+                let pressure = try!(units::from_str(pressure));
+                Ok(CellRelaxation::new(pressure, deformation))
+            }
+            (false, true) => {
+                let stress = try!(extract::slice("stress", config, "cell relaxation minimizer"));
+                if stress.len() != 6 {
+                    return Err(Error::from(
+                        "'stress' must be an array of 6 strings in cell relaxation minimizer"
+                    ));
+                }
+                let mut values = [0.0; 6];
+                for (value, component) in values.iter_mut().zip(stress) {
+                    let component = try!(component.as_str().ok_or(Error::from(
+                        "'stress' must be an array of 6 strings in cell relaxation minimizer"
+                    )));
+                    *value = try!(units::from_str(component));
+                }
+                // Voigt notation: xx yy zz yz xz xy
+                let stress = Matrix3::new(
+                    values[0], values[5], values[4],
+                    values[5], values[1], values[3],
+                    values[4], values[3], values[2],
+                );
+                Ok(CellRelaxation::with_stress(stress, deformation))
+            }
+            _ => Err(Error::from(
+                "cell relaxation minimizer needs exactly one of 'pressure' or 'stress'"
+            ))
+        }
+    }
+}
+
+/******************************************************************************/
+
+impl ToToml for Minimization {
+    fn to_toml(&self) -> Result<Table> {
+        let mut config = insert::typ("Minimization");
+        insert::table("minimizer", try!(write_minimizer(self.minimizer())), &mut config);
+        insert::table("tolerance", try!(self.tolerance().to_toml()), &mut config);
+        Ok(config)
+    }
+}
+
+/// Write the `minimizer` to a new TOML table
+pub fn write_minimizer(minimizer: &Minimizer) -> Result<Table> {
+    if let Some(minimizer) = minimizer.downcast_ref::<SteepestDescent>() {
+        minimizer.to_toml()
+    } else if let Some(minimizer) = minimizer.downcast_ref::<ConjugateGradient>() {
+        minimizer.to_toml()
+    } else if let Some(minimizer) = minimizer.downcast_ref::<LBFGS>() {
+        minimizer.to_toml()
+    } else if let Some(minimizer) = minimizer.downcast_ref::<FIRE>() {
+        minimizer.to_toml()
+    } else if let Some(minimizer) = minimizer.downcast_ref::<CellRelaxation>() {
+        minimizer.to_toml()
+    } else {
+        Err(Error::from("Can not convert this minimizer to TOML"))
+    }
+}
+
+impl ToToml for Tolerance {
+    fn to_toml(&self) -> Result<Table> {
+        let mut config = Table::new();
+        insert::quantity("energy", self.energy, &mut config);
+        insert::quantity("force2", self.force2, &mut config);
+        Ok(config)
+    }
+}
+
+impl ToToml for SteepestDescent {
+    fn to_toml(&self) -> Result<Table> {
+        Ok(insert::typ("SteepestDescent"))
+    }
+}
+
+impl ToToml for ConjugateGradient {
+    fn to_toml(&self) -> Result<Table> {
+        Ok(insert::typ("ConjugateGradient"))
+    }
+}
+
+impl ToToml for LBFGS {
+    fn to_toml(&self) -> Result<Table> {
+        let mut config = insert::typ("LBFGS");
+        insert::uint("history", self.history() as u64, &mut config);
+        Ok(config)
+    }
+}
+
+impl ToToml for FIRE {
+    fn to_toml(&self) -> Result<Table> {
+        let mut config = insert::typ("FIRE");
+        insert::quantity("timestep", self.timestep(), &mut config);
+        insert::quantity("max_timestep", self.max_timestep(), &mut config);
+        Ok(config)
+    }
+}
+
+impl ToToml for CellRelaxation {
+    fn to_toml(&self) -> Result<Table> {
+        let mut config = insert::typ("CellRelaxation");
+        let cell = match self.deformation() {
+            CellDeformation::Isotropic => "isotropic",
+            CellDeformation::Orthorhombic => "orthorhombic",
+            CellDeformation::Triclinic => "triclinic",
+        };
+        insert::str("cell", cell, &mut config);
+
+        if let Some(stress) = self.stress() {
+            // Voigt notation: xx yy zz yz xz xy
+            let stress = [
+                stress[0][0], stress[1][1], stress[2][2],
+                stress[1][2], stress[0][2], stress[0][1],
+            ];
+            let stress = stress.iter()
+                               .map(|value| Value::String(format!("{}", value)))
+                               .collect();
+            let _ = config.insert(String::from("stress"), Value::Array(stress));
+        } else {
+            insert::quantity("pressure", self.pressure(), &mut config);
+        }
+        Ok(config)
+    }
+}
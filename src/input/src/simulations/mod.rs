@@ -1,29 +1,41 @@
 // Lumol, an extensible molecular simulation engine
 // Copyright (C) 2015-2016 Lumol's contributors — BSD license
-use toml::{Parser, Table};
+use toml::{Table, Value};
 
 use std::io::prelude::*;
 use std::path::{Path, PathBuf};
-use std::fs::File;
+use std::fs::{self, File};
+
+use tempdir::TempDir;
 
 use validate;
-use error::{Error, Result};
-use error::toml_error_to_string;
+use extract;
+use error::{self, Error, Result};
+use preprocess::{self, Source};
 
 use lumol::sim::Simulation;
+use lumol::sim::stop::interrupt_requested;
 use lumol::sys::System;
+use lumol::sys::veloc::{BoltzmannVelocities, InitVelocities};
 
 mod system;
+mod log;
 mod outputs;
+mod stop;
 mod propagator;
 mod simulations;
 mod min;
+mod neb;
 mod md;
 mod mc;
+mod rng;
+
+pub use self::log::LogConfig;
+use self::propagator::check_propagator;
 
-/// A configuration about how to run a single simulation. This contains the
-/// system to simulate, the simulation itself and the number of steps to run
-/// the simulation.
+/// A configuration about how to run a simulation. This contains the system to
+/// simulate, the first simulation and the number of steps to run it, and the
+/// additional stages to run afterward on the same system.
 pub struct Config {
     /// The simulated system
     pub system: System,
@@ -31,6 +43,53 @@ pub struct Config {
     pub simulation: Simulation,
     /// The simulation duration
     pub nsteps: usize,
+    /// Additional stages of the simulation, to run in order after the first
+    /// simulation
+    pub stages: Vec<Stage>,
+}
+
+impl Config {
+    /// Run the first simulation and then all the additional stages on the
+    /// system. If a simulation is interrupted by a stop condition, or if an
+    /// interruption is requested at the end of a stage, the remaining stages
+    /// do not run.
+    pub fn run(&mut self) {
+        self.simulation.run(&mut self.system, self.nsteps);
+        if self.simulation.interrupted() || interrupt_requested() {
+            return;
+        }
+
+        for (i, stage) in self.stages.iter_mut().enumerate() {
+            info!("Starting simulation stage {}", i + 2);
+            stage.run(&mut self.system);
+            if stage.simulation.interrupted() || interrupt_requested() {
+                return;
+            }
+        }
+    }
+}
+
+/// A stage in a multi-stage simulation. All the stages run on the same system,
+/// so that each stage starts with the positions, velocities and unit cell at
+/// the end of the previous one.
+pub struct Stage {
+    /// The simulation object
+    pub simulation: Simulation,
+    /// The stage duration
+    pub nsteps: usize,
+    /// Initializer for the velocities at the beginning of the stage
+    pub velocities: Option<BoltzmannVelocities>,
+}
+
+impl Stage {
+    /// Initialize the velocities if needed, and run this stage on the
+    /// `system`.
+    pub fn run(&mut self, system: &mut System) {
+        if let Some(ref mut velocities) = self.velocities {
+            velocities.init(system);
+        }
+        self.simulation.run(system, self.nsteps);
+    }
 }
 
 /// An input file for Lumol.
@@ -39,6 +98,19 @@ pub struct Input {
     path: PathBuf,
     /// The TOML configuration
     config: Table,
+    /// The TOML sources of the configuration, used to locate errors
+    sources: Vec<Source>,
+    /// Seed overriding all the random number generators seeds in the input
+    seed: Option<u64>,
+    /// Checkpoint file to restart the simulation from
+    restart: Option<PathBuf>,
+    /// Number of steps overriding the one of all the simulations
+    nsteps: Option<usize>,
+    /// Directory where the output files are created
+    output_dir: Option<PathBuf>,
+    /// Are we only checking the input? In this case, all the output files
+    /// are created in `output_dir`.
+    checking: bool,
 }
 
 impl Input {
@@ -55,30 +127,227 @@ impl Input {
     // TODO: use restricted privacy here
     #[doc(hidden)]
     pub fn from_str(path: PathBuf, buffer: &str) -> Result<Input> {
-        let mut parser = Parser::new(buffer);
-        let config = try!(parser.parse().ok_or(
-            Error::TOML(toml_error_to_string(&parser))
+        let (config, sources) = try!(preprocess::parse(String::from(buffer), Some(path.clone())));
+        let check = validate(&config).and_then(|_| extract::check_keys(
+            &config, &["input", "log", "systems", "simulations"], "input file"
         ));
+        if let Err(err) = check {
+            return Err(error::locate(err, &sources));
+        }
+
+        Ok(Input {
+            path: path,
+            config: config,
+            sources: sources,
+            seed: None,
+            restart: None,
+            nsteps: None,
+            output_dir: None,
+            checking: false,
+        })
+    }
+
+    /// Use `seed` for all the random number generators created from this
+    /// input, overriding any seed given in the input file. This allow to run
+    /// independent replicas of the same simulation. Each generator uses a
+    /// different seed derived from `seed`, so that the different stages and
+    /// velocities initializations do not use the same random numbers.
+    pub fn set_seed(&mut self, seed: u64) {
+        self.seed = Some(seed);
+    }
+
+    /// Restart the simulation from the checkpoint file at `path`, written by
+    /// a previous run of the same input. The system and the state of the
+    /// simulation are read from the checkpoint, and the simulation runs for
+    /// the steps remaining to reach the end of the last stage.
+    pub fn set_restart<P: Into<PathBuf>>(&mut self, path: P) {
+        self.restart = Some(path.into());
+    }
+
+    /// Run all the simulations in this input for `nsteps` steps, overriding
+    /// the number of steps given in the input file.
+    pub fn set_nsteps(&mut self, nsteps: usize) {
+        self.nsteps = Some(nsteps);
+    }
 
-        try!(validate(&config));
-        Ok(Input{path: path, config: config})
+    /// Create the output files and the checkpoints given with a relative path
+    /// in the input file in the directory at `path`, instead of the current
+    /// directory. The directory is created if it does not exist.
+    pub fn set_output_dir<P: Into<PathBuf>>(&mut self, path: P) {
+        self.output_dir = Some(path.into());
+    }
+
+    /// Check this input, constructing the system and all the simulations
+    /// without running them, and get all the errors found. The errors in the
+    /// systems and in each simulation are reported separately, so that a
+    /// single check can report multiple errors.
+    ///
+    /// The output files are created in a temporary directory, and removed
+    /// afterward. The checkpoint to restart from is not checked.
+    pub fn check(&self) -> Vec<Error> {
+        let mut errors = Vec::new();
+        let tmpdir = match TempDir::new("lumol-check") {
+            Ok(tmpdir) => tmpdir,
+            Err(err) => {
+                errors.push(Error::from((err, ::std::env::temp_dir())));
+                return errors;
+            }
+        };
+
+        let input = Input {
+            path: self.path.clone(),
+            config: self.config.clone(),
+            sources: self.sources.clone(),
+            seed: self.seed,
+            restart: None,
+            nsteps: self.nsteps,
+            output_dir: Some(tmpdir.path().to_owned()),
+            checking: true,
+        };
+
+        if let Err(err) = input.read_log() {
+            errors.push(err);
+        }
+
+        let system = match input.read_system() {
+            Ok(system) => Some(system),
+            Err(err) => {
+                errors.push(err);
+                None
+            }
+        };
+
+        match input.simulation_tables() {
+            Ok(tables) => {
+                for (i, config) in tables.into_iter().enumerate() {
+                    let checked = input.read_stage(i, config).and_then(|stage| {
+                        match system {
+                            Some(ref system) => check_propagator(stage.simulation.propagator(), system),
+                            None => Ok(()),
+                        }
+                    });
+                    if let Err(err) = checked {
+                        errors.push(err);
+                    }
+                }
+            }
+            Err(err) => errors.push(err),
+        }
+
+        if let Err(err) = tmpdir.close() {
+            warn!("could not remove temporary directory: {}", err);
+        }
+        return errors.into_iter().map(|err| self.locate(err)).collect();
     }
 
     /// Read input file and get the corresponding `Config`
     pub fn read(&self) -> Result<Config> {
-        let system = try!(self.read_system());
-        let simulation = try!(self.read_simulation());
-        let nsteps = try!(self.read_nsteps());
+        self.read_config().map_err(|err| self.locate(err))
+    }
+
+    fn read_config(&self) -> Result<Config> {
+        if let Some(ref dir) = self.output_dir {
+            try_io!(fs::create_dir_all(dir), dir.clone());
+        }
+        // The logging configuration is used before reading the input, but
+        // still needs to be checked here.
+        let _ = try!(self.read_log());
+
+        let mut system = try!(self.read_system());
+        let stages = if let Some(ref path) = self.restart {
+            let info = try!(self.read_checkpoint_system(path, &mut system));
+            let count = try!(self.simulation_tables()).len();
+            if info.stage >= count {
+                return Err(Error::from(format!(
+                    "Invalid checkpoint file '{}': it was written by simulation stage {}, \
+                    but the input only contains {} stages",
+                    path.display(), info.stage + 1, count
+                )));
+            }
+
+            // Skip all the stages finished before the checkpoint, without
+            // creating them to keep their output files.
+            let step = system.step();
+            if info.finished || step >= info.end {
+                if info.stage + 1 == count {
+                    return Err(Error::from(format!(
+                        "The simulation in the checkpoint file '{}' is already \
+                        finished, there is nothing to restart", path.display()
+                    )));
+                }
+                try!(self.read_stages(info.stage + 1))
+            } else {
+                // Continue the stage in progress from the checkpoint
+                let mut stages = try!(self.read_stages(info.stage));
+                {
+                    let stage = &mut stages[0];
+                    try!(self.read_checkpoint(path, &mut system, &mut stage.simulation));
+                    stage.nsteps = (info.end - step) as usize;
+                    stage.velocities = None;
+                }
+                stages
+            }
+        } else {
+            try!(self.read_stages(0))
+        };
+
+        for stage in &stages {
+            try!(check_propagator(stage.simulation.propagator(), &system));
+        }
+
+        let mut stages = stages.into_iter();
+        let mut first = stages.next().expect("missing simulation stage");
+        if let Some(ref mut velocities) = first.velocities {
+            velocities.init(&mut system);
+        }
 
         Ok(Config {
             system: system,
-            simulation: simulation,
-            nsteps: nsteps,
+            simulation: first.simulation,
+            nsteps: first.nsteps,
+            stages: stages.collect(),
         })
     }
 }
 
-fn get_input_path<P1: AsRef<Path>, P2: AsRef<Path>>(root: P1, path: P2) -> PathBuf {
+impl Input {
+    /// Find the position of the key associated with `error` in this input
+    /// file.
+    fn locate(&self, error: Error) -> Error {
+        error::locate(error, &self.sources)
+    }
+
+    /// Get a copy of the `config` table, with the paths to the output files in
+    /// `keys` updated to use the output directory.
+    fn output_table(&self, config: &Table, keys: &[&str]) -> Table {
+        let mut config = config.clone();
+        for key in keys {
+            let path = match config.get(*key) {
+                Some(&Value::String(ref path)) => self.output_path(path),
+                _ => continue,
+            };
+            let path = Value::String(path.to_string_lossy().into_owned());
+            let _ = config.insert(String::from(*key), path);
+        }
+        return config;
+    }
+
+    /// Get the path to use for the output file at `path` in the input file,
+    /// taking the output directory into account.
+    fn output_path(&self, path: &str) -> PathBuf {
+        let path = Path::new(path);
+        match self.output_dir {
+            Some(ref dir) if self.checking => {
+                dir.join(path.file_name().unwrap_or("output".as_ref()))
+            }
+            // Absolute paths are kept as-is by `join`
+            Some(ref dir) => dir.join(path),
+            None => path.to_owned(),
+        }
+    }
+}
+
+pub fn get_input_path<P1: AsRef<Path>, P2: AsRef<Path>>(root: P1, path: P2) -> PathBuf {
     let path = PathBuf::from(path.as_ref());
     if path.is_absolute() {
         path
@@ -0,0 +1,124 @@
+// Lumol, an extensible molecular simulation engine
+// Copyright (C) 2015-2016 Lumol's contributors — BSD license
+use toml::Table;
+use std::path::{Path, PathBuf};
+
+use lumol::sim::NudgedElasticBand;
+use lumol::sys::Trajectory;
+use lumol::types::Vector3D;
+use lumol::units;
+
+use error::{Error, Result};
+use {FromTomlWithData, ToToml};
+use extract;
+use insert;
+use simulations::get_input_path;
+use super::min::{read_minimizer, write_minimizer};
+
+impl FromTomlWithData for NudgedElasticBand {
+    type Data = PathBuf;
+    fn from_toml(config: &Table, root: PathBuf) -> Result<NudgedElasticBand> {
+        try!(extract::check_keys(config, &[
+            "type", "initial", "final", "images", "minimizer", "spring", "climbing",
+            "tolerance", "profile", "band"
+        ], "nudged elastic band propagator"));
+        let initial_file = try!(extract::str("initial", config, "nudged elastic band propagator"));
+        let initial_file = get_input_path(&root, initial_file);
+        let initial = try!(read_positions(&initial_file));
+        let last_file = try!(extract::str("final", config, "nudged elastic band propagator"));
+        let last_file = get_input_path(&root, last_file);
+        let last = try!(read_positions(&last_file));
+        if initial.len() != last.len() {
+            return Err(Error::from(
+                "'initial' and 'final' configurations must contain the same \
+                number of atoms in nudged elastic band propagator"
+            ));
+        }
+
+        let images = try!(extract::uint("images", config, "nudged elastic band propagator"));
+        if images < 3 {
+            return Err(Error::from(
+                "'images' must be at least 3 in nudged elastic band propagator"
+            ));
+        }
+
+        let minimizer = try!(extract::table("minimizer", config, "nudged elastic band propagator"));
+        if try!(extract::typ(minimizer, "minimizer")) == "CellRelaxation" {
+            return Err(Error::from(
+                "'CellRelaxation' minimizer can not be used in nudged elastic band propagator"
+            ));
+        }
+        let mut minimizers = Vec::new();
+        for _ in 0..(images - 2) {
+            minimizers.push(try!(read_minimizer(minimizer)));
+        }
+
+        let mut neb = NudgedElasticBand::new(initial, last, minimizers);
+        neb.set_configuration_files(initial_file, last_file);
+
+        if config.contains_key("spring") {
+            let spring = try!(extract::str("spring", config, "nudged elastic band propagator"));
+            neb.set_spring(try!(units::from_str(spring)));
+        }
+
+        if let Some(climbing) = config.get("climbing") {
+            let climbing = try!(climbing.as_bool().ok_or(Error::from(
+                "'climbing' should be a boolean value in nudged elastic band propagator"
+            )));
+            neb.set_climbing(climbing);
+        }
+
+        if config.contains_key("tolerance") {
+            let tolerance = try!(extract::str("tolerance", config, "nudged elastic band propagator"));
+            neb.set_tolerance(try!(units::from_str(tolerance)));
+        }
+
+        if config.contains_key("profile") {
+            let profile = try!(extract::str("profile", config, "nudged elastic band propagator"));
+            try_io!(neb.set_profile_output(profile), PathBuf::from(profile));
+        }
+
+        if config.contains_key("band") {
+            let band = try!(extract::str("band", config, "nudged elastic band propagator"));
+            try!(neb.set_band_output(band));
+        }
+
+        Ok(neb)
+    }
+}
+
+/// Read the positions of the atoms in the first frame of the trajectory at
+/// `path`.
+fn read_positions(path: &Path) -> Result<Vec<Vector3D>> {
+    let mut trajectory = try!(Trajectory::open(path));
+    let system = try!(trajectory.read());
+    Ok(system.iter().map(|particle| particle.position).collect())
+}
+
+/******************************************************************************/
+
+impl ToToml for NudgedElasticBand {
+    fn to_toml(&self) -> Result<Table> {
+        let (initial, last) = try!(self.configuration_files().ok_or(Error::from(
+            "Can not convert nudged elastic band without configuration files to TOML"
+        )));
+
+        let mut config = insert::typ("NudgedElasticBand");
+        try!(insert::path("initial", initial, &mut config, "nudged elastic band propagator"));
+        try!(insert::path("final", last, &mut config, "nudged elastic band propagator"));
+        let minimizers = self.minimizers();
+        insert::uint("images", minimizers.len() as u64 + 2, &mut config);
+        // All the images use the same kind of minimizer
+        insert::table("minimizer", try!(write_minimizer(&*minimizers[0])), &mut config);
+        insert::quantity("spring", self.spring(), &mut config);
+        insert::bool("climbing", self.climbing(), &mut config);
+        insert::quantity("tolerance", self.tolerance(), &mut config);
+        if let Some(profile) = self.profile_output() {
+            try!(insert::path("profile", profile, &mut config, "nudged elastic band propagator"));
+        }
+        if let Some(band) = self.band_output() {
+            try!(insert::path("band", band, &mut config, "nudged elastic band propagator"));
+        }
+        Ok(config)
+    }
+}
@@ -1,23 +1,29 @@
 // Lumol, an extensible molecular simulation engine
 // Copyright (C) 2015-2016 Lumol's contributors — BSD license
-use toml::Table;
-use std::path::PathBuf;
+use toml::{Table, Value};
+use std::path::{Path, PathBuf};
 
 use lumol::out::Output;
 use lumol::out::{TrajectoryOutput, CellOutput, EnergyOutput, PropertiesOutput};
+use lumol::out::{NormalModesOutput, RdfOutput, MsdOutput};
+use lumol::out::{VacfOutput, ViscosityOutput, ConductivityOutput};
+use lumol::out::{StructureFactorOutput, FluctuationsOutput};
+use lumol::out::{CustomOutput, CustomOutputError};
+use lumol::out::{EnergyBreakdownOutput, DensityProfileOutput};
+use lumol::units;
 
 use error::{Error, Result};
-use FromToml;
+use {FromToml, ToToml};
 use extract;
+use insert;
 use super::Input;
 
 impl Input {
-    /// Get the the simulation outputs. This is an internal function, public
-    /// because of the code organization.
+    /// Get the the simulation outputs from the `config` simulation table.
+    /// This is an internal function, public because of the code organization.
     // TODO: use restricted privacy here
     #[doc(hidden)]
-    pub fn read_outputs(&self) -> Result<Vec<(Box<Output>, u64)>> {
-        let config = try!(self.simulation_table());
+    pub fn read_outputs(&self, config: &Table) -> Result<Vec<(Box<Output>, u64)>> {
         if let Some(outputs) = config.get("outputs") {
             let outputs = try!(outputs.as_slice().ok_or(
                 Error::from("'outputs' must be an array of tables in simulation")
@@ -38,14 +44,28 @@ impl Input {
                     None => 1u64
                 };
 
-                let output: Box<Output> = match try!(extract::typ(output, "output")) {
-                    "Trajectory" | "trajectory" => Box::new(try!(TrajectoryOutput::from_toml(output))),
-                    "Energy" | "energy" => Box::new(try!(EnergyOutput::from_toml(output))),
-                    "Cell" | "cell" => Box::new(try!(CellOutput::from_toml(output))),
-                    "Properties" | "properties" => Box::new(try!(PropertiesOutput::from_toml(output))),
-                    other => {
+                let output = &self.output_table(output, &["file", "summary", "modes"]);
+                // Output types are case insensitive
+                let typ = try!(extract::typ(output, "output"));
+                let output: Box<Output> = match &*typ.to_lowercase() {
+                    "trajectory" => Box::new(try!(TrajectoryOutput::from_toml(output))),
+                    "energy" => Box::new(try!(EnergyOutput::from_toml(output))),
+                    "energybreakdown" => Box::new(try!(EnergyBreakdownOutput::from_toml(output))),
+                    "cell" => Box::new(try!(CellOutput::from_toml(output))),
+                    "properties" => Box::new(try!(PropertiesOutput::from_toml(output))),
+                    "normalmodes" => Box::new(try!(NormalModesOutput::from_toml(output))),
+                    "rdf" => Box::new(try!(RdfOutput::from_toml(output))),
+                    "custom" => Box::new(try!(CustomOutput::from_toml(output))),
+                    "fluctuations" => Box::new(try!(FluctuationsOutput::from_toml(output))),
+                    "structurefactor" => Box::new(try!(StructureFactorOutput::from_toml(output))),
+                    "densityprofile" => Box::new(try!(DensityProfileOutput::from_toml(output))),
+                    "msd" => Box::new(try!(MsdOutput::from_toml(output))),
+                    "vacf" => Box::new(try!(VacfOutput::from_toml(output))),
+                    "viscosity" => Box::new(try!(ViscosityOutput::from_toml(output))),
+                    "conductivity" => Box::new(try!(ConductivityOutput::from_toml(output))),
+                    _ => {
                         return Err(Error::from(
-                            format!("Unknown output type '{}'", other)
+                            format!("Unknown output type '{}'", typ)
                         ))
                     }
                 };
@@ -59,6 +79,16 @@ impl Input {
     }
 }
 
+/// Keys shared by all the outputs
+const OUTPUT_KEYS: &'static [&'static str] = &["type", "frequency", "file"];
+
+/// Check the keys in the output `config` table, which can contain the keys
+/// shared by all outputs and the `specific` keys of this output.
+fn check_output_keys(config: &Table, specific: &[&str], context: &str) -> Result<()> {
+    let known = OUTPUT_KEYS.iter().chain(specific).cloned().collect::<Vec<_>>();
+    extract::check_keys(config, &known, context)
+}
+
 fn get_file(config: &Table) -> Result<&str> {
     let file = try!(config.get("file").ok_or(
         Error::from("Missing 'file' key in output")
@@ -71,6 +101,7 @@ fn get_file(config: &Table) -> Result<&str> {
 
 impl FromToml for TrajectoryOutput {
     fn from_toml(config: &Table) -> Result<TrajectoryOutput> {
+        try!(check_output_keys(config, &[], "trajectory output"));
         let path = try!(get_file(config));
         let output = try!(TrajectoryOutput::new(path));
         Ok(output)
@@ -79,6 +110,7 @@ impl FromToml for TrajectoryOutput {
 
 impl FromToml for CellOutput {
     fn from_toml(config: &Table) -> Result<CellOutput> {
+        try!(check_output_keys(config, &[], "cell output"));
         let path = try!(get_file(config));
         let output = try_io!(CellOutput::new(path), PathBuf::from(path));
         Ok(output)
@@ -87,16 +119,411 @@ impl FromToml for CellOutput {
 
 impl FromToml for EnergyOutput {
     fn from_toml(config: &Table) -> Result<EnergyOutput> {
+        try!(check_output_keys(config, &["summary"], "energy output"));
+        let path = try!(get_file(config));
+        let mut output = try_io!(EnergyOutput::new(path), PathBuf::from(path));
+        if config.contains_key("summary") {
+            let summary = try!(extract::str("summary", config, "energy output"));
+            try_io!(output.set_summary(summary), PathBuf::from(summary));
+        }
+        Ok(output)
+    }
+}
+
+impl FromToml for EnergyBreakdownOutput {
+    fn from_toml(config: &Table) -> Result<EnergyBreakdownOutput> {
+        try!(check_output_keys(config, &[], "energy breakdown output"));
         let path = try!(get_file(config));
-        let output = try_io!(EnergyOutput::new(path), PathBuf::from(path));
+        let output = try_io!(EnergyBreakdownOutput::new(path), PathBuf::from(path));
         Ok(output)
     }
 }
 
 impl FromToml for PropertiesOutput {
     fn from_toml(config: &Table) -> Result<PropertiesOutput> {
+        try!(check_output_keys(config, &["summary"], "properties output"));
+        let path = try!(get_file(config));
+        let mut output = try_io!(PropertiesOutput::new(path), PathBuf::from(path));
+        if config.contains_key("summary") {
+            let summary = try!(extract::str("summary", config, "properties output"));
+            try_io!(output.set_summary(summary), PathBuf::from(summary));
+        }
+        Ok(output)
+    }
+}
+
+impl FromToml for CustomOutput {
+    fn from_toml(config: &Table) -> Result<CustomOutput> {
+        try!(check_output_keys(config, &["columns"], "custom output"));
+        let path = try!(get_file(config));
+        let columns = try!(extract::slice("columns", config, "custom output"));
+        let columns = try!(columns.iter().map(|column| column.as_str()).collect::<Option<Vec<_>>>().ok_or(
+            Error::from("'columns' must be an array of strings in custom output")
+        ));
+        if columns.is_empty() {
+            return Err(Error::from("'columns' can not be empty in custom output"));
+        }
+
+        match CustomOutput::new(path, &columns) {
+            Ok(output) => Ok(output),
+            Err(CustomOutputError::Io(err)) => Err(Error::from((err, PathBuf::from(path)))),
+            Err(CustomOutputError::Unit(err)) => Err(Error::from(err)),
+            Err(CustomOutputError::Expr(err)) => Err(Error::from(
+                format!("invalid column in custom output: {}", err)
+            )),
+        }
+    }
+}
+
+impl FromToml for FluctuationsOutput {
+    fn from_toml(config: &Table) -> Result<FluctuationsOutput> {
+        try!(check_output_keys(config, &[], "fluctuations output"));
+        let path = try!(get_file(config));
+        let output = try_io!(FluctuationsOutput::new(path), PathBuf::from(path));
+        Ok(output)
+    }
+}
+
+impl FromToml for NormalModesOutput {
+    fn from_toml(config: &Table) -> Result<NormalModesOutput> {
+        try!(check_output_keys(config, &["modes", "amplitude"], "normal modes output"));
+        let path = try!(get_file(config));
+        let mut output = try_io!(NormalModesOutput::new(path), PathBuf::from(path));
+        if config.contains_key("modes") {
+            let modes = try!(extract::str("modes", config, "normal modes output"));
+            let amplitude = if config.contains_key("amplitude") {
+                let amplitude = try!(extract::str("amplitude", config, "normal modes output"));
+                try!(units::from_str(amplitude))
+            } else {
+                try!(units::from(0.5, "A"))
+            };
+            try!(output.set_modes_output(modes, amplitude));
+        } else if config.contains_key("amplitude") {
+            warn!("'amplitude' key does nothing without 'modes' in normal modes output");
+        }
+        Ok(output)
+    }
+}
+
+impl FromToml for RdfOutput {
+    fn from_toml(config: &Table) -> Result<RdfOutput> {
+        try!(check_output_keys(config, &["max", "width", "pair"], "rdf output"));
+        let path = try!(get_file(config));
+        let max = try!(extract::str("max", config, "rdf output"));
+        let max = try!(units::from_str(max));
+        let width = if config.contains_key("width") {
+            let width = try!(extract::str("width", config, "rdf output"));
+            try!(units::from_str(width))
+        } else {
+            try!(units::from(0.1, "A"))
+        };
+
+        if width <= 0.0 {
+            return Err(Error::from("'width' must be positive in rdf output"));
+        }
+        if max < width {
+            return Err(Error::from("'max' must be larger than 'width' in rdf output"));
+        }
+
+        let mut output = try_io!(RdfOutput::new(path, max, width), PathBuf::from(path));
+        if config.contains_key("pair") {
+            let pair = try!(extract::slice("pair", config, "rdf output"));
+            let pair = pair.iter().map(|name| name.as_str()).collect::<Option<Vec<_>>>();
+            match pair {
+                Some(ref pair) if pair.len() == 2 => output.set_pair(pair[0], pair[1]),
+                _ => return Err(Error::from(
+                    "'pair' must be an array of two strings in rdf output"
+                ))
+            }
+        }
+        Ok(output)
+    }
+}
+
+impl FromToml for StructureFactorOutput {
+    fn from_toml(config: &Table) -> Result<StructureFactorOutput> {
+        try!(check_output_keys(config, &["max", "width"], "structure factor output"));
+        let path = try!(get_file(config));
+        let max = try!(extract::str("max", config, "structure factor output"));
+        let max = try!(units::from_str(max));
+        let width = if config.contains_key("width") {
+            let width = try!(extract::str("width", config, "structure factor output"));
+            try!(units::from_str(width))
+        } else {
+            try!(units::from(0.05, "A^-1"))
+        };
+
+        if width <= 0.0 {
+            return Err(Error::from("'width' must be positive in structure factor output"));
+        }
+        if max < width {
+            return Err(Error::from("'max' must be larger than 'width' in structure factor output"));
+        }
+
+        Ok(try_io!(StructureFactorOutput::new(path, max, width), PathBuf::from(path)))
+    }
+}
+
+impl FromToml for DensityProfileOutput {
+    fn from_toml(config: &Table) -> Result<DensityProfileOutput> {
+        try!(check_output_keys(config, &["axis", "bins"], "density profile output"));
         let path = try!(get_file(config));
-        let output = try_io!(PropertiesOutput::new(path), PathBuf::from(path));
+        let axis = if config.contains_key("axis") {
+            match try!(extract::str("axis", config, "density profile output")) {
+                "a" => 0,
+                "b" => 1,
+                "c" => 2,
+                other => return Err(Error::from(format!(
+                    "'axis' must be 'a', 'b' or 'c' in density profile output, got '{}'", other
+                )))
+            }
+        } else {
+            2
+        };
+        let bins = if config.contains_key("bins") {
+            try!(extract::uint("bins", config, "density profile output"))
+        } else {
+            100
+        };
+        if bins == 0 {
+            return Err(Error::from("'bins' must be positive in density profile output"));
+        }
+
+        Ok(try_io!(DensityProfileOutput::new(path, axis, bins as usize), PathBuf::from(path)))
+    }
+}
+
+impl FromToml for MsdOutput {
+    fn from_toml(config: &Table) -> Result<MsdOutput> {
+        try!(check_output_keys(config, &["timestep", "lags", "molecules"], "msd output"));
+        let path = try!(get_file(config));
+        let timestep = try!(extract::str("timestep", config, "msd output"));
+        let timestep = try!(units::from_str(timestep));
+        let lags = if config.contains_key("lags") {
+            try!(extract::uint("lags", config, "msd output"))
+        } else {
+            100
+        };
+        if lags == 0 {
+            return Err(Error::from("'lags' must be positive in msd output"));
+        }
+
+        let mut output = try_io!(MsdOutput::new(path, timestep, lags as usize), PathBuf::from(path));
+        if let Some(molecules) = config.get("molecules") {
+            let molecules = try!(molecules.as_bool().ok_or(
+                Error::from("'molecules' should be a boolean value in msd output")
+            ));
+            output.set_molecules(molecules);
+        }
         Ok(output)
     }
 }
+
+/// Read the parameters shared by all Green-Kubo outputs: the file path, the
+/// simulation timestep and the number of levels in the correlator.
+fn read_green_kubo<'a>(config: &'a Table, context: &str) -> Result<(&'a str, f64, usize)> {
+    try!(check_output_keys(config, &["timestep", "levels"], context));
+    let path = try!(get_file(config));
+    let timestep = try!(extract::str("timestep", config, context));
+    let timestep = try!(units::from_str(timestep));
+    let levels = if config.contains_key("levels") {
+        try!(extract::uint("levels", config, context))
+    } else {
+        12
+    };
+    if levels == 0 {
+        return Err(Error::from(format!("'levels' must be positive in {}", context)));
+    }
+    Ok((path, timestep, levels as usize))
+}
+
+impl FromToml for VacfOutput {
+    fn from_toml(config: &Table) -> Result<VacfOutput> {
+        let (path, timestep, levels) = try!(read_green_kubo(config, "vacf output"));
+        Ok(try_io!(VacfOutput::new(path, timestep, levels), PathBuf::from(path)))
+    }
+}
+
+impl FromToml for ViscosityOutput {
+    fn from_toml(config: &Table) -> Result<ViscosityOutput> {
+        let (path, timestep, levels) = try!(read_green_kubo(config, "viscosity output"));
+        Ok(try_io!(ViscosityOutput::new(path, timestep, levels), PathBuf::from(path)))
+    }
+}
+
+impl FromToml for ConductivityOutput {
+    fn from_toml(config: &Table) -> Result<ConductivityOutput> {
+        let (path, timestep, levels) = try!(read_green_kubo(config, "conductivity output"));
+        Ok(try_io!(ConductivityOutput::new(path, timestep, levels), PathBuf::from(path)))
+    }
+}
+
+/******************************************************************************/
+
+impl<'a> ToToml for Output + 'a {
+    /// Convert the output to TOML. The output frequency is managed by the
+    /// simulation, and is added to the table when converting the simulation.
+    fn to_toml(&self) -> Result<Table> {
+        macro_rules! dispatch {
+            ($($Output: ty),*) => (
+                $(if let Some(output) = self.downcast_ref::<$Output>() {
+                    return output.to_toml();
+                })*
+            );
+        }
+        dispatch!(
+            TrajectoryOutput, CellOutput, EnergyOutput, EnergyBreakdownOutput,
+            PropertiesOutput, CustomOutput, FluctuationsOutput, NormalModesOutput,
+            RdfOutput, StructureFactorOutput, DensityProfileOutput, MsdOutput,
+            VacfOutput, ViscosityOutput, ConductivityOutput
+        );
+        Err(Error::from("Can not convert this output to TOML"))
+    }
+}
+
+/// Create the TOML table for an output with the given `typ`, writing to `path`
+fn output_table(typ: &str, path: &Path) -> Result<Table> {
+    let mut config = insert::typ(typ);
+    try!(insert::path("file", path, &mut config, "output"));
+    Ok(config)
+}
+
+impl ToToml for TrajectoryOutput {
+    fn to_toml(&self) -> Result<Table> {
+        output_table("Trajectory", self.path())
+    }
+}
+
+impl ToToml for CellOutput {
+    fn to_toml(&self) -> Result<Table> {
+        output_table("Cell", self.path())
+    }
+}
+
+impl ToToml for EnergyOutput {
+    fn to_toml(&self) -> Result<Table> {
+        let mut config = try!(output_table("Energy", self.path()));
+        if let Some(summary) = self.summary() {
+            try!(insert::path("summary", summary, &mut config, "energy output"));
+        }
+        Ok(config)
+    }
+}
+
+impl ToToml for EnergyBreakdownOutput {
+    fn to_toml(&self) -> Result<Table> {
+        output_table("EnergyBreakdown", self.path())
+    }
+}
+
+impl ToToml for PropertiesOutput {
+    fn to_toml(&self) -> Result<Table> {
+        let mut config = try!(output_table("Properties", self.path()));
+        if let Some(summary) = self.summary() {
+            try!(insert::path("summary", summary, &mut config, "properties output"));
+        }
+        Ok(config)
+    }
+}
+
+impl ToToml for CustomOutput {
+    fn to_toml(&self) -> Result<Table> {
+        let mut config = try!(output_table("Custom", self.path()));
+        let columns = self.columns().iter()
+                                    .map(|&column| Value::String(String::from(column)))
+                                    .collect();
+        let _ = config.insert(String::from("columns"), Value::Array(columns));
+        Ok(config)
+    }
+}
+
+impl ToToml for FluctuationsOutput {
+    fn to_toml(&self) -> Result<Table> {
+        output_table("Fluctuations", self.path())
+    }
+}
+
+impl ToToml for NormalModesOutput {
+    fn to_toml(&self) -> Result<Table> {
+        let mut config = try!(output_table("NormalModes", self.path()));
+        if let Some((modes, amplitude)) = self.modes() {
+            try!(insert::path("modes", modes, &mut config, "normal modes output"));
+            insert::quantity("amplitude", amplitude, &mut config);
+        }
+        Ok(config)
+    }
+}
+
+impl ToToml for RdfOutput {
+    fn to_toml(&self) -> Result<Table> {
+        let mut config = try!(output_table("Rdf", self.path()));
+        insert::quantity("max", self.max(), &mut config);
+        insert::quantity("width", self.width(), &mut config);
+        if let Some((first, second)) = self.pair() {
+            let pair = vec![Value::String(String::from(first)), Value::String(String::from(second))];
+            let _ = config.insert(String::from("pair"), Value::Array(pair));
+        }
+        Ok(config)
+    }
+}
+
+impl ToToml for StructureFactorOutput {
+    fn to_toml(&self) -> Result<Table> {
+        let mut config = try!(output_table("StructureFactor", self.path()));
+        insert::quantity("max", self.max(), &mut config);
+        insert::quantity("width", self.width(), &mut config);
+        Ok(config)
+    }
+}
+
+impl ToToml for DensityProfileOutput {
+    fn to_toml(&self) -> Result<Table> {
+        let mut config = try!(output_table("DensityProfile", self.path()));
+        let axis = match self.axis() {
+            0 => "a",
+            1 => "b",
+            2 => "c",
+            _ => unreachable!(),
+        };
+        insert::str("axis", axis, &mut config);
+        insert::uint("bins", self.bins() as u64, &mut config);
+        Ok(config)
+    }
+}
+
+impl ToToml for MsdOutput {
+    fn to_toml(&self) -> Result<Table> {
+        let mut config = try!(output_table("Msd", self.path()));
+        insert::quantity("timestep", self.timestep(), &mut config);
+        insert::uint("lags", self.lags() as u64, &mut config);
+        insert::bool("molecules", self.molecules(), &mut config);
+        Ok(config)
+    }
+}
+
+impl ToToml for VacfOutput {
+    fn to_toml(&self) -> Result<Table> {
+        let mut config = try!(output_table("Vacf", self.path()));
+        insert::quantity("timestep", self.timestep(), &mut config);
+        insert::uint("levels", self.levels() as u64, &mut config);
+        Ok(config)
+    }
+}
+
+impl ToToml for ViscosityOutput {
+    fn to_toml(&self) -> Result<Table> {
+        let mut config = try!(output_table("Viscosity", self.path()));
+        insert::quantity("timestep", self.timestep(), &mut config);
+        insert::uint("levels", self.levels() as u64, &mut config);
+        Ok(config)
+    }
+}
+
+impl ToToml for ConductivityOutput {
+    fn to_toml(&self) -> Result<Table> {
+        let mut config = try!(output_table("Conductivity", self.path()));
+        insert::quantity("timestep", self.timestep(), &mut config);
+        insert::uint("levels", self.levels() as u64, &mut config);
+        Ok(config)
+    }
+}
@@ -1,33 +1,113 @@
 // Lumol, an extensible molecular simulation engine
 // Copyright (C) 2015-2016 Lumol's contributors — BSD license
-use lumol::sim::{Propagator, MolecularDynamics, MonteCarlo, Minimization};
+use toml::Table;
+use lumol::sim::{Propagator, MolecularDynamics, MonteCarlo, Minimization, NudgedElasticBand};
+use lumol::sim::min::{CellRelaxation, CellDeformation};
+use lumol::sys::{System, CellShape};
 
 use error::{Error, Result};
-use {FromToml, FromTomlWithData};
+use {FromToml, FromTomlWithData, ToToml};
 use extract;
 use super::Input;
+use super::rng::{read_rng, RngUse};
 
 impl Input {
-    /// Get the the simulation propagator. This is an internal function, public
+    /// Get the the simulation propagator from the `config` table of the
+    /// simulation stage at index `stage`. This is an internal function, public
     /// because of the code organization.
     // TODO: use restricted privacy here
     #[doc(hidden)]
-    pub fn read_propagator(&self) -> Result<Box<Propagator>> {
-        let config = try!(self.simulation_table());
+    pub fn read_propagator(&self, stage: usize, config: &Table) -> Result<Box<Propagator>> {
         let propagator = try!(extract::table("propagator", config, "simulation"));
+        let rng = try!(read_rng(config, "simulation", self.seed, RngUse::Propagator(stage)));
         match try!(extract::typ(propagator, "propagator")) {
-            "MolecularDynamics" => Ok(Box::new(try!(
-                MolecularDynamics::from_toml(propagator)
-            ))),
-            "MonteCarlo" => Ok(Box::new(try!(
-                MonteCarlo::from_toml(propagator, self.path.clone())
-            ))),
-            "Minimization" => Ok(Box::new(try!(
-                Minimization::from_toml(propagator)
-            ))),
+            "MolecularDynamics" => {
+                if config.get("rng").is_some() {
+                    warn!("'rng' key does nothing in molecular dynamics simulations");
+                }
+                Ok(Box::new(try!(MolecularDynamics::from_toml(propagator))))
+            }
+            "MonteCarlo" => {
+                let mut mc = try!(MonteCarlo::from_toml(propagator, self.path.clone()));
+                if let Some(rng) = rng {
+                    mc.set_rng(rng);
+                }
+                Ok(Box::new(mc))
+            }
+            "Minimization" => {
+                if config.get("rng").is_some() {
+                    warn!("'rng' key does nothing in minimization simulations");
+                }
+                Ok(Box::new(try!(Minimization::from_toml(propagator))))
+            }
+            "NudgedElasticBand" => {
+                if config.get("rng").is_some() {
+                    warn!("'rng' key does nothing in nudged elastic band simulations");
+                }
+                let propagator = &self.output_table(propagator, &["profile", "band"]);
+                Ok(Box::new(try!(NudgedElasticBand::from_toml(propagator, self.path.clone()))))
+            }
             other => Err(Error::from(
                 format!("Unknown propagator type '{}'", other)
             ))
         }
     }
 }
+
+/// Check that the `propagator` can be used with the `system`, to report the
+/// errors depending on the system when reading the input instead of failing
+/// when the simulation starts.
+pub fn check_propagator(propagator: &Propagator, system: &System) -> Result<()> {
+    if let Some(minimization) = propagator.downcast_ref::<Minimization>() {
+        if let Some(relaxation) = minimization.minimizer().downcast_ref::<CellRelaxation>() {
+            try!(check_cell_relaxation(relaxation, system));
+        }
+    }
+
+    if let Some(neb) = propagator.downcast_ref::<NudgedElasticBand>() {
+        if neb.size() != system.size() {
+            return Err(Error::from(format!(
+                "'initial' and 'final' configurations contain {} atoms, but the \
+                system contains {} atoms in nudged elastic band propagator",
+                neb.size(), system.size()
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Check that the cell of the `system` can be deformed by the `relaxation`
+fn check_cell_relaxation(relaxation: &CellRelaxation, system: &System) -> Result<()> {
+    match (system.cell().shape(), relaxation.deformation()) {
+        (CellShape::Infinite, _) => Err(Error::from(
+            "Can not use cell relaxation minimizer with an infinite cell"
+        )),
+        (CellShape::Triclinic, CellDeformation::Orthorhombic) => Err(Error::from(
+            "'orthorhombic' cell deformation can only be used with orthorhombic \
+            cells in cell relaxation minimizer"
+        )),
+        (CellShape::Orthorombic, CellDeformation::Triclinic) => Err(Error::from(
+            "'triclinic' cell deformation can only be used with triclinic cells \
+            in cell relaxation minimizer"
+        )),
+        _ => Ok(()),
+    }
+}
+
+/******************************************************************************/
+
+impl<'a> ToToml for Propagator + 'a {
+    fn to_toml(&self) -> Result<Table> {
+        if let Some(md) = self.downcast_ref::<MolecularDynamics>() {
+            md.to_toml()
+        } else if let Some(mc) = self.downcast_ref::<MonteCarlo>() {
+            mc.to_toml()
+        } else if let Some(minimization) = self.downcast_ref::<Minimization>() {
+            minimization.to_toml()
+        } else if let Some(neb) = self.downcast_ref::<NudgedElasticBand>() {
+            neb.to_toml()
+        } else {
+            Err(Error::from("Can not convert this propagator to TOML"))
+        }
+    }
+}
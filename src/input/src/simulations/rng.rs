@@ -0,0 +1,101 @@
+// Lumol, an extensible molecular simulation engine
+// Copyright (C) 2015-2016 Lumol's contributors — BSD license
+use toml::Table;
+
+use lumol::rng::{Random, RngAlgorithm, DEFAULT_SEED, derive_seed};
+
+use error::{Error, Result};
+use {FromToml, ToToml};
+use extract;
+use insert;
+
+impl FromToml for Random {
+    fn from_toml(config: &Table) -> Result<Random> {
+        try!(extract::check_keys(config, &["algorithm", "seed"], "random number generator"));
+        let algorithm = if config.get("algorithm").is_some() {
+            match try!(extract::str("algorithm", config, "random number generator")) {
+                "xorshift" => RngAlgorithm::XorShift,
+                "pcg32" => RngAlgorithm::Pcg32,
+                other => return Err(Error::from(
+                    format!("Unknown random number generator algorithm '{}'", other)
+                ))
+            }
+        } else {
+            RngAlgorithm::XorShift
+        };
+
+        let seed = if config.get("seed").is_some() {
+            try!(extract::uint("seed", config, "random number generator"))
+        } else {
+            DEFAULT_SEED
+        };
+
+        Ok(Random::new(algorithm, seed))
+    }
+}
+
+/// The different random number generators created from an input file. When
+/// the seed is overridden with `Input::set_seed`, each generator uses its own
+/// seed derived from the overriding one, so that all the generators produce
+/// different sequences of numbers.
+#[derive(Clone, Copy, Debug)]
+pub enum RngUse {
+    /// Initialization of the velocities in the system
+    SystemVelocities,
+    /// Propagator of the simulation stage at the given index
+    Propagator(usize),
+    /// Initialization of the velocities in the simulation stage at the given
+    /// index
+    StageVelocities(usize),
+}
+
+impl RngUse {
+    /// Get the stream used to derive the seed for this generator
+    fn stream(&self) -> u64 {
+        match *self {
+            RngUse::SystemVelocities => 0,
+            RngUse::Propagator(stage) => 2 * stage as u64 + 1,
+            RngUse::StageVelocities(stage) => 2 * stage as u64 + 2,
+        }
+    }
+}
+
+/// Read the optional random number generator in the `rng` key of `config`,
+/// interpreted as a `context` and used for `usage`. If `seed` is not `None`,
+/// it overrides the seed given in the configuration, after being mixed with
+/// the `usage`.
+pub fn read_rng(config: &Table, context: &str, seed: Option<u64>, usage: RngUse) -> Result<Option<Random>> {
+    let rng = match config.get("rng") {
+        Some(rng) => {
+            let rng = try!(rng.as_table().ok_or(Error::from(
+                format!("'rng' must be a table in {}", context)
+            )));
+            Some(try!(Random::from_toml(rng)))
+        }
+        None => None,
+    };
+
+    if let Some(seed) = seed {
+        let algorithm = rng.map_or(RngAlgorithm::XorShift, |rng| rng.algorithm());
+        Ok(Some(Random::new(algorithm, derive_seed(seed, usage.stream()))))
+    } else {
+        Ok(rng)
+    }
+}
+
+/******************************************************************************/
+
+impl ToToml for Random {
+    /// Convert the random number generator to TOML. Only generators created
+    /// from a seed can be converted, since the seed is used to create them
+    /// again when reading the input.
+    fn to_toml(&self) -> Result<Table> {
+        let seed = try!(self.seed().ok_or(Error::from(
+            "Can not convert a random number generator without seed to TOML"
+        )));
+        let mut config = Table::new();
+        insert::str("algorithm", self.algorithm().name(), &mut config);
+        insert::uint("seed", seed, &mut config);
+        Ok(config)
+    }
+}
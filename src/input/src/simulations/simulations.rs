@@ -1,34 +1,133 @@
 // Lumol, an extensible molecular simulation engine
 // Copyright (C) 2015-2016 Lumol's contributors — BSD license
-use toml::Table;
-use lumol::sim::Simulation;
+use toml::{Table, Value};
+use std::path::Path;
+
+use lumol::sim::{Simulation, RunInfo, MonteCarlo};
+use lumol::sys::System;
+use lumol::sys::veloc::{BoltzmannVelocities, InitVelocities};
+use lumol::checkpoint::CheckpointError;
+use lumol::units;
 
 use error::{Error, Result};
+use ToToml;
 use extract;
-use super::Input;
+use insert;
+use super::{Input, Stage};
+use super::rng::{read_rng, RngUse};
 
 impl Input {
-    /// Get the the simulation. This is an internal function, public because of
-    /// the code organization.
+    /// Get the stages of the simulation starting with the stage at index
+    /// `first`, in the order they should run. The previous stages are not
+    /// created, and their output files are left untouched. This is an
+    /// internal function, public because of the code organization.
+    // TODO: use restricted privacy here
+    #[doc(hidden)]
+    pub fn read_stages(&self, first: usize) -> Result<Vec<Stage>> {
+        let mut stages = Vec::new();
+        for (i, config) in try!(self.simulation_tables()).into_iter().enumerate().skip(first) {
+            let mut stage = try!(self.read_stage(i, config));
+            stage.simulation.set_stage(i);
+            stages.push(stage);
+        }
+        Ok(stages)
+    }
+
+    /// Get the simulation stage at index `stage`, corresponding to the
+    /// `config` simulation table. This is an internal function, public because
+    /// of the code organization.
+    // TODO: use restricted privacy here
+    #[doc(hidden)]
+    pub fn read_stage(&self, stage: usize, config: &Table) -> Result<Stage> {
+        try!(extract::check_keys(config, &[
+            "nsteps", "propagator", "outputs", "checkpoint", "velocities", "stop", "rng"
+        ], "simulation"));
+        Ok(Stage {
+            simulation: try!(self.read_simulation(stage, config)),
+            nsteps: try!(self.read_nsteps(config)),
+            velocities: try!(self.read_stage_velocities(stage, config)),
+        })
+    }
+
+    /// Get the the simulation corresponding to the `config` table of the
+    /// simulation stage at index `stage`. This is an internal function, public
+    /// because of the code organization.
     // TODO: use restricted privacy here
     #[doc(hidden)]
-    pub fn read_simulation(&self) -> Result<Simulation> {
-        let propagator = try!(self.read_propagator());
+    pub fn read_simulation(&self, stage: usize, config: &Table) -> Result<Simulation> {
+        let propagator = try!(self.read_propagator(stage, config));
         let mut simulation = Simulation::new(propagator);
-        for (output, frequency) in try!(self.read_outputs()) {
+        for (output, frequency) in try!(self.read_outputs(config)) {
             simulation.add_output_with_frequency(output, frequency);
         }
 
+        if let Some(checkpoint) = config.get("checkpoint") {
+            let checkpoint = try!(checkpoint.as_table().ok_or(
+                Error::from("'checkpoint' must be a table in simulation")
+            ));
+            try!(extract::check_keys(checkpoint, &["file", "frequency"], "checkpoint"));
+            let file = try!(extract::str("file", checkpoint, "checkpoint"));
+            let frequency = try!(extract::uint("frequency", checkpoint, "checkpoint"));
+            if frequency == 0 {
+                return Err(Error::from("'frequency' must be positive in checkpoint"));
+            }
+            simulation.set_checkpoint(self.output_path(file), frequency);
+        }
+
+        for condition in try!(self.read_stop_conditions(config)) {
+            simulation.add_stop_condition(condition);
+        }
+
         Ok(simulation)
     }
 
-    /// Get the number of steps in the simulation. This is an internal function,
-    /// public because of the code organization.
+    /// Get the optional velocities initializer used at the beginning of the
+    /// simulation stage at index `stage`, in the `config` table.
+    fn read_stage_velocities(&self, stage: usize, config: &Table) -> Result<Option<BoltzmannVelocities>> {
+        if let Some(velocities) = config.get("velocities") {
+            let velocities = try!(velocities.as_table().ok_or(
+                Error::from("'velocities' must be a table in simulation")
+            ));
+            try!(extract::check_keys(velocities, &["init", "rng"], "velocities initializer"));
+
+            let temperature = try!(extract::str("init", velocities, "velocities initializer"));
+            let temperature = try!(units::from_str(temperature));
+            let rng = try!(read_rng(velocities, "velocities initializer", self.seed, RngUse::StageVelocities(stage)));
+
+            let mut initializer = BoltzmannVelocities::new(temperature);
+            if let Some(rng) = rng {
+                initializer.set_rng(rng);
+            }
+            Ok(Some(initializer))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Restart the `simulation` and the `system` from the checkpoint file at
+    /// `path`. This is an internal function, public because of the code
+    /// organization.
+    // TODO: use restricted privacy here
+    #[doc(hidden)]
+    pub fn read_checkpoint(&self, path: &Path, system: &mut System, simulation: &mut Simulation) -> Result<()> {
+        checkpoint_error(simulation.restart(system, path), path)
+    }
+
+    /// Restore only the `system` from the checkpoint file at `path`, and get
+    /// information about the run which wrote it. This is an internal
+    /// function, public because of the code organization.
     // TODO: use restricted privacy here
     #[doc(hidden)]
-    pub fn read_nsteps(&self) -> Result<usize> {
-        let simulation = try!(self.simulation_table());
-        let nsteps = try!(simulation.get("nsteps").ok_or(
+    pub fn read_checkpoint_system(&self, path: &Path, system: &mut System) -> Result<RunInfo> {
+        checkpoint_error(Simulation::restore_system(system, path), path)
+    }
+
+    /// Get the number of steps in the `config` simulation table. This is an
+    /// internal function, public because of the code organization.
+    // TODO: use restricted privacy here
+    #[doc(hidden)]
+    pub fn read_nsteps(&self, config: &Table) -> Result<usize> {
+        let nsteps = try!(config.get("nsteps").ok_or(
             Error::from("Missing 'nsteps' key in simulation")
         ));
 
@@ -36,25 +135,96 @@ impl Input {
             Error::from("'nsteps' key must be an integer")
         ));
 
-        Ok(nsteps as usize)
+        Ok(self.nsteps.unwrap_or(nsteps as usize))
     }
 
-    /// Get the simulation TOML table. This is an internal function, public
-    /// because of the code organization.
+    /// Get all the simulations TOML tables. This is an internal function,
+    /// public because of the code organization.
     // TODO: use restricted privacy here
     #[doc(hidden)]
-    pub fn simulation_table(&self) -> Result<&Table> {
+    pub fn simulation_tables(&self) -> Result<Vec<&Table>> {
         let simulations = try!(extract::slice("simulations", &self.config, "input file"));
-        if simulations.len() != 1 {
+        if simulations.is_empty() {
             return Err(Error::from(
-                "Only one simulation is supported in the input"
+                "'simulations' array should contain a simulation"
             ));
         }
 
-        let simulation = try!(simulations[0].as_table().ok_or(
-            Error::from("Simulations should be tables")
-        ));
+        let mut tables = Vec::new();
+        for simulation in simulations {
+            tables.push(try!(simulation.as_table().ok_or(
+                Error::from("Simulations should be tables")
+            )));
+        }
+
+        return Ok(tables);
+    }
+}
 
-        return Ok(simulation);
+/// Convert a checkpoint error for the file at `path` to an input error
+fn checkpoint_error<T>(result: ::std::result::Result<T, CheckpointError>, path: &Path) -> Result<T> {
+    match result {
+        Ok(value) => Ok(value),
+        Err(CheckpointError::Io(err)) => Err(Error::from((err, path.to_owned()))),
+        Err(CheckpointError::Format(message)) => Err(Error::from(format!(
+            "Invalid checkpoint file '{}': {}", path.display(), message
+        ))),
     }
 }
+
+/******************************************************************************/
+
+impl ToToml for Simulation {
+    /// Convert the simulation to a simulation table. The number of steps and
+    /// the velocities initializer are not part of the simulation, and must be
+    /// added to the table. Hooks can not be used from input files, and are
+    /// not written.
+    fn to_toml(&self) -> Result<Table> {
+        let mut config = Table::new();
+        let propagator = self.propagator();
+        insert::table("propagator", try!(propagator.to_toml()), &mut config);
+        if let Some(mc) = propagator.downcast_ref::<MonteCarlo>() {
+            if mc.rng().seed().is_some() {
+                insert::table("rng", try!(mc.rng().to_toml()), &mut config);
+            } else if !has_default_rng(mc) {
+                return Err(Error::from(
+                    "Can not convert Monte-Carlo random number generator without seed to TOML"
+                ));
+            }
+        }
+
+        let mut outputs = Vec::new();
+        for (output, frequency) in self.outputs() {
+            let mut output = try!(output.to_toml());
+            insert::uint("frequency", frequency, &mut output);
+            outputs.push(Value::Table(output));
+        }
+        if !outputs.is_empty() {
+            let _ = config.insert(String::from("outputs"), Value::Array(outputs));
+        }
+
+        if let Some((path, frequency)) = self.checkpoint() {
+            let mut checkpoint = Table::new();
+            try!(insert::path("file", path, &mut checkpoint, "checkpoint"));
+            insert::uint("frequency", frequency, &mut checkpoint);
+            insert::table("checkpoint", checkpoint, &mut config);
+        }
+
+        let mut conditions = Vec::new();
+        for condition in self.stop_conditions() {
+            conditions.push(Value::Table(try!(condition.to_toml())));
+        }
+        if !conditions.is_empty() {
+            let _ = config.insert(String::from("stop"), Value::Array(conditions));
+        }
+
+        Ok(config)
+    }
+}
+
+/// Check if the Monte-Carlo propagator `mc` still uses the generator created
+/// by `MonteCarlo::new`, which is also used when the input does not contain
+/// a `rng` key.
+fn has_default_rng(mc: &MonteCarlo) -> bool {
+    mc.rng().state() == MonteCarlo::new(mc.temperature()).rng().state()
+}
@@ -0,0 +1,151 @@
+// Lumol, an extensible molecular simulation engine
+// Copyright (C) 2015-2016 Lumol's contributors — BSD license
+use toml::{Table, Value};
+
+use lumol::sim::StopCondition;
+use lumol::sim::stop::{Converged, WallClockLimit, StopFile, Threshold};
+use lumol::out::CustomOutputError;
+use lumol::units;
+
+use error::{Error, Result};
+use {FromToml, ToToml};
+use extract;
+use insert;
+use super::Input;
+
+impl Input {
+    /// Get the the simulation stop conditions from the `config` simulation
+    /// table. This is an internal function, public because of the code
+    /// organization.
+    // TODO: use restricted privacy here
+    #[doc(hidden)]
+    pub fn read_stop_conditions(&self, config: &Table) -> Result<Vec<Box<StopCondition>>> {
+        let mut result = Vec::new();
+        if let Some(conditions) = config.get("stop") {
+            let conditions = try!(conditions.as_slice().ok_or(
+                Error::from("'stop' must be an array of tables in simulation")
+            ));
+
+            for condition in conditions {
+                let condition = try!(condition.as_table().ok_or(
+                    Error::from("'stop' must be an array of tables in simulation")
+                ));
+
+                let condition: Box<StopCondition> = match try!(extract::typ(condition, "stop condition")) {
+                    "Converged" => {
+                        try!(extract::check_keys(condition, &["type"], "converged stop condition"));
+                        Box::new(Converged)
+                    }
+                    "WallClock" => Box::new(try!(WallClockLimit::from_toml(condition))),
+                    "StopFile" => Box::new(try!(StopFile::from_toml(condition))),
+                    "Threshold" => Box::new(try!(Threshold::from_toml(condition))),
+                    other => return Err(Error::from(
+                        format!("Unknown stop condition '{}'", other)
+                    ))
+                };
+                result.push(condition);
+            }
+        }
+        Ok(result)
+    }
+}
+
+impl FromToml for WallClockLimit {
+    fn from_toml(config: &Table) -> Result<WallClockLimit> {
+        try!(extract::check_keys(config, &["type", "limit"], "wall-clock stop condition"));
+        let limit = try!(extract::str("limit", config, "wall-clock stop condition"));
+        let limit = units::to(try!(units::from_str(limit)), "s").expect("bad unit");
+        if limit <= 0.0 {
+            return Err(Error::from("'limit' must be positive in wall-clock stop condition"));
+        }
+        Ok(WallClockLimit::new(limit))
+    }
+}
+
+impl FromToml for StopFile {
+    fn from_toml(config: &Table) -> Result<StopFile> {
+        try!(extract::check_keys(config, &["type", "file"], "stop file condition"));
+        let file = try!(extract::str("file", config, "stop file condition"));
+        Ok(StopFile::new(file))
+    }
+}
+
+impl FromToml for Threshold {
+    fn from_toml(config: &Table) -> Result<Threshold> {
+        try!(extract::check_keys(config, &["type", "property", "above", "below"], "threshold stop condition"));
+        let property = try!(extract::str("property", config, "threshold stop condition"));
+        let threshold = match (config.get("above"), config.get("below")) {
+            (Some(value), None) => Threshold::above(property, try!(threshold_value("above", value))),
+            (None, Some(value)) => Threshold::below(property, try!(threshold_value("below", value))),
+            _ => return Err(Error::from(
+                "threshold stop condition needs exactly one of 'above' or 'below' keys"
+            )),
+        };
+
+        match threshold {
+            Ok(threshold) => Ok(threshold),
+            Err(CustomOutputError::Expr(err)) => Err(Error::from(
+                format!("invalid property in threshold stop condition: {}", err)
+            )),
+            Err(CustomOutputError::Unit(err)) => Err(Error::from(err)),
+            Err(CustomOutputError::Io(..)) => unreachable!(),
+        }
+    }
+}
+
+/// Get the threshold value, either a string with unit or a number in internal
+/// units
+fn threshold_value(key: &str, value: &Value) -> Result<f64> {
+    match *value {
+        Value::String(ref value) => Ok(try!(units::from_str(value))),
+        Value::Integer(value) => Ok(value as f64),
+        Value::Float(value) => Ok(value),
+        _ => Err(Error::from(format!(
+            "'{}' must be a string or a number in threshold stop condition", key
+        ))),
+    }
+}
+
+/******************************************************************************/
+
+impl<'a> ToToml for StopCondition + 'a {
+    fn to_toml(&self) -> Result<Table> {
+        if self.downcast_ref::<Converged>().is_some() {
+            Ok(insert::typ("Converged"))
+        } else if let Some(condition) = self.downcast_ref::<WallClockLimit>() {
+            condition.to_toml()
+        } else if let Some(condition) = self.downcast_ref::<StopFile>() {
+            condition.to_toml()
+        } else if let Some(condition) = self.downcast_ref::<Threshold>() {
+            condition.to_toml()
+        } else {
+            Err(Error::from("Can not convert this stop condition to TOML"))
+        }
+    }
+}
+
+impl ToToml for WallClockLimit {
+    fn to_toml(&self) -> Result<Table> {
+        let mut config = insert::typ("WallClock");
+        insert::str("limit", &format!("{} s", self.limit()), &mut config);
+        Ok(config)
+    }
+}
+
+impl ToToml for StopFile {
+    fn to_toml(&self) -> Result<Table> {
+        let mut config = insert::typ("StopFile");
+        try!(insert::path("file", self.path(), &mut config, "stop file condition"));
+        Ok(config)
+    }
+}
+
+impl ToToml for Threshold {
+    fn to_toml(&self) -> Result<Table> {
+        let mut config = insert::typ("Threshold");
+        insert::str("property", self.property(), &mut config);
+        let key = if self.is_above() { "above" } else { "below" };
+        insert::float(key, self.value(), &mut config);
+        Ok(config)
+    }
+}
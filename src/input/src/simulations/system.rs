@@ -10,14 +10,20 @@ use error::{Error, Result};
 use extract;
 use {Input, InteractionsInput};
 use simulations::get_input_path;
+use super::rng::{read_rng, RngUse};
 
 impl Input {
-    /// Get the the simulated system. This is an internal function, public
-    /// because of the code organization.
-    // TODO: use restricted privacy here
-    #[doc(hidden)]
+    /// Read only the simulated system from this input, without creating the
+    /// simulations and the output files.
     pub fn read_system(&self) -> Result<System> {
+        self.build_system().map_err(|err| self.locate(err))
+    }
+
+    fn build_system(&self) -> Result<System> {
         let config = try!(self.system_table());
+        try!(extract::check_keys(config, &[
+            "file", "cell", "topology", "guess_bonds", "velocities", "potentials"
+        ], "system"));
 
         let file = try!(extract::str("file", config, "system"));
         let file = get_input_path(&self.path, file);
@@ -126,11 +132,17 @@ impl Input {
             let velocities = try!(velocities.as_table().ok_or(
                 Error::from("'velocities' must be a table in system")
             ));
+            try!(extract::check_keys(velocities, &["init", "rng"], "velocities initializer"));
 
             if velocities.get("init").is_some() {
                 let temperature = try!(extract::str("init", velocities, "velocities initializer"));
                 let temperature = try!(units::from_str(temperature));
+                let rng = try!(read_rng(velocities, "velocities initializer", self.seed, RngUse::SystemVelocities));
+
                 let mut velocities = BoltzmannVelocities::new(temperature);
+                if let Some(rng) = rng {
+                    velocities.set_rng(rng);
+                }
                 velocities.init(system);
             } else {
                 warn!("'velocities' key does nothing in this input file");
@@ -0,0 +1,69 @@
+// Lumol, an extensible molecular simulation engine
+// Copyright (C) 2015-2016 Lumol's contributors — BSD license
+
+//! Testing that checking an input file does not create any file in the
+//! current directory. This is in a separate file because the test changes the
+//! current directory of the whole process.
+extern crate test;
+extern crate walkdir;
+extern crate tempdir;
+
+extern crate lumol_input;
+
+use std::{env, fs};
+use std::path::PathBuf;
+
+use walkdir::WalkDir;
+use tempdir::TempDir;
+
+use test::{TestDesc, TestDescAndFn, DynTestName, DynTestFn};
+use test::ShouldPanic::No;
+
+use lumol_input::Input;
+
+fn main() {
+    let tests = vec![TestDescAndFn {
+        desc: TestDesc {
+            name: DynTestName(String::from("no_outputs")),
+            ignore: false,
+            should_panic: No,
+        },
+        testfn: DynTestFn(Box::new(no_outputs)),
+    }];
+
+    let args: Vec<_> = env::args().collect();
+    let opts = match test::parse_opts(&args) {
+        Some(Ok(opts)) => opts,
+        Some(Err(msg)) => panic!("{:?}", msg),
+        None => return,
+    };
+
+    match test::run_tests_console(&opts, tests) {
+        Ok(true) => {}
+        Ok(false) => std::process::exit(-1),
+        Err(err) => panic!("io error when running tests: {:?}", err),
+    }
+}
+
+/// Check all the good input files, and check that no file was created in the
+/// current directory
+fn no_outputs() {
+    let inputs = PathBuf::new().join(env!("CARGO_MANIFEST_DIR"))
+                               .join("tests").join("simulation").join("good");
+    let directory = TempDir::new("lumol-check").unwrap();
+    env::set_current_dir(directory.path()).unwrap();
+
+    for entry in WalkDir::new(inputs) {
+        let entry = entry.unwrap();
+        if entry.file_type().is_file() && entry.path().extension() == Some("toml".as_ref()) {
+            let input = Input::new(entry.path()).unwrap();
+            let errors = input.check();
+            assert!(errors.is_empty(), "unexpected errors in {}: {:?}", entry.path().display(), errors);
+        }
+    }
+
+    let created = fs::read_dir(directory.path()).unwrap()
+                                                .map(|entry| entry.unwrap().file_name())
+                                                .collect::<Vec<_>>();
+    assert!(created.is_empty(), "check() created files in the current directory: {:?}", created);
+}
@@ -3,6 +3,8 @@
 
 extern crate test;
 extern crate walkdir;
+extern crate tempdir;
+extern crate toml;
 
 extern crate lumol;
 extern crate lumol_input;
@@ -13,12 +15,15 @@ use std::fs::File;
 use std::io::prelude::*;
 
 use walkdir::WalkDir;
+use tempdir::TempDir;
+use toml::{Table, Value};
 
 use test::{TestDesc, TestDescAndFn, DynTestName, DynTestFn};
 use test::ShouldPanic::No;
 
 use lumol::sys::System;
-use lumol_input::{InteractionsInput, Input, Error};
+use lumol::sim::{Simulation, MonteCarlo};
+use lumol_input::{InteractionsInput, Input, Error, ToToml};
 
 fn main() {
     let mut tests = Vec::new();
@@ -26,7 +31,10 @@ fn main() {
     tests.extend(generate_tests("simulation/good", |path| {
         Box::new(move || {
             let input = Input::new(path.clone()).unwrap();
+            let errors = input.check();
+            assert!(errors.is_empty(), "unexpected errors: {:?}", errors);
             input.read().unwrap();
+            check_simulation_to_toml(&path);
         })
     }).expect("Could not generate the tests"));
 
@@ -37,9 +45,18 @@ fn main() {
                                .and_then(|input| input.read());
 
             match result {
-                Err(Error::Config(reason)) => assert_eq!(reason, message),
+                Err(ref error) if config_message(error).is_some() => {
+                    assert_eq!(config_message(error).unwrap(), message)
+                }
                 _ => panic!("This test should fail with a Config error")
             }
+
+            if let Ok(input) = Input::new(path.clone()) {
+                let found = input.check().iter().any(|error| {
+                    config_message(error) == Some(&message)
+                });
+                assert!(found, "check() should report '{}'", message);
+            }
         })
     }).expect("Could not generate the tests"));
 
@@ -48,6 +65,7 @@ fn main() {
             let mut system = System::new();
             let input = InteractionsInput::new(path.clone()).unwrap();
             input.read(&mut system).unwrap();
+            check_interactions_to_toml(&system);
         })
     }).expect("Could not generate the tests"));
 
@@ -60,12 +78,41 @@ fn main() {
                                            .and_then(|input| input.read(&mut system));
 
             match result {
-                Err(Error::Config(reason)) => assert_eq!(reason, message),
+                Err(ref error) if config_message(error).is_some() => {
+                    assert_eq!(config_message(error).unwrap(), message)
+                }
                 _ => panic!("This test should fail with a Config error")
             }
         })
     }).expect("Could not generate the tests"));
 
+    tests.push(TestDescAndFn {
+        desc: TestDesc {
+            name: DynTestName(String::from("overrides")),
+            ignore: false,
+            should_panic: No,
+        },
+        testfn: DynTestFn(Box::new(overrides)),
+    });
+
+    tests.push(TestDescAndFn {
+        desc: TestDesc {
+            name: DynTestName(String::from("locations")),
+            ignore: false,
+            should_panic: No,
+        },
+        testfn: DynTestFn(Box::new(locations)),
+    });
+
+    tests.push(TestDescAndFn {
+        desc: TestDesc {
+            name: DynTestName(String::from("includes")),
+            ignore: false,
+            should_panic: No,
+        },
+        testfn: DynTestFn(Box::new(includes)),
+    });
+
     let args: Vec<_> = env::args().collect();
     let mut opts = match test::parse_opts(&args) {
         Some(Ok(opts)) => opts,
@@ -116,9 +163,160 @@ fn generate_tests<F>(group: &str, callback: F) -> Result<Vec<TestDescAndFn>, io:
     Ok(tests)
 }
 
+/// Check the overrides of the number of steps and the output directory
+fn overrides() {
+    let path = PathBuf::new().join(env!("CARGO_MANIFEST_DIR"))
+                             .join("tests").join("simulation").join("good").join("md.toml");
+    let outputs = env::temp_dir().join("lumol-input-overrides");
+
+    let mut input = Input::new(&path).unwrap();
+    input.set_nsteps(42);
+    input.set_output_dir(&outputs);
+    let config = input.read().unwrap();
+    assert_eq!(config.nsteps, 42);
+    assert!(outputs.join("energy.dat").exists());
+
+    let path = path.parent().unwrap().join("neb.toml");
+    let mut input = Input::new(&path).unwrap();
+    input.set_output_dir(&outputs);
+    let _ = input.read().unwrap();
+    assert!(outputs.join("neb-profile.dat").exists());
+    assert!(outputs.join("neb-band.xyz").exists());
+
+    let path = path.parent().unwrap().join("min-6.toml");
+    let mut input = Input::new(&path).unwrap();
+    input.set_output_dir(&outputs);
+    let _ = input.read().unwrap();
+    assert!(outputs.join("modes.dat").exists());
+    assert!(outputs.join("modes.xyz").exists());
+
+    // Each stage uses a different seed derived from the overriding one
+    let path = path.parent().unwrap().join("mc.toml");
+    let mut input = Input::new(path).unwrap();
+    input.set_output_dir(&outputs);
+    input.set_seed(7);
+    let tables = input.simulation_tables().unwrap();
+    let first = input.read_propagator(0, tables[0]).unwrap();
+    let second = input.read_propagator(1, tables[0]).unwrap();
+    let first = first.downcast_ref::<MonteCarlo>().unwrap().rng();
+    let second = second.downcast_ref::<MonteCarlo>().unwrap().rng();
+    assert!(first.seed().is_some());
+    assert!(first.seed() != second.seed());
+    assert!(first.state() != second.state());
+
+    fs::remove_dir_all(&outputs).unwrap();
+}
+
+/// Check the location of errors in the input files
+fn locations() {
+    fn check_location(error: Error, path: &Path, line: usize, column: usize) {
+        let message = String::from(config_message(&error).expect("not a Config error"));
+        match error {
+            Error::Located(_, ref location) => {
+                assert_eq!(location.path, Some(path.to_owned()));
+                assert_eq!(location.position, Some((line, column)));
+            }
+            _ => panic!("missing location for '{}'", message),
+        }
+        let expected = format!("{}:{}:{}: {}", path.display(), line, column, message);
+        assert_eq!(error.to_string(), expected);
+    }
+
+    let root = PathBuf::new().join(env!("CARGO_MANIFEST_DIR")).join("tests");
+
+    let path = root.join("simulation").join("bad").join("system").join("system-16.toml");
+    let error = Input::new(&path).unwrap().read().err().unwrap();
+    check_location(error, &path, 6, 1);
+
+    // Key in an inline table
+    let path = root.join("simulation").join("bad").join("mc").join("move-17.toml");
+    let error = Input::new(&path).unwrap().read().err().unwrap();
+    check_location(error, &path, 14, 41);
+
+    let path = root.join("interactions").join("bad").join("pairs").join("pairs-13.toml");
+    let mut system = System::new();
+    let error = InteractionsInput::new(&path).unwrap().read(&mut system).err().unwrap();
+    check_location(error, &path, 10, 1);
+
+    // Errors in the interactions file are located in this file
+    let path = root.join("simulation").join("bad").join("system").join("system-17.toml");
+    let potentials = path.parent().unwrap().join("../../../interactions/bad/pairs/pairs-13.toml");
+    let error = Input::new(&path).unwrap().read().err().unwrap();
+    check_location(error, &potentials, 10, 1);
+}
+
+/// Check the included files and the variables substitution
+fn includes() {
+    let root = PathBuf::new().join(env!("CARGO_MANIFEST_DIR")).join("tests").join("simulation");
+
+    let path = root.join("good").join("include.toml");
+    let config = Input::new(&path).unwrap().read().unwrap();
+    assert_eq!(config.nsteps, 100);
+    assert_eq!(config.system.size(), 3);
+    assert_eq!(config.system.cell().a(), 20.0);
+
+    let first = root.join("include").join("cycle-1.toml");
+    let second = root.join("include").join("cycle-2.toml");
+    let error = Input::new(&first).err().unwrap();
+    let expected = format!(
+        "{}:1:1: Cycle in included files: '{}' -> '{}' -> '{}'",
+        second.display(), first.display(), second.display(), first.display()
+    );
+    assert_eq!(error.to_string(), expected);
+}
+
+/// Write the `config` table to a string and parse it again, to check that the
+/// output of `ToToml` is valid TOML.
+fn reparse(config: Table) -> Table {
+    let string = Value::Table(config).to_string();
+    toml::Parser::new(&string).parse().expect("invalid TOML generated by ToToml")
+}
+
+/// Check that the interactions in `system` are read back identically after
+/// conversion to TOML
+fn check_interactions_to_toml(system: &System) {
+    let config = system.interactions().to_toml().unwrap();
+
+    let mut other = System::new();
+    let input = InteractionsInput::from_toml(reparse(config.clone())).unwrap();
+    input.read(&mut other).unwrap();
+    assert_eq!(other.interactions().to_toml().unwrap(), config);
+}
+
+/// Check that all the simulations in the input file at `path` are converted
+/// to TOML, and read back identically.
+fn check_simulation_to_toml(path: &Path) {
+    let directory = TempDir::new("lumol-to-toml").unwrap();
+    let mut input = Input::new(path).unwrap();
+    input.set_output_dir(directory.path());
+
+    for (i, table) in input.simulation_tables().unwrap().into_iter().enumerate() {
+        let simulation = input.read_simulation(i, table).unwrap();
+        let config = simulation.to_toml().unwrap();
+        // The number of steps and the velocities are not part of the simulation
+        for key in table.keys().filter(|key| *key != "nsteps" && *key != "velocities") {
+            assert!(config.contains_key(key), "'{}' is missing in the converted simulation", key);
+        }
+
+        let read_back = input.read_simulation(i, &reparse(config.clone())).unwrap();
+        assert_eq!(read_back.to_toml().unwrap(), config);
+        assert_eq!(frequencies(&read_back), frequencies(&simulation));
+    }
+}
+
+/// Get the frequencies of all the outputs in the `simulation`
+fn frequencies(simulation: &Simulation) -> Vec<u64> {
+    simulation.outputs().iter().map(|&(_, frequency)| frequency).collect()
+}
+
 fn cleanup() {
     const REMOVE: &'static [&'static str] = &[
-        "energy.dat", "filename.xyz", "cell.dat", "properties.dat"
+        "energy.dat", "filename.xyz", "cell.dat", "properties.dat",
+        "modes.dat", "modes.xyz", "rdf.dat", "msd.dat",
+        "vacf.dat", "viscosity.dat", "conductivity.dat", "sk.dat",
+        "energy-summary.dat", "properties-summary.dat", "fluctuations.dat",
+        "custom.dat", "energy-breakdown.dat", "profiles.dat", "energy-300.dat",
+        "neb-profile.dat", "neb-band.xyz",
     ];
 
     for file in REMOVE {
@@ -131,6 +329,15 @@ fn cleanup() {
     }
 }
 
+/// Get the message of a configuration error, without its location
+fn config_message(error: &Error) -> Option<&str> {
+    match *error {
+        Error::Config(ref message) => Some(message),
+        Error::Located(ref error, _) => config_message(error),
+        _ => None,
+    }
+}
+
 fn get_error_message(path: &Path) -> String {
     let mut buffer = String::new();
     File::open(path)
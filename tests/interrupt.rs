@@ -0,0 +1,60 @@
+// Lumol, an extensible molecular simulation engine
+// Copyright (C) 2015-2016 Lumol's contributors — BSD license
+
+//! Testing that simulations stop cleanly when an interruption is requested,
+//! for example by a termination signal. This is in a separate file because
+//! the interruption request applies to all the simulations running in the
+//! process, including the ones in other tests running in parallel.
+extern crate lumol;
+extern crate lumol_input as input;
+
+use lumol::Logger;
+use lumol::sys::System;
+use lumol::sim::{Simulation, RunInfo, HookAction, StepInfo};
+use lumol::sim::stop;
+use input::Input;
+
+use std::env;
+use std::fs;
+use std::path::Path;
+
+#[test]
+fn interrupt() {
+    Logger::stdout();
+    let path = Path::new(file!()).parent().unwrap()
+                                 .join("data")
+                                 .join("md-helium")
+                                 .join("stages.toml");
+    let checkpoint = env::temp_dir().join("lumol-interrupt.chk");
+
+    let mut config = Input::new(&path).unwrap().read().unwrap();
+    config.simulation.set_checkpoint(&checkpoint, 100_000);
+    config.simulation.before_step(|system: &mut System, _: StepInfo| {
+        // This is what the signal handler of the lumol binary does
+        if system.step() == 200 {
+            stop::request_interrupt();
+        }
+        HookAction::Continue
+    });
+    config.run();
+
+    // The current step is finished, and the other stages do not run
+    assert!(stop::interrupt_requested());
+    assert!(config.simulation.interrupted());
+    assert_eq!(config.system.step(), 201);
+
+    // The final checkpoint allow to restart the simulation
+    let mut system = System::new();
+    *system.interactions_mut() = config.system.interactions().clone();
+    let info = Simulation::restore_system(&mut system, &checkpoint).unwrap();
+    fs::remove_file(&checkpoint).unwrap();
+    assert_eq!(info, RunInfo{stage: 0, end: 500, finished: false});
+    assert_eq!(system.step(), 201);
+
+    // The request only applies to the simulations which were running
+    let mut config = Input::new(path).unwrap().read().unwrap();
+    config.simulation.run(&mut config.system, 50);
+    assert!(!stop::interrupt_requested());
+    assert!(!config.simulation.interrupted());
+    assert_eq!(config.system.step(), 50);
+}
@@ -0,0 +1,159 @@
+// Lumol, an extensible molecular simulation engine
+// Copyright (C) 2015-2016 Lumol's contributors — BSD license
+
+//! Testing that simulations restarted from a checkpoint continue exactly as
+//! the original simulation
+extern crate lumol;
+extern crate lumol_input as input;
+
+use lumol::Logger;
+use lumol::sys::System;
+use lumol::sim::{Propagator, StopCondition};
+use input::Input;
+
+use std::env;
+use std::fs::{self, File};
+use std::io::prelude::*;
+use std::path::{Path, PathBuf};
+use std::sync::{Once, ONCE_INIT};
+static START: Once = ONCE_INIT;
+
+/// Interrupt the simulation at a given step, as if the job was killed
+struct Interrupt(u64);
+
+impl StopCondition for Interrupt {
+    fn check(&mut self, system: &System, _: &Propagator) -> bool {
+        system.step() >= self.0
+    }
+
+    fn describe(&self) -> &str {
+        "interrupted"
+    }
+
+    fn interrupt(&self) -> bool {
+        true
+    }
+}
+
+fn check_same_particles(system: &System, reference: &System) {
+    assert_eq!(system.step(), reference.step());
+    for (particle, expected) in system.iter().zip(reference.iter()) {
+        assert_eq!(particle.position, expected.position);
+        assert_eq!(particle.velocity, expected.velocity);
+    }
+}
+
+fn read_file(path: &Path) -> String {
+    let mut content = String::new();
+    let _ = File::open(path).unwrap().read_to_string(&mut content).unwrap();
+    return content;
+}
+
+fn check_restart(input: PathBuf, name: &str) {
+    START.call_once(|| {Logger::stdout();});
+    let checkpoint = env::temp_dir().join(format!("{}.chk", name));
+    let expected = env::temp_dir().join(format!("{}-expected", name));
+    let outputs = env::temp_dir().join(format!("{}-outputs", name));
+
+    let mut reference = Input::new(input.clone()).unwrap();
+    reference.set_output_dir(&expected);
+    let mut reference = reference.read().unwrap();
+    reference.simulation.run(&mut reference.system, reference.nsteps);
+
+    let mut config = Input::new(input.clone()).unwrap();
+    config.set_output_dir(&outputs);
+    let mut config = config.read().unwrap();
+    let half = config.nsteps / 2;
+    config.simulation.set_checkpoint(&checkpoint, 100_000);
+    config.simulation.add_stop_condition(Box::new(Interrupt(half as u64)));
+    config.simulation.run(&mut config.system, config.nsteps);
+    assert!(config.simulation.interrupted());
+
+    let mut input = Input::new(input).unwrap();
+    input.set_output_dir(&outputs);
+    input.set_restart(checkpoint.clone());
+    let mut restarted = input.read().unwrap();
+    assert_eq!(restarted.nsteps, reference.nsteps - half);
+    restarted.simulation.run(&mut restarted.system, restarted.nsteps);
+    fs::remove_file(&checkpoint).unwrap();
+
+    check_same_particles(&restarted.system, &reference.system);
+    // The output files are continued when restarting
+    for entry in fs::read_dir(&expected).unwrap() {
+        let entry = entry.unwrap();
+        assert_eq!(read_file(&outputs.join(entry.file_name())), read_file(&entry.path()));
+    }
+    fs::remove_dir_all(&expected).unwrap();
+    fs::remove_dir_all(&outputs).unwrap();
+}
+
+#[test]
+fn molecular_dynamics() {
+    let path = Path::new(file!()).parent().unwrap()
+                                 .join("data")
+                                 .join("md-helium")
+                                 .join("restart.toml");
+    check_restart(path, "lumol-restart-md");
+}
+
+#[test]
+fn monte_carlo() {
+    let path = Path::new(file!()).parent().unwrap()
+                                 .join("data")
+                                 .join("mc-helium")
+                                 .join("nvt.toml");
+    check_restart(path, "lumol-restart-mc");
+}
+
+#[test]
+fn stages() {
+    START.call_once(|| {Logger::stdout();});
+    let path = Path::new(file!()).parent().unwrap()
+                                 .join("data")
+                                 .join("md-helium")
+                                 .join("stages.toml");
+
+    let finished = env::temp_dir().join("lumol-restart-stages-finished.chk");
+    let mut reference = Input::new(path.clone()).unwrap().read().unwrap();
+    assert_eq!(reference.stages.len(), 1);
+    reference.stages[0].simulation.set_checkpoint(&finished, 100_000);
+    reference.run();
+    assert_eq!(reference.system.step(), 1000);
+
+    // Nothing to restart when the last stage is finished
+    let mut input = Input::new(path.clone()).unwrap();
+    input.set_restart(finished.clone());
+    assert!(input.read().is_err());
+    fs::remove_file(&finished).unwrap();
+
+    // Checkpoint at the end of the first stage
+    let checkpoint = env::temp_dir().join("lumol-restart-stages-1.chk");
+    let mut config = Input::new(path.clone()).unwrap().read().unwrap();
+    config.simulation.set_checkpoint(&checkpoint, 500);
+    config.simulation.run(&mut config.system, config.nsteps);
+
+    let mut input = Input::new(path.clone()).unwrap();
+    input.set_restart(checkpoint.clone());
+    let mut restarted = input.read().unwrap();
+    assert_eq!(restarted.nsteps, 500);
+    assert!(restarted.stages.is_empty());
+    restarted.run();
+    fs::remove_file(&checkpoint).unwrap();
+    check_same_particles(&restarted.system, &reference.system);
+
+    // Checkpoint in the middle of the second stage
+    let checkpoint = env::temp_dir().join("lumol-restart-stages-2.chk");
+    let mut config = Input::new(path.clone()).unwrap().read().unwrap();
+    config.stages[0].simulation.set_checkpoint(&checkpoint, 100_000);
+    config.stages[0].simulation.add_stop_condition(Box::new(Interrupt(750)));
+    config.run();
+    assert_eq!(config.system.step(), 750);
+
+    let mut input = Input::new(path).unwrap();
+    input.set_restart(checkpoint.clone());
+    let mut restarted = input.read().unwrap();
+    assert_eq!(restarted.nsteps, 250);
+    restarted.run();
+    fs::remove_file(&checkpoint).unwrap();
+    check_same_particles(&restarted.system, &reference.system);
+}